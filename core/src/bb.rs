@@ -44,6 +44,10 @@ impl Bitboard {
     pub const RANK_7: Bitboard = Bitboard(RANK_7);
     /// Bitboard Rank 1.
     pub const RANK_8: Bitboard = Bitboard(RANK_8);
+    /// Bitboard of the dark squares.
+    pub const DARK_SQUARES: Bitboard = Bitboard(DARK_SQUARES);
+    /// Bitboard of the light squares.
+    pub const LIGHT_SQUARES: Bitboard = Bitboard(LIGHT_SQUARES);
 
     // TODO: rename this to `from()` - OR DELETE?
     pub fn new(bb: u64) -> Self {
@@ -145,9 +149,61 @@ impl Bitboard {
             Some(self.pop_lsb_and_bit())
         }
     }
+
+    /// Flips the `Bitboard` vertically, so that the first rank swaps with the eighth, the second
+    /// with the seventh and so on.
+    #[inline(always)]
+    pub const fn flip_vertical(self) -> Self {
+        Bitboard(self.0.swap_bytes())
+    }
+
+    /// Mirrors the `Bitboard` horizontally, so that the a-file swaps with the h-file, the b-file
+    /// with the g-file and so on.
+    #[inline(always)]
+    pub const fn mirror_horizontal(self) -> Self {
+        // Reversing all the bits flips the ranks as well as the files, so flip them back.
+        Bitboard(self.0.reverse_bits().swap_bytes())
+    }
+
+    /// Flips the `Bitboard` about the a1-h8 diagonal, so that each rank swaps with the file of the
+    /// same number: the first rank with the a-file, the second with the b-file and so on.
+    #[inline]
+    pub const fn flip_diagonal(self) -> Self {
+        // Swap 4x4 blocks, then 2x2 blocks, then single bits either side of the diagonal.
+        const K1: u64 = 0x5500_5500_5500_5500;
+        const K2: u64 = 0x3333_0000_3333_0000;
+        const K4: u64 = 0x0F0F_0F0F_0000_0000;
+
+        let mut x = self.0;
+        let mut t = K4 & (x ^ (x << 28));
+        x ^= t ^ (t >> 28);
+        t = K2 & (x ^ (x << 14));
+        x ^= t ^ (t >> 14);
+        t = K1 & (x ^ (x << 7));
+        x ^= t ^ (t >> 7);
+
+        Bitboard(x)
+    }
+
+    /// Returns an iterator over every subset of the `Bitboard`, from the empty set up to the full
+    /// set, in increasing numerical order.
+    ///
+    /// This uses the Carry-Rippler trick: subtracting the set from a subset and masking with the
+    /// set again gives the next subset, with the borrow rippling through the unset bits.
+    #[inline]
+    pub fn subsets(self) -> impl Iterator<Item = Bitboard> {
+        let set = self.0;
+        std::iter::successors(Some(Bitboard(0)), move |subset| {
+            match subset.0.wrapping_sub(set) & set {
+                0 => None,
+                next => Some(Bitboard(next)),
+            }
+        })
+    }
 }
 
 impl_bit_ops!(Bitboard, u64);
+impl_from_bits!(Bitboard, u64);
 
 impl std::iter::Iterator for Bitboard {
     type Item = Square;
@@ -225,4 +281,75 @@ mod tests {
             assert_eq!(bb.pop_some_lsb_and_bit(), None);
         }
     }
+
+    #[test]
+    fn flips_and_mirrors() {
+        let b1 = Square::B1.to_bb();
+        assert_eq!(b1.flip_vertical(), Square::B8.to_bb());
+        assert_eq!(b1.mirror_horizontal(), Square::G1.to_bb());
+        assert_eq!(b1.flip_diagonal(), Square::A2.to_bb());
+
+        assert_eq!(Bitboard::RANK_2.flip_vertical(), Bitboard::RANK_7);
+        assert_eq!(Bitboard::FILE_C.mirror_horizontal(), Bitboard::FILE_F);
+        assert_eq!(Bitboard::RANK_1.flip_diagonal(), Bitboard::FILE_A);
+
+        // Every square lands where it should.
+        for sq in 0..64u8 {
+            let (rank, file) = (sq / 8, sq % 8);
+            let bb = Bitboard::from_sq_idx(sq);
+            assert_eq!(
+                bb.flip_vertical(),
+                Bitboard::from_sq_idx((7 - rank) * 8 + file)
+            );
+            assert_eq!(
+                bb.mirror_horizontal(),
+                Bitboard::from_sq_idx(rank * 8 + 7 - file)
+            );
+            assert_eq!(bb.flip_diagonal(), Bitboard::from_sq_idx(file * 8 + rank));
+        }
+
+        // Doing any of them twice gets back to where we started.
+        for bb in [
+            Bitboard(0x0123_4567_89AB_CDEF),
+            Bitboard(0xFFFF_0000_F0F0_AA55),
+            Bitboard::DARK_SQUARES,
+            Bitboard::empty(),
+            Bitboard::ALL,
+        ] {
+            assert_eq!(bb.flip_vertical().flip_vertical(), bb);
+            assert_eq!(bb.mirror_horizontal().mirror_horizontal(), bb);
+            assert_eq!(bb.flip_diagonal().flip_diagonal(), bb);
+        }
+
+        // They can be used in constants.
+        const RANK_8: Bitboard = Bitboard::RANK_1.flip_vertical();
+        assert_eq!(RANK_8, Bitboard::RANK_8);
+    }
+
+    #[test]
+    fn subsets_carry_ripple() {
+        let bb = Square::A1.to_bb() | Square::C1.to_bb() | Square::H8.to_bb();
+        let subsets: Vec<Bitboard> = bb.subsets().collect();
+
+        let (a1, c1, h8) = (Square::A1.to_bb(), Square::C1.to_bb(), Square::H8.to_bb());
+        assert_eq!(
+            subsets,
+            vec![
+                Bitboard::empty(),
+                a1,
+                c1,
+                a1 | c1,
+                h8,
+                a1 | h8,
+                c1 | h8,
+                a1 | c1 | h8,
+            ]
+        );
+
+        assert_eq!(
+            Bitboard::empty().subsets().collect::<Vec<_>>(),
+            vec![Bitboard::empty()]
+        );
+        assert_eq!(Bitboard::RANK_1.subsets().count(), 256);
+    }
 }
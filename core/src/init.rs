@@ -1,5 +1,7 @@
 use crate::precalc::boards::init_boards;
 use crate::precalc::magic::init_magics;
+#[cfg(all(feature = "pext", target_arch = "x86_64", target_feature = "bmi2"))]
+use crate::precalc::pext::init_pext;
 use crate::precalc::zobrist::init_zobrist;
 use std::sync::Once;
 
@@ -12,13 +14,55 @@ static INITALIZED: Once = Once::new();
 /// - zobrist hash keys.
 ///
 /// Any subsequent calls to this function after the first have no
-/// effect and should return instantly.
+/// effect and should return instantly. It is safe to call from several
+/// threads at once: one of them does the initialisation, and the others
+/// wait for it to finish.
 pub fn init_globals() {
     // The closure inside `call_once()` is only ever invoked on the first call,
     // so this function will return instantly on further calls.
     INITALIZED.call_once(|| {
         init_magics();
+        #[cfg(all(feature = "pext", target_arch = "x86_64", target_feature = "bmi2"))]
+        init_pext();
         init_boards();
         init_zobrist();
     })
 }
+
+/// Whether `init_globals` has finished initialising the global variables.
+pub fn is_initialized() -> bool {
+    INITALIZED.is_completed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bb::Bitboard;
+    use crate::movegen::{bishop_moves, rook_moves};
+    use crate::position::Square;
+
+    use std::thread;
+
+    #[test]
+    fn init_from_many_threads() {
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                thread::spawn(|| {
+                    init_globals();
+
+                    // Whichever thread did the work, the tables are ready once the call returns.
+                    assert!(is_initialized());
+                    assert_eq!(rook_moves(Bitboard::empty(), Square::A1).popcnt(), 14);
+                    assert_eq!(bishop_moves(Bitboard::empty(), Square::D4).popcnt(), 13);
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        init_globals();
+        assert!(is_initialized());
+    }
+}
@@ -10,14 +10,14 @@ macro_rules! impl_indv_shift_ops {
 
             #[inline]
             fn $fname(self, rhs: usize) -> $t {
-                Self::from((self.0).$w(rhs as u32))
+                Self((self.0).$w(rhs as u32))
             }
         }
 
         impl $ta_name<usize> for $t {
             #[inline]
             fn $fa_name(&mut self, rhs: usize) {
-                *self = Self::from((self.0).$w(rhs as u32));
+                *self = Self((self.0).$w(rhs as u32));
             }
         }
     };
@@ -32,14 +32,14 @@ macro_rules! impl_indv_bit_ops {
 
             #[inline]
             fn $fname(self, rhs: $t) -> $t {
-                Self::from((self.0).$w(rhs.0))
+                Self((self.0).$w(rhs.0))
             }
         }
 
         impl $ta_name for $t {
             #[inline]
             fn $fa_name(&mut self, rhs: $t) {
-                *self = Self::from((self.0).$w(rhs.0));
+                *self = Self((self.0).$w(rhs.0));
             }
         }
 
@@ -48,24 +48,22 @@ macro_rules! impl_indv_bit_ops {
 
             #[inline]
             fn $fname(self, rhs: $b) -> $t {
-                Self::from((self.0).$w(rhs))
+                Self((self.0).$w(rhs))
             }
         }
 
         impl $ta_name<$b> for $t {
             #[inline]
             fn $fa_name(&mut self, rhs: $b) {
-                *self = Self::from((self.0).$w(rhs));
+                *self = Self((self.0).$w(rhs));
             }
         }
     };
 }
 
-/// Implies bit operations `&, |, ^, !`, shifting operations `<< >>`,
-/// math operations `+, -, *, /, %` and `From` trait to a struct consisting of a
-/// singular tuple. This tuple must contain a type that implements these bit operations.
-// TODO: use `impl_bit_ops` to implement everything for `Bitboard`s (rather than current manual impl)
-macro_rules! impl_bit_ops {
+/// Implements `From` the inner type for a struct consisting of a singular tuple. This is left out
+/// of `impl_bit_ops` for types, like `Square`, where not every value of the inner type is valid.
+macro_rules! impl_from_bits {
     ($t:tt, $b:tt) => {
         impl From<$b> for $t {
             #[inline]
@@ -73,7 +71,15 @@ macro_rules! impl_bit_ops {
                 $t(bit_type)
             }
         }
+    };
+}
 
+/// Implies bit operations `&, |, ^, !`, shifting operations `<< >>`,
+/// math operations `+, -, *, /, %` and `From` the struct to its inner type, to a struct consisting
+/// of a singular tuple. This tuple must contain a type that implements these bit operations.
+// TODO: use `impl_bit_ops` to implement everything for `Bitboard`s (rather than current manual impl)
+macro_rules! impl_bit_ops {
+    ($t:tt, $b:tt) => {
         impl From<$t> for $b {
             #[inline]
             fn from(it: $t) -> Self {
@@ -146,7 +152,9 @@ mod tests {
     struct DummySQ(pub u8);
 
     impl_bit_ops!(DummySQ, u8);
+    impl_from_bits!(DummySQ, u8);
     impl_bit_ops!(DummyBB, u64);
+    impl_from_bits!(DummyBB, u64);
 
     const SQ_CONSTS: [u8; 18] = [
         0xFE, 0xC1, 0x21, 0x9F, 0x44, 0xA0, 0xF7, 0xFF, 0x11, 0x7A, 0x01, 0x02, 0x03, 0x04, 0x05,
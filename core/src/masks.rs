@@ -46,6 +46,11 @@ pub const RANK_7: u64 = 0x00FF_0000_0000_0000;
 /// Bit representation of rank 8.
 pub const RANK_8: u64 = 0xFF00_0000_0000_0000;
 
+/// Bit representation of the dark squares.
+pub const DARK_SQUARES: u64 = 0xAA55_AA55_AA55_AA55;
+/// Bit representation of the light squares.
+pub const LIGHT_SQUARES: u64 = !DARK_SQUARES;
+
 /// Array of all files and their corresponding bits, indexed from
 /// file A to file H.
 pub static FILE_BB: [u64; FILE_CNT] = [
@@ -338,6 +338,10 @@ pub struct Promotions {}
 pub struct QueenPromotions {}
 /// Dummy type to represent a `Generation::Quiets` which implements `Generate`.
 pub struct Quiets {}
+/// Dummy type to represent a `Generation::QuietChecks` which implements `Generate`.
+pub struct QuietChecks {}
+/// Dummy type to represent a `Generation::Evasions` which implements `Generate`.
+pub struct Evasions {}
 
 impl Generate for All {
     #[inline(always)]
@@ -374,6 +378,20 @@ impl Generate for Quiets {
     }
 }
 
+impl Generate for QuietChecks {
+    #[inline(always)]
+    fn kind() -> Generation {
+        Generation::QuietChecks
+    }
+}
+
+impl Generate for Evasions {
+    #[inline(always)]
+    fn kind() -> Generation {
+        Generation::Evasions
+    }
+}
+
 /// The `Legality` allows for monomorphizing movegen code to different version based on
 /// whether we want to generate just legal moves, or include pseudolegal moves as well.
 pub trait Legality {
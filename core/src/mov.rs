@@ -59,6 +59,8 @@ impl Move {
         self.ty.contains(MoveType::CASTLE)
     }
 
+    /// Whether the move is a plain move of a piece to an empty square. Captures, promotions and
+    /// castling moves are not quiet.
     #[inline(always)]
     pub fn is_quiet(&self) -> bool {
         self.ty.contains(MoveType::QUIET)
@@ -69,8 +71,9 @@ impl Move {
         self.ty.intersects(MoveType::QUIET | MoveType::CASTLE)
     }
 
+    /// Whether the move is a promotion, with or without a capture.
     #[inline(always)]
-    pub fn is_promo(&self) -> bool {
+    pub fn is_promotion(&self) -> bool {
         debug_assert!(if self.ty.contains(MoveType::PROMOTION) {
             self.promo_piece_type.is_some()
         } else {
@@ -79,6 +82,12 @@ impl Move {
         self.ty.contains(MoveType::PROMOTION)
     }
 
+    #[deprecated(note = "renamed to `is_promotion`")]
+    #[inline(always)]
+    pub fn is_promo(&self) -> bool {
+        self.is_promotion()
+    }
+
     #[inline(always)]
     pub fn promo_piece_type(&self) -> Option<PieceType> {
         self.promo_piece_type
@@ -87,7 +96,7 @@ impl Move {
     /// Returns a clone of `self` with the promo piece type set to the passed value.
     #[inline(always)]
     pub fn set_promo_type(&self, promo_type: PieceType) -> Move {
-        debug_assert!(self.is_promo());
+        debug_assert!(self.is_promotion());
         debug_assert!(&[
             PieceType::Queen,
             PieceType::Rook,
@@ -129,6 +138,19 @@ impl Move {
         }
     }
 
+    /// Returns true if `self` and `other` represent the same move, i.e. they have the same origin
+    /// and destination squares and the same promotion piece.
+    ///
+    /// Unlike `==`, this ignores the `MoveType` flags, which are derived information and may
+    /// differ between two encodings of the same move (e.g. a move reconstructed from the
+    /// transposition table versus the one produced by movegen).
+    #[inline(always)]
+    pub fn same_move(&self, other: &Move) -> bool {
+        self.orig == other.orig
+            && self.dest == other.dest
+            && self.promo_piece_type == other.promo_piece_type
+    }
+
     #[inline(always)]
     pub fn dest(&self) -> Square {
         self.dest
@@ -170,8 +192,9 @@ impl Move {
     ///
     /// E.g. 'e2e4'
     pub fn to_uci_string(&self) -> String {
+        // UCI writes the null move as `0000`.
         if self.is_null() {
-            return format!("Null");
+            return "0000".to_string();
         }
 
         if let Some(promo_piece) = self.promo_piece_type {
@@ -223,7 +246,7 @@ impl UndoableMove {
         self.ty.contains(MoveType::CASTLE)
     }
 
-    pub fn is_promo(&self) -> bool {
+    pub fn is_promotion(&self) -> bool {
         debug_assert!(if self.ty.contains(MoveType::PROMOTION) {
             self.promo_piece_type.is_some()
         } else {
@@ -232,6 +255,12 @@ impl UndoableMove {
         self.ty.contains(MoveType::PROMOTION)
     }
 
+    #[deprecated(note = "renamed to `is_promotion`")]
+    #[inline(always)]
+    pub fn is_promo(&self) -> bool {
+        self.is_promotion()
+    }
+
     /// Returns a string containing the uci encoding of this move.
     ///
     /// E.g. 'e2e4'
@@ -272,6 +301,85 @@ mod tests {
         assert_eq!(mem::size_of::<Move>(), 4);
     }
 
+    #[test]
+    fn same_move_ignores_flags() {
+        let a = Move::build(Square::E4, Square::D5, None, MoveType::CAPTURE);
+        let b = Move::build(Square::E4, Square::D5, None, MoveType::QUIET);
+        let c = Move::build(Square::E4, Square::E5, None, MoveType::QUIET);
+
+        assert!(a.same_move(&b));
+        assert_ne!(a, b);
+        assert!(!a.same_move(&c));
+
+        let q = Move::build(
+            Square::A7,
+            Square::A8,
+            Some(PieceType::Queen),
+            MoveType::PROMOTION,
+        );
+        let n = q.set_promo_type(PieceType::Knight);
+        assert!(!q.same_move(&n));
+    }
+
+    #[test]
+    fn move_type_predicates() {
+        let quiet = Move::build(Square::E2, Square::E4, None, MoveType::QUIET);
+        assert!(quiet.is_quiet());
+        assert!(quiet.is_quiet_or_castle());
+        assert!(!quiet.is_capture());
+        assert!(!quiet.is_promotion());
+
+        let capture = Move::build(Square::E4, Square::D5, None, MoveType::CAPTURE);
+        assert!(!capture.is_quiet());
+        assert!(!capture.is_quiet_or_castle());
+        assert!(capture.is_capture());
+        assert!(!capture.is_promotion());
+
+        let promo = Move::build(
+            Square::A7,
+            Square::A8,
+            Some(PieceType::Queen),
+            MoveType::PROMOTION,
+        );
+        assert!(!promo.is_quiet());
+        assert!(!promo.is_capture());
+        assert!(promo.is_promotion());
+
+        let capture_promo = Move::build(
+            Square::A7,
+            Square::B8,
+            Some(PieceType::Knight),
+            MoveType::PROMOTION | MoveType::CAPTURE,
+        );
+        assert!(!capture_promo.is_quiet());
+        assert!(capture_promo.is_capture());
+        assert!(capture_promo.is_promotion());
+
+        let en_passant = Move::build(
+            Square::E5,
+            Square::D6,
+            None,
+            MoveType::EN_PASSANT | MoveType::CAPTURE,
+        );
+        assert!(!en_passant.is_quiet());
+        assert!(en_passant.is_capture());
+        assert!(en_passant.is_en_passant());
+        assert!(!en_passant.is_promotion());
+
+        let castle = Move::build(Square::E1, Square::G1, None, MoveType::CASTLE);
+        assert!(!castle.is_quiet());
+        assert!(castle.is_quiet_or_castle());
+        assert!(castle.is_castle());
+        assert!(!castle.is_capture());
+        assert!(!castle.is_promotion());
+
+        let null = Move::null();
+        assert!(null.is_null());
+        assert!(!null.is_quiet());
+        assert!(!null.is_capture());
+        assert!(!null.is_promotion());
+    }
+
     #[test]
     fn undoable_move_is_56_bytes() {
         assert_eq!(mem::size_of::<UndoableMove>(), 56);
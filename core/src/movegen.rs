@@ -6,8 +6,15 @@ use crate::mono_traits::{
 use crate::mov::{Move, MoveType};
 use crate::movelist::{BasicMoveList, Frame, MoveList, MoveStack};
 use crate::position::{CastleType, PieceType, Player, Position, Square, PROMO_PIECES};
-use crate::precalc::boards::{between_bb, king_moves, knight_moves, line_bb, pawn_attacks_from};
-use crate::precalc::magic;
+use crate::precalc::boards::{
+    aligned, between_bb, king_moves, knight_moves, line_bb, pawn_attacks_from,
+};
+// The sliding piece attack lookups: `PEXT` ones where the target has BMI2 and the `pext` feature is
+// enabled, and magic ones otherwise.
+#[cfg(not(all(feature = "pext", target_arch = "x86_64", target_feature = "bmi2")))]
+use crate::precalc::magic as sliders;
+#[cfg(all(feature = "pext", target_arch = "x86_64", target_feature = "bmi2"))]
+use crate::precalc::pext as sliders;
 
 /// Types of move generating options.
 ///
@@ -21,6 +28,12 @@ use crate::precalc::magic;
 ///
 /// `Generation::Quiets` -> All moves which are not promotions or captures.
 ///
+/// `Generation::QuietChecks` -> All moves which are not promotions or captures, and which give
+/// check, either directly or by discovery.
+///
+/// `Generation::Evasions` -> All moves which get out of check. Nothing is generated when not in
+/// check.
+///
 /// # Safety
 ///
 /// `Generation::QuietChecks` and `Generation::NonEvasions` can only be used if the board
@@ -33,6 +46,8 @@ pub enum Generation {
     Promotions,
     QueenPromotions,
     Quiets,
+    QuietChecks,
+    Evasions,
 }
 
 /// Legality of moves to be generated.
@@ -147,10 +162,14 @@ impl<'a, MP: MoveList> InnerMoveGen<'a, MP> {
             return false;
         }
 
-        if (mov.move_type().contains(MoveType::CAPTURE))
-            && (position.get_occupied_enemy::<PL>() & dest_bb).is_empty()
-            || ((position.get_occupied_enemy::<PL>() & dest_bb).is_not_empty()
-                && !mov.move_type().contains(MoveType::CAPTURE))
+        // An en passant capture lands on an empty square, so it is checked against the en passant
+        // square instead. Any pawn move onto that square is an en passant capture, so the flags
+        // must be set exactly when this is one.
+        let en_passant =
+            piece.type_of() == PieceType::Pawn && position.ep_square() == Some(mov.dest());
+        let capture = en_passant || (position.get_occupied_enemy::<PL>() & dest_bb).is_not_empty();
+        if mov.move_type().contains(MoveType::EN_PASSANT) != en_passant
+            || mov.move_type().contains(MoveType::CAPTURE) != capture
         {
             return false;
         }
@@ -159,6 +178,21 @@ impl<'a, MP: MoveList> InnerMoveGen<'a, MP> {
             return false;
         }
 
+        if mov.move_type().contains(MoveType::CASTLE) {
+            if movegen.position.in_check() || orig != movegen.position.king_sq(PL::player()) {
+                return false;
+            }
+
+            let side = if mov.dest().file() > orig.file() {
+                CastleType::Kingside
+            } else {
+                CastleType::Queenside
+            };
+
+            return mov.dest() == Self::castle_dest::<PL>(side)
+                && movegen.castle_allowed::<PL>(side);
+        }
+
         if movegen.position.in_check() {
             if piece.is_none() || piece.player() != movegen.position.turn() {
                 return false;
@@ -218,27 +252,41 @@ impl<'a, MP: MoveList> InnerMoveGen<'a, MP> {
         let gen_type = G::kind();
 
         if movegen.position.in_check() {
-            movegen.generate_evasions::<G, PL, L>();
+            debug_assert_ne!(
+                gen_type,
+                Generation::QuietChecks,
+                "quiet checks generated while in check"
+            );
+            if gen_type == Generation::Evasions {
+                movegen.generate_evasions::<All, PL, L>();
+            } else {
+                movegen.generate_evasions::<G, PL, L>();
+            }
             return movegen.movelist;
         }
 
-        use Generation::*;
         match gen_type {
-            All => {
+            Generation::All => {
                 movegen.generate_all::<PL, L>();
             }
-            Captures => {
+            Generation::Captures => {
                 movegen.generate_captures::<PL, L>();
             }
-            Promotions => {
+            Generation::Promotions => {
                 movegen.generate_promotions::<PL, L>();
             }
-            QueenPromotions => {
+            Generation::QueenPromotions => {
                 movegen.generate_queen_promotions::<PL, L>();
             }
-            Quiets => {
+            Generation::Quiets => {
                 movegen.generate_quiets::<PL, L>();
             }
+            Generation::QuietChecks => {
+                movegen.generate_quiet_checks::<PL, L>();
+            }
+            Generation::Evasions => {
+                // There is no check to evade.
+            }
         }
 
         movegen.movelist
@@ -286,6 +334,118 @@ impl<'a, MP: MoveList> InnerMoveGen<'a, MP> {
         self.moves_per_piece::<Quiets, P, Queen, L>(Bitboard::ALL);
     }
 
+    /// Generate the quiet moves which give check. A piece gives a direct check by moving to one of
+    /// the squares from which it would attack the enemy king, and a discovered check by moving off
+    /// the line between the enemy king and one of our sliders. Assumes that we aren't in check.
+    #[inline(always)]
+    fn generate_quiet_checks<P: Side, L: Legality>(&mut self) {
+        debug_assert!(!self.position.in_check());
+
+        let ksq = self.position.king_sq(P::opp_player());
+
+        // Our pieces which stand alone between one of our sliders and the enemy king.
+        let candidates =
+            self.position.state.blockers[P::opp_player().inner() as usize] & self.us_occ;
+
+        self.generate_pawn_quiet_checks::<P, L>(ksq, candidates);
+        self.quiet_checks_per_piece::<P, Knight, L>(ksq, candidates);
+        self.quiet_checks_per_piece::<P, Bishop, L>(ksq, candidates);
+        self.quiet_checks_per_piece::<P, Rook, L>(ksq, candidates);
+        self.quiet_checks_per_piece::<P, Queen, L>(ksq, candidates);
+        self.quiet_checks_per_piece::<P, King, L>(ksq, candidates);
+
+        // Castling can only give check with the rook, which is rare enough to test directly.
+        for side in [CastleType::Queenside, CastleType::Kingside] {
+            if self.castle_allowed::<P>(side) {
+                let orig = self.position.king_sq(P::player());
+                let mov = Move::build(orig, Self::castle_dest::<P>(side), None, MoveType::CASTLE);
+                if self.position.gives_check(&mov) {
+                    self.add_move::<L>(mov);
+                }
+            }
+        }
+    }
+
+    /// Generate the pawn pushes which give check, not including promotions.
+    #[inline(always)]
+    fn generate_pawn_quiet_checks<P: Side, L: Legality>(
+        &mut self,
+        ksq: Square,
+        candidates: Bitboard,
+    ) {
+        let (rank_7, rank_3): (Bitboard, Bitboard) = if P::player() == Player::WHITE {
+            (Bitboard::RANK_7, Bitboard::RANK_3)
+        } else {
+            (Bitboard::RANK_2, Bitboard::RANK_6)
+        };
+
+        let pawns = self.position.piece_bb(P::player(), PieceType::Pawn) & !rank_7;
+        let empty_squares = !self.occ;
+
+        // The squares from which one of our pawns would attack the enemy king.
+        let check_sqs = Bitboard(pawn_attacks_from(ksq, P::opp_player()));
+
+        let push_one = empty_squares & P::shift_up(pawns);
+        let push_two = P::shift_up(push_one & rank_3) & empty_squares;
+
+        for dest in push_one {
+            let orig = P::down(dest);
+            if Self::gives_quiet_check(orig, dest, ksq, check_sqs, candidates) {
+                self.add_move::<L>(Move::build(orig, dest, None, MoveType::QUIET));
+            }
+        }
+
+        for dest in push_two {
+            let orig = P::down(P::down(dest));
+            if Self::gives_quiet_check(orig, dest, ksq, check_sqs, candidates) {
+                self.add_move::<L>(Move::build(orig, dest, None, MoveType::QUIET));
+            }
+        }
+    }
+
+    /// Generate the quiet moves giving check for a `Knight`, `King`, `Rook`, `Bishop` or `Queen`.
+    #[inline(always)]
+    fn quiet_checks_per_piece<PL: Side, P: PieceTrait, L: Legality>(
+        &mut self,
+        ksq: Square,
+        candidates: Bitboard,
+    ) {
+        let check_sqs = match P::kind() {
+            PieceType::Knight => knight_moves(ksq),
+            PieceType::Bishop => bishop_moves(self.occ, ksq),
+            PieceType::Rook => rook_moves(self.occ, ksq),
+            PieceType::Queen => queen_moves(self.occ, ksq),
+            _ => Bitboard(0),
+        };
+
+        let piece_bb: Bitboard = self.position.piece_bb(PL::player(), P::kind());
+        for orig in piece_bb {
+            let mut moves_bb = self.moves_bb::<P>(orig) & !self.occ;
+
+            if (candidates & orig.to_bb()).is_empty() {
+                moves_bb &= check_sqs;
+            } else {
+                moves_bb &= check_sqs | !Bitboard(line_bb(orig, ksq));
+            }
+
+            self.move_append_from_bb_flag::<L>(&mut moves_bb, orig, MoveType::QUIET);
+        }
+    }
+
+    /// Whether moving a piece from `orig` to `dest` gives check, given the squares from which the
+    /// piece attacks the enemy king and the candidates for a discovered check.
+    #[inline(always)]
+    fn gives_quiet_check(
+        orig: Square,
+        dest: Square,
+        ksq: Square,
+        check_sqs: Bitboard,
+        candidates: Bitboard,
+    ) -> bool {
+        (check_sqs & dest.to_bb()).is_not_empty()
+            || ((candidates & orig.to_bb()).is_not_empty() && !aligned(orig, dest, ksq))
+    }
+
     #[inline(always)]
     fn generate_evasions<G: Generate, P: Side, L: Legality>(&mut self) {
         debug_assert!(self.position.in_check());
@@ -668,41 +828,58 @@ impl<'a, MP: MoveList> InnerMoveGen<'a, MP> {
     // Generates castling for a single side
     #[inline(always)]
     fn castling_side<PL: Side, L: Legality>(&mut self, side: CastleType) {
-        if self.position.can_castle(PL::player(), side)
+        if self.castle_allowed::<PL>(side) {
+            let ksq = self.position.king_sq(PL::player());
+            let k_to = Self::castle_dest::<PL>(side);
+            self.add_move::<L>(Move::build(ksq, k_to, None, MoveType::CASTLE));
+        }
+    }
+
+    /// The square the king lands on when castling to `side`.
+    #[inline(always)]
+    fn castle_dest<PL: Side>(side: CastleType) -> Square {
+        let king_side = side == CastleType::Kingside;
+        PL::player().relative_square(if king_side { Square::G1 } else { Square::C1 })
+    }
+
+    /// Whether the side to move may castle to `side`: it has the castling right, the squares
+    /// between king and rook are empty, the rook is there, and the king doesn't pass through or
+    /// land on an attacked square. This assumes that the king isn't in check.
+    #[inline(always)]
+    fn castle_allowed<PL: Side>(&self, side: CastleType) -> bool {
+        if !(self.position.can_castle(PL::player(), side)
             && !self.position.castle_impeded(side)
             && self
                 .position
                 .piece_at_sq(self.position.castling_rook_square(side))
                 .type_of()
-                == PieceType::Rook
+                == PieceType::Rook)
         {
-            let king_side = side == CastleType::Kingside;
-            let ksq = self.position.king_sq(PL::player());
-            let k_to =
-                PL::player().relative_square(if king_side { Square::G1 } else { Square::C1 });
-            let enemies = self.them_occ;
-            let direction: fn(Square) -> Square = if king_side {
-                |x: Square| x - Square(1)
-            } else {
-                |x: Square| x + Square(1)
-            };
+            return false;
+        }
 
-            let mut s: Square = k_to;
-            let mut can_castle = true;
-            // Loop through all the squares the king goes through
-            // If any enemies attack that square, cannot castle
-            'outer: while s != ksq {
-                let attackers = self.position.attackers_to(s) & enemies;
-                if attackers.is_not_empty() {
-                    can_castle = false;
-                    break 'outer;
-                }
-                s = direction(s);
-            }
-            if can_castle {
-                self.add_move::<L>(Move::build(ksq, k_to, None, MoveType::CASTLE));
+        let king_side = side == CastleType::Kingside;
+        let ksq = self.position.king_sq(PL::player());
+        let k_to = Self::castle_dest::<PL>(side);
+        let enemies = self.them_occ;
+        let direction: fn(Square) -> Square = if king_side {
+            |x: Square| x - Square(1)
+        } else {
+            |x: Square| x + Square(1)
+        };
+
+        let mut s: Square = k_to;
+        // Loop through all the squares the king goes through
+        // If any enemies attack that square, cannot castle
+        while s != ksq {
+            let attackers = self.position.attackers_to(s) & enemies;
+            if attackers.is_not_empty() {
+                return false;
             }
+            s = direction(s);
         }
+
+        true
     }
 
     #[inline(always)]
@@ -795,7 +972,7 @@ impl<'a, MP: MoveList> InnerMoveGen<'a, MP> {
 #[inline(always)]
 pub fn bishop_moves(occupied: Bitboard, sq: Square) -> Bitboard {
     debug_assert!(sq.is_okay());
-    Bitboard(magic::bishop_attacks(occupied.0, sq.0))
+    Bitboard(sliders::bishop_attacks(occupied.0, sq.0))
 }
 
 /// Generate rook moves `Bitboard` from a square and an occupancy bitboard.
@@ -804,7 +981,7 @@ pub fn bishop_moves(occupied: Bitboard, sq: Square) -> Bitboard {
 #[inline(always)]
 pub fn rook_moves(occupied: Bitboard, sq: Square) -> Bitboard {
     debug_assert!(sq.is_okay());
-    Bitboard(magic::rook_attacks(occupied.0, sq.0))
+    Bitboard(sliders::rook_attacks(occupied.0, sq.0))
 }
 
 /// Generate queen moves `Bitboard` from a square and an occupancy bitboard.
@@ -813,13 +990,14 @@ pub fn rook_moves(occupied: Bitboard, sq: Square) -> Bitboard {
 #[inline(always)]
 pub fn queen_moves(occupied: Bitboard, sq: Square) -> Bitboard {
     debug_assert!(sq.is_okay());
-    Bitboard(magic::rook_attacks(occupied.0, sq.0) | magic::bishop_attacks(occupied.0, sq.0))
+    Bitboard(sliders::rook_attacks(occupied.0, sq.0) | sliders::bishop_attacks(occupied.0, sq.0))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::init::init_globals;
+    use crate::mono_traits::{Evasions, QuietChecks};
     use crate::position::Position;
 
     fn number_of_captures(fen: &str) -> usize {
@@ -905,7 +1083,7 @@ mod tests {
 
         let res = perft_captures_only(
             "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
-            8,
+            6,
         );
 
         println!("res: {}", res);
@@ -914,6 +1092,114 @@ mod tests {
         // captures but not promotions. Promotion captures are generated as part of the promotion
         // generation phase, and to avoid complexity deduplicating, they are not generated as part
         // of the capture phase.
-        assert_eq!(res, 4_224_543);
+        assert_eq!(res, 142_201);
+    }
+
+    fn number_of_quiet_checks(fen: &str) -> usize {
+        let pos = Position::from_fen(fen).unwrap();
+        pos.generate::<BasicMoveList, QuietChecks, Legal>().len()
+    }
+
+    #[test]
+    fn correct_quiet_check_counts() {
+        init_globals();
+
+        // Only Rh8 checks.
+        assert_eq!(number_of_quiet_checks("4k3/8/8/8/8/8/8/4K2R w K - 0 1"), 1);
+        // Every knight move uncovers the rook.
+        assert_eq!(
+            number_of_quiet_checks("4k3/8/8/8/4N3/8/8/4RK2 w - - 0 1"),
+            8
+        );
+        // d4 and f4 check, but d3 and f3 don't.
+        assert_eq!(
+            number_of_quiet_checks("8/8/8/4k3/8/3P4/5P2/4K3 w - - 0 1"),
+            2
+        );
+        // Ra8, Rd1 and castling queenside, where the rook lands on d1.
+        assert_eq!(number_of_quiet_checks("3k4/8/8/8/8/8/8/R3K3 w Q - 0 1"), 3);
+    }
+
+    /// Quiet checks must be exactly the quiet moves which give check, in every position reached
+    /// from the perft positions, other than those where the side to move is in check.
+    #[test]
+    fn quiet_checks_are_the_checking_quiets() {
+        init_globals();
+
+        fn check(pos: &mut Position, depth: usize) {
+            if !pos.in_check() {
+                let mut expected: Vec<String> = pos
+                    .generate::<BasicMoveList, Quiets, Legal>()
+                    .iter()
+                    .filter(|mov| pos.gives_check(mov))
+                    .map(|mov| mov.to_string())
+                    .collect();
+                let mut actual: Vec<String> = pos
+                    .generate::<BasicMoveList, QuietChecks, Legal>()
+                    .iter()
+                    .map(|mov| mov.to_string())
+                    .collect();
+                expected.sort();
+                actual.sort();
+                assert_eq!(actual, expected, "{}", pos.to_fen());
+            }
+
+            if depth > 0 {
+                for mov in &pos.generate::<BasicMoveList, All, Legal>() {
+                    pos.make_move(mov);
+                    check(pos, depth - 1);
+                    pos.unmake_move();
+                }
+            }
+        }
+
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ] {
+            let mut pos = Position::from_fen(fen).unwrap();
+            check(&mut pos, 2);
+        }
+    }
+
+    #[test]
+    fn evasions_are_the_legal_moves_in_check() {
+        init_globals();
+
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq - 0 2",
+            "8/8/8/2k5/3Pp3/8/8/4K2Q b - d3 0 1",
+            "4k3/8/8/8/8/8/4r3/R3K2R w KQ - 0 1",
+            "4k3/8/5n2/8/8/8/8/R3K2R w KQ - 0 1",
+        ] {
+            let mut pos = Position::from_fen(fen).unwrap();
+            for mov in &pos.generate::<BasicMoveList, All, Legal>() {
+                pos.make_move(mov);
+
+                let mut all: Vec<String> = pos
+                    .generate::<BasicMoveList, All, Legal>()
+                    .iter()
+                    .map(|mov| mov.to_string())
+                    .collect();
+                let mut evasions: Vec<String> = pos
+                    .generate::<BasicMoveList, Evasions, Legal>()
+                    .iter()
+                    .map(|mov| mov.to_string())
+                    .collect();
+                all.sort();
+                evasions.sort();
+
+                if pos.in_check() {
+                    assert_eq!(evasions, all, "{}", pos.to_fen());
+                } else {
+                    assert!(evasions.is_empty(), "{}", pos.to_fen());
+                }
+
+                pos.unmake_move();
+            }
+        }
     }
 }
@@ -598,6 +598,13 @@ impl<'a> MoveList for Frame<'a> {
         // So when we iterate, we need to iterate over `&Frame`. The iterator's existence means
         // that we can't push onto that `Frame` until it's dropped. Creating an iterator pushes a
         // new `Frame` onto the `MoveStack`.
+        //
+        // The `Frame`s point into the `MoveStack`'s storage, so it mustn't be reallocated. This is
+        // checked in release builds too: growing the storage would leave every `Frame` dangling.
+        assert!(
+            self.movestack().data.len() < self.movestack().data.capacity(),
+            "`MoveStack` is full"
+        );
         self.movestack().data.push(mv);
         self.rng.end = unsafe { self.rng.end.add(1) };
     }
@@ -673,4 +680,14 @@ mod tests {
     fn basic_move_list_is_1024_bytes() {
         assert_eq!(mem::size_of::<ArrayVec<Move, 254>>(), 1024);
     }
+
+    #[test]
+    #[should_panic(expected = "`MoveStack` is full")]
+    fn full_move_stack_panics() {
+        let mut stack = MoveStack::new();
+        let mut frame = stack.new_frame();
+        for _ in 0..=1_024 {
+            frame.push(Move::null());
+        }
+    }
 }
@@ -0,0 +1,326 @@
+//! Building a `Position` piece by piece, as an alternative to writing out a fen string.
+
+use super::{CastlingRights, Piece, PieceType, Player, Position, Square};
+
+use crate::bb::Bitboard;
+
+use std::fmt;
+
+/// Builds a `Position` from its pieces, side to move, castling rights and en passant square.
+///
+/// ```ignore
+/// let pos = PositionBuilder::new()
+///     .piece(Square::E1, Piece::WhiteKing)
+///     .piece(Square::E8, Piece::BlackKing)
+///     .piece(Square::D2, Piece::WhitePawn)
+///     .build()?;
+/// ```
+///
+/// `build` checks that the result is a position which could arise in a game, as far as the search
+/// and move generation rely on it.
+#[derive(Clone, Debug)]
+pub struct PositionBuilder {
+    pieces: Vec<(Square, Piece)>,
+    turn: Player,
+    castling_rights: CastlingRights,
+    ep_square: Option<Square>,
+    half_move_clock: u32,
+    move_number: u32,
+}
+
+/// The reasons a `PositionBuilder` can fail to build a `Position`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum BuildError {
+    /// The player doesn't have exactly one king.
+    KingCount(Player, u32),
+    /// There is a pawn on the first or eighth rank.
+    PawnOnBackRank(Square),
+    /// The side which isn't to move is in check.
+    OpponentInCheck,
+    /// A castling right was given without the king and rook on their starting squares.
+    CastlingRightsInvalid(CastlingRights),
+    /// The en passant square doesn't follow a double pawn push by the side which just moved.
+    EnPassantSquareInvalid(Square),
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            BuildError::KingCount(player, count) => {
+                write!(
+                    f,
+                    "{} has {} kings; there must be exactly one",
+                    player, count
+                )
+            }
+            BuildError::PawnOnBackRank(sq) => write!(f, "pawn on the back rank at {}", sq),
+            BuildError::OpponentInCheck => write!(f, "the side not to move is in check"),
+            BuildError::CastlingRightsInvalid(rights) => write!(
+                f,
+                "castling rights {} need the king and rook on their starting squares",
+                rights
+            ),
+            BuildError::EnPassantSquareInvalid(sq) => write!(
+                f,
+                "en passant square {} doesn't follow a double pawn push",
+                sq
+            ),
+        }
+    }
+}
+
+impl PositionBuilder {
+    /// An empty board with White to move, no castling rights and no en passant square.
+    pub fn new() -> Self {
+        Self {
+            pieces: Vec::new(),
+            turn: Player::WHITE,
+            castling_rights: CastlingRights::none(),
+            ep_square: None,
+            half_move_clock: 0,
+            move_number: 1,
+        }
+    }
+
+    /// Put `piece` on `sq`, replacing anything put there before.
+    pub fn piece(mut self, sq: Square, piece: Piece) -> Self {
+        self.pieces.push((sq, piece));
+        self
+    }
+
+    /// Set the side to move.
+    pub fn turn(mut self, turn: Player) -> Self {
+        self.turn = turn;
+        self
+    }
+
+    /// Set the castling rights.
+    pub fn castling_rights(mut self, castling_rights: CastlingRights) -> Self {
+        self.castling_rights = castling_rights;
+        self
+    }
+
+    /// Set the en passant square: the square behind a pawn which has just been pushed two squares.
+    pub fn ep_square(mut self, ep_square: Square) -> Self {
+        self.ep_square = Some(ep_square);
+        self
+    }
+
+    /// Set the half move clock, for the fifty-move rule.
+    pub fn half_move_clock(mut self, half_move_clock: u32) -> Self {
+        self.half_move_clock = half_move_clock;
+        self
+    }
+
+    /// Set the full move number.
+    pub fn move_number(mut self, move_number: u32) -> Self {
+        self.move_number = move_number;
+        self
+    }
+
+    /// Build the `Position`, checking that it is valid.
+    pub fn build(&self) -> Result<Position, BuildError> {
+        let mut pos = Position::blank();
+        for &(sq, piece) in &self.pieces {
+            pos.set_piece(sq, piece);
+        }
+
+        pos.turn = self.turn;
+        pos.castling_rights = self.castling_rights;
+        pos.ep_square = self.ep_square;
+        pos.half_move_clock = self.half_move_clock;
+        pos.move_number = self.move_number;
+
+        for player in [Player::WHITE, Player::BLACK] {
+            let kings = pos.piece_bb(player, PieceType::King).popcnt();
+            if kings != 1 {
+                return Err(BuildError::KingCount(player, kings));
+            }
+        }
+
+        let pawns = pos.piece_bb(Player::WHITE, PieceType::Pawn)
+            | pos.piece_bb(Player::BLACK, PieceType::Pawn);
+        let back_ranks = pawns & (Bitboard::RANK_1 | Bitboard::RANK_8);
+        if let Some(sq) = back_ranks.into_iter().next() {
+            return Err(BuildError::PawnOnBackRank(sq));
+        }
+
+        if pos.enemy_in_check() {
+            return Err(BuildError::OpponentInCheck);
+        }
+
+        self.validate_castling_rights(&pos)?;
+        self.validate_ep_square(&pos)?;
+
+        pos.recompute_state();
+
+        Ok(pos)
+    }
+
+    fn validate_castling_rights(&self, pos: &Position) -> Result<(), BuildError> {
+        let rights = [
+            (CastlingRights::WHITE_KINGSIDE, Player::WHITE, Square::H1),
+            (CastlingRights::WHITE_QUEENSIDE, Player::WHITE, Square::A1),
+            (CastlingRights::BLACK_KINGSIDE, Player::BLACK, Square::H8),
+            (CastlingRights::BLACK_QUEENSIDE, Player::BLACK, Square::A8),
+        ];
+
+        for (right, player, rook_sq) in rights {
+            if self.castling_rights.contains(right)
+                && (pos.king_sq(player) != player.relative_square(Square::E1)
+                    || pos.piece_at_sq(rook_sq) != Piece::make(player, PieceType::Rook))
+            {
+                return Err(BuildError::CastlingRightsInvalid(right));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn validate_ep_square(&self, pos: &Position) -> Result<(), BuildError> {
+        let ep_square = match self.ep_square {
+            Some(sq) => sq,
+            None => return Ok(()),
+        };
+
+        // The pawn which was pushed belongs to the side which isn't to move, and passed over the
+        // en passant square from its starting square.
+        let them = !self.turn;
+        let (orig, dest) = match them {
+            Player::WHITE => (ep_square - Square(8), ep_square + Square(8)),
+            Player::BLACK => (ep_square + Square(8), ep_square - Square(8)),
+        };
+
+        let valid = them.relative_rank(ep_square.rank()) == 2
+            && pos.piece_at_sq(dest) == Piece::make(them, PieceType::Pawn)
+            && pos.piece_at_sq(ep_square).is_none()
+            && pos.piece_at_sq(orig).is_none();
+
+        match valid {
+            true => Ok(()),
+            false => Err(BuildError::EnPassantSquareInvalid(ep_square)),
+        }
+    }
+}
+
+impl Default for PositionBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::init::init_globals;
+
+    fn kings() -> PositionBuilder {
+        PositionBuilder::new()
+            .piece(Square::E1, Piece::WhiteKing)
+            .piece(Square::E8, Piece::BlackKing)
+    }
+
+    #[test]
+    fn builds_valid_positions() {
+        init_globals();
+
+        let pos = kings()
+            .piece(Square::A1, Piece::WhiteRook)
+            .piece(Square::H8, Piece::BlackRook)
+            .piece(Square::D5, Piece::WhitePawn)
+            .piece(Square::E5, Piece::BlackPawn)
+            .castling_rights(CastlingRights::new(false, true, true, false))
+            .ep_square(Square::E6)
+            .half_move_clock(0)
+            .move_number(20)
+            .build()
+            .unwrap();
+
+        let fen = "4k2r/8/8/3Pp3/8/8/8/R3K3 w Qk e6 0 20";
+        assert_eq!(pos.to_fen(), fen);
+
+        let from_fen = Position::from_fen(fen).unwrap();
+        assert_eq!(pos.zobrist(), from_fen.zobrist());
+        assert_eq!(pos.state, from_fen.state);
+        assert_eq!(pos.material_key(), from_fen.material_key());
+        assert_eq!(pos.material_zobrist(), from_fen.material_zobrist());
+
+        // A later piece on the same square replaces an earlier one.
+        let pos = kings()
+            .piece(Square::D4, Piece::WhiteQueen)
+            .piece(Square::D4, Piece::WhiteKnight)
+            .turn(Player::BLACK)
+            .build()
+            .unwrap();
+        assert_eq!(pos.to_fen(), "4k3/8/8/8/3N4/8/8/4K3 b - - 0 1");
+    }
+
+    #[test]
+    fn rejects_illegal_setups() {
+        init_globals();
+
+        assert_eq!(
+            PositionBuilder::new()
+                .piece(Square::E1, Piece::WhiteKing)
+                .build()
+                .unwrap_err(),
+            BuildError::KingCount(Player::BLACK, 0)
+        );
+        assert_eq!(
+            kings()
+                .piece(Square::A1, Piece::WhiteKing)
+                .build()
+                .unwrap_err(),
+            BuildError::KingCount(Player::WHITE, 2)
+        );
+        assert_eq!(
+            kings()
+                .piece(Square::C8, Piece::WhitePawn)
+                .build()
+                .unwrap_err(),
+            BuildError::PawnOnBackRank(Square::C8)
+        );
+
+        // Black is in check, but it's White's move.
+        assert_eq!(
+            kings()
+                .piece(Square::E4, Piece::WhiteRook)
+                .build()
+                .unwrap_err(),
+            BuildError::OpponentInCheck
+        );
+        assert!(kings()
+            .piece(Square::E4, Piece::WhiteRook)
+            .turn(Player::BLACK)
+            .build()
+            .is_ok());
+
+        assert_eq!(
+            kings()
+                .castling_rights(CastlingRights::new(true, false, false, false))
+                .build()
+                .unwrap_err(),
+            BuildError::CastlingRightsInvalid(CastlingRights::WHITE_KINGSIDE)
+        );
+
+        // The en passant square must be behind a pawn of the side which just moved.
+        assert_eq!(
+            kings().ep_square(Square::E6).build().unwrap_err(),
+            BuildError::EnPassantSquareInvalid(Square::E6)
+        );
+        assert_eq!(
+            kings()
+                .piece(Square::E4, Piece::WhitePawn)
+                .ep_square(Square::E3)
+                .build()
+                .unwrap_err(),
+            BuildError::EnPassantSquareInvalid(Square::E3)
+        );
+        assert!(kings()
+            .piece(Square::E4, Piece::WhitePawn)
+            .ep_square(Square::E3)
+            .turn(Player::BLACK)
+            .build()
+            .is_ok());
+    }
+}
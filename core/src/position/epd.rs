@@ -0,0 +1,248 @@
+//! Support for Extended Position Description (EPD) records, as used by test suites like WAC and
+//! STS.
+
+use super::{FenError, Position};
+use crate::mov::Move;
+
+use std::collections::HashMap;
+
+/// A parsed EPD record: a position and its operations, keyed by opcode.
+#[derive(Debug)]
+pub struct Epd {
+    pub pos: Position,
+    pub ops: HashMap<String, EpdOperation>,
+}
+
+/// The operand(s) of an EPD operation.
+#[derive(Clone, Debug, PartialEq)]
+pub enum EpdOperation {
+    /// `bm`: the best move(s) in the position.
+    BestMoves(Vec<Move>),
+    /// `am`: move(s) to avoid in the position.
+    AvoidMoves(Vec<Move>),
+    /// `id`: an identifier for the record.
+    Id(String),
+    /// `dm`: the side to move has a mate in this many moves.
+    DirectMate(u32),
+    /// `ce`: the evaluation of the position in centipawns, from the side to move's perspective.
+    CentipawnEval(i32),
+    /// Any other operation, with its operands unparsed.
+    Other(Vec<String>),
+}
+
+#[derive(Debug)]
+pub enum EpdError {
+    /// There were fewer than four fields describing the position.
+    MissingFields,
+    /// The position fields were invalid.
+    InvalidPosition(FenError),
+    /// A string operand was missing its closing quote.
+    UnterminatedString,
+    /// A `bm` or `am` operand was not a legal move in the position.
+    InvalidMove(String),
+    /// An operand had the wrong type for its opcode.
+    InvalidOperand(String),
+}
+
+impl std::fmt::Display for EpdError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EpdError::MissingFields => write!(f, "expected at least four fields"),
+            EpdError::InvalidPosition(err) => write!(f, "invalid position; {}", err),
+            EpdError::UnterminatedString => write!(f, "unterminated string operand"),
+            EpdError::InvalidMove(mov) => write!(f, "invalid move {}", mov),
+            EpdError::InvalidOperand(op) => write!(f, "invalid operand {}", op),
+        }
+    }
+}
+
+impl Epd {
+    /// Parse an EPD record, e.g. `4k3/8/8/8/8/8/8/4K2R w K - bm Rh8+; id "test1";`.
+    ///
+    /// The first four fields are the same as in a fen string. EPD has no halfmove clock or
+    /// fullmove number, so these are set to 0 and 1 respectively. The operands of `bm` and `am`
+    /// are resolved to `Move`s from SAN.
+    pub fn parse(epd: &str) -> Result<Self, EpdError> {
+        let epd = epd.trim_start();
+        let mut fields = ["", "", "", "", "0", "1"];
+        let mut rest = epd;
+
+        for field in fields.iter_mut().take(4) {
+            let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            if end == 0 {
+                return Err(EpdError::MissingFields);
+            }
+
+            *field = &rest[..end];
+            rest = rest[end..].trim_start();
+        }
+
+        let pos = Position::from_fen_fields(fields).map_err(EpdError::InvalidPosition)?;
+
+        let mut ops = HashMap::new();
+        for op in split_operations(rest)? {
+            let (opcode, operands) = match op.split_first() {
+                Some(op) => op,
+                None => continue,
+            };
+
+            let operation = match opcode.as_str() {
+                "bm" => EpdOperation::BestMoves(resolve_moves(&pos, operands)?),
+                "am" => EpdOperation::AvoidMoves(resolve_moves(&pos, operands)?),
+                "id" => EpdOperation::Id(operands.join(" ")),
+                "dm" => EpdOperation::DirectMate(parse_operand(operands)?),
+                "ce" => EpdOperation::CentipawnEval(parse_operand(operands)?),
+                _ => EpdOperation::Other(operands.to_vec()),
+            };
+
+            ops.insert(opcode.clone(), operation);
+        }
+
+        Ok(Self { pos, ops })
+    }
+
+    /// The best moves given by the `bm` operation, if present.
+    pub fn best_moves(&self) -> Option<&[Move]> {
+        match self.ops.get("bm") {
+            Some(EpdOperation::BestMoves(moves)) => Some(moves),
+            _ => None,
+        }
+    }
+
+    /// The moves to avoid given by the `am` operation, if present.
+    pub fn avoid_moves(&self) -> Option<&[Move]> {
+        match self.ops.get("am") {
+            Some(EpdOperation::AvoidMoves(moves)) => Some(moves),
+            _ => None,
+        }
+    }
+
+    /// The identifier given by the `id` operation, if present.
+    pub fn id(&self) -> Option<&str> {
+        match self.ops.get("id") {
+            Some(EpdOperation::Id(id)) => Some(id),
+            _ => None,
+        }
+    }
+}
+
+/// Split the operations section of an EPD record into operations, each of which is a list of
+/// tokens beginning with the opcode. Operations are terminated by `;`, and string operands are
+/// enclosed in double quotes, within which whitespace and `;` don't act as separators.
+fn split_operations(ops: &str) -> Result<Vec<Vec<String>>, EpdError> {
+    let mut res = Vec::new();
+    let mut op = Vec::new();
+    let mut chars = ops.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            ';' => res.push(std::mem::take(&mut op)),
+            '"' => {
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => s.push(c),
+                        None => return Err(EpdError::UnterminatedString),
+                    }
+                }
+                op.push(s);
+            }
+            c if c.is_whitespace() => {}
+            c => {
+                let mut s = String::from(c);
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == ';' || c == '"' {
+                        break;
+                    }
+                    s.push(c);
+                    chars.next();
+                }
+                op.push(s);
+            }
+        }
+    }
+
+    // Be lenient about a missing `;` after the final operation.
+    if !op.is_empty() {
+        res.push(op);
+    }
+
+    Ok(res)
+}
+
+fn resolve_moves(pos: &Position, operands: &[String]) -> Result<Vec<Move>, EpdError> {
+    operands
+        .iter()
+        .map(|san| pos.from_san(san).ok_or(EpdError::InvalidMove(san.clone())))
+        .collect()
+}
+
+fn parse_operand<T: std::str::FromStr>(operands: &[String]) -> Result<T, EpdError> {
+    match operands {
+        [operand] => operand
+            .parse()
+            .map_err(|_| EpdError::InvalidOperand(operand.clone())),
+        _ => Err(EpdError::InvalidOperand(operands.join(" "))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::init::init_globals;
+
+    #[test]
+    fn parses_operations() {
+        init_globals();
+
+        let epd = Epd::parse(r#"4k3/8/8/8/8/8/8/4K2R w K - bm Rh8+; id "test 1; mate";"#).unwrap();
+
+        assert_eq!(epd.pos.to_fen(), "4k3/8/8/8/8/8/8/4K2R w K - 0 1");
+        assert_eq!(epd.best_moves().unwrap().len(), 1);
+        assert_eq!(epd.best_moves().unwrap()[0].to_uci_string(), "h1h8");
+        assert_eq!(epd.id(), Some("test 1; mate"));
+        assert!(epd.avoid_moves().is_none());
+    }
+
+    #[test]
+    fn parses_wac_style_records() {
+        init_globals();
+
+        let epd = Epd::parse(
+            "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - bm Qg6; id \"WAC.001\";",
+        )
+        .unwrap();
+        assert_eq!(epd.best_moves().unwrap()[0].to_uci_string(), "g3g6");
+        assert_eq!(epd.id(), Some("WAC.001"));
+
+        let epd = Epd::parse(
+            "r1b1k2r/ppppnppp/2n2q2/2b5/3NP3/2P1B3/PP3PPP/RN1QKB1R w KQkq - am Nf5 Nb5; ce -20; \
+             dm 3; c0 \"a comment\"",
+        )
+        .unwrap();
+        let avoid: Vec<String> = epd
+            .avoid_moves()
+            .unwrap()
+            .iter()
+            .map(|m| m.to_uci_string())
+            .collect();
+        assert_eq!(avoid, vec!["d4f5", "d4b5"]);
+        assert_eq!(epd.ops["ce"], EpdOperation::CentipawnEval(-20));
+        assert_eq!(epd.ops["dm"], EpdOperation::DirectMate(3));
+        assert_eq!(
+            epd.ops["c0"],
+            EpdOperation::Other(vec!["a comment".to_string()])
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_records() {
+        init_globals();
+
+        assert!(Epd::parse("4k3/8/8/8/8/8/8/4K2R w K").is_err());
+        assert!(Epd::parse("4k3/8/8/8/8/8/8/4K2R w K - bm Nf3;").is_err());
+        assert!(Epd::parse("4k3/8/8/8/8/8/8/4K2R w K - dm x;").is_err());
+        assert!(Epd::parse("4k3/8/8/8/8/8/8/4K2R w K - id \"unterminated;").is_err());
+    }
+}
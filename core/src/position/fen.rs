@@ -1,6 +1,7 @@
 use super::{Board, CastlingRights, Piece, Player, Position, Square, State, Zobrist};
 
 use crate::bb::Bitboard;
+use crate::init::is_initialized;
 
 pub const START_POSITION: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
 
@@ -67,8 +68,17 @@ impl std::fmt::Display for FenErrorType {
 
 impl Position {
     pub fn from_fen(fen: &str) -> Result<Self, FenError> {
+        debug_assert!(
+            is_initialized(),
+            "`init_globals` must be called before building a `Position`"
+        );
+        Self::from_fen_fields(Self::split_fen_fields(fen)?)
+    }
+
+    /// Build a `Position` from the six fields of a fen string.
+    pub(super) fn from_fen_fields(fields: [&str; 6]) -> Result<Self, FenError> {
         let [piece_positions, side_to_move, castling_rights, ep_square, half_move_clock, move_number] =
-            Self::split_fen_fields(fen)?;
+            fields;
 
         let (bbs, player_occ, board) = Self::parse_piece_position_string(piece_positions)?;
         let turn = Self::parse_side_to_move(side_to_move)?;
@@ -89,10 +99,15 @@ impl Position {
             state: State::blank(), // Temporary. The real `State` is generated below.
             history: Vec::new(),
             zobrist: Zobrist(0),
+            pawn_zobrist: Zobrist(0),
+            material_zobrist: Zobrist(0),
+            material_score: 0,
+            material_key: 0,
         };
 
         pos.set_state();
         pos.set_zobrist();
+        pos.set_material();
 
         Ok(pos)
     }
@@ -411,37 +426,27 @@ impl Position {
             return Ok(None);
         }
 
-        if ep_square.len() != 2 {
-            return Err(FenError {
-                ty: FenErrorType::EnPassantSquareInvalid,
-                msg: format!(
-                    "`{}` not a valid square name for the en passant square",
-                    ep_square
-                ),
-            });
-        }
-
         // TODO: can also run a check to ensure that the en passant square reconciles with the
         // side to move
 
-        match ep_square {
-            "a3" => Ok(Some(Square(16))),
-            "b3" => Ok(Some(Square(17))),
-            "c3" => Ok(Some(Square(18))),
-            "d3" => Ok(Some(Square(19))),
-            "e3" => Ok(Some(Square(20))),
-            "f3" => Ok(Some(Square(21))),
-            "g3" => Ok(Some(Square(22))),
-            "h3" => Ok(Some(Square(23))),
-            "a6" => Ok(Some(Square(40))),
-            "b6" => Ok(Some(Square(41))),
-            "c6" => Ok(Some(Square(42))),
-            "d6" => Ok(Some(Square(43))),
-            "e6" => Ok(Some(Square(44))),
-            "f6" => Ok(Some(Square(45))),
-            "g6" => Ok(Some(Square(46))),
-            "h6" => Ok(Some(Square(47))),
-            _ => Err(FenError {
+        // Square names parse in either case, but a FEN only allows lower case files.
+        let sq = match ep_square.parse::<Square>() {
+            Ok(sq) if sq.to_string() == ep_square => sq,
+            _ => {
+                return Err(FenError {
+                    ty: FenErrorType::EnPassantSquareInvalid,
+                    msg: format!(
+                        "`{}` not a valid square name for the en passant square",
+                        ep_square
+                    ),
+                })
+            }
+        };
+
+        if sq.rank() == 2 || sq.rank() == 5 {
+            Ok(Some(sq))
+        } else {
+            Err(FenError {
                 ty: FenErrorType::EnPassantSquareInvalid,
                 msg: format!("invalid en passant square `{}`; must be a valid algebraic notation square on the 3rd or 6th rank", ep_square),
             })
@@ -557,6 +562,57 @@ impl Position {
 
         s
     }
+
+    /// The colour-reflected position: the board is mirrored vertically, and the colours of the
+    /// pieces, the side to move, the castling rights and the en passant square are swapped over.
+    /// A symmetric evaluation scores this as the negation of the original. The move history isn't
+    /// kept.
+    pub fn flip_colors(&self) -> Position {
+        let fen = self.to_fen();
+        let [pieces, turn, castling, ep, half_move_clock, move_number] =
+            Self::split_fen_fields(&fen).expect("to_fen gives six fields");
+
+        let swap_case = |s: &str| -> String {
+            s.chars()
+                .map(|c| match c.is_ascii_uppercase() {
+                    true => c.to_ascii_lowercase(),
+                    false => c.to_ascii_uppercase(),
+                })
+                .collect()
+        };
+
+        let pieces = pieces
+            .split('/')
+            .rev()
+            .map(swap_case)
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let turn = match turn {
+            "w" => "b",
+            _ => "w",
+        };
+
+        let castling = match castling {
+            "-" => String::from("-"),
+            rights => {
+                let swapped = swap_case(rights);
+                "KQkq".chars().filter(|&c| swapped.contains(c)).collect()
+            }
+        };
+
+        let ep: String = ep
+            .chars()
+            .map(|c| match c {
+                '3' => '6',
+                '6' => '3',
+                c => c,
+            })
+            .collect();
+
+        Self::from_fen_fields([&pieces, turn, &castling, &ep, half_move_clock, move_number])
+            .expect("a flipped position is valid")
+    }
 }
 
 fn rank_file_to_idx(rank: u32, file: u8) -> u8 {
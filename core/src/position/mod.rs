@@ -1,7 +1,10 @@
 mod board;
+mod builder;
 mod castling;
+mod epd;
 mod fen;
 mod notation;
+mod pgn;
 mod piece;
 mod square;
 mod state;
@@ -9,18 +12,25 @@ mod zobrist;
 
 use crate::bb::Bitboard;
 use crate::masks::{CASTLING_PATH, CASTLING_ROOK_START, FILE_BB, PLAYER_CNT, RANK_BB};
-use crate::mono_traits::{All, Generate, Legal, Legality, Side};
+use crate::mono_traits::{All, Evasions, Generate, Legal, Legality, PseudoLegal, Side};
 use crate::mov::{Move, MoveType, UndoableMove};
 use crate::movegen::{bishop_moves, queen_moves, rook_moves, MoveGen};
 use crate::movelist::{BasicMoveList, Frame, MoveList, MoveStack};
 use crate::precalc::boards::{aligned, between_bb, king_moves, knight_moves, pawn_attacks_from};
 
 pub use board::Board;
+pub use builder::{BuildError, PositionBuilder};
 pub use castling::{CastleType, CastlingRights};
+pub use epd::{Epd, EpdError, EpdOperation};
 pub use fen::{FenError, START_POSITION};
-pub use piece::{Piece, PieceType, PIECE_TYPES, PROMO_PIECES};
-pub use square::Square;
+pub use pgn::PgnError;
+pub use piece::{
+    Piece, PieceType, BISHOP_VALUE, KING_VALUE, KNIGHT_VALUE, PAWN_VALUE, PIECE_TYPES,
+    PIECE_VALUES, PROMO_PIECES, QUEEN_VALUE, ROOK_VALUE,
+};
+pub use square::{Square, SquareError};
 pub use state::State;
+use zobrist::in_pawn_key;
 pub use zobrist::Zobrist;
 
 use std::fmt;
@@ -29,6 +39,9 @@ use std::ops::Not;
 /// The number of piece types including color on a chess board. Includes `Piece::None`.
 pub const PIECE_TYPE_CNT: usize = 13;
 
+/// The game phase of the starting position. See [`Position::game_phase`].
+pub const MAX_GAME_PHASE: i32 = 24;
+
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub struct Player(bool);
 
@@ -144,15 +157,35 @@ pub struct Position {
     /// The Zobrist key of the current position. Incrementally updated in `makemove()`
     /// and `unmakemove()`.
     pub(crate) zobrist: Zobrist,
+
+    /// A Zobrist key of the pawns and kings only, for caching pawn structure evaluation.
+    /// Incrementally updated alongside `zobrist`.
+    pub(crate) pawn_zobrist: Zobrist,
+
+    /// A Zobrist key of the number of each piece only, for caching verdicts on material
+    /// configurations. Incrementally updated as pieces are put on and removed from the board.
+    pub(crate) material_zobrist: Zobrist,
+
+    /// White's material minus Black's, in centipawns, not counting the kings. Incrementally
+    /// updated as pieces are put on and removed from the board.
+    pub(crate) material_score: i16,
+
+    /// The count of each piece type for each player, packed by `material_key_part`. Incrementally
+    /// updated alongside `material_score`.
+    pub(crate) material_key: u32,
 }
 
 impl Position {
     /// Creates a 'blank' `Position` struct. This method is safe to call even
     /// before `init_globals()`.
     pub fn blank() -> Self {
+        // The `Piece::None` bitboard holds the empty squares.
+        let mut bbs = [Bitboard::new(0); PIECE_TYPE_CNT];
+        bbs[Piece::None as usize] = Bitboard::ALL;
+
         Self {
             board: Board::new(),
-            bbs: [Bitboard::new(0); PIECE_TYPE_CNT],
+            bbs,
             player_occ: [Bitboard::new(0); PLAYER_CNT],
             turn: Player::WHITE,
             castling_rights: CastlingRights::none(),
@@ -162,6 +195,10 @@ impl Position {
             state: State::blank(),
             history: Vec::with_capacity(16),
             zobrist: Zobrist::empty(),
+            pawn_zobrist: Zobrist::empty(),
+            material_zobrist: Zobrist::empty(),
+            material_score: 0,
+            material_key: 0,
         }
     }
 
@@ -176,17 +213,104 @@ impl Position {
         self.state = State::from_position(&self);
     }
 
-    /// Set the `Zobrist` key for the current position based on the other data in
-    /// the `Position` struct. Should only be called when initialising a new `Position`
-    /// as the zobrist key is kept incrementally updated thereafter.
+    /// Set the `Zobrist` key, pawn key and material zobrist for the current position based on
+    /// the other data in the `Position` struct. Should only be called when initialising a new
+    /// `Position` as the keys are kept incrementally updated thereafter.
     pub fn set_zobrist(&mut self) {
         self.zobrist = Zobrist::from_position(&self);
+        self.pawn_zobrist = Zobrist::pawns_from_position(self);
+        self.material_zobrist = Zobrist::material_from_position(self);
+    }
+
+    /// Set the material score and material key for the current position from its bitboards.
+    /// Should only be called when initialising a new `Position`, as these are kept incrementally
+    /// updated thereafter.
+    pub fn set_material(&mut self) {
+        self.material_score = self.compute_material();
+        self.material_key = self.compute_material_key();
+    }
+
+    /// The material key, counted from scratch.
+    fn compute_material_key(&self) -> u32 {
+        let mut key = 0u32;
+        for player in [Player::WHITE, Player::BLACK] {
+            for piece_type in PieceType::iter() {
+                let count = self.piece_bb(player, piece_type).popcnt();
+                key = key.wrapping_add(material_key_part(player, piece_type, count));
+            }
+        }
+
+        key
+    }
+
+    /// White's material minus Black's, counted from scratch.
+    fn compute_material(&self) -> i16 {
+        PieceType::iter()
+            .filter(|&piece_type| piece_type != PieceType::King)
+            .map(|piece_type| {
+                (self.piece_bb(Player::WHITE, piece_type).popcnt() as i16
+                    - self.piece_bb(Player::BLACK, piece_type).popcnt() as i16)
+                    * PIECE_VALUES[piece_type as usize]
+            })
+            .sum()
+    }
+
+    /// White's material minus Black's, in centipawns, not counting the kings.
+    #[inline(always)]
+    pub fn material_eval(&self) -> i16 {
+        self.material_score
+    }
+
+    /// A key identifying the material on the board: the number of each type of piece for each
+    /// player. Positions with the same material share a key whatever the placement of the pieces,
+    /// so this can be used to recognise specific endgames. The key for a given combination of
+    /// material is the sum of the `material_key_part`s for each player and piece type.
+    #[inline(always)]
+    pub fn material_key(&self) -> u32 {
+        self.material_key
+    }
+
+    /// A hash of the material on the board, which like `material_key` depends only on the number
+    /// of each type of piece for each player. Unlike `material_key`, it never overflows whatever
+    /// the counts, and its bits are spread evenly, so it can index a table of verdicts on material
+    /// configurations directly.
+    #[inline(always)]
+    pub fn material_zobrist(&self) -> u64 {
+        self.material_zobrist.0
     }
 
     pub fn history(&self) -> &Vec<UndoableMove> {
         &self.history
     }
 
+    /// A copy of the current position with an empty history.
+    ///
+    /// Unlike `clone`, this doesn't copy the moves which led to the position, so it is cheap to
+    /// take however long the game has been. The snapshot plays on independently of `self`, but its
+    /// moves can't be unmade past the point it was taken, and repetitions of positions from
+    /// before that point aren't detected. This suits a search thread, which needs the root
+    /// position but not the game history. Use `clone` where repetitions of earlier positions
+    /// matter.
+    pub fn snapshot(&self) -> Position {
+        Self {
+            board: self.board.clone(),
+            bbs: self.bbs,
+            player_occ: self.player_occ,
+            turn: self.turn,
+            castling_rights: self.castling_rights,
+            ep_square: self.ep_square,
+            half_move_clock: self.half_move_clock,
+            move_number: self.move_number,
+            state: self.state,
+            history: Vec::with_capacity(16),
+            zobrist: self.zobrist,
+            pawn_zobrist: self.pawn_zobrist,
+            material_zobrist: self.material_zobrist,
+            material_score: self.material_score,
+            material_key: self.material_key,
+        }
+    }
+
     pub fn print_history(&self) -> String {
         let mut string = String::new();
         for mov in &self.history {
@@ -204,6 +328,123 @@ impl Position {
         self.zobrist
     }
 
+    /// A key hashing only the placement of the pawns and kings. Positions which differ only in
+    /// the placement of the other pieces share a pawn key.
+    pub fn pawn_key(&self) -> u64 {
+        self.pawn_zobrist.0
+    }
+
+    /// Cheaply compute the Zobrist key of the position after the given move, without making it.
+    ///
+    /// Any en passant square created by a double pawn push is not accounted for, so the key may
+    /// occasionally differ from the true key after the move. This is intended for speculative uses
+    /// like prefetching transposition table entries, where that doesn't matter.
+    pub fn key_after(&self, mov: &Move) -> Zobrist {
+        let us = self.turn();
+        let from = mov.orig();
+        let to = mov.dest();
+        let piece = self.piece_at_sq(from);
+
+        let mut key = self.zobrist;
+        key.update_ep_square(self.ep_square, None);
+        key.toggle_side_to_move();
+        key.update_castling_rights(self.castling_rights, self.castling_rights.update(from));
+
+        if mov.is_castle() {
+            let (r_orig, r_dest) = if from < to {
+                (Square::H1, Square::F1)
+            } else {
+                (Square::A1, Square::D1)
+            };
+            let rook = Piece::make(us, PieceType::Rook);
+            key.toggle_piece_sq(rook, us.relative_square(r_orig));
+            key.toggle_piece_sq(rook, us.relative_square(r_dest));
+        } else if mov.is_en_passant() {
+            let cap_sq = match us {
+                Player::WHITE => to - Square(8),
+                Player::BLACK => to + Square(8),
+            };
+            key.toggle_piece_sq(Piece::make(!us, PieceType::Pawn), cap_sq);
+        } else if !self.piece_at_sq(to).is_none() {
+            key.toggle_piece_sq(self.piece_at_sq(to), to);
+        }
+
+        key.toggle_piece_sq(piece, from);
+        match mov.promo_piece_type() {
+            Some(promo) => key.toggle_piece_sq(Piece::make(us, promo), to),
+            None => key.toggle_piece_sq(piece, to),
+        }
+
+        key
+    }
+
+    /// Returns whether the given legal move gives check, without making it.
+    ///
+    /// This detects direct checks by the moved piece (or the promoted piece), discovered checks by
+    /// a piece moving off the line between one of our sliders and the enemy king, and the rarer
+    /// checks given by the rook when castling or discovered by an en passant capture.
+    pub fn gives_check(&self, mov: &Move) -> bool {
+        let us = self.turn();
+        let from = mov.orig();
+        let to = mov.dest();
+        let ksq = self.king_sq(!us);
+        let occupied = (self.occupied() ^ from.to_bb()) | to.to_bb();
+
+        // Direct check.
+        let piece_type = match mov.promo_piece_type() {
+            Some(promo) => promo,
+            None => self.piece_at_sq(from).type_of(),
+        };
+
+        let attacks = match piece_type {
+            PieceType::Pawn => Bitboard(pawn_attacks_from(to, us)),
+            PieceType::Knight => knight_moves(to),
+            PieceType::Bishop => bishop_moves(occupied, to),
+            PieceType::Rook => rook_moves(occupied, to),
+            PieceType::Queen => queen_moves(occupied, to),
+            _ => Bitboard(0),
+        };
+
+        if (attacks & ksq.to_bb()).is_not_empty() {
+            return true;
+        }
+
+        // Discovered check. The blockers of the enemy king are the pieces standing alone between
+        // it and one of our sliders.
+        let blockers = self.state.blockers[(!us).inner() as usize];
+        if (blockers & from.to_bb()).is_not_empty() && !aligned(from, to, ksq) {
+            return true;
+        }
+
+        if mov.is_castle() {
+            let (r_orig, r_dest) = if from < to {
+                (Square::H1, Square::F1)
+            } else {
+                (Square::A1, Square::D1)
+            };
+            let r_orig = us.relative_square(r_orig);
+            let r_dest = us.relative_square(r_dest);
+            let occupied =
+                (self.occupied() ^ from.to_bb() ^ r_orig.to_bb()) | to.to_bb() | r_dest.to_bb();
+
+            return (rook_moves(occupied, r_dest) & ksq.to_bb()).is_not_empty();
+        }
+
+        if mov.is_en_passant() {
+            // Removing the captured pawn may open a line to the king.
+            let cap_sq = match us {
+                Player::WHITE => to - Square(8),
+                Player::BLACK => to + Square(8),
+            };
+            let occupied = occupied ^ cap_sq.to_bb();
+
+            return (rook_moves(occupied, ksq) & self.sliding_piece_bb(us)).is_not_empty()
+                || (bishop_moves(occupied, ksq) & self.diagonal_piece_bb(us)).is_not_empty();
+        }
+
+        false
+    }
+
     /// Make a move on the Board and update the `Position`.
     ///
     /// The supplied `Move` must be legal in the current position, otherwise undefined behaviour
@@ -316,11 +557,15 @@ impl Position {
         // Update "invisible" state
         self.turn = them;
         self.state = State::from_position(&self);
+
+        self.debug_check_keys("making", mov);
     }
 
     /// Unmake the most recent move, returning the `Position` to the previous state.
     pub fn unmake_move(&mut self) -> Option<UndoableMove> {
         if let Some(undoable_move) = self.history.pop() {
+            debug_assert!(!undoable_move.is_null());
+
             self.turn = !self.turn();
             let us = self.turn();
             let orig = undoable_move.orig;
@@ -330,7 +575,7 @@ impl Position {
             // Sanity check (only in debug mode) that the move makes sense.
             debug_assert!(self.piece_at_sq(orig) == Piece::None || undoable_move.is_castle());
 
-            if undoable_move.is_promo() {
+            if undoable_move.is_promotion() {
                 debug_assert_eq!(piece_on.type_of(), undoable_move.promo_piece_type.unwrap());
 
                 self.remove_piece_c(piece_on, dest);
@@ -365,12 +610,94 @@ impl Position {
                 self.move_number -= 1;
             }
 
+            self.debug_check_keys("unmaking", &undoable_move);
+
             Some(undoable_move)
         } else {
             None
         }
     }
 
+    /// Pass the turn to the opponent without moving a piece, as used by null move pruning.
+    ///
+    /// A null move is recorded in the history, so that it can be undone with `unmake_null_move`.
+    /// It must not be made while in check.
+    pub fn make_null_move(&mut self) {
+        debug_assert!(!self.in_check());
+
+        self.history.push(UndoableMove {
+            orig: Square(64),
+            dest: Square(64),
+            piece: Piece::None,
+            promo_piece_type: None,
+            captured: PieceType::None,
+            ty: MoveType::NULL,
+            prev_castling_rights: self.castling_rights,
+            prev_ep_square: self.ep_square,
+            prev_half_move_clock: self.half_move_clock,
+            state: self.state,
+            zobrist: self.zobrist,
+        });
+
+        self.zobrist.update_ep_square(self.ep_square, None);
+        self.ep_square = None;
+        self.zobrist.toggle_side_to_move();
+
+        self.half_move_clock += 1;
+        if self.turn() == Player::BLACK {
+            self.move_number += 1;
+        }
+
+        self.turn = !self.turn();
+        self.state = State::from_position(self);
+    }
+
+    /// Undo a null move made with `make_null_move`.
+    pub fn unmake_null_move(&mut self) {
+        let undoable_move = self.history.pop().expect("no null move to unmake");
+        debug_assert!(undoable_move.is_null());
+
+        self.turn = !self.turn();
+        if self.turn() == Player::BLACK {
+            self.move_number -= 1;
+        }
+
+        self.zobrist = undoable_move.zobrist;
+        self.half_move_clock = undoable_move.prev_half_move_clock;
+        self.ep_square = undoable_move.prev_ep_square;
+        self.state = undoable_move.state;
+    }
+
+    /// In debug builds, check that the incrementally updated zobrist keys agree with the keys
+    /// computed from scratch, naming the move which was just made or unmade if they don't.
+    #[inline(always)]
+    fn debug_check_keys(&self, action: &str, mov: &dyn fmt::Display) {
+        debug_assert_eq!(
+            self.zobrist,
+            Zobrist::from_position(self),
+            "zobrist key is wrong after {} {} in {}",
+            action,
+            mov,
+            self.to_fen()
+        );
+        debug_assert_eq!(
+            self.pawn_zobrist,
+            Zobrist::pawns_from_position(self),
+            "pawn key is wrong after {} {} in {}",
+            action,
+            mov,
+            self.to_fen()
+        );
+        debug_assert_eq!(
+            self.material_zobrist,
+            Zobrist::material_from_position(self),
+            "material zobrist is wrong after {} {} in {}",
+            action,
+            mov,
+            self.to_fen()
+        );
+    }
+
     /// Helper function to apply a castling move for a given player.
     ///
     /// Takes in the player to castle, the original king square and the original rook square.
@@ -438,17 +765,36 @@ impl Position {
     /// Returns `Option<Move>` with `Some(mov)` if the move was legal, and
     /// None if it wasn't.
     pub fn make_uci_move(&mut self, uci: &str) -> Option<Move> {
-        let moves = self.generate::<BasicMoveList, All, Legal>();
+        let mov = self.parse_uci_move(uci)?;
+        self.make_move(&mov);
+        Some(mov)
+    }
+
+    /// Returns the legal moves of the piece on `sq`, e.g. to highlight where a piece can go when it
+    /// is picked up in a GUI. This is empty if `sq` is empty or holds a piece of the player not to
+    /// move. Castling moves are included from the king's square.
+    pub fn moves_from(&self, sq: Square) -> BasicMoveList {
+        let mut moves = BasicMoveList::new();
+
+        let piece = self.piece_at_sq(sq);
+        if piece.is_none() || piece.player() != self.turn() {
+            return moves;
+        }
 
-        for mov in &moves {
-            let uci_mov = mov.to_uci_string();
-            if uci == uci_mov {
-                self.make_move(mov);
-                return Some(*mov);
+        for mov in self.generate::<BasicMoveList, All, Legal>().iter() {
+            if mov.orig() == sq {
+                moves.push_val(*mov);
             }
         }
 
-        return None;
+        moves
+    }
+
+    /// Returns the number of pseudo-legal moves in the position, skipping the legality check which
+    /// legal move generation makes of each move. Moves which leave the king in check, such as
+    /// those of pinned pieces, are counted, so this is at least the number of legal moves.
+    pub fn pseudo_legal_count(&self) -> usize {
+        self.generate::<BasicMoveList, All, PseudoLegal>().len()
     }
 
     /// Moves a piece on the board for a given player from square `from`
@@ -471,6 +817,11 @@ impl Position {
 
         self.zobrist.toggle_piece_sq(piece, from);
         self.zobrist.toggle_piece_sq(piece, to);
+
+        if in_pawn_key(piece_ty) {
+            self.pawn_zobrist.toggle_piece_sq(piece, from);
+            self.pawn_zobrist.toggle_piece_sq(piece, to);
+        }
     }
 
     /// Removes a `Piece` from the board for a given player.
@@ -491,6 +842,15 @@ impl Position {
         self.board.remove(square);
 
         self.zobrist.toggle_piece_sq(piece, square);
+        if in_pawn_key(piece.type_of()) {
+            self.pawn_zobrist.toggle_piece_sq(piece, square);
+        }
+        self.material_zobrist
+            .toggle_piece_count(piece, self.bbs[piece as usize].popcnt() as u8);
+        self.material_score -= material_of(piece);
+        self.material_key =
+            self.material_key
+                .wrapping_sub(material_key_part(player, piece.type_of(), 1));
     }
 
     /// Places a `Piece` on the board at a given `Square`.
@@ -510,6 +870,44 @@ impl Position {
         self.board.place(square, player, piece_ty);
 
         self.zobrist.toggle_piece_sq(piece, square);
+        if in_pawn_key(piece_ty) {
+            self.pawn_zobrist.toggle_piece_sq(piece, square);
+        }
+        self.material_zobrist
+            .toggle_piece_count(piece, self.bbs[piece as usize].popcnt() as u8 - 1);
+        self.material_score += material_of(piece);
+        self.material_key = self
+            .material_key
+            .wrapping_add(material_key_part(player, piece_ty, 1));
+    }
+
+    /// Put `piece` on `sq`, replacing whatever was there. Putting `Piece::None` clears the square.
+    ///
+    /// The bitboards, keys and material are kept up to date, but the checkers and pins aren't, as
+    /// they depend on the whole board. Call `recompute_state` once the editing is done.
+    pub fn set_piece(&mut self, sq: Square, piece: Piece) {
+        self.clear_square(sq);
+        if !piece.is_none() {
+            self.put_piece_c(piece, sq);
+        }
+    }
+
+    /// Remove any piece from `sq`. As for `set_piece`, `recompute_state` must be called once the
+    /// editing is done.
+    pub fn clear_square(&mut self, sq: Square) {
+        let piece = self.piece_at_sq(sq);
+        if !piece.is_none() {
+            self.remove_piece_c(piece, sq);
+        }
+    }
+
+    /// Recompute everything about the position which isn't kept up to date by `set_piece` and
+    /// `clear_square`: the checkers and pins, and the keys, in case the position was created by
+    /// `Position::blank()` before the zobrist keys were initialised. Both kings must be on the
+    /// board.
+    pub fn recompute_state(&mut self) {
+        self.set_state();
+        self.set_zobrist();
     }
 
     // CHECKING
@@ -530,11 +928,8 @@ impl Position {
     }
 
     /// Whether the player to move is in checkmate in this position.
-    ///
-    /// TODO: this should really switch `Generation` type to `Evasions`? Need to thoroughly check
-    /// correctness when making that change.
     pub fn in_checkmate(&self) -> bool {
-        self.in_check() && self.generate::<BasicMoveList, All, Legal>().is_empty()
+        self.in_check() && self.generate::<BasicMoveList, Evasions, Legal>().is_empty()
     }
 
     pub fn in_double_check(&self) -> bool {
@@ -578,18 +973,106 @@ impl Position {
         false
     }
 
-    /// Returns a `Bitboard` of possible attacks to a square with a given occupancy.
+    /// The number of times the current position has occurred, counting this occurrence, so it is 1
+    /// for a position which is new. A count of 3 or more is a threefold repetition which can be
+    /// claimed as a draw.
+    ///
+    /// Only positions since the last capture or pawn move are counted, since no earlier position
+    /// can recur after one. Unlike `in_threefold`, this doesn't stop at the second repetition, so
+    /// it can tell a position seen once before, which the search already scores as a draw, from
+    /// one which can be claimed.
+    pub fn repetition_count(&self) -> u8 {
+        let curr_zob = self.zobrist();
+        let mut count = 1u8;
+
+        // The moves are visited last first. The position before every second one has the same
+        // side to move as the current position, and so may repeat it.
+        for (i, m) in self.history().iter().rev().enumerate() {
+            if m.ty.contains(MoveType::CAPTURE) || m.piece.type_of() == PieceType::Pawn {
+                break;
+            }
+
+            if i % 2 == 1 && m.zobrist == curr_zob {
+                count = count.saturating_add(1);
+            }
+        }
+
+        count
+    }
+
+    /// Returns true if neither player has enough material left to checkmate, by any sequence of
+    /// legal moves. This is the case for K vs K, K vs KN, and positions where the only remaining
+    /// pieces besides the kings are bishops, all on the same colour complex.
+    ///
+    /// Positions like KNN vs K, where mate is possible but can't be forced, are not included.
+    pub fn is_insufficient_material(&self) -> bool {
+        if self.piece_bb_both_players(PieceType::Pawn).is_not_empty()
+            || self
+                .piece_two_bb_both_players(PieceType::Rook, PieceType::Queen)
+                .is_not_empty()
+        {
+            return false;
+        }
+
+        let knights = self.piece_bb_both_players(PieceType::Knight);
+        let bishops = self.piece_bb_both_players(PieceType::Bishop);
+
+        if (knights | bishops).popcnt() <= 1 {
+            return true;
+        }
+
+        knights.is_empty()
+            && ((bishops & Bitboard::DARK_SQUARES).is_empty()
+                || (bishops & Bitboard::LIGHT_SQUARES).is_empty())
+    }
+
+    /// Whether the game is drawn in the current position, by the rules of chess rather than
+    /// anything the search assumes: either there is insufficient material to mate, or fifty moves
+    /// have passed without a capture or pawn move, or the current position has occurred for the
+    /// third time. The latter two are draws which a player can claim, as an arbiter or GUI would
+    /// treat them.
+    ///
+    /// Stalemate isn't included; check for that with the legal moves. A checkmate delivered on
+    /// the move which reaches the fifty-move limit stands, and isn't reported as a draw.
+    pub fn is_draw(&self) -> bool {
+        self.is_insufficient_material()
+            || (self.half_move_clock >= 100 && !self.in_checkmate())
+            || self.in_threefold()
+    }
+
+    /// The game phase, from `MAX_GAME_PHASE` with all the pieces on the board down to 0 with only
+    /// kings and pawns left. Each knight or bishop counts 1, each rook 2 and each queen 4. Extra
+    /// pieces from promotions don't take the phase above `MAX_GAME_PHASE`.
+    pub fn game_phase(&self) -> i32 {
+        let minors = self
+            .piece_two_bb_both_players(PieceType::Knight, PieceType::Bishop)
+            .popcnt() as i32;
+        let rooks = self.piece_bb_both_players(PieceType::Rook).popcnt() as i32;
+        let queens = self.piece_bb_both_players(PieceType::Queen).popcnt() as i32;
+
+        std::cmp::min(minors + 2 * rooks + 4 * queens, MAX_GAME_PHASE)
+    }
+
+    /// Returns a `Bitboard` of possible attacks to a square with the current occupancy.
     /// Includes pieces from both players.
-    // TODO: dedup this and `attack_defend`? which is faster?
+    #[inline]
     pub fn attackers_to(&self, sq: Square) -> Bitboard {
-        (Bitboard(pawn_attacks_from(sq, Player::BLACK)) & self.bbs[1])
-            | (Bitboard(pawn_attacks_from(sq, Player::WHITE)) & self.bbs[7])
-            | (knight_moves(sq) & (self.bbs[2] | self.bbs[8]))
-            | (rook_moves(self.occupied(), sq)
-                & (self.bbs[4] | self.bbs[10] | self.bbs[5] | self.bbs[11]))
-            | (bishop_moves(self.occupied(), sq)
-                & (self.bbs[3] | self.bbs[9] | self.bbs[5] | self.bbs[11]))
-            | (king_moves(sq) & (self.bbs[6] | self.bbs[12]))
+        self.attack_defend(self.occupied(), sq)
+    }
+
+    /// Returns a `Bitboard` of the given player's pieces attacking a square with the current
+    /// occupancy. This is `attackers_to` restricted to `player`'s pieces, without looking at the
+    /// other player's pieces at all.
+    #[inline]
+    pub fn attackers_of_color(&self, sq: Square, player: Player) -> Bitboard {
+        let occ = self.occupied();
+        let queens = self.piece_bb(player, PieceType::Queen);
+
+        (Bitboard(pawn_attacks_from(sq, !player)) & self.piece_bb(player, PieceType::Pawn))
+            | (knight_moves(sq) & self.piece_bb(player, PieceType::Knight))
+            | (king_moves(sq) & self.piece_bb(player, PieceType::King))
+            | (bishop_moves(occ, sq) & (self.piece_bb(player, PieceType::Bishop) | queens))
+            | (rook_moves(occ, sq) & (self.piece_bb(player, PieceType::Rook) | queens))
     }
 
     #[inline(always)]
@@ -666,7 +1149,7 @@ impl Position {
 
     #[inline]
     pub fn piece_bb(&self, player: Player, piece_type: PieceType) -> Bitboard {
-        let idx = 6 * (player.inner() as usize) + (piece_type as usize);
+        let idx = 6 * (player.inner() as usize) + usize::from(piece_type);
         unsafe { *self.bbs.get_unchecked(idx) }
     }
     /// Returns the Bitboard of the Queens and Rooks for a given player.
@@ -801,6 +1284,24 @@ impl Position {
         self.state.blockers[player.inner() as usize] & self.get_occupied_player_runtime(player)
     }
 
+    /// Returns the pinned pieces of both players which cannot move to `sq`, because doing so would
+    /// take them off the line between the pinner and their king.
+    pub fn pinned_off_line(&self, sq: Square) -> Bitboard {
+        let mut pinned = Bitboard(0);
+
+        for player in [Player::WHITE, Player::BLACK] {
+            let ksq = self.king_sq(player);
+
+            for pinned_sq in self.pinned_pieces(player) {
+                if !aligned(pinned_sq, sq, ksq) {
+                    pinned |= pinned_sq.to_bb();
+                }
+            }
+        }
+
+        pinned
+    }
+
     // MOVE GENERATION
     /// Generate moves for the current position according to the generic parameters.
     #[inline]
@@ -853,7 +1354,7 @@ impl Position {
         // Note: castling moves are already checked in movegen.
         if piece.type_of() == PieceType::King {
             return mov.move_type().contains(MoveType::CASTLE)
-                || (self.attackers_to(dest) & self.get_occupied_player_runtime(them)).is_empty();
+                || self.attackers_of_color(dest, them).is_empty();
         }
 
         // Ensure we are not moving a pinned piece, or if we are, it is remaining staying
@@ -869,8 +1370,11 @@ impl Position {
     /// This checks that the board has a piece of the correct colour at the origin square, and that
     /// that piece is able to move to the destination square. If so, it calls `self.legal_move` to
     /// further test for legality.
+    ///
+    /// Both checks are needed. `MoveGen::valid_move` only establishes that the move is
+    /// pseudo-legal: outside of check it does not look at pins, nor at whether a king steps onto
+    /// an attacked square, and in check it does not look at pins.
     pub fn valid_move(&self, mov: &Move) -> bool {
-        // TODO: believe we can kill the second check
         MoveGen::valid_move(self, mov) && self.legal_move(mov)
     }
 }
@@ -987,6 +1491,47 @@ impl fmt::Debug for Position {
     }
 }
 
+/// The contribution of `piece` to the material score: positive for White, negative for Black,
+/// and nothing for the kings.
+#[inline(always)]
+fn material_of(piece: Piece) -> i16 {
+    let (player, piece_ty) = piece.player_piece();
+    let value = match piece_ty {
+        PieceType::King => 0,
+        _ => PIECE_VALUES[piece_ty as usize],
+    };
+
+    match player {
+        Player::WHITE => value,
+        Player::BLACK => -value,
+    }
+}
+
+/// The contribution of `count` pieces of type `piece_type` belonging to `player` to the material
+/// key.
+///
+/// Each player has 16 bits of the key: 4 bits counting pawns, then 3 bits each counting knights,
+/// bishops, rooks and queens. Kings aren't counted. A count of more than 7 pieces of one type,
+/// only possible after several underpromotions, overflows into the next field, so such positions
+/// may share a key with others.
+#[inline(always)]
+pub const fn material_key_part(player: Player, piece_type: PieceType, count: u32) -> u32 {
+    let shift = match piece_type {
+        PieceType::Pawn => 0,
+        PieceType::Knight => 4,
+        PieceType::Bishop => 7,
+        PieceType::Rook => 10,
+        PieceType::Queen => 13,
+        PieceType::King | PieceType::None => return 0,
+    };
+    let shift = match player {
+        Player::WHITE => shift,
+        Player::BLACK => shift + 16,
+    };
+
+    count.wrapping_shl(shift)
+}
+
 /// For whatever rank the bit (inner value of a `Square`) is, returns the
 /// corresponding rank as a u64.
 #[inline(always)]
@@ -1027,3 +1572,815 @@ pub fn u8_to_u64(s: u8) -> u64 {
     debug_assert!(s < 64);
     (1 as u64).wrapping_shl(s as u32)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::init::init_globals;
+
+    #[test]
+    fn material_is_incrementally_updated() {
+        init_globals();
+
+        fn walk(pos: &mut Position, depth: u8) {
+            assert_eq!(
+                pos.material_eval(),
+                pos.compute_material(),
+                "{}",
+                pos.to_fen()
+            );
+            assert_eq!(pos.material_key(), pos.compute_material_key());
+            if depth == 0 {
+                return;
+            }
+
+            for mov in pos.generate::<BasicMoveList, All, Legal>().iter() {
+                pos.make_move(mov);
+                walk(pos, depth - 1);
+                pos.unmake_move();
+                assert_eq!(
+                    pos.material_eval(),
+                    pos.compute_material(),
+                    "{}",
+                    pos.to_fen()
+                );
+            }
+        }
+
+        for fen in [
+            // Captures, promotions and capturing promotions for both sides.
+            "r3k2r/1P4P1/8/8/8/8/1p4p1/R3K2R w KQkq - 0 1",
+            // En passant.
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1",
+            "rnbqkbnr/pp1ppppp/8/8/2pPP3/8/PPP2PPP/RNBQKBNR b KQkq d3 0 3",
+        ] {
+            let mut pos = Position::from_fen(fen).unwrap();
+            assert_eq!(pos.material_eval(), pos.compute_material());
+            walk(&mut pos, 3);
+        }
+    }
+
+    #[test]
+    fn material_key() {
+        init_globals();
+
+        // Capture the knight, then promote to a rook.
+        let mut pos = Position::from_fen("4k3/P7/8/8/8/8/3n4/4K3 w - - 0 1").unwrap();
+        for mov in ["e1d2", "e8f7", "a7a8r"] {
+            pos.make_uci_move(mov).unwrap();
+        }
+
+        let krk = Position::from_fen("R7/5k2/8/8/8/8/3K4/8 b - - 0 2").unwrap();
+        assert_eq!(pos.material_key(), krk.material_key());
+        assert_eq!(
+            krk.material_key(),
+            material_key_part(Player::WHITE, PieceType::Rook, 1)
+        );
+
+        // Colours are distinguished.
+        let kkr = Position::from_fen("r7/5K2/8/8/8/8/3k4/8 b - - 0 2").unwrap();
+        assert_ne!(krk.material_key(), kkr.material_key());
+
+        pos.unmake_move();
+        let kpkn = Position::from_fen("4k3/P7/8/8/8/8/3n4/4K3 w - - 0 1").unwrap();
+        assert_ne!(pos.material_key(), kpkn.material_key());
+        pos.unmake_move();
+        pos.unmake_move();
+        assert_eq!(pos.material_key(), kpkn.material_key());
+    }
+
+    #[test]
+    fn material_zobrist() {
+        init_globals();
+
+        // The same material, placed differently and with the other side to move.
+        let a = Position::from_fen("4k3/pp6/2n5/8/8/5B2/PP6/4K2R w K - 0 1").unwrap();
+        let b = Position::from_fen("1n6/5p1p/8/3k4/8/8/B2R2PP/6K1 b - - 0 1").unwrap();
+        assert_ne!(a.zobrist(), b.zobrist());
+        assert_eq!(a.material_zobrist(), b.material_zobrist());
+
+        // Different counts of the same pieces are distinguished, as are colours.
+        let c = Position::from_fen("4k3/pp6/2n5/8/8/5B2/PPP5/4K2R w K - 0 1").unwrap();
+        assert_ne!(a.material_zobrist(), c.material_zobrist());
+        let d = Position::from_fen("4k2r/pp6/2b5/8/8/5N2/PP6/4K3 w - - 0 1").unwrap();
+        assert_ne!(a.material_zobrist(), d.material_zobrist());
+
+        // A capture changes the key, after which it follows the material wherever it came from.
+        let mut pos = Position::from_fen("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1").unwrap();
+        let before = pos.material_zobrist();
+        pos.make_uci_move("e4d5").unwrap();
+        assert_ne!(pos.material_zobrist(), before);
+        let kpk = Position::from_fen("8/8/4k3/8/2P5/8/8/K7 b - - 0 1").unwrap();
+        assert_eq!(pos.material_zobrist(), kpk.material_zobrist());
+        pos.unmake_move();
+        assert_eq!(pos.material_zobrist(), before);
+    }
+
+    #[test]
+    fn pawn_key() {
+        init_globals();
+
+        // The same pawns and kings, with the pieces arranged differently.
+        let a =
+            Position::from_fen("r1bqkbnr/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 2 3")
+                .unwrap();
+        let b =
+            Position::from_fen("rnbqkb1r/pppp1ppp/5n2/4p3/4P3/2N5/PPPP1PPP/R1BQKBNR b KQkq - 2 3")
+                .unwrap();
+        assert_ne!(a.zobrist(), b.zobrist());
+        assert_eq!(a.pawn_key(), b.pawn_key());
+
+        // Pawn and king moves change the key; other moves don't.
+        let mut pos = a.clone();
+        pos.make_uci_move("f1c4").unwrap();
+        assert_eq!(pos.pawn_key(), a.pawn_key());
+        pos.unmake_move();
+        pos.make_uci_move("d2d4").unwrap();
+        assert_ne!(pos.pawn_key(), a.pawn_key());
+        pos.unmake_move();
+        pos.make_uci_move("e1e2").unwrap();
+        assert_ne!(pos.pawn_key(), a.pawn_key());
+        pos.unmake_move();
+        assert_eq!(pos.pawn_key(), a.pawn_key());
+    }
+
+    #[test]
+    fn game_phase() {
+        init_globals();
+
+        let pos = Position::start_pos();
+        assert_eq!(pos.game_phase(), MAX_GAME_PHASE);
+
+        let pos = Position::from_fen("4k3/pp3ppp/8/8/8/8/PP3PPP/4K3 w - - 0 1").unwrap();
+        assert_eq!(pos.game_phase(), 0);
+
+        let pos = Position::from_fen("r3k3/8/8/8/8/8/8/3QK1N1 w - - 0 1").unwrap();
+        assert_eq!(pos.game_phase(), 7);
+
+        let pos = Position::from_fen("QQQQk3/8/8/8/8/8/8/QQQQK3 w - - 0 1").unwrap();
+        assert_eq!(pos.game_phase(), MAX_GAME_PHASE);
+    }
+
+    #[test]
+    fn insufficient_material() {
+        init_globals();
+
+        let dead = [
+            "8/8/4k3/8/8/3K4/8/8 w - - 0 1",
+            "8/8/4k3/8/8/3K4/5N2/8 w - - 0 1",
+            "8/8/4k3/8/8/3K4/5B2/8 w - - 0 1",
+            "8/8/4k3/8/8/3K4/8/2b5 b - - 0 1",
+            // Bishops on the same colour complex, for either player.
+            "8/8/4kb2/8/8/3K4/5B2/8 w - - 0 1",
+            "8/8/4k3/8/8/3K4/5B2/2B5 w - - 0 1",
+        ];
+
+        let alive = [
+            // Mate can't be forced with two knights, but it's possible.
+            "8/8/4k3/8/8/3K4/5N2/6N1 w - - 0 1",
+            "8/8/4kn2/8/8/3K4/5B2/8 w - - 0 1",
+            "8/8/4kn2/8/8/3K4/5N2/8 w - - 0 1",
+            // Bishops on opposite colour complexes.
+            "8/8/4k1b1/8/8/3K4/5B2/8 w - - 0 1",
+            "8/8/4k3/8/8/3K4/5BB1/8 w - - 0 1",
+            "8/8/4k3/8/8/3K4/4P3/8 w - - 0 1",
+            "8/8/4k3/8/8/3K4/4R3/8 w - - 0 1",
+            "8/8/4k3/8/8/3K4/4Q3/8 w - - 0 1",
+            START_POSITION,
+        ];
+
+        for fen in dead {
+            assert!(
+                Position::from_fen(fen).unwrap().is_insufficient_material(),
+                "{}",
+                fen
+            );
+        }
+
+        for fen in alive {
+            assert!(
+                !Position::from_fen(fen).unwrap().is_insufficient_material(),
+                "{}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn is_draw() {
+        init_globals();
+
+        // A dead position.
+        assert!(Position::from_fen("8/8/4k3/8/8/3K4/8/8 w - - 0 1")
+            .unwrap()
+            .is_draw());
+
+        // Fifty moves without a capture or pawn move.
+        let fifty = "8/8/4k3/8/8/3K4/4R3/8 w - - 100 80";
+        assert!(Position::from_fen(fifty).unwrap().is_draw());
+        assert!(!Position::from_fen("8/8/4k3/8/8/3K4/4R3/8 w - - 99 80")
+            .unwrap()
+            .is_draw());
+
+        // Threefold repetition, shuffling the knights out and back twice.
+        let mut pos = Position::start_pos();
+        for _ in 0..2 {
+            for uci in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+                let mv = pos.parse_uci_move(uci).expect(uci);
+                pos.make_move(&mv);
+            }
+        }
+        assert!(pos.is_draw());
+
+        let middlegame = "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N2N2/PP3PPP/R1BQKB1R w KQ - 4 8";
+        assert!(!Position::from_fen(middlegame).unwrap().is_draw());
+    }
+
+    #[test]
+    fn repetition_count() {
+        init_globals();
+
+        let mut pos = Position::start_pos();
+        assert_eq!(pos.repetition_count(), 1);
+
+        // Each time the knights go out and back, the starting position occurs once more.
+        for count in 2..=4 {
+            for uci in ["g1f3", "g8f6", "f3g1", "f6g8"] {
+                pos.make_uci_move(uci).expect(uci);
+            }
+            assert_eq!(pos.repetition_count(), count);
+            assert_eq!(pos.in_threefold(), count >= 3);
+        }
+
+        // The knights are out; that position has occurred four times too, but not the one after
+        // a different move.
+        pos.make_uci_move("g1f3").unwrap();
+        assert_eq!(pos.repetition_count(), 4);
+        pos.make_uci_move("b8c6").unwrap();
+        assert_eq!(pos.repetition_count(), 1);
+
+        // A pawn move means no earlier position can recur.
+        pos.make_uci_move("e2e4").unwrap();
+        for uci in ["c6b8", "f3g1", "b8c6", "g1f3"] {
+            pos.make_uci_move(uci).expect(uci);
+        }
+        assert_eq!(pos.repetition_count(), 2);
+    }
+
+    #[test]
+    fn snapshot() {
+        init_globals();
+
+        let mut pos = Position::start_pos();
+        for uci in ["e2e4", "e7e5", "g1f3", "b8c6", "f1c4"] {
+            pos.make_uci_move(uci).expect(uci);
+        }
+
+        let mut snapshot = pos.snapshot();
+        assert!(snapshot.history().is_empty());
+        assert_eq!(snapshot.zobrist(), pos.zobrist());
+        assert_eq!(snapshot.pawn_key(), pos.pawn_key());
+        assert_eq!(snapshot.bbs, pos.bbs);
+        assert_eq!(snapshot.player_occ, pos.player_occ);
+        assert_eq!(snapshot.state, pos.state);
+        assert_eq!(snapshot.to_fen(), pos.to_fen());
+        assert_eq!(
+            snapshot.generate::<BasicMoveList, All, Legal>().len(),
+            pos.generate::<BasicMoveList, All, Legal>().len()
+        );
+
+        // The snapshot can be played on and back without touching the original.
+        let before = pos.clone();
+        for uci in ["g8f6", "f3g5", "d7d5"] {
+            snapshot.make_uci_move(uci).expect(uci);
+        }
+        assert_eq!(snapshot.history().len(), 3);
+        assert!(pos == before);
+
+        for _ in 0..3 {
+            snapshot.unmake_move();
+        }
+        assert!(snapshot.history().is_empty());
+        assert_eq!(snapshot.zobrist(), pos.zobrist());
+        assert_eq!(snapshot.to_fen(), pos.to_fen());
+    }
+
+    #[test]
+    fn key_after() {
+        init_globals();
+
+        let fens = [
+            START_POSITION,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1",
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+        ];
+
+        for fen in fens {
+            let mut pos = Position::from_fen(fen).unwrap();
+
+            for mov in &pos.generate::<BasicMoveList, All, Legal>() {
+                let key = pos.key_after(mov);
+                pos.make_move(mov);
+                if pos.ep_square.is_none() {
+                    assert_eq!(key, pos.zobrist(), "{} {}", fen, mov);
+                }
+                pos.unmake_move();
+            }
+        }
+    }
+
+    #[test]
+    fn gives_check() {
+        init_globals();
+
+        let fens = [
+            START_POSITION,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R b KQkq - 0 1",
+            "n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1",
+            // Discovered checks, including from an en passant capture and a promotion.
+            "4k3/8/8/3Pp3/8/8/4R2K/8 w - e6 0 1",
+            "2r1k3/1P6/8/8/4B3/8/8/4RK2 w - - 0 1",
+            // Checks from the rook when castling.
+            "5k2/8/8/8/8/8/8/R3K2R w KQ - 0 1",
+            "3k4/8/8/8/8/8/8/R3K2R w KQ - 0 1",
+        ];
+
+        for fen in fens {
+            let mut pos = Position::from_fen(fen).unwrap();
+
+            for mov in &pos.generate::<BasicMoveList, All, Legal>() {
+                let gives_check = pos.gives_check(mov);
+                pos.make_move(mov);
+                assert_eq!(gives_check, pos.in_check(), "{} {}", fen, mov);
+                pos.unmake_move();
+            }
+        }
+    }
+
+    #[test]
+    fn gives_check_special_cases() {
+        init_globals();
+
+        let gives_check = |fen: &str, uci: &str| {
+            let pos = Position::from_fen(fen).unwrap();
+            let mov = pos.parse_uci_move(uci).expect(uci);
+            pos.gives_check(&mov)
+        };
+
+        // Direct checks.
+        assert!(!gives_check("4k3/8/8/8/8/8/8/4K1N1 w - - 0 1", "g1f3"));
+        assert!(gives_check("4k3/8/8/3N4/8/8/8/4K3 w - - 0 1", "d5f6"));
+
+        // A discovered check, unless the piece stays on the line.
+        assert!(gives_check("4k3/8/8/8/4B3/8/8/4RK2 w - - 0 1", "e4d5"));
+        assert!(!gives_check("4k3/8/8/8/8/4P3/8/4RK2 w - - 0 1", "e3e4"));
+
+        // Castling checks with the rook.
+        assert!(gives_check("5k2/8/8/8/8/8/8/4K2R w K - 0 1", "e1g1"));
+        assert!(gives_check("3k4/8/8/8/8/8/8/R3K3 w Q - 0 1", "e1c1"));
+        assert!(!gives_check("4k3/8/8/8/8/8/8/R3K2R w KQ - 0 1", "e1g1"));
+
+        // An en passant capture removes both pawns from the rank.
+        assert!(gives_check("8/8/8/k2Pp2R/8/8/8/4K3 w - e6 0 1", "d5e6"));
+        assert!(!gives_check("8/8/8/k2Pp3/8/8/8/4K2R w - e6 0 1", "d5e6"));
+
+        // The promoted piece gives the check.
+        assert!(gives_check("3k4/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8q"));
+        assert!(gives_check("3k4/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8r"));
+        assert!(!gives_check("3k4/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8n"));
+        assert!(!gives_check("3k4/1P6/8/8/8/8/8/4K3 w - - 0 1", "b7b8b"));
+    }
+
+    #[test]
+    fn attackers_of_color() {
+        init_globals();
+
+        let fens = [
+            START_POSITION,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ];
+
+        for fen in fens {
+            let pos = Position::from_fen(fen).unwrap();
+            for sq in 0..64 {
+                let sq = Square(sq);
+                for player in [Player::WHITE, Player::BLACK] {
+                    assert_eq!(
+                        pos.attackers_of_color(sq, player),
+                        pos.attackers_to(sq) & pos.get_occupied_player_runtime(player),
+                        "{} {} {}",
+                        fen,
+                        sq,
+                        player
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pseudo_legal_count() {
+        init_globals();
+
+        let counts = |fen: &str| {
+            let pos = Position::from_fen(fen).unwrap();
+            let legal = pos.generate::<BasicMoveList, All, Legal>().len();
+            (pos.pseudo_legal_count(), legal)
+        };
+
+        // With nothing pinned and no checks, every pseudo-legal move is legal.
+        assert_eq!(counts(START_POSITION), (20, 20));
+
+        // The knight on e2 is pinned, and has six pseudo-legal moves but no legal ones.
+        let (pseudo, legal) = counts("4k3/4r3/8/8/8/8/4N3/4K3 w - - 0 1");
+        assert_eq!(pseudo, legal + 6);
+
+        // Pins in both directions, from the middle of a game.
+        let (pseudo, legal) =
+            counts("r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1");
+        assert_eq!(legal, 48);
+        assert!(pseudo >= legal);
+
+        let (pseudo, legal) = counts("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1");
+        assert_eq!(legal, 14);
+        assert!(pseudo > legal);
+    }
+
+    #[test]
+    fn moves_from() {
+        init_globals();
+
+        let destinations = |fen: &str, sq: Square| {
+            let pos = Position::from_fen(fen).unwrap();
+            let mut dests: Vec<String> = pos
+                .moves_from(sq)
+                .iter()
+                .map(|mov| mov.dest().to_string())
+                .collect();
+            dests.sort();
+            dests
+        };
+
+        // A knight in the centre, with one destination blocked by its own pawn.
+        let fen = "4k3/8/8/8/3N4/8/4P3/4K3 w - - 0 1";
+        assert_eq!(
+            destinations(fen, Square::D4),
+            ["b3", "b5", "c2", "c6", "e6", "f3", "f5"]
+        );
+
+        // A knight pinned to its king can't move at all.
+        let fen = "4k3/4r3/8/8/8/8/4N3/4K3 w - - 0 1";
+        assert!(destinations(fen, Square::E2).is_empty());
+
+        // Castling from the king, and en passant from the capturing pawn.
+        let fen = "4k3/8/8/3pP3/8/8/8/4K2R w K d6 0 2";
+        assert_eq!(
+            destinations(fen, Square::E1),
+            ["d1", "d2", "e2", "f1", "f2", "g1"]
+        );
+        assert_eq!(destinations(fen, Square::E5), ["d6", "e6"]);
+
+        // Empty squares and the opponent's pieces have no moves.
+        assert!(destinations(fen, Square::A1).is_empty());
+        assert!(destinations(fen, Square::D5).is_empty());
+    }
+
+    #[test]
+    fn flip_colors() {
+        init_globals();
+
+        let pos = Position::from_fen("r3k2r/8/8/8/3pP3/8/8/4K2R b Kkq e3 0 1").unwrap();
+        let flipped = pos.flip_colors();
+        assert_eq!(flipped.to_fen(), "4k2r/8/8/3Pp3/8/8/8/R3K2R w KQk e6 0 1");
+        assert_eq!(flipped.flip_colors().to_fen(), pos.to_fen());
+
+        // The flipped position has the same moves, mirrored.
+        let moves = |pos: &Position| pos.generate::<BasicMoveList, All, Legal>().len();
+        assert_eq!(moves(&flipped), moves(&pos));
+        assert_eq!(flipped.material_eval(), -pos.material_eval());
+    }
+
+    #[test]
+    fn build_start_position_square_by_square() {
+        init_globals();
+
+        let start = Position::start_pos();
+        let mut pos = start.clone();
+        for sq in 0..64 {
+            pos.clear_square(Square(sq));
+        }
+        assert!(pos.occupied().is_empty());
+        assert_eq!(pos.material_eval(), 0);
+        assert_eq!(pos.material_key(), 0);
+
+        let back_rank = [
+            PieceType::Rook,
+            PieceType::Knight,
+            PieceType::Bishop,
+            PieceType::Queen,
+            PieceType::King,
+            PieceType::Bishop,
+            PieceType::Knight,
+            PieceType::Rook,
+        ];
+        for (file, piece_type) in back_rank.into_iter().enumerate() {
+            let file = file as u8;
+            pos.set_piece(Square(file), Piece::make(Player::WHITE, piece_type));
+            pos.set_piece(
+                Square(8 + file),
+                Piece::make(Player::WHITE, PieceType::Pawn),
+            );
+            pos.set_piece(
+                Square(48 + file),
+                Piece::make(Player::BLACK, PieceType::Pawn),
+            );
+            pos.set_piece(Square(56 + file), Piece::make(Player::BLACK, piece_type));
+        }
+
+        // The incrementally updated keys are already right.
+        assert_eq!(pos.zobrist(), start.zobrist());
+        assert_eq!(pos.pawn_key(), start.pawn_key());
+
+        pos.recompute_state();
+        assert_eq!(pos.zobrist(), start.zobrist());
+        assert_eq!(pos.bbs, start.bbs);
+        assert_eq!(pos.player_occ, start.player_occ);
+        assert_eq!(pos.state, start.state);
+        assert_eq!(pos.material_eval(), start.material_eval());
+        assert_eq!(pos.material_key(), start.material_key());
+        assert_eq!(pos.to_fen(), start.to_fen());
+
+        // Replacing a piece, and setting `Piece::None`.
+        pos.set_piece(Square::D1, Piece::make(Player::WHITE, PieceType::Knight));
+        pos.set_piece(Square::E2, Piece::None);
+        pos.recompute_state();
+        assert_eq!(
+            pos.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPP1PPP/RNBNKBNR w KQkq - 0 1"
+        );
+        assert_eq!(pos.zobrist(), Zobrist::from_position(&pos));
+    }
+
+    #[test]
+    fn random_games_keep_zobrist_keys_in_step() {
+        init_globals();
+
+        // These positions have castling, en passant and promotions available within a few moves.
+        // In debug builds, `make_move` and `unmake_move` also check the keys against a full
+        // recompute, and name the move at fault.
+        let fens = [
+            START_POSITION,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ];
+
+        let check_keys = |pos: &Position| {
+            assert_eq!(pos.zobrist, Zobrist::from_position(pos), "{}", pos.to_fen());
+            assert_eq!(
+                pos.pawn_zobrist,
+                Zobrist::pawns_from_position(pos),
+                "{}",
+                pos.to_fen()
+            );
+            assert_eq!(
+                pos.material_zobrist,
+                Zobrist::material_from_position(pos),
+                "{}",
+                pos.to_fen()
+            );
+        };
+
+        for fen in fens {
+            for _ in 0..50 {
+                let mut pos = Position::from_fen(fen).unwrap();
+                let orig = pos.clone();
+
+                let mut plies = 0;
+                while plies < 60 {
+                    match pos.random_move() {
+                        Some(mov) => pos.make_move(&mov),
+                        None => break,
+                    }
+                    check_keys(&pos);
+                    plies += 1;
+                }
+
+                for _ in 0..plies {
+                    pos.unmake_move();
+                    check_keys(&pos);
+                }
+
+                assert_eq!(pos.zobrist(), orig.zobrist());
+                assert!(pos == orig);
+            }
+        }
+    }
+
+    #[test]
+    fn in_checkmate() {
+        init_globals();
+
+        // The definition, which generating only evasions must agree with: in check, with no legal
+        // moves at all.
+        fn slow_checkmate(pos: &Position) -> bool {
+            pos.in_check() && pos.generate::<BasicMoveList, All, Legal>().is_empty()
+        }
+
+        let mates = [
+            // Fool's mate.
+            "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3",
+            // Back rank.
+            "3R2k1/5ppp/8/8/8/8/8/6K1 b - - 0 1",
+            // Smothered.
+            "6rk/5Npp/8/8/8/8/8/6K1 b - - 0 1",
+            // Double check, where capturing the knight would otherwise escape.
+            "5rkr/5p1p/5N2/8/8/8/8/K5R1 b - - 0 1",
+        ];
+        let not_mates = [
+            START_POSITION,
+            // Stalemate.
+            "7k/5Q2/6K1/8/8/8/8/8 b - - 0 1",
+            // In check, escaped only by the king.
+            "3R2k1/5pp1/8/8/8/8/8/6K1 b - - 0 1",
+            // In check, escaped only by a block.
+            "3R2k1/5ppp/8/8/8/8/4r3/6K1 b - - 0 1",
+            // In check from a pawn which can be captured en passant.
+            "8/8/8/5k2/4Pp2/8/8/4K3 b - e3 0 1",
+            // In double check, escaped by the king.
+            "5rk1/5p1p/5N2/8/8/8/8/K5R1 b - - 0 1",
+        ];
+
+        for fen in mates {
+            let pos = Position::from_fen(fen).unwrap();
+            assert!(pos.in_checkmate(), "{}", fen);
+            assert!(slow_checkmate(&pos), "{}", fen);
+        }
+
+        for fen in not_mates {
+            let pos = Position::from_fen(fen).unwrap();
+            assert!(!pos.in_checkmate(), "{}", fen);
+            assert!(!slow_checkmate(&pos), "{}", fen);
+        }
+
+        // And every position a couple of moves on from each of these.
+        fn walk(pos: &mut Position, depth: u8) {
+            assert_eq!(pos.in_checkmate(), slow_checkmate(pos), "{}", pos.to_fen());
+            if depth == 0 {
+                return;
+            }
+
+            for mov in pos.generate::<BasicMoveList, All, Legal>().iter() {
+                pos.make_move(mov);
+                walk(pos, depth - 1);
+                pos.unmake_move();
+            }
+        }
+
+        for fen in mates.iter().chain(not_mates.iter()) {
+            walk(&mut Position::from_fen(fen).unwrap(), 2);
+        }
+    }
+
+    #[test]
+    fn valid_move_agrees_with_movegen() {
+        use rand::rngs::StdRng;
+        use rand::{Rng, SeedableRng};
+
+        init_globals();
+
+        let fens = [
+            START_POSITION,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ];
+
+        // Collect positions from random games, and every legal move seen along the way. A move
+        // legal somewhere else is what a transposition table collision hands to `valid_move`.
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let mut positions = Vec::new();
+        let mut pool = Vec::new();
+        for fen in fens {
+            for _ in 0..10 {
+                let mut pos = Position::from_fen(fen).unwrap();
+                for _ in 0..40 {
+                    let moves = pos.generate::<BasicMoveList, All, Legal>();
+                    pool.extend(moves.iter().copied());
+                    positions.push(pos.snapshot());
+                    match rng.choose(&moves) {
+                        Some(mov) => pos.make_move(mov),
+                        None => break,
+                    }
+                }
+            }
+        }
+
+        for pos in &positions {
+            let legal = pos.generate::<BasicMoveList, All, Legal>();
+            let check = |mov: Move| {
+                assert_eq!(
+                    pos.valid_move(&mov),
+                    legal.contains(&mov),
+                    "{:?} in {}",
+                    mov,
+                    pos.to_fen()
+                );
+            };
+
+            for _ in 0..100 {
+                check(pool[rng.gen_range(0, pool.len())]);
+            }
+
+            // Moves between random squares, flagged as movegen would flag them on this board.
+            for _ in 0..100 {
+                let orig = Square(rng.gen_range(0, 64));
+                let dest = Square(rng.gen_range(0, 64));
+                let piece = pos.piece_at_sq(orig);
+                let mut ty = if pos.piece_at_sq(dest).is_none() {
+                    MoveType::QUIET
+                } else {
+                    MoveType::CAPTURE
+                };
+                let mut promo = None;
+                if piece.type_of() == PieceType::Pawn {
+                    if pos.ep_square() == Some(dest) {
+                        ty = MoveType::EN_PASSANT | MoveType::CAPTURE;
+                    } else if dest.rank() == 0 || dest.rank() == 7 {
+                        ty |= MoveType::PROMOTION;
+                        ty.remove(MoveType::QUIET);
+                        promo = Some(PieceType::Queen);
+                    }
+                } else if piece.type_of() == PieceType::King
+                    && (orig.file() as i8 - dest.file() as i8).abs() == 2
+                {
+                    ty = MoveType::CASTLE;
+                }
+                check(Move::build(orig, dest, promo, ty));
+            }
+        }
+    }
+
+    #[test]
+    fn null_move_round_trip() {
+        init_globals();
+
+        let fens = [
+            START_POSITION,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3",
+        ];
+
+        for fen in fens {
+            let mut pos = Position::from_fen(fen).unwrap();
+            let orig = pos.clone();
+
+            pos.make_null_move();
+            assert_eq!(pos.turn(), !orig.turn());
+            assert_eq!(pos.ep_square, None);
+            assert_ne!(pos.zobrist(), orig.zobrist());
+
+            pos.unmake_null_move();
+            assert_eq!(pos.zobrist(), orig.zobrist());
+            assert!(pos == orig);
+        }
+    }
+
+    #[test]
+    fn null_move_matches_fen_with_side_flipped() {
+        init_globals();
+
+        let mut pos =
+            Position::from_fen("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3")
+                .unwrap();
+        pos.make_null_move();
+
+        let flipped =
+            Position::from_fen("rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR w KQkq - 1 4")
+                .unwrap();
+
+        assert_eq!(pos.zobrist(), flipped.zobrist());
+        assert_eq!(pos.to_fen(), flipped.to_fen());
+        assert_eq!(pos.state, flipped.state);
+    }
+
+    #[test]
+    fn fen_en_passant_square_must_be_lower_case() {
+        init_globals();
+
+        let fen = "rnbqkbnr/ppp1pppp/8/8/3pP3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 3";
+        assert_eq!(
+            Position::from_fen(fen).unwrap().ep_square(),
+            Some(Square::E3)
+        );
+        assert!(Position::from_fen(&fen.replace("e3", "E3")).is_err());
+    }
+}
@@ -103,12 +103,14 @@ impl<'a> SanParser<'a> {
     fn parse(san: &'a str) -> PResult<MoveDetails> {
         let mut parser = Self::new(san);
 
-        match san {
-            "O-O" | "o-o" => {
+        // Castling can be followed by check markers and annotations like any other move, so
+        // strip those before matching.
+        match san.trim_end_matches(&['+', '#', '!', '?'][..]) {
+            "O-O" | "o-o" | "0-0" => {
                 parser.is_ks_castle = true;
                 parser.piece_type = Some(PieceType::King);
             }
-            "O-O-O" | "o-o-o" => {
+            "O-O-O" | "o-o-o" | "0-0-0" => {
                 parser.is_qs_castle = true;
                 parser.piece_type = Some(PieceType::King);
             }
@@ -172,6 +174,11 @@ impl<'a> SanParser<'a> {
     }
 
     fn parse_remainder_after_piece(&mut self) -> PResult<()> {
+        if let Some("x") | Some("-") = self.peek() {
+            // there's no disambiguation, and we go straight to the capture or hyphen
+            return self.parse_middle();
+        }
+
         if self.parse_orig_file().is_err() {
             // we had no file identifier immediately after the piece, which can only occur if we
             // have a rank identifier as part of disambiguating a move
@@ -273,9 +280,14 @@ impl<'a> SanParser<'a> {
     }
 
     fn parse_check_markers(&mut self) -> PResult<()> {
-        // here, we just check to see if the move was marked as one of "+" or "#" or some
+        // here, we just check to see if the move was marked as one of "+" or "#" and/or some
         // combination of "!" / "?" characters before ending
 
+        match self.peek() {
+            Some("!") | Some("?") => return self.parse_annotations(),
+            _ => {}
+        }
+
         match self.eat() {
             Ok(c) => match c {
                 "+" | "#" => self.parse_annotations(),
@@ -360,18 +372,19 @@ impl<'a> SanParser<'a> {
         // and the square was the destination.
         //
         // We peek the next character:
-        // 1) If it's "+" or "#", then it was the destination.
+        // 1) If it's "+", "#", "!" or "?", then it was the destination.
         // 2) If it's "=" then it looks like a promotion move, so it was the destination
         // 3) If it's a "Q", "R", "B", "N" then it looks like a promotion move, so it was
         //    the destination
-        // 4) If it was a hyphen "-" then it was the origin square
+        // 4) If it was a hyphen "-" or a capture "x" then it was the origin square
         // 5) If it was another file then we had an origin square
         // 6) If it was end of string, then it was the destination square
         // 7) If it was anything else then it's an error
         match self.peek() {
             Some("+") | Some("#") | Some("=") => Ok(true),
+            Some("!") | Some("?") => Ok(true),
             Some("Q") | Some("R") | Some("B") | Some("N") => Ok(true),
-            Some("-") => Ok(false),
+            Some("-") | Some("x") => Ok(false),
             Some(c) => {
                 if SanParser::is_file(c) {
                     Ok(false)
@@ -413,7 +426,8 @@ impl Position {
     ///
     /// This function is deliberately designed to be somewhat forgiving of lax notation. For
     /// example, the passed string does not need to accurately report check ('+') or checkmate ('#')
-    /// for the `Move` to be correctly identified.
+    /// for the `Move` to be correctly identified, and trailing annotations like `!?` are ignored.
+    /// Castling may be written with either the letter O or zero (`O-O`, `0-0-0`).
     ///
     /// Additionally, if the string is overspecified (e.g. `Nd4e4` instead of `Ne4` when there is
     /// no ambiguity) we correctly identify the move.
@@ -422,7 +436,7 @@ impl Position {
     /// match the string, then `None` is returned.
     ///
     /// If no move is found which matches the string, `None` is returned.
-    pub fn move_from_san(&self, mov: &str) -> Option<Move> {
+    pub fn from_san(&self, mov: &str) -> Option<Move> {
         // Algorithm:
         //
         // 1. Parse the following from the text: (Piece, FileFrom, RankFrom, SquareTo, IsCapture,
@@ -453,11 +467,162 @@ impl Position {
             Err(_) => None,
         }
     }
+
+    #[deprecated(note = "renamed to `from_san`")]
+    pub fn move_from_san(&self, mov: &str) -> Option<Move> {
+        self.from_san(mov)
+    }
+
+    /// Render the passed `Move` in standard algebraic notation, e.g. `Nbd7`, `exd5`, `O-O`,
+    /// `e8=Q+` or `Qh4#`.
+    ///
+    /// Where another piece of the same type could also move to the destination square, the origin
+    /// is disambiguated by file if that is enough, otherwise by rank, and otherwise by both.
+    ///
+    /// The passed `Move` must be legal in the current position.
+    pub fn to_san(&self, mov: &Move) -> String {
+        let mut san = String::new();
+        let orig = mov.orig();
+        let dest = mov.dest();
+        let piece_type = self.piece_at_sq(orig).type_of();
+
+        if mov.is_castle() {
+            if dest.file() > orig.file() {
+                san.push_str("O-O");
+            } else {
+                san.push_str("O-O-O");
+            }
+        } else if piece_type == PieceType::Pawn {
+            if mov.is_capture() {
+                san.push(file_char(orig));
+                san.push('x');
+            }
+
+            san.push_str(&dest.to_string());
+
+            if let Some(promo_piece) = mov.promo_piece_type() {
+                san.push('=');
+                san.push_str(&format!("{:1}", promo_piece).to_uppercase());
+            }
+        } else {
+            san.push_str(&format!("{:1}", piece_type).to_uppercase());
+
+            // Find the other pieces of the same type which can also move to `dest`.
+            let legal_moves = self.generate::<BasicMoveList, All, Legal>();
+            let mut ambiguous = false;
+            let mut shares_file = false;
+            let mut shares_rank = false;
+
+            for other in &legal_moves {
+                if other.dest() == dest
+                    && other.orig() != orig
+                    && self.piece_at_sq(other.orig()).type_of() == piece_type
+                {
+                    ambiguous = true;
+                    shares_file |= other.orig().file() == orig.file();
+                    shares_rank |= other.orig().rank() == orig.rank();
+                }
+            }
+
+            if ambiguous {
+                if !shares_file {
+                    san.push(file_char(orig));
+                } else if !shares_rank {
+                    san.push(rank_char(orig));
+                } else {
+                    san.push(file_char(orig));
+                    san.push(rank_char(orig));
+                }
+            }
+
+            if mov.is_capture() {
+                san.push('x');
+            }
+
+            san.push_str(&dest.to_string());
+        }
+
+        let mut pos = self.clone();
+        pos.make_move(mov);
+
+        if pos.in_checkmate() {
+            san.push('#');
+        } else if pos.in_check() {
+            san.push('+');
+        }
+
+        san
+    }
+
+    /// Parse a move in UCI notation (e.g. `e2e4`, `e7e8q`) without generating the legal moves or
+    /// making the move.
+    ///
+    /// The `MoveType` flags are inferred from the board, so the returned `Move` is the same as the
+    /// one produced by movegen and can be passed straight to `make_move`. Castling is written as
+    /// the king's move (`e1g1`), and a promotion must be suffixed with one of `q`, `r`, `b` or `n`.
+    ///
+    /// Returns `None` if the string is malformed or the move isn't legal in this position.
+    pub fn parse_uci_move(&self, uci: &str) -> Option<Move> {
+        let chars: Vec<char> = uci.chars().collect();
+        if chars.len() != 4 && chars.len() != 5 {
+            return None;
+        }
+
+        let orig: Square = uci.get(0..2)?.parse().ok()?;
+        let dest: Square = uci.get(2..4)?.parse().ok()?;
+        let promo_piece = match chars.get(4) {
+            None => None,
+            Some('q') => Some(PieceType::Queen),
+            Some('r') => Some(PieceType::Rook),
+            Some('b') => Some(PieceType::Bishop),
+            Some('n') => Some(PieceType::Knight),
+            Some(_) => return None,
+        };
+
+        let piece_type = self.piece_at_sq(orig).type_of();
+        let mut move_type = MoveType::empty();
+
+        if promo_piece.is_some() {
+            move_type |= MoveType::PROMOTION;
+        }
+
+        if !self.piece_at_sq(dest).is_none() {
+            move_type |= MoveType::CAPTURE;
+        } else if piece_type == PieceType::Pawn && self.ep_square() == Some(dest) {
+            move_type |= MoveType::EN_PASSANT | MoveType::CAPTURE;
+        }
+
+        if piece_type == PieceType::King && orig.file().abs_diff(dest.file()) == 2 {
+            move_type |= MoveType::CASTLE;
+        }
+
+        if move_type.is_empty() {
+            move_type = MoveType::QUIET;
+        }
+
+        let mov = Move::build(orig, dest, promo_piece, move_type);
+        if self.valid_move(&mov) {
+            Some(mov)
+        } else {
+            None
+        }
+    }
+}
+
+fn file_char(sq: Square) -> char {
+    (b'a' + sq.file()) as char
+}
+
+fn rank_char(sq: Square) -> char {
+    (b'1' + sq.rank()) as char
 }
 
 #[cfg(test)]
 mod tests {
     use crate::init::init_globals;
+    use crate::mono_traits::{All, Legal};
+    use crate::mov::Move;
+    use crate::movelist::BasicMoveList;
     use crate::position::Position;
 
     #[test]
@@ -466,12 +631,12 @@ mod tests {
 
         let pos = Position::start_pos();
 
-        assert!(pos.move_from_san("e4").is_some());
-        assert!(pos.move_from_san("d4").is_some());
-        assert!(pos.move_from_san("Nf3").is_some());
-        assert!(pos.move_from_san("Nb1-a3").is_some());
-        assert!(pos.move_from_san("Nb1c3").is_some());
-        assert!(!pos.move_from_san("e5").is_some());
+        assert!(pos.from_san("e4").is_some());
+        assert!(pos.from_san("d4").is_some());
+        assert!(pos.from_san("Nf3").is_some());
+        assert!(pos.from_san("Nb1-a3").is_some());
+        assert!(pos.from_san("Nb1c3").is_some());
+        assert!(!pos.from_san("e5").is_some());
     }
 
     #[test]
@@ -483,30 +648,177 @@ mod tests {
                 .unwrap();
 
         // Promotions
-        assert!(pos.move_from_san("bxa8R").is_some());
-        assert!(pos.move_from_san("bxa8=R").is_some());
-        assert!(pos.move_from_san("bxa8=N").is_some());
-        assert!(pos.move_from_san("ba8B").is_some());
-        assert!(pos.move_from_san("bxa8Q").is_some());
-        assert!(pos.move_from_san("bxc8Q").is_some());
-        assert!(pos.move_from_san("bxc8=Q").is_some());
-        assert!(pos.move_from_san("bxc8=R").is_some());
-        assert!(pos.move_from_san("bxc8=B").is_some());
-        assert!(pos.move_from_san("bxc8=N").is_some());
-        assert!(pos.move_from_san("bxc8=b").is_none());
+        assert!(pos.from_san("bxa8R").is_some());
+        assert!(pos.from_san("bxa8=R").is_some());
+        assert!(pos.from_san("bxa8=N").is_some());
+        assert!(pos.from_san("ba8B").is_some());
+        assert!(pos.from_san("bxa8Q").is_some());
+        assert!(pos.from_san("bxc8Q").is_some());
+        assert!(pos.from_san("bxc8=Q").is_some());
+        assert!(pos.from_san("bxc8=R").is_some());
+        assert!(pos.from_san("bxc8=B").is_some());
+        assert!(pos.from_san("bxc8=N").is_some());
+        assert!(pos.from_san("bxc8=b").is_none());
 
         // Disambiguations
-        assert!(pos.move_from_san("Qd4").is_none());
-        assert!(pos.move_from_san("Qad4").is_none());
-        assert!(pos.move_from_san("Q1d4").is_some());
-        assert!(pos.move_from_san("Qhd4").is_some());
-        assert!(pos.move_from_san("Qa1d4").is_some());
-        assert!(pos.move_from_san("Qa4d4").is_some());
-        assert!(pos.move_from_san("Qg4").is_none());
-        assert!(pos.move_from_san("Qag4").is_some());
+        assert!(pos.from_san("Qd4").is_none());
+        assert!(pos.from_san("Qad4").is_none());
+        assert!(pos.from_san("Q1d4").is_some());
+        assert!(pos.from_san("Qhd4").is_some());
+        assert!(pos.from_san("Qa1d4").is_some());
+        assert!(pos.from_san("Qa4d4").is_some());
+        assert!(pos.from_san("Qg4").is_none());
+        assert!(pos.from_san("Qag4").is_some());
 
         // En passant
-        assert!(pos.move_from_san("fxg6").is_some());
-        assert!(pos.move_from_san("fxg5").is_none());
+        assert!(pos.from_san("fxg6").is_some());
+        assert!(pos.from_san("fxg5").is_none());
+    }
+
+    /// Find the legal move with the given uci encoding.
+    fn uci_move(pos: &Position, uci: &str) -> Move {
+        *pos.generate::<BasicMoveList, All, Legal>()
+            .iter()
+            .find(|m| m.to_uci_string() == uci)
+            .unwrap()
+    }
+
+    #[test]
+    fn to_san() {
+        init_globals();
+
+        let suite = [
+            // Pawn moves, captures, en passant and promotions
+            ("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", "e2e4", "e4"),
+            ("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1", "e4d5", "exd5"),
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6", "exd6"),
+            ("k7/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7e8q", "e8=Q+"),
+            ("k7/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7e8n", "e8=N"),
+            // Castling
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1g1", "O-O"),
+            ("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", "e8c8", "O-O-O"),
+            // Disambiguation by file
+            ("rn2k3/8/5n2/8/8/8/8/4K3 b - - 0 1", "b8d7", "Nbd7"),
+            ("rn2k3/8/5n2/8/8/8/8/4K3 b - - 0 1", "f6d7", "Nfd7"),
+            ("rn2k3/8/5n2/8/8/8/8/4K3 b - - 0 1", "f6e4", "Ne4"),
+            // Disambiguation by rank
+            ("7k/8/8/R7/8/8/8/R3K3 w - - 0 1", "a1a3", "R1a3"),
+            ("7k/8/8/R7/8/8/8/R3K3 w - - 0 1", "a5a3", "R5a3"),
+            // Disambiguation by file, by rank, and by both
+            ("1k6/8/8/8/4Q2Q/8/8/K6Q w - - 0 1", "h4e1", "Qh4e1"),
+            ("1k6/8/8/8/4Q2Q/8/8/K6Q w - - 0 1", "h1e1", "Q1e1"),
+            ("1k6/8/8/8/4Q2Q/8/8/K6Q w - - 0 1", "e4e1", "Qee1"),
+            // Captures, checks and mates
+            ("1k6/8/8/3p4/8/8/8/K2R4 w - - 0 1", "d1d5", "Rxd5"),
+            ("1k6/8/8/8/8/8/8/K2R4 w - - 0 1", "d1b1", "Rb1+"),
+            (
+                "rnbqkbnr/pppp1ppp/8/4p3/6P1/5P2/PPPPP2P/RNBQKBNR b KQkq g3 0 2",
+                "d8h4",
+                "Qh4#",
+            ),
+        ];
+
+        for (fen, uci, san) in suite {
+            let pos = Position::from_fen(fen).unwrap();
+            let mov = uci_move(&pos, uci);
+            assert_eq!(pos.to_san(&mov), san, "{} {}", fen, uci);
+            assert_eq!(pos.from_san(san), Some(mov), "{} {}", fen, san);
+        }
+    }
+
+    #[test]
+    fn parse_uci_move() {
+        init_globals();
+
+        let suite = [
+            // A normal move, a capture and en passant
+            ("4k3/8/8/8/8/8/4P3/4K3 w - - 0 1", "e2e4"),
+            ("4k3/8/8/3p4/4P3/8/8/4K3 w - - 0 1", "e4d5"),
+            ("4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1", "e5d6"),
+            // Castling
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "e1g1"),
+            ("r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 1", "e8c8"),
+            // Promotions, with and without a capture
+            ("3r3k/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7e8q"),
+            ("3n3k/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7d8n"),
+        ];
+
+        for (fen, uci) in suite {
+            let pos = Position::from_fen(fen).unwrap();
+            let mov = pos.parse_uci_move(uci);
+
+            // The flags must match movegen's, not just the squares.
+            assert_eq!(mov, Some(uci_move(&pos, uci)), "{} {}", fen, uci);
+
+            let mut after = pos.clone();
+            after.make_move(&mov.unwrap());
+            let mut expected = pos.clone();
+            expected.make_uci_move(uci).unwrap();
+            assert_eq!(after.to_fen(), expected.to_fen(), "{} {}", fen, uci);
+        }
+    }
+
+    #[test]
+    fn parse_uci_move_rejects_bad_input() {
+        init_globals();
+
+        let pos = Position::from_fen("3r3k/4P3/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+
+        for uci in [
+            "", "e7", "e7e", "e7e8", "e7e8k", "e7e8Q", "e7e8qq", "i7e8q", "e7e9q",
+        ] {
+            assert_eq!(pos.parse_uci_move(uci), None, "{}", uci);
+        }
+
+        // Well-formed, but not legal.
+        assert_eq!(pos.parse_uci_move("e1e3"), None);
+        assert_eq!(pos.parse_uci_move("h8h7"), None);
+        assert_eq!(pos.parse_uci_move("e1d2q"), None);
+
+        // Castling without the right, or out of check.
+        let pos = Position::from_fen("r3k2r/8/8/8/8/8/8/R3K2R w Qkq - 0 1").unwrap();
+        assert_eq!(pos.parse_uci_move("e1g1"), None);
+        let pos = Position::from_fen("r3k2r/8/8/8/8/8/4r3/R3K2R w KQkq - 0 1").unwrap();
+        assert_eq!(pos.parse_uci_move("e1c1"), None);
+    }
+
+    #[test]
+    fn from_san() {
+        init_globals();
+
+        let suite = [
+            ("rn2k3/8/5n2/8/8/8/8/4K3 b - - 0 1", "Nbd7", Some("b8d7")),
+            ("rn2k3/8/5n2/8/8/8/8/4K3 b - - 0 1", "Nfd7!?", Some("f6d7")),
+            ("rn2k3/8/5n2/8/8/8/8/4K3 b - - 0 1", "Nd7", None),
+            ("rn2k3/8/5n2/8/8/8/8/4K3 b - - 0 1", "Nxe4", None),
+            ("rn2k3/8/5n2/8/4P3/8/8/4K3 b - - 0 1", "Nxe4", Some("f6e4")),
+            ("rn2k3/8/5n2/8/4P3/8/8/4K3 b - - 0 1", "Ne4?", Some("f6e4")),
+            ("7k/8/8/R7/8/8/8/R3K3 w - - 0 1", "R1a3", Some("a1a3")),
+            ("7k/8/8/R7/8/8/8/R3K3 w - - 0 1", "Ra3", None),
+            ("1k6/8/8/8/4Q2Q/8/8/K6Q w - - 0 1", "Qh4xe1", None),
+            ("1k6/8/8/8/4Q2Q/8/8/K6Q w - - 0 1", "Qh4-e1", Some("h4e1")),
+            ("1k6/8/8/8/4Q2Q/8/8/K6Q w - - 0 1", "Qhe1", None),
+            ("k7/4P3/8/8/8/8/8/4K3 w - - 0 1", "e8=Q+", Some("e7e8q")),
+            ("k7/4P3/8/8/8/8/8/4K3 w - - 0 1", "e8R!", Some("e7e8r")),
+            ("k7/4P3/8/8/8/8/8/4K3 w - - 0 1", "e8", None),
+            ("r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1", "O-O", Some("e1g1")),
+            (
+                "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+                "0-0-0",
+                Some("e1c1"),
+            ),
+            (
+                "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+                "O-O-O+!",
+                Some("e1c1"),
+            ),
+            ("r3k2r/8/8/8/8/8/8/R3K2R w Qkq - 0 1", "0-0", None),
+        ];
+
+        for (fen, san, uci) in suite {
+            let pos = Position::from_fen(fen).unwrap();
+            let mov = uci.map(|uci| uci_move(&pos, uci));
+            assert_eq!(pos.from_san(san), mov, "{} {}", fen, san);
+        }
     }
 }
@@ -0,0 +1,366 @@
+//! Support for games in Portable Game Notation (PGN).
+
+use super::{Player, Position, START_POSITION};
+use crate::mono_traits::{All, Legal};
+use crate::movelist::BasicMoveList;
+
+/// The maximum length of a line of movetext in exported PGN.
+const PGN_LINE_LENGTH: usize = 80;
+
+/// An error encountered while applying PGN movetext to a `Position`.
+#[derive(Debug)]
+pub struct PgnError {
+    ty: PgnErrorType,
+    /// The offending token.
+    pub token: String,
+    /// The ply at which the error occurred, counting from 1 for the first move in the movetext.
+    pub ply: usize,
+}
+
+impl std::fmt::Display for PgnError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} `{}` at ply {}", self.ty, self.token, self.ply)
+    }
+}
+
+#[derive(Debug)]
+pub enum PgnErrorType {
+    IllegalMove,
+    UnterminatedComment,
+    UnbalancedVariation,
+}
+
+impl std::fmt::Display for PgnErrorType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PgnErrorType::IllegalMove => write!(f, "illegal or ambiguous move"),
+            PgnErrorType::UnterminatedComment => write!(f, "unterminated comment"),
+            PgnErrorType::UnbalancedVariation => write!(f, "unbalanced variation"),
+        }
+    }
+}
+
+/// Splits PGN movetext into the SAN tokens of the main line, skipping move numbers, comments,
+/// NAGs, variations and the game termination marker.
+struct Movetext<'a> {
+    text: &'a str,
+    pos: usize,
+}
+
+impl<'a> Movetext<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { text, pos: 0 }
+    }
+
+    /// Returns the next token of the main line, or `None` at the end of the movetext.
+    fn next_token(&mut self) -> Result<Option<&'a str>, (PgnErrorType, &'a str)> {
+        let mut depth = 0;
+
+        loop {
+            let rest = &self.text[self.pos..];
+            let c = match rest.chars().next() {
+                Some(c) => c,
+                None if depth > 0 => return Err((PgnErrorType::UnbalancedVariation, "(")),
+                None => return Ok(None),
+            };
+
+            match c {
+                '{' => match rest.find('}') {
+                    Some(end) => self.pos += end + 1,
+                    None => return Err((PgnErrorType::UnterminatedComment, rest)),
+                },
+                ';' => self.pos += rest.find('\n').unwrap_or(rest.len()),
+                '(' => {
+                    depth += 1;
+                    self.pos += 1;
+                }
+                ')' if depth == 0 => return Err((PgnErrorType::UnbalancedVariation, ")")),
+                ')' => {
+                    depth -= 1;
+                    self.pos += 1;
+                }
+                c if c.is_whitespace() => self.pos += c.len_utf8(),
+                _ => {
+                    let len = rest
+                        .find(|c: char| c.is_whitespace() || "{};()".contains(c))
+                        .unwrap_or(rest.len());
+                    self.pos += len;
+
+                    if depth > 0 {
+                        continue;
+                    }
+
+                    match Self::strip_move_number(&rest[..len]) {
+                        "" => continue,
+                        tok if tok.starts_with('$') => continue,
+                        "1-0" | "0-1" | "1/2-1/2" | "*" => continue,
+                        tok => return Ok(Some(tok)),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Strip a leading move number like `12.` or `12...` from a token. The move itself may follow
+    /// the number without any whitespace, e.g. `1.e4`.
+    fn strip_move_number(tok: &str) -> &str {
+        let digits = tok.trim_start_matches(|c: char| c.is_ascii_digit());
+
+        if digits.len() < tok.len() && digits.starts_with('.') {
+            digits.trim_start_matches('.')
+        } else {
+            tok
+        }
+    }
+}
+
+impl Position {
+    /// Play the moves of PGN movetext (e.g. `1. e4 e5 2. Nf3 {comment} Nc6 (2... d6) 1-0`) on
+    /// this position, leaving it at the final position with the moves in its history.
+    ///
+    /// Move numbers, comments, NAGs (e.g. `$1`) and the result are skipped, as is the content of
+    /// any variations. If a move cannot be applied, an error naming the move and its ply is
+    /// returned and the position is left after the last move which was applied.
+    pub fn play_pgn(&mut self, movetext: &str) -> Result<(), PgnError> {
+        let mut movetext = Movetext::new(movetext);
+        let mut ply = 1;
+
+        loop {
+            let tok = match movetext.next_token() {
+                Ok(Some(tok)) => tok,
+                Ok(None) => return Ok(()),
+                Err((ty, token)) => {
+                    return Err(PgnError {
+                        ty,
+                        token: token.to_string(),
+                        ply,
+                    })
+                }
+            };
+
+            match self.from_san(tok) {
+                Some(mov) => self.make_move(&mov),
+                None => {
+                    return Err(PgnError {
+                        ty: PgnErrorType::IllegalMove,
+                        token: tok.to_string(),
+                        ply,
+                    })
+                }
+            }
+
+            ply += 1;
+        }
+    }
+
+    /// Export the game played from the root of this position's history as PGN.
+    ///
+    /// The seven-tag roster is included with placeholder values, along with a `FEN` tag if the
+    /// game didn't begin from the standard starting position. The result is taken from the current
+    /// position: a decisive result if it is checkmate, a draw if it is stalemate or a draw by rule,
+    /// and `*` otherwise.
+    pub fn to_pgn(&self) -> String {
+        // The history only stores enough to undo each move, so rewind to the root of the game and
+        // then replay forwards to render each move in SAN.
+        let mut pos = self.clone();
+        let mut moves = Vec::with_capacity(pos.history().len());
+        while let Some(undoable_move) = pos.unmake_move() {
+            moves.push(undoable_move.to_move());
+        }
+        moves.reverse();
+
+        let result = self.result();
+        let root_fen = pos.to_fen();
+
+        let mut pgn = String::new();
+        for (tag, value) in [
+            ("Event", "?"),
+            ("Site", "?"),
+            ("Date", "????.??.??"),
+            ("Round", "?"),
+            ("White", "?"),
+            ("Black", "?"),
+            ("Result", result),
+        ] {
+            pgn.push_str(&format!("[{} \"{}\"]\n", tag, value));
+        }
+
+        if root_fen != START_POSITION {
+            pgn.push_str("[SetUp \"1\"]\n");
+            pgn.push_str(&format!("[FEN \"{}\"]\n", root_fen));
+        }
+
+        pgn.push('\n');
+
+        let mut tokens = Vec::with_capacity(moves.len() * 3 / 2 + 1);
+        for (i, mov) in moves.iter().enumerate() {
+            if pos.turn() == Player::WHITE {
+                tokens.push(format!("{}.", pos.move_number()));
+            } else if i == 0 {
+                tokens.push(format!("{}...", pos.move_number()));
+            }
+
+            tokens.push(pos.to_san(mov));
+            pos.make_move(mov);
+        }
+        tokens.push(result.to_string());
+
+        let mut line_len = 0;
+        for tok in tokens {
+            if line_len > 0 && line_len + 1 + tok.len() > PGN_LINE_LENGTH {
+                pgn.push('\n');
+                line_len = 0;
+            } else if line_len > 0 {
+                pgn.push(' ');
+                line_len += 1;
+            }
+
+            line_len += tok.len();
+            pgn.push_str(&tok);
+        }
+        pgn.push('\n');
+
+        pgn
+    }
+
+    /// The PGN result token for the current position.
+    fn result(&self) -> &'static str {
+        if self.generate::<BasicMoveList, All, Legal>().is_empty() {
+            if !self.in_check() {
+                "1/2-1/2"
+            } else if self.turn() == Player::WHITE {
+                "0-1"
+            } else {
+                "1-0"
+            }
+        } else if self.in_threefold()
+            || self.half_move_clock() >= 100
+            || self.is_insufficient_material()
+        {
+            "1/2-1/2"
+        } else {
+            "*"
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::init::init_globals;
+    use crate::position::Position;
+
+    #[test]
+    fn plays_main_line() {
+        init_globals();
+
+        let mut pos = Position::start_pos();
+        pos.play_pgn(
+            "1. e4 e5 2. Nf3 {The most common move.} Nc6 $1 (2... d6 3. d4 (3. Bc4) exd4) \
+             3.Bb5 a6 ; the Morphy defence\n 4. Ba4 Nf6 5. O-O 1-0",
+        )
+        .unwrap();
+
+        let expected = Position::from_fen(
+            "r1bqkb1r/1ppp1ppp/p1n2n2/4p3/B3P3/5N2/PPPP1PPP/RNBQ1RK1 b kq - 3 5",
+        )
+        .unwrap();
+
+        assert_eq!(pos.to_fen(), expected.to_fen());
+        assert_eq!(pos.history().len(), 9);
+    }
+
+    #[test]
+    fn black_to_move_continuation() {
+        init_globals();
+
+        let mut pos = Position::start_pos();
+        pos.play_pgn("1. d4").unwrap();
+        pos.play_pgn("1... d5 2. c4 dxc4 *").unwrap();
+
+        assert_eq!(pos.history().len(), 4);
+    }
+
+    #[test]
+    fn reports_offending_move() {
+        init_globals();
+
+        let mut pos = Position::start_pos();
+        let err = pos.play_pgn("1. e4 e5 2. Nf3 Nf6 3. Ke3").unwrap_err();
+
+        assert_eq!(err.token, "Ke3");
+        assert_eq!(err.ply, 5);
+        assert_eq!(pos.history().len(), 4);
+        assert_eq!(err.to_string(), "illegal or ambiguous move `Ke3` at ply 5");
+    }
+
+    #[test]
+    fn reports_malformed_movetext() {
+        init_globals();
+
+        let mut pos = Position::start_pos();
+        assert!(pos.play_pgn("1. e4 {unterminated e5").is_err());
+
+        let mut pos = Position::start_pos();
+        assert!(pos.play_pgn("1. e4 (1. d4 e5").is_err());
+
+        let mut pos = Position::start_pos();
+        assert!(pos.play_pgn("1. e4 e5) 2. Nf3").is_err());
+    }
+
+    #[test]
+    fn exports_game() {
+        init_globals();
+
+        let mut pos = Position::start_pos();
+        pos.play_pgn("1. f3 e5 2. g4 Qh4#").unwrap();
+
+        let pgn = pos.to_pgn();
+        assert_eq!(
+            pgn,
+            "[Event \"?\"]\n\
+             [Site \"?\"]\n\
+             [Date \"????.??.??\"]\n\
+             [Round \"?\"]\n\
+             [White \"?\"]\n\
+             [Black \"?\"]\n\
+             [Result \"0-1\"]\n\
+             \n\
+             1. f3 e5 2. g4 Qh4# 0-1\n"
+        );
+    }
+
+    #[test]
+    fn exports_game_from_fen() {
+        init_globals();
+
+        let fen = "r3k2r/8/8/8/8/8/8/R3K2R b KQkq - 0 20";
+        let mut pos = Position::from_fen(fen).unwrap();
+        pos.play_pgn("20... O-O 21. Rxa8 Rxa8").unwrap();
+
+        let pgn = pos.to_pgn();
+        assert!(pgn.contains("[Result \"*\"]\n"));
+        assert!(pgn.contains(&format!("[SetUp \"1\"]\n[FEN \"{}\"]\n", fen)));
+        assert!(pgn.ends_with("\n20... O-O 21. Rxa8 Rxa8 *\n"));
+    }
+
+    #[test]
+    fn round_trips_long_game() {
+        init_globals();
+
+        let movetext = "1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Ba4 Nf6 5. O-O Be7 6. Re1 b5 7. Bb3 d6 \
+                        8. c3 O-O 9. h3 Nb8 10. d4 Nbd7 11. c4 c6 12. cxb5 axb5 13. Nc3 Bb7";
+
+        let mut pos = Position::start_pos();
+        pos.play_pgn(movetext).unwrap();
+
+        let pgn = pos.to_pgn();
+        assert!(pgn.lines().all(|line| line.len() <= 80));
+
+        // The exported PGN can be read back in, tag pairs aside.
+        let exported_movetext = pgn.split("\n\n").nth(1).unwrap();
+        let mut replayed = Position::start_pos();
+        replayed.play_pgn(exported_movetext).unwrap();
+
+        assert_eq!(replayed.to_fen(), pos.to_fen());
+    }
+}
@@ -113,6 +113,24 @@ pub enum PieceType {
     King = 6,
 }
 
+pub const PAWN_VALUE: i16 = 100;
+pub const KNIGHT_VALUE: i16 = 300;
+pub const BISHOP_VALUE: i16 = 300;
+pub const ROOK_VALUE: i16 = 500;
+pub const QUEEN_VALUE: i16 = 900;
+pub const KING_VALUE: i16 = 10000;
+
+/// Material values in centipawns, indexed by `PieceType`.
+pub const PIECE_VALUES: [i16; 7] = [
+    0, // PieceType::None,
+    PAWN_VALUE,
+    KNIGHT_VALUE,
+    BISHOP_VALUE,
+    ROOK_VALUE,
+    QUEEN_VALUE,
+    KING_VALUE,
+];
+
 pub const PIECE_TYPES: [PieceType; 7] = [
     PieceType::None,
     PieceType::Pawn,
@@ -135,6 +153,13 @@ impl PieceType {
         *self == PieceType::None
     }
 
+    /// Returns an iterator over the six real piece types, from `Pawn` to `King`. Unlike
+    /// `PIECE_TYPES`, this excludes `PieceType::None`.
+    #[inline]
+    pub fn iter() -> impl Iterator<Item = PieceType> {
+        PIECE_TYPES[1..].iter().copied()
+    }
+
     fn long_name(&self) -> &str {
         match self {
             PieceType::None => "none",
@@ -160,6 +185,23 @@ impl PieceType {
     }
 }
 
+impl From<PieceType> for usize {
+    #[inline(always)]
+    fn from(pt: PieceType) -> Self {
+        pt as usize
+    }
+}
+
+impl TryFrom<usize> for PieceType {
+    type Error = ();
+
+    /// Convert an index back to a `PieceType`. Fails if `idx` is outside `0..=6`.
+    #[inline]
+    fn try_from(idx: usize) -> Result<Self, Self::Error> {
+        PIECE_TYPES.get(idx).copied().ok_or(())
+    }
+}
+
 impl fmt::Display for PieceType {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         if let Some(width) = f.width() {
@@ -193,3 +235,29 @@ impl fmt::Display for Piece {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iter_excludes_none() {
+        let pts: Vec<PieceType> = PieceType::iter().collect();
+        assert_eq!(pts.len(), 6);
+        assert_eq!(pts.first(), Some(&PieceType::Pawn));
+        assert_eq!(pts.last(), Some(&PieceType::King));
+        assert!(!pts.contains(&PieceType::None));
+    }
+
+    #[test]
+    fn usize_round_trip() {
+        for pt in PIECE_TYPES {
+            let idx = usize::from(pt);
+            assert_eq!(idx, pt as usize);
+            assert_eq!(PieceType::try_from(idx), Ok(pt));
+        }
+
+        assert_eq!(PieceType::try_from(7), Err(()));
+        assert_eq!(PieceType::try_from(usize::MAX), Err(()));
+    }
+}
@@ -1,6 +1,7 @@
 use crate::bb::Bitboard;
 use std::fmt;
 use std::ops::*;
+use std::str::FromStr;
 
 /// Represents a single square of a chess board.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, PartialOrd)]
@@ -9,6 +10,20 @@ pub struct Square(pub u8);
 
 impl_bit_ops!(Square, u8);
 
+/// The square with the given index, or an error if it is 64 or more. Hot paths which already know
+/// the index is in range should build the `Square` directly instead.
+impl TryFrom<u8> for Square {
+    type Error = SquareError;
+
+    fn try_from(idx: u8) -> Result<Square, SquareError> {
+        if idx < 64 {
+            Ok(Square(idx))
+        } else {
+            Err(SquareError::OutOfRange(idx))
+        }
+    }
+}
+
 impl Square {
     /// Creates a square from a rank and a file. This is slow because it performs assertions to
     /// ensure that the rank and file are within bounds. It should never be needed in hot engine
@@ -197,6 +212,47 @@ impl Square {
     pub const H8: Square = Square(0b111111);
 }
 
+#[derive(Debug, PartialEq)]
+pub enum SquareError {
+    /// The string was not a file `a`-`h` followed by a rank `1`-`8`.
+    Malformed(String),
+    /// The index was not less than 64.
+    OutOfRange(u8),
+}
+
+impl fmt::Display for SquareError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SquareError::Malformed(s) => write!(f, "`{}` is not a valid square name", s),
+            SquareError::OutOfRange(idx) => write!(f, "square index {} is out of range", idx),
+        }
+    }
+}
+
+impl FromStr for Square {
+    type Err = SquareError;
+
+    /// Parse a square name, `a1` to `h8`. The file may be upper or lower case.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+
+        match (chars.next(), chars.next(), chars.next()) {
+            (Some(file), Some(rank), None) => {
+                let file = file.to_ascii_lowercase();
+                if ('a'..='h').contains(&file) && ('1'..='8').contains(&rank) {
+                    Ok(Square::from_rank_file(
+                        rank as usize - '1' as usize,
+                        file as usize - 'a' as usize,
+                    ))
+                } else {
+                    Err(SquareError::Malformed(s.to_string()))
+                }
+            }
+            _ => Err(SquareError::Malformed(s.to_string())),
+        }
+    }
+}
+
 impl fmt::Display for Square {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let Square(idx) = self;
@@ -221,3 +277,43 @@ impl fmt::Display for Square {
         write!(f, "{}{}", file_name, rank.to_string())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_square_names() {
+        assert_eq!("a1".parse(), Ok(Square::A1));
+        assert_eq!("e4".parse(), Ok(Square::E4));
+        assert_eq!("H8".parse(), Ok(Square::H8));
+
+        for idx in 0..64 {
+            let sq = Square(idx);
+            assert_eq!(sq.to_string().parse(), Ok(sq));
+        }
+
+        for name in ["", "a", "i9", "a0", "a9", "i1", "e44", "4e", " e4"] {
+            assert_eq!(
+                name.parse::<Square>(),
+                Err(SquareError::Malformed(name.to_string())),
+                "{}",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn converts_indices() {
+        assert_eq!(Square::try_from(0), Ok(Square::A1));
+        assert_eq!(Square::try_from(63), Ok(Square::H8));
+        assert_eq!(Square::try_from(64), Err(SquareError::OutOfRange(64)));
+        assert_eq!(
+            Square::try_from(u8::MAX),
+            Err(SquareError::OutOfRange(u8::MAX))
+        );
+
+        let sq: Result<Square, _> = 64u8.try_into();
+        assert_eq!(sq, Err(SquareError::OutOfRange(64)));
+    }
+}
@@ -42,7 +42,7 @@ impl State {
         let them = !us;
         let ksq = position.king_sq(us);
 
-        state.checkers = position.attackers_to(ksq) & position.get_occupied_player_runtime(them);
+        state.checkers = position.attackers_of_color(ksq, them);
         state.set_check_info(position);
 
         state
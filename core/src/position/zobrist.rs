@@ -1,4 +1,4 @@
-use crate::position::{CastlingRights, Piece, Position, Square};
+use crate::position::{CastlingRights, Piece, PieceType, Player, Position, Square};
 use crate::precalc::zobrist::{
     castling_rights_keys, ep_file_keys, piece_square_key, side_to_move_key, side_to_move_toggler,
 };
@@ -11,6 +11,7 @@ use std::ops::*;
 pub struct Zobrist(pub u64);
 
 impl_bit_ops!(Zobrist, u64);
+impl_from_bits!(Zobrist, u64);
 
 impl Zobrist {
     pub fn empty() -> Self {
@@ -21,9 +22,12 @@ impl Zobrist {
     /// `Position` struct.
     pub fn from_position(pos: &Position) -> Self {
         let mut zob = Zobrist::empty();
-        // Piece-squares
+        // Piece-squares. Empty squares aren't hashed, so that the key agrees with one kept
+        // updated as pieces are put on and taken off the board.
         for (sq, piece) in &pos.board {
-            zob ^= piece_square_key(piece, sq);
+            if !piece.is_none() {
+                zob ^= piece_square_key(piece, sq);
+            }
         }
         // Side-to-move
         zob ^= side_to_move_key(pos.turn());
@@ -38,6 +42,44 @@ impl Zobrist {
         zob
     }
 
+    /// Generates the pawn key, which hashes only the pawns and kings, from an otherwise fully
+    /// built `Position` struct.
+    pub fn pawns_from_position(pos: &Position) -> Self {
+        let mut zob = Zobrist::empty();
+        for (sq, piece) in &pos.board {
+            if in_pawn_key(piece.type_of()) {
+                zob ^= piece_square_key(piece, sq);
+            }
+        }
+
+        zob
+    }
+
+    /// Generates the material key, which hashes only the number of each piece, from an otherwise
+    /// fully built `Position` struct.
+    pub fn material_from_position(pos: &Position) -> Self {
+        let mut zob = Zobrist::empty();
+        for player in [Player::WHITE, Player::BLACK] {
+            for piece_type in PieceType::iter() {
+                let piece = Piece::make(player, piece_type);
+                for index in 0..pos.piece_bb(player, piece_type).popcnt() {
+                    zob.toggle_piece_count(piece, index as u8);
+                }
+            }
+        }
+
+        zob
+    }
+
+    /// Updates a material key by xor'ing with the key for the `index`th `Piece` of its kind, where
+    /// the first is index 0. A piece put on the board toggles on the key for the number of such
+    /// pieces already there; a piece taken off toggles off the key for the number left behind.
+    ///
+    /// This borrows the piece-square keys, using the index in place of a square.
+    pub fn toggle_piece_count(&mut self, piece: Piece, index: u8) {
+        *self ^= piece_square_key(piece, Square(index));
+    }
+
     /// Updates a Zobrist key by xor'ing with the piece-square key for the given `Piece` and `Square`.
     /// For normal moves, this will be called twice: once to remove the key for where the piece started,
     /// and once to add in the key for where the piece moves to. For a capture, there will be another call,
@@ -73,6 +115,12 @@ impl Zobrist {
     }
 }
 
+/// Whether pieces of type `piece_type` are hashed into the pawn key.
+#[inline(always)]
+pub(crate) fn in_pawn_key(piece_type: PieceType) -> bool {
+    matches!(piece_type, PieceType::Pawn | PieceType::King)
+}
+
 impl fmt::Display for Zobrist {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         fmt::Display::fmt(&self.0, f)
@@ -1,4 +1,5 @@
 use super::prng::PRNG;
+use crate::bb::Bitboard;
 use crate::masks::{FILE_A, FILE_H, RANK_1, RANK_8};
 use crate::position::{file_bb, rank_bb, Square};
 
@@ -139,9 +140,6 @@ unsafe fn gen_magic_board(
     // Size tracks the size of permutations of the current block
     let mut size: usize;
 
-    // b is used for generating the permutations through ripple - carry
-    let mut b: u64;
-
     // current tracks the iteration of potential magics for each square, incrementing by 1 each time we
     // try a new candidate
     let mut current: i32 = 0;
@@ -167,18 +165,13 @@ unsafe fn gen_magic_board(
 
         // Shift = number of bits in 64 - bits in mask = log2(size)
         let shift: u32 = (64 - popcount64(mask)) as u32;
-        b = 0;
         size = 0;
 
         // Ripple carry to determine occupancy, reference, and size
-        'bit: loop {
-            occupancy[size] = b;
-            reference[size] = sliding_attack(deltas, s, b);
+        for b in Bitboard(mask).subsets() {
+            occupancy[size] = b.0;
+            reference[size] = sliding_attack(deltas, s, b.0);
             size += 1;
-            b = ((b).wrapping_sub(mask)) as u64 & mask;
-            if b == 0 {
-                break 'bit;
-            }
         }
 
         // Set current PreSMagic length to be of size
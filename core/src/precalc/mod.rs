@@ -1,4 +1,6 @@
 pub mod boards;
 pub mod magic;
+#[cfg(all(feature = "pext", target_arch = "x86_64"))]
+pub mod pext;
 pub mod prng;
 pub mod zobrist;
@@ -89,5 +89,5 @@ pub fn castling_rights_keys(castling_rights: CastlingRights) -> u64 {
 #[inline(always)]
 pub fn ep_file_keys(sq: Square) -> u64 {
     debug_assert!(sq.is_okay());
-    unsafe { *EP_FILE_KEYS.get_unchecked(sq.rank() as usize) }
+    unsafe { *EP_FILE_KEYS.get_unchecked(sq.file() as usize) }
 }
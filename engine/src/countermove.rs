@@ -0,0 +1,45 @@
+//! Countermove table.
+
+use core::mov::Move;
+use core::position::{Piece, Position, Square};
+
+/// The quiet move which last refuted each move, indexed by the piece which moved and its
+/// destination square.
+///
+/// A move which refutes, say, a knight landing on f5 tends to refute it wherever else in the tree
+/// it happens, so the countermove is tried straight after the killers.
+#[derive(Debug)]
+pub struct CounterMoveTable {
+    data: [[Move; 64]; 13],
+}
+
+impl CounterMoveTable {
+    pub fn new() -> Self {
+        Self {
+            data: [[Move::null(); 64]; 13],
+        }
+    }
+
+    /// Probe for the countermove to `piece` moving to `dest`. Only returns a move which is valid
+    /// and legal in the given position.
+    pub fn probe(&self, piece: Piece, dest: Square, pos: &Position) -> Option<Move> {
+        let mov = self.data[piece as usize][dest.0 as usize];
+        if !mov.is_null() && pos.valid_move(&mov) {
+            Some(mov)
+        } else {
+            None
+        }
+    }
+
+    /// Store `counter` as the countermove to `piece` moving to `dest`.
+    pub fn store(&mut self, piece: Piece, dest: Square, counter: Move) {
+        debug_assert!(dest.is_okay());
+        self.data[piece as usize][dest.0 as usize] = counter;
+    }
+}
+
+impl Default for CounterMoveTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
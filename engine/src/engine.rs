@@ -1,29 +1,258 @@
-use super::search::{Master, Search, Worker};
-use super::time::TimingMode;
+use super::history::Histories;
+use super::kpk;
+use super::options::{self, Config, EngineOpt};
+use super::search::{Master, Search, Worker, MAX_PLY};
+#[cfg(feature = "syzygy")]
+use super::tablebase::Tablebases;
+use super::time::{Deadline, TimingMode};
 use super::tt::Table;
 use super::uci::{self, Command};
+use core::mov::Move;
 use core::position::Position;
 
 use crossbeam_channel::unbounded;
 
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use std::{
     io,
     thread::{self, Scope},
 };
 
-const MAX_DEPTH: u8 = 255;
+/// Engine state which persists between UCI commands.
+///
+/// Per the UCI protocol, options can arrive at any time (before or after `position` or
+/// `ucinewgame`), so they are applied immediately to `config` and take effect from the next `go`.
+struct Engine {
+    /// The current engine configuration.
+    config: Config,
+    /// The internal board position.
+    pos: Position,
+    /// The transposition table. Searches hold their own handle to the table, so that a `Hash`
+    /// resize can swap in a new table without disturbing a search which is still running.
+    tt: Arc<Table>,
+    /// The timing mode of the current ponder search, if one is running, and when it started. On
+    /// `ponderhit`, this is used to set the search deadline, counting the time already spent
+    /// pondering towards the move.
+    ponder: Option<(TimingMode, Instant)>,
+    /// The history tables of each search thread, kept from one search to the next. A thread holds
+    /// the lock on its slot for as long as it searches, and leaves its tables there when done.
+    history: Vec<Arc<Mutex<Option<Histories>>>>,
+    /// The Syzygy tablebases, if a path to them has been set.
+    #[cfg(feature = "syzygy")]
+    tb: Option<Arc<Tablebases>>,
+}
+
+impl Engine {
+    fn new() -> Self {
+        let config = Config::default();
+        let tt = Arc::new(Table::new(config.hash_size()));
+
+        Self {
+            config,
+            pos: Position::start_pos(),
+            tt,
+            ponder: None,
+            history: Vec::new(),
+            #[cfg(feature = "syzygy")]
+            tb: None,
+        }
+    }
+
+    /// Apply an engine option.
+    fn set_option(&mut self, opt: EngineOpt) {
+        self.config.set_option(opt.clone());
+
+        if let EngineOpt::Hash(_) = opt {
+            self.tt = Arc::new(Table::new(self.config.hash_size()));
+            println!(
+                "info string Hash set to {} MB ({} entries)",
+                self.tt.capacity_mb(),
+                self.tt.capacity_entries()
+            );
+        }
+
+        #[cfg(feature = "syzygy")]
+        if let EngineOpt::SyzygyPath(_) = opt {
+            self.tb = None;
+            if let Some(path) = self.config.syzygy_path() {
+                match Tablebases::open(path) {
+                    Ok(tb) => {
+                        println!(
+                            "info string found tablebases with up to {} pieces",
+                            tb.max_pieces()
+                        );
+                        self.tb = Some(Arc::new(tb));
+                    }
+                    Err(err) => println!("info string couldn't load tablebases; {}", err),
+                }
+            }
+        }
+    }
+
+    /// Launch a search on the current position with the given timing mode. If `root_moves` is
+    /// given, only those moves are considered at the root.
+    fn go<'scope, 'env>(
+        &mut self,
+        s: &'scope Scope<'scope, 'env>,
+        stop_flag: &'env AtomicBool,
+        deadline: &'env Deadline,
+        timing_mode: &TimingMode,
+        root_moves: Option<Vec<Move>>,
+    ) {
+        let timing_mode = &timing_mode
+            .clone()
+            .with_move_overhead(self.config.move_overhead());
+
+        // Entries from earlier searches are kept, but aged, so that they give way to fresh ones.
+        self.tt.new_generation();
+        self.history
+            .resize_with(self.config.threads(), Default::default);
+
+        stop_flag.store(false, Ordering::Relaxed);
+        deadline.set(timing_mode.stop_time(
+            Instant::now(),
+            self.pos.move_number(),
+            self.pos.turn(),
+        ));
+        launch_search(s, stop_flag, deadline, self, timing_mode, root_moves);
+    }
+
+    /// Resolve the moves given to `go searchmoves` against the current position. Illegal moves are
+    /// reported and ignored. If none of the moves are legal, all moves are searched.
+    fn resolve_search_moves(&self, moves: &[String]) -> Option<Vec<Move>> {
+        let mut root_moves = Vec::with_capacity(moves.len());
+        for mov in moves {
+            match self.pos.parse_uci_move(mov) {
+                Some(mov) if !root_moves.contains(&mov) => root_moves.push(mov),
+                Some(_) => {}
+                None => println!("info string ignoring illegal searchmove {}", mov),
+            }
+        }
+
+        if root_moves.is_empty() {
+            None
+        } else {
+            Some(root_moves)
+        }
+    }
+
+    /// Handle a single UCI command. Returns `false` when the engine should quit.
+    fn handle<'scope, 'env>(
+        &mut self,
+        s: &'scope Scope<'scope, 'env>,
+        stop_flag: &'env AtomicBool,
+        deadline: &'env Deadline,
+        cmd: Command,
+    ) -> bool {
+        match cmd {
+            Command::Quit => {
+                stop_flag.store(true, Ordering::Relaxed);
+                return false;
+            }
+            Command::Stop => {
+                // Stopping a ponder search ends it like any other; the master still reports its
+                // best move.
+                self.ponder = None;
+                stop_flag.store(true, Ordering::Relaxed);
+            }
+            Command::Go(timing_mode) => {
+                self.ponder = None;
+                self.go(s, stop_flag, deadline, &timing_mode, None);
+            }
+            Command::GoSearchMoves(moves, timing_mode) => {
+                self.ponder = None;
+                let root_moves = self.resolve_search_moves(&moves);
+                self.go(s, stop_flag, deadline, &timing_mode, root_moves);
+            }
+            Command::GoPonder(moves, timing_mode) => {
+                // Ponder until told otherwise; the deadline is only set on `ponderhit`.
+                let root_moves = self.resolve_search_moves(&moves);
+                self.go(s, stop_flag, deadline, &TimingMode::Infinite, root_moves);
+                let timing_mode = timing_mode.with_move_overhead(self.config.move_overhead());
+                self.ponder = Some((timing_mode, Instant::now()));
+            }
+            Command::PonderHit => match self.ponder.take() {
+                Some((timing_mode, ponder_start)) => {
+                    let now = Instant::now();
+                    deadline.set(timing_mode.ponderhit_stop_time(
+                        now,
+                        now - ponder_start,
+                        self.pos.move_number(),
+                        self.pos.turn(),
+                    ))
+                }
+                None => println!("info string ignoring ponderhit while not pondering"),
+            },
+            Command::SetPosition((fen, moves)) => match uci::set_position(&fen, &moves) {
+                Ok(pos) => self.pos = pos,
+                Err(err) => println!("info string invalid position; {}", err),
+            },
+            Command::UciNewGame => {
+                // Nothing from the previous game should influence this one. Killers belong to each
+                // search, but the transposition table and history tables carry over between them.
+                self.tt.clear();
+                self.tt.new_generation();
+                for history in &self.history {
+                    *history.lock().unwrap() = None;
+                }
+                self.ponder = None;
+                self.pos = Position::start_pos();
+            }
+            Command::SetOption(opt) => self.set_option(opt),
+            Command::Display => println!("{}", self.pos),
+            Command::DisplayLichess => {
+                let fen_url_safe = self.pos.to_fen().replace(" ", "_");
+                let lichess_url = format!("https://lichess.org/analysis/standard/{}", fen_url_safe);
+
+                let _ = open::that(lichess_url);
+            }
+            Command::Move(mov) => match self.pos.make_uci_move(&mov) {
+                Some(_) => {}
+                None => {
+                    // If the move wasn't valid uci, try to see if it was SAN.
+                    match self.pos.from_san(&mov) {
+                        Some(mov) => self.pos.make_move(&mov),
+                        None => println!("illegal move: {}", mov),
+                    }
+                }
+            },
+            Command::Perft(d) => {
+                let start = Instant::now();
+                let result = super::perft::Perft::divide(&mut self.pos, d, true, false);
+                println!("{}", result);
+                println!("Time: {}ms", start.elapsed().as_millis());
+            }
+            Command::Uci => {
+                println!("id name seaborg 0.0.2");
+                println!("id author George Seabridge");
+                for option in options::uci_options() {
+                    println!("{}", option);
+                }
+                println!("uciok");
+            }
+            Command::IsReady => {
+                println!("readyok");
+            }
+            Command::Config => println!("{:#?}", self.config),
+        }
+
+        true
+    }
+}
 
 /// Launch the engine process.
 pub fn launch() {
     core::init::init_globals();
+    kpk::init();
 
     let stop_flag = AtomicBool::new(false);
     let flag = &stop_flag;
+    let deadline = Deadline::none();
+    let deadline = &deadline;
 
-    let tt = Table::new(16);
-
-    let mut pos = Position::start_pos();
+    let mut engine = Engine::new();
 
     // Everything happens inside a global thread scope.
     thread::scope(|s| {
@@ -58,99 +287,479 @@ pub fn launch() {
 
         loop {
             match uci_rx.try_recv() {
-                Ok(Command::Quit) => {
-                    stop_flag.store(true, Ordering::Relaxed);
-                    break;
-                }
-                Ok(Command::Stop) => {
-                    stop_flag.store(true, Ordering::Relaxed);
-                }
-                Ok(Command::Go(d)) => match d {
-                    TimingMode::Depth(depth) => {
-                        stop_flag.store(false, Ordering::Relaxed);
-                        launch_search(s, flag, None, 1, depth, pos.clone(), &tt);
-                    }
-                    TimingMode::Infinite => {
-                        stop_flag.store(false, Ordering::Relaxed);
-                        launch_search(s, flag, None, 1, MAX_DEPTH, pos.clone(), &tt);
-                    }
-                    TimingMode::Timed(tc) => {
-                        let move_time = tc.to_move_time(pos.move_number(), pos.turn());
-                        let stop_time = std::time::Instant::now()
-                            + std::time::Duration::from_millis(move_time.into());
-                        launch_search(s, flag, Some(stop_time), 1, MAX_DEPTH, pos.clone(), &tt);
-                    }
-                    TimingMode::MoveTime(t) => {
-                        let stop_time =
-                            std::time::Instant::now() + std::time::Duration::from_millis(t as u64);
-                        launch_search(s, flag, Some(stop_time), 1, MAX_DEPTH, pos.clone(), &tt);
-                    }
-                },
-                Ok(Command::SetPosition((fen, moves))) => match Position::from_fen(&fen) {
-                    Ok(mut p) => {
-                        for mov in moves {
-                            if p.make_uci_move(&mov).is_none() {
-                                println!("invalid move {}", mov);
-                            }
-                        }
-                        pos = p;
-                    }
-                    Err(err) => println!("invalid position; {}", err),
-                },
-                Ok(Command::Display) => println!("{}", pos),
-                Ok(Command::DisplayLichess) => {
-                    let fen_url_safe = pos.to_fen().replace(" ", "_");
-                    let lichess_url =
-                        format!("https://lichess.org/analysis/standard/{}", fen_url_safe);
-
-                    let _ = open::that(lichess_url);
-                }
-                Ok(Command::Move(mov)) => match pos.make_uci_move(&mov) {
-                    Some(_) => {}
-                    None => {
-                        // If the move wasn't valid uci, try to see if it was SAN.
-                        match pos.move_from_san(&mov) {
-                            Some(mov) => pos.make_move(&mov),
-                            None => println!("illegal move: {}", mov),
-                        }
+                Ok(cmd) => {
+                    if !engine.handle(s, flag, deadline, cmd) {
+                        break;
                     }
-                },
-                Ok(Command::Perft(d)) => {
-                    super::perft::Perft::divide(&mut pos, d, true, false);
-                }
-                Ok(Command::Uci) => {
-                    println!("id name seaborg 0.0.2");
-                    println!("id author George Seabridge");
-                    println!("uciok");
                 }
-                Ok(Command::IsReady) => {
-                    println!("readyok");
-                }
-                Ok(cmd) => println!("{:?}: not yet implemented", cmd),
                 Err(_err) => {}
             }
         }
     });
 }
 
+/// Launch a Lazy SMP search with the configured number of threads. Each thread searches the same
+/// position independently, sharing only the transposition table and the stop signals. Thread 0 is
+/// the master, which reports info and the best move to the GUI, searches the configured number of
+/// principal variations and enforces any node limit or mate search.
 fn launch_search<'scope, 'engine>(
     s: &'scope Scope<'scope, 'engine>,
     flag: &'engine AtomicBool,
-    stop_time: Option<std::time::Instant>,
-    num_threads: u8,
-    depth: u8,
-    pos: Position,
-    tt: &'engine Table,
+    stop_time: &'engine Deadline,
+    engine: &Engine,
+    timing_mode: &TimingMode,
+    root_moves: Option<Vec<Move>>,
 ) {
-    for i in 0..num_threads {
-        let thread_pos = pos.clone();
+    let config = &engine.config;
+    let depth = match timing_mode {
+        TimingMode::Depth(depth) => *depth,
+        // A mate in `n` moves takes at most `2n - 1` plies, and we need one more ply of depth to
+        // see that the mated side has no moves.
+        TimingMode::Mate(moves) => std::cmp::max(1, moves.saturating_mul(2)),
+        _ => MAX_PLY,
+    };
+    let mate_limit = match timing_mode {
+        TimingMode::Mate(moves) => Some(*moves),
+        _ => None,
+    };
+    let node_limit = match timing_mode {
+        TimingMode::Nodes(nodes) => Some(*nodes),
+        _ => None,
+    };
+    let multipv = config.multipv();
+    let contempt = config.contempt();
+    let debug = config.debug_mode();
+    let infinite = matches!(timing_mode, TimingMode::Infinite);
+
+    for i in 0..config.threads() {
+        let thread_pos = engine.pos.clone();
+        let tt = Arc::clone(&engine.tt);
+        let root_moves = root_moves.clone();
+        let history = Arc::clone(&engine.history[i]);
+        #[cfg(feature = "syzygy")]
+        let tb = engine.tb.clone();
         s.spawn(move || {
-            let mut search = Search::new(thread_pos, flag, stop_time, tt);
+            let mut history = history.lock().unwrap();
+            let mut search = Search::new(thread_pos, flag, stop_time, &tt)
+                .with_thread_id(i)
+                .with_history(history.take())
+                .with_root_moves(root_moves)
+                .with_contempt(contempt);
+            #[cfg(feature = "syzygy")]
+            {
+                search = search.with_tablebases(tb.as_deref());
+            }
             if i == 0 {
+                search = search
+                    .with_multipv(multipv)
+                    .with_node_limit(node_limit)
+                    .with_mate_limit(mate_limit)
+                    .with_infinite(infinite)
+                    .with_debug(debug);
                 search.run::<Master>(depth);
             } else {
                 search.run::<Worker>(depth);
             }
+            *history = Some(search.into_history());
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::options::HASH_DEFAULT_MB;
+    use crate::time::TimeControl;
+    use core::position::{Player, Square};
+    use std::time::Duration;
+
+    /// The stop flag and deadline which the UCI loop shares with the engine's searches.
+    struct Fixture {
+        stop_flag: AtomicBool,
+        deadline: Deadline,
+    }
+
+    impl Fixture {
+        fn new() -> Self {
+            Self {
+                stop_flag: AtomicBool::new(false),
+                deadline: Deadline::none(),
+            }
+        }
+
+        /// Parse each of `lines` as a UCI command and have `engine` handle it, as if it had been
+        /// read from stdin.
+        fn send<'scope, 'env>(
+            &'env self,
+            engine: &mut Engine,
+            s: &'scope Scope<'scope, 'env>,
+            lines: &[&str],
+        ) {
+            for line in lines {
+                let cmd = uci::Parser::parse(line).unwrap();
+                assert!(
+                    engine.handle(s, &self.stop_flag, &self.deadline, cmd),
+                    "{}",
+                    line
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn hash_option_applies_to_next_search() {
+        core::init::init_globals();
+
+        let fixture = Fixture::new();
+        let mut engine = Engine::new();
+
+        thread::scope(|s| {
+            fixture.send(
+                &mut engine,
+                s,
+                &[
+                    "uci",
+                    "setoption name Hash value 64",
+                    "position startpos",
+                    "go depth 4",
+                ],
+            );
+        });
+
+        // The search has finished, so the root position must have been written to the table.
+        assert_eq!(engine.tt.capacity_mb(), 64);
+        assert!(engine.tt.probe(&engine.pos).is_hit());
+    }
+
+    #[test]
+    fn hash_option_between_moves() {
+        core::init::init_globals();
+
+        let fixture = Fixture::new();
+        let mut engine = Engine::new();
+        assert_eq!(engine.tt.capacity_mb(), HASH_DEFAULT_MB);
+
+        thread::scope(|s| {
+            fixture.send(
+                &mut engine,
+                s,
+                &[
+                    "position startpos moves e2e4",
+                    "go depth 3",
+                    "setoption name Hash value 32",
+                    "position startpos moves e2e4 e7e5",
+                    "go depth 3",
+                ],
+            );
+        });
+
+        assert_eq!(engine.tt.capacity_mb(), 32);
+        assert!(engine.tt.probe(&engine.pos).is_hit());
+    }
+
+    #[test]
+    fn setoption_updates_config() {
+        core::init::init_globals();
+
+        let mut engine = Engine::new();
+        for line in [
+            "setoption name Hash value 32",
+            "setoption name Threads value 4",
+            "setoption name MultiPV value 3",
+            "setoption name Contempt value -20",
+            "setoption name Move Overhead value 50",
+            "setoption name Ponder value true",
+        ] {
+            match uci::Parser::parse(line).unwrap() {
+                Command::SetOption(opt) => engine.set_option(opt),
+                cmd => panic!("{} parsed as {:?}", line, cmd),
+            }
+        }
+
+        assert_eq!(engine.config.hash_size(), 32);
+        assert_eq!(engine.config.threads(), 4);
+        assert_eq!(engine.config.multipv(), 3);
+        assert_eq!(engine.config.contempt(), -20);
+        assert_eq!(engine.config.move_overhead(), 50);
+        assert!(engine.config.ponder());
+        assert_eq!(engine.tt.capacity_mb(), 32);
+
+        // Out of range values are clamped, and unknown options are rejected.
+        engine.set_option(EngineOpt::Contempt(1000));
+        assert_eq!(engine.config.contempt(), 100);
+        assert!(uci::Parser::parse("setoption name Style value Risky").is_err());
+    }
+
+    #[test]
+    fn ucinewgame_clears_search_state() {
+        core::init::init_globals();
+
+        let fixture = Fixture::new();
+        let mut engine = Engine::new();
+
+        thread::scope(|s| {
+            fixture.send(&mut engine, s, &["position startpos", "go depth 6"]);
+        });
+        assert!(engine.tt.hashfull() > 0);
+        let generation = engine.tt.generation();
+
+        thread::scope(|s| {
+            fixture.send(&mut engine, s, &["ucinewgame"]);
+        });
+
+        assert_eq!(engine.tt.hashfull(), 0);
+        assert!(!engine.tt.probe(&engine.pos).is_hit());
+        assert_ne!(engine.tt.generation(), generation);
+    }
+
+    #[test]
+    fn go_keeps_and_ages_earlier_entries() {
+        core::init::init_globals();
+
+        let fixture = Fixture::new();
+        let mut engine = Engine::new();
+        let start = Position::start_pos();
+
+        thread::scope(|s| {
+            fixture.send(&mut engine, s, &["position startpos", "go depth 6"]);
+        });
+        let generation = engine.tt.generation();
+        let root = engine.tt.probe(&start).into_inner().read().clone();
+        assert_eq!(root.gen(), generation);
+
+        thread::scope(|s| {
+            fixture.send(
+                &mut engine,
+                s,
+                &["position startpos moves e2e4 e7e5", "go depth 4"],
+            );
+        });
+
+        // The second search starts a new generation, but the first search's entries survive it,
+        // stamped with the generation they were written in.
+        assert_ne!(engine.tt.generation(), generation);
+        let entry = engine.tt.probe(&engine.pos);
+        assert!(entry.is_hit());
+        assert_eq!(entry.into_inner().read().gen(), engine.tt.generation());
+
+        let old = engine.tt.probe(&start);
+        assert!(old.is_hit());
+        let old = old.into_inner();
+        assert_eq!(old.read().gen(), generation);
+        assert_eq!(old.read().depth, root.depth);
+        assert_eq!(old.read().score, root.score);
+    }
+
+    #[test]
+    fn history_decays_between_searches() {
+        core::init::init_globals();
+
+        let fixture = Fixture::new();
+        let mut engine = Engine::new();
+
+        let go = |engine: &mut Engine, depth: u8| {
+            thread::scope(|s| {
+                fixture.send(
+                    engine,
+                    s,
+                    &["position startpos", &format!("go depth {}", depth)],
+                );
+            });
+
+            let history = engine.history[0].lock().unwrap();
+            let butterfly = &history.as_ref().unwrap().butterfly;
+            let mut values = Vec::new();
+            for side in [Player::WHITE, Player::BLACK] {
+                for from in 0..64 {
+                    for to in 0..64 {
+                        values.push(butterfly.get(Square(from), Square(to), side));
+                    }
+                }
+            }
+            values
+        };
+
+        let learnt = go(&mut engine, 6);
+        assert!(learnt.iter().any(|&value| value != 0));
+
+        // A depth 1 search from the start position has no cutoffs to learn from, so all it does is
+        // halve what the first search learnt.
+        let decayed = go(&mut engine, 1);
+        let halved: Vec<i32> = learnt.iter().map(|value| value / 2).collect();
+        assert_eq!(decayed, halved);
+
+        thread::scope(|s| {
+            fixture.send(&mut engine, s, &["ucinewgame"]);
+        });
+        assert!(engine.history[0].lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn hash_option_rounds_down() {
+        core::init::init_globals();
+
+        let mut engine = Engine::new();
+        engine.set_option(EngineOpt::Hash(100));
+
+        assert_eq!(engine.tt.capacity_mb(), 64);
+        assert!(engine.tt.capacity_entries().is_power_of_two());
+    }
+
+    #[test]
+    fn searchmoves_ignores_illegal_moves() {
+        core::init::init_globals();
+
+        let engine = Engine::new();
+        let moves = ["e2e4", "e2e5", "g1f3"].map(String::from);
+        let root_moves: Vec<String> = engine
+            .resolve_search_moves(&moves)
+            .unwrap()
+            .iter()
+            .map(|mov| mov.to_uci_string())
+            .collect();
+        assert_eq!(root_moves, vec!["e2e4", "g1f3"]);
+
+        // With no legal search moves, the search is unrestricted.
+        assert!(engine.resolve_search_moves(&["e1e2".to_string()]).is_none());
+    }
+
+    #[test]
+    fn go_sets_a_deadline_from_the_clock() {
+        core::init::init_globals();
+
+        let engine = Engine::new();
+        let pos = Position::from_fen(
+            "r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N1PN2/PP3PPP/R2QKB1R b KQ - 0 25",
+        )
+        .unwrap();
+        let timing_mode = match uci::Parser::parse("go wtime 30000 btime 12000 winc 200 binc 100") {
+            Ok(Command::Go(timing_mode)) => {
+                timing_mode.with_move_overhead(engine.config.move_overhead())
+            }
+            cmd => panic!("parsed as {:?}", cmd),
+        };
+
+        // Black to move at move 25, with 12s and a 100ms increment, expects to make at least 20
+        // more moves: 12000 / 20 + 100, less the buffer time.
+        let move_time = match &timing_mode {
+            TimingMode::Timed(tc) => tc.to_move_time(pos.move_number(), pos.turn()),
+            timing_mode => panic!("{:?} isn't timed", timing_mode),
+        };
+        assert_eq!(move_time, 12_000 / 20 + 100 - 150);
+
+        let start = Instant::now();
+        assert_eq!(
+            timing_mode.stop_time(start, pos.move_number(), pos.turn()),
+            Some(start + Duration::from_millis(move_time.into()))
+        );
+    }
+
+    #[test]
+    fn ponderhit_counts_ponder_time() {
+        core::init::init_globals();
+
+        let fixture = Fixture::new();
+        let mut engine = Engine::new();
+
+        // With 10s on each clock at move 1, the engine allots roughly 100ms to the move. Pondering
+        // for longer than that leaves only the minimum time per move after the ponderhit.
+        let tc = TimeControl::new(10_000, 10_000, 0, 0, None);
+        let move_time = Duration::from_millis(tc.to_move_time(1, Player::WHITE).into());
+        let ponder_time = Duration::from_millis(300);
+        let min_time =
+            Duration::from_millis(tc.to_move_time_after(1, Player::WHITE, ponder_time).into());
+        assert!(min_time < move_time && move_time < ponder_time);
+
+        let mut ponderhit_at = None;
+        thread::scope(|s| {
+            fixture.send(
+                &mut engine,
+                s,
+                &["position startpos", "go ponder wtime 10000 btime 10000"],
+            );
+
+            // Pondering is unbounded, so the search must outlive the would-be move time.
+            thread::sleep(ponder_time);
+            assert!(fixture.deadline.get().is_none());
+
+            let t = Instant::now();
+            fixture.send(&mut engine, s, &["ponderhit"]);
+            ponderhit_at = Some(t);
+
+            let stop_time = fixture
+                .deadline
+                .get()
+                .expect("ponderhit should set a deadline");
+            assert!(stop_time >= t + min_time);
+            assert!(stop_time < t + move_time);
+        });
+
+        assert!(ponderhit_at.unwrap().elapsed() >= min_time);
+    }
+
+    #[test]
+    fn ponder_miss_gets_a_fresh_budget() {
+        core::init::init_globals();
+
+        let fixture = Fixture::new();
+        let mut engine = Engine::new();
+
+        let tc = TimeControl::new(10_000, 10_000, 0, 0, None);
+        let move_time = Duration::from_millis(tc.to_move_time(2, Player::WHITE).into());
+
+        thread::scope(|s| {
+            fixture.send(
+                &mut engine,
+                s,
+                &["position startpos", "go ponder wtime 10000 btime 10000"],
+            );
+
+            thread::sleep(Duration::from_millis(300));
+
+            // The opponent played something else, so the GUI stops the ponder search and starts
+            // a new one. None of the ponder time counts towards it.
+            fixture.send(&mut engine, s, &["stop"]);
+        });
+
+        thread::scope(|s| {
+            let t = Instant::now();
+            fixture.send(
+                &mut engine,
+                s,
+                &[
+                    "position startpos moves e2e4 c7c5",
+                    "go wtime 10000 btime 10000",
+                ],
+            );
+
+            let stop_time = fixture.deadline.get().expect("go should set a deadline");
+            assert!(stop_time >= t + move_time);
+        });
+    }
+
+    #[test]
+    fn stop_ends_ponder_search() {
+        core::init::init_globals();
+
+        let fixture = Fixture::new();
+        let mut engine = Engine::new();
+
+        let start = Instant::now();
+        thread::scope(|s| {
+            fixture.send(
+                &mut engine,
+                s,
+                &["position startpos", "go ponder wtime 10000 btime 10000"],
+            );
+
+            thread::sleep(Duration::from_millis(100));
+
+            fixture.send(&mut engine, s, &["stop"]);
+        });
+
+        // The ponder search was aborted, rather than running on to its maximum depth.
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert!(engine.ponder.is_none());
+        assert!(fixture.deadline.get().is_none());
+    }
+}
@@ -1,48 +1,619 @@
-use core::position::{PieceType, Player, Position};
+use super::kpk;
+use super::pawns;
+use super::trace::Tracer;
 
-pub const PAWN_VALUE: i16 = 100;
-pub const KNIGHT_VALUE: i16 = 300;
-pub const BISHOP_VALUE: i16 = 300;
-pub const ROOK_VALUE: i16 = 500;
-pub const QUEEN_VALUE: i16 = 900;
-pub const KING_VALUE: i16 = 10000;
+use core::bb::Bitboard;
+use core::position::{material_key_part, PieceType, Player, Position, Square, MAX_GAME_PHASE};
 
-pub const PIECE_VALUES: [i16; 7] = [
-    0, // PieceType::None,
-    PAWN_VALUE,
-    KNIGHT_VALUE,
-    BISHOP_VALUE,
-    ROOK_VALUE,
-    QUEEN_VALUE,
-    KING_VALUE,
+pub use core::position::{
+    BISHOP_VALUE, KING_VALUE, KNIGHT_VALUE, PAWN_VALUE, PIECE_VALUES, QUEEN_VALUE, ROOK_VALUE,
+};
+
+/// A piece-square table: a positional bonus for a piece on each square, from White's point of
+/// view.
+///
+/// Tables are laid out as the board is seen from White's side, with the eighth rank first, so they
+/// are flipped vertically when indexed by `Square`.
+///
+/// Each piece has a midgame and an endgame table, which are blended by the game phase. Only pawns
+/// and the king have a distinct endgame table.
+pub type PieceSquareTable = [i16; 64];
+
+#[rustfmt::skip]
+const PAWN_MG_PSQT: PieceSquareTable = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     50,  50,  50,  50,  50,  50,  50,  50,
+     10,  10,  20,  30,  30,  20,  10,  10,
+      5,   5,  10,  25,  25,  10,   5,   5,
+      0,   0,   0,  20,  20,   0,   0,   0,
+      5,  -5, -10,   0,   0, -10,  -5,   5,
+      5,  10,  10, -20, -20,  10,  10,   5,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+/// Passed pawns become more valuable as the pieces come off, so advanced pawns are rewarded more
+/// heavily in the endgame.
+#[rustfmt::skip]
+const PAWN_EG_PSQT: PieceSquareTable = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+     80,  80,  80,  80,  80,  80,  80,  80,
+     50,  50,  50,  50,  50,  50,  50,  50,
+     30,  30,  30,  30,  30,  30,  30,  30,
+     20,  20,  20,  20,  20,  20,  20,  20,
+     10,  10,  10,  10,  10,  10,  10,  10,
+     10,  10,  10,  10,  10,  10,  10,  10,
+      0,   0,   0,   0,   0,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const KNIGHT_PSQT: PieceSquareTable = [
+    -50, -40, -30, -30, -30, -30, -40, -50,
+    -40, -20,   0,   0,   0,   0, -20, -40,
+    -30,   0,  10,  15,  15,  10,   0, -30,
+    -30,   5,  15,  20,  20,  15,   5, -30,
+    -30,   0,  15,  20,  20,  15,   0, -30,
+    -30,   5,  10,  15,  15,  10,   5, -30,
+    -40, -20,   0,   5,   5,   0, -20, -40,
+    -50, -40, -30, -30, -30, -30, -40, -50,
+];
+
+#[rustfmt::skip]
+const BISHOP_PSQT: PieceSquareTable = [
+    -20, -10, -10, -10, -10, -10, -10, -20,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -10,   0,   5,  10,  10,   5,   0, -10,
+    -10,   5,   5,  10,  10,   5,   5, -10,
+    -10,   0,  10,  10,  10,  10,   0, -10,
+    -10,  10,  10,  10,  10,  10,  10, -10,
+    -10,   5,   0,   0,   0,   0,   5, -10,
+    -20, -10, -10, -10, -10, -10, -10, -20,
+];
+
+#[rustfmt::skip]
+const ROOK_PSQT: PieceSquareTable = [
+      0,   0,   0,   0,   0,   0,   0,   0,
+      5,  10,  10,  10,  10,  10,  10,   5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+     -5,   0,   0,   0,   0,   0,   0,  -5,
+      0,   0,   0,   5,   5,   0,   0,   0,
+];
+
+#[rustfmt::skip]
+const QUEEN_PSQT: PieceSquareTable = [
+    -20, -10, -10,  -5,  -5, -10, -10, -20,
+    -10,   0,   0,   0,   0,   0,   0, -10,
+    -10,   0,   5,   5,   5,   5,   0, -10,
+     -5,   0,   5,   5,   5,   5,   0,  -5,
+      0,   0,   5,   5,   5,   5,   0,  -5,
+    -10,   5,   5,   5,   5,   5,   0, -10,
+    -10,   0,   5,   0,   0,   0,   0, -10,
+    -20, -10, -10,  -5,  -5, -10, -10, -20,
+];
+
+/// In the midgame the king should stay sheltered behind its pawns.
+#[rustfmt::skip]
+const KING_MG_PSQT: PieceSquareTable = [
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -30, -40, -40, -50, -50, -40, -40, -30,
+    -20, -30, -30, -40, -40, -30, -30, -20,
+    -10, -20, -20, -20, -20, -20, -20, -10,
+     20,  20,   0,   0,   0,   0,  20,  20,
+     20,  30,  10,   0,   0,  10,  30,  20,
+];
+
+/// In the endgame there is little danger of mate, and the king should head for the centre.
+#[rustfmt::skip]
+const KING_EG_PSQT: PieceSquareTable = [
+    -50, -40, -30, -20, -20, -30, -40, -50,
+    -30, -20, -10,   0,   0, -10, -20, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  30,  40,  40,  30, -10, -30,
+    -30, -10,  20,  30,  30,  20, -10, -30,
+    -30, -30,   0,   0,   0,   0, -30, -30,
+    -50, -30, -30, -30, -30, -30, -30, -50,
+];
+
+/// The midgame piece-square tables, indexed by `PieceType`.
+const MG_PSQT: [PieceSquareTable; 7] = [
+    [0; 64], // PieceType::None
+    PAWN_MG_PSQT,
+    KNIGHT_PSQT,
+    BISHOP_PSQT,
+    ROOK_PSQT,
+    QUEEN_PSQT,
+    KING_MG_PSQT,
 ];
 
+/// The endgame piece-square tables, indexed by `PieceType`.
+const EG_PSQT: [PieceSquareTable; 7] = [
+    [0; 64], // PieceType::None
+    PAWN_EG_PSQT,
+    KNIGHT_PSQT,
+    BISHOP_PSQT,
+    ROOK_PSQT,
+    QUEEN_PSQT,
+    KING_EG_PSQT,
+];
+
+/// Bonus for having both bishops.
+const BISHOP_PAIR: i16 = 30;
+
+/// Penalty for having a second rook, which duplicates much of what the first one does.
+const ROOK_REDUNDANCY: i16 = -10;
+
+/// Adjustment to each knight's value per friendly pawn more or fewer than five. Knights thrive in
+/// closed positions with many pawns, and rooks in open ones with few.
+const KNIGHT_PAWN_ADJUSTMENT: i16 = 4;
+
+/// Adjustment to each rook's value per friendly pawn more or fewer than five.
+const ROOK_PAWN_ADJUSTMENT: i16 = -8;
+
+/// The score of a position known to be won, less any adjustments to encourage progress. This is
+/// more than any material advantage the search could otherwise expect in such an endgame.
+pub const KNOWN_WIN: i16 = 1000;
+
+/// The endgame scale factor of an ordinary position, which leaves the endgame evaluation as it is.
+pub const SCALE_NORMAL: i32 = 64;
+
+/// The endgame scale factor when the only pieces besides pawns are a bishop each, on opposite
+/// coloured squares. Each side can blockade the other's pawns on the squares the enemy bishop
+/// can't reach, so an extra pawn or two is often not enough to win.
+const SCALE_OPPOSITE_BISHOPS: i32 = SCALE_NORMAL / 2;
+
+/// The half move clock, in plies, beyond which the evaluation is scaled towards a draw.
+const FIFTY_MOVE_SCALE_START: u32 = 20;
+
+/// The half move clock, in plies, at which the fifty-move rule makes the game a draw.
+const FIFTY_MOVE_LIMIT: u32 = 100;
+
+/// The number of entries in the pawn hash table.
+pub const PAWN_HASH_ENTRIES: usize = 4096;
+
+#[derive(Clone, Copy, Default)]
+struct PawnHashEntry {
+    key: u64,
+    mg: i16,
+    eg: i16,
+}
+
+/// A direct-mapped cache of pawn structure scores, keyed by `Position::pawn_key`.
+///
+/// The midgame and endgame scores are cached separately, and tapered on the way out, because the
+/// game phase depends on pieces which the pawn key doesn't hash.
+pub struct PawnHashTable {
+    entries: Box<[PawnHashEntry]>,
+}
+
+impl PawnHashTable {
+    pub fn new() -> Self {
+        Self {
+            entries: vec![PawnHashEntry::default(); PAWN_HASH_ENTRIES].into_boxed_slice(),
+        }
+    }
+
+    /// The pawn structure evaluation of `pos`, from White's point of view. This is taken from the
+    /// table if possible, and otherwise computed and stored.
+    pub fn pawn_structure(&mut self, pos: &Position, trace: &mut Tracer) -> i16 {
+        let key = pos.pawn_key();
+        let entry = &mut self.entries[key as usize % PAWN_HASH_ENTRIES];
+
+        if entry.key == key {
+            trace.pawn_hash_hit();
+        } else {
+            trace.pawn_hash_miss();
+            let (mg, eg) = pawns::pawn_structure_terms(pos);
+            *entry = PawnHashEntry {
+                key,
+                mg: mg as i16,
+                eg: eg as i16,
+            };
+        }
+
+        taper(entry.mg as i32, entry.eg as i32, pos.game_phase()) as i16
+    }
+}
+
+impl Default for PawnHashTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// Adds static evaluation functionality to a type representing a chess position.
 pub trait Evaluation {
-    /// Simple material evaluation
-    fn material_eval(&self) -> i16;
+    /// The material of the given player, not counting pawns or the king.
+    fn non_pawn_material(&self, player: Player) -> i16;
+
+    /// Positional evaluation from the piece-square tables, from White's point of view. The midgame
+    /// and endgame tables are blended according to the game phase.
+    fn psqt_eval(&self) -> i16;
+
+    /// Evaluation of doubled, isolated and passed pawns, from White's point of view.
+    fn pawn_structure_eval(&self) -> i16;
+
+    /// Corrections to the plain material count for the combination of pieces on the board, from
+    /// White's point of view.
+    fn imbalance_eval(&self) -> i16;
+
+    /// The evaluation of a recognised endgame, from White's point of view, which replaces the
+    /// general evaluation. `None` if the endgame isn't recognised.
+    fn endgame_eval(&self) -> Option<i16>;
+
+    /// The factor, out of `SCALE_NORMAL`, by which the endgame part of the evaluation should be
+    /// scaled in material configurations which are harder to win than the material suggests.
+    fn endgame_scale(&self) -> i32;
 }
 
 impl Evaluation for Position {
-    fn material_eval(&self) -> i16 {
-        material_evaluation(self)
+    fn non_pawn_material(&self, player: Player) -> i16 {
+        self.piece_bb(player, PieceType::Knight).popcnt() as i16 * KNIGHT_VALUE
+            + self.piece_bb(player, PieceType::Bishop).popcnt() as i16 * BISHOP_VALUE
+            + self.piece_bb(player, PieceType::Rook).popcnt() as i16 * ROOK_VALUE
+            + self.piece_bb(player, PieceType::Queen).popcnt() as i16 * QUEEN_VALUE
+    }
+
+    fn psqt_eval(&self) -> i16 {
+        psqt_evaluation(self)
     }
-}
 
-fn material_evaluation(pos: &Position) -> i16 {
-    pos.piece_bb(Player::WHITE, PieceType::Pawn).popcnt() as i16 * PAWN_VALUE
-        + pos.piece_bb(Player::WHITE, PieceType::Knight).popcnt() as i16 * KNIGHT_VALUE
-        + pos.piece_bb(Player::WHITE, PieceType::Bishop).popcnt() as i16 * BISHOP_VALUE
-        + pos.piece_bb(Player::WHITE, PieceType::Rook).popcnt() as i16 * ROOK_VALUE
-        + pos.piece_bb(Player::WHITE, PieceType::Queen).popcnt() as i16 * QUEEN_VALUE
-        - pos.piece_bb(Player::BLACK, PieceType::Pawn).popcnt() as i16 * PAWN_VALUE
-        - pos.piece_bb(Player::BLACK, PieceType::Knight).popcnt() as i16 * KNIGHT_VALUE
-        - pos.piece_bb(Player::BLACK, PieceType::Bishop).popcnt() as i16 * BISHOP_VALUE
-        - pos.piece_bb(Player::BLACK, PieceType::Rook).popcnt() as i16 * ROOK_VALUE
-        - pos.piece_bb(Player::BLACK, PieceType::Queen).popcnt() as i16 * QUEEN_VALUE
+    fn pawn_structure_eval(&self) -> i16 {
+        pawns::pawn_structure(self)
+    }
+
+    fn imbalance_eval(&self) -> i16 {
+        imbalance(self, Player::WHITE) - imbalance(self, Player::BLACK)
+    }
+
+    fn endgame_eval(&self) -> Option<i16> {
+        if self.material_key() == material_key_part(Player::WHITE, PieceType::Pawn, 1) {
+            Some(kpk_evaluation(self, Player::WHITE))
+        } else if self.material_key() == material_key_part(Player::BLACK, PieceType::Pawn, 1) {
+            Some(-kpk_evaluation(self, Player::BLACK))
+        } else {
+            None
+        }
+    }
+
+    fn endgame_scale(&self) -> i32 {
+        let lone_bishop = |player| {
+            self.piece_bb(player, PieceType::Bishop).popcnt() == 1
+                && self.non_pawn_material(player) == BISHOP_VALUE
+        };
+
+        if lone_bishop(Player::WHITE) && lone_bishop(Player::BLACK) {
+            let bishops = self.piece_bb_both_players(PieceType::Bishop);
+            if (bishops & Bitboard::DARK_SQUARES).popcnt() == 1 {
+                return SCALE_OPPOSITE_BISHOPS;
+            }
+        }
+
+        SCALE_NORMAL
+    }
 }
 
 /// The material evaluation of `PieceType`.
 pub fn piece_value(piece_type: PieceType) -> i16 {
     unsafe { *PIECE_VALUES.get_unchecked(piece_type as usize) }
 }
+
+/// The midgame and endgame piece-square table bonuses for a piece of type `piece_type` belonging
+/// to `player` on `sq`.
+pub fn psqt_value(player: Player, piece_type: PieceType, sq: Square) -> (i16, i16) {
+    let idx = (player.relative_square(sq).0 ^ 56) as usize;
+    (
+        MG_PSQT[piece_type as usize][idx],
+        EG_PSQT[piece_type as usize][idx],
+    )
+}
+
+/// Blend a midgame and an endgame score by the game `phase`, which runs from `MAX_GAME_PHASE`
+/// (pure midgame) down to 0 (pure endgame).
+pub fn taper(mg: i32, eg: i32, phase: i32) -> i32 {
+    (mg * phase + eg * (MAX_GAME_PHASE - phase)) / MAX_GAME_PHASE
+}
+
+/// Scale the endgame part of `eval` by `scale`, out of `SCALE_NORMAL`. The evaluation isn't kept
+/// split into midgame and endgame parts, so the whole of it is scaled in proportion to how far the
+/// game `phase` is into the endgame.
+pub fn scale_eval(eval: i32, scale: i32, phase: i32) -> i32 {
+    eval * taper(SCALE_NORMAL, scale, phase) / SCALE_NORMAL
+}
+
+/// Scale `eval` towards 0 once the `half_move_clock` has passed `FIFTY_MOVE_SCALE_START`, reaching 0
+/// when the fifty-move rule would draw the game. An advantage counts for less as that draw nears,
+/// so this encourages the winning side to push a pawn or make a capture.
+pub fn fifty_move_scale(eval: i32, half_move_clock: u32) -> i32 {
+    match half_move_clock {
+        hmc if hmc <= FIFTY_MOVE_SCALE_START => eval,
+        hmc => {
+            let remaining = FIFTY_MOVE_LIMIT - std::cmp::min(hmc, FIFTY_MOVE_LIMIT);
+            eval * remaining as i32 / (FIFTY_MOVE_LIMIT - FIFTY_MOVE_SCALE_START) as i32
+        }
+    }
+}
+
+fn psqt_evaluation(pos: &Position) -> i16 {
+    const PIECES: [PieceType; 6] = [
+        PieceType::Pawn,
+        PieceType::Knight,
+        PieceType::Bishop,
+        PieceType::Rook,
+        PieceType::Queen,
+        PieceType::King,
+    ];
+
+    let (mut mg, mut eg) = (0, 0);
+
+    for piece_type in PIECES {
+        for sq in pos.piece_bb(Player::WHITE, piece_type) {
+            let (m, e) = psqt_value(Player::WHITE, piece_type, sq);
+            mg += m as i32;
+            eg += e as i32;
+        }
+        for sq in pos.piece_bb(Player::BLACK, piece_type) {
+            let (m, e) = psqt_value(Player::BLACK, piece_type, sq);
+            mg -= m as i32;
+            eg -= e as i32;
+        }
+    }
+
+    taper(mg, eg, pos.game_phase()) as i16
+}
+
+/// The evaluation of a king and pawn versus king endgame from the bitbase, from the point of view
+/// of `strong`, the side with the pawn. Wins are scored higher the further the pawn has advanced.
+fn kpk_evaluation(pos: &Position, strong: Player) -> i16 {
+    let pawn = pos.piece_bb(strong, PieceType::Pawn).to_square();
+    let wins = kpk::probe(
+        strong,
+        pos.king_sq(strong),
+        pawn,
+        pos.king_sq(strong.other_player()),
+        pos.turn() == strong,
+    );
+
+    match wins {
+        true => KNOWN_WIN + PAWN_VALUE + 10 * strong.relative_rank(pawn.rank()) as i16,
+        false => 0,
+    }
+}
+
+/// The material imbalance adjustments for `player`'s pieces.
+fn imbalance(pos: &Position, player: Player) -> i16 {
+    let count = |piece_type| pos.piece_bb(player, piece_type).popcnt() as i16;
+    let pawns_over_five = count(PieceType::Pawn) - 5;
+    let mut score = 0;
+
+    if count(PieceType::Bishop) >= 2 {
+        score += BISHOP_PAIR;
+    }
+
+    if count(PieceType::Rook) >= 2 {
+        score += ROOK_REDUNDANCY;
+    }
+
+    score += count(PieceType::Knight) * pawns_over_five * KNIGHT_PAWN_ADJUSTMENT;
+    score += count(PieceType::Rook) * pawns_over_five * ROOK_PAWN_ADJUSTMENT;
+
+    score
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::position::START_POSITION;
+
+    #[test]
+    fn start_position_is_balanced() {
+        core::init::init_globals();
+
+        let pos = Position::from_fen(START_POSITION).unwrap();
+        assert_eq!(pos.psqt_eval(), 0);
+    }
+
+    #[test]
+    fn centralised_knight_beats_rim_knight() {
+        core::init::init_globals();
+
+        let centre = Position::from_fen("4k3/8/8/8/3N4/8/8/4K3 w - - 0 1").unwrap();
+        let rim = Position::from_fen("4k3/8/8/8/N7/8/8/4K3 w - - 0 1").unwrap();
+        assert!(centre.psqt_eval() > rim.psqt_eval());
+
+        // The tables are mirrored for Black.
+        let black = Position::from_fen("4k3/8/8/3n4/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(black.psqt_eval(), -centre.psqt_eval());
+    }
+
+    #[test]
+    fn tapers_by_game_phase() {
+        assert_eq!(taper(30, -50, MAX_GAME_PHASE), 30);
+        assert_eq!(taper(30, -50, 0), -50);
+        assert_eq!(taper(30, -50, MAX_GAME_PHASE / 2), -10);
+    }
+
+    #[test]
+    fn king_centralisation_depends_on_phase() {
+        core::init::init_globals();
+
+        // With all the pieces on, a king in the centre is exposed.
+        let home = Position::from_fen(START_POSITION).unwrap();
+        let centre =
+            Position::from_fen("rnbqkbnr/pppppppp/8/8/4K3/8/PPPPPPPP/RNBQ1BNR w kq - 0 1").unwrap();
+        assert!(centre.psqt_eval() < home.psqt_eval());
+
+        // With only kings and pawns, it belongs in the centre.
+        let home = Position::from_fen("4k3/pppppppp/8/8/8/8/PPPPPPPP/4K3 w - - 0 1").unwrap();
+        let centre = Position::from_fen("4k3/pppppppp/8/8/4K3/8/PPPPPPPP/8 w - - 0 1").unwrap();
+        assert!(centre.psqt_eval() > home.psqt_eval());
+    }
+
+    #[test]
+    fn bishop_pair_beats_bishop_and_knight() {
+        core::init::init_globals();
+
+        let pair = Position::from_fen("2bnk3/pppppppp/8/8/8/8/PPPPPPPP/2B1KB2 w - - 0 1").unwrap();
+        let mixed = Position::from_fen("2bnk3/pppppppp/8/8/8/8/PPPPPPPP/2BNK3 w - - 0 1").unwrap();
+
+        assert_eq!(pair.material_eval(), mixed.material_eval());
+        assert!(
+            pair.material_eval() + pair.imbalance_eval()
+                > mixed.material_eval() + mixed.imbalance_eval()
+        );
+        assert!(pair.imbalance_eval() > 0);
+
+        // Bishop and knight each is level; the bishop pair is mirrored for Black.
+        assert_eq!(mixed.imbalance_eval(), 0);
+        let black = Position::from_fen("2b1kb2/pppppppp/8/8/8/8/PPPPPPPP/2BNK3 w - - 0 1").unwrap();
+        assert!(black.imbalance_eval() < 0);
+    }
+
+    #[test]
+    fn knights_and_rooks_depend_on_pawn_count() {
+        core::init::init_globals();
+
+        // With many pawns on the board, the knight is the better piece; with few, the rook.
+        let closed = Position::from_fen("3rk3/pppppppp/8/8/8/8/PPPPPPPP/3NK3 w - - 0 1").unwrap();
+        let open = Position::from_fen("3rk3/pp6/8/8/8/8/PP6/3NK3 w - - 0 1").unwrap();
+        assert!(closed.imbalance_eval() > open.imbalance_eval());
+    }
+
+    #[test]
+    fn pawn_hash_matches_uncached_evaluation() {
+        use core::mono_traits::{All, Legal};
+        use core::movelist::BasicMoveList;
+        use rand::rngs::StdRng;
+        use rand::seq::SliceRandom;
+        use rand::{Rng, SeedableRng};
+
+        core::init::init_globals();
+
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let mut table = PawnHashTable::new();
+        let mut trace = Tracer::new();
+        let mut pos = Position::from_fen(START_POSITION).unwrap();
+
+        for _ in 0..2000 {
+            assert_eq!(
+                table.pawn_structure(&pos, &mut trace),
+                pos.pawn_structure_eval(),
+                "{}",
+                pos.to_fen()
+            );
+
+            // Step back now and then, so that pawn structures are revisited.
+            if !pos.history().is_empty() && rng.gen_bool(0.3) {
+                pos.unmake_move();
+                continue;
+            }
+
+            let moves = pos.generate::<BasicMoveList, All, Legal>();
+            let pawn_moves: Vec<_> = moves
+                .iter()
+                .filter(|m| pos.piece_at_sq(m.orig()).type_of() == PieceType::Pawn)
+                .copied()
+                .collect();
+
+            let mov = if pawn_moves.is_empty() {
+                moves.choose(&mut rng).copied()
+            } else {
+                Some(pawn_moves[rng.gen_range(0..pawn_moves.len())])
+            };
+
+            match mov {
+                Some(mov) => pos.make_move(&mov),
+                None => pos = Position::from_fen(START_POSITION).unwrap(),
+            }
+        }
+
+        assert!(trace.pawn_hash_hits() > 0);
+        assert_eq!(trace.pawn_hash_probes(), 2000);
+    }
+
+    #[test]
+    fn recognises_kpk() {
+        core::init::init_globals();
+
+        let won = Position::from_fen("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1").unwrap();
+        assert!(won.endgame_eval().unwrap() > KNOWN_WIN);
+        let drawn = Position::from_fen("8/4k3/8/4K3/4P3/8/8/8 w - - 0 1").unwrap();
+        assert_eq!(drawn.endgame_eval(), Some(0));
+        let black = Position::from_fen("8/8/8/8/4p3/4k3/8/4K3 w - - 0 1").unwrap();
+        assert!(black.endgame_eval().unwrap() < -KNOWN_WIN);
+
+        let other = Position::from_fen("4k3/8/4K3/4P3/8/8/8/7R b - - 0 1").unwrap();
+        assert_eq!(other.endgame_eval(), None);
+    }
+
+    #[test]
+    fn opposite_coloured_bishops_are_drawish() {
+        core::init::init_globals();
+
+        let mut table = PawnHashTable::new();
+        let mut trace = Tracer::new();
+        let mut eval = |pos: &Position| {
+            pos.material_eval() as i32
+                + pos.psqt_eval() as i32
+                + table.pawn_structure(pos, &mut trace) as i32
+                + pos.imbalance_eval() as i32
+        };
+
+        // White is a pawn up in both. The bishops on c8 and f8 are worth the same to the tables.
+        let opposite = Position::from_fen("2b1k3/5pp1/8/8/8/8/4PPP1/2B1K3 w - - 0 1").unwrap();
+        let same = Position::from_fen("4kb2/5pp1/8/8/8/8/4PPP1/2B1K3 w - - 0 1").unwrap();
+        assert_eq!(eval(&opposite), eval(&same));
+
+        assert_eq!(opposite.endgame_scale(), SCALE_OPPOSITE_BISHOPS);
+        assert_eq!(same.endgame_scale(), SCALE_NORMAL);
+        assert_eq!(
+            opposite.flip_colors().endgame_scale(),
+            SCALE_OPPOSITE_BISHOPS
+        );
+
+        let scaled = |pos: &Position, eval| scale_eval(eval, pos.endgame_scale(), pos.game_phase());
+        let opposite_eval = scaled(&opposite, eval(&opposite));
+        let same_eval = scaled(&same, eval(&same));
+        assert!(opposite_eval > 0);
+        assert!(opposite_eval < same_eval * 2 / 3);
+
+        // With a knight as well, the bishops no longer make the ending drawish.
+        let knight = Position::from_fen("2b1k3/5pp1/8/8/8/8/4PPP1/2BNK3 w - - 0 1").unwrap();
+        assert_eq!(knight.endgame_scale(), SCALE_NORMAL);
+    }
+
+    #[test]
+    fn evaluation_is_antisymmetric() {
+        use core::mono_traits::{All, Legal};
+        use core::movelist::BasicMoveList;
+        use rand::rngs::StdRng;
+        use rand::seq::SliceRandom;
+        use rand::SeedableRng;
+
+        core::init::init_globals();
+
+        let mut rng = StdRng::seed_from_u64(0x5eed);
+        let eval = |pos: &Position| pos.material_eval() + pos.psqt_eval();
+        let mut pos = Position::from_fen(START_POSITION).unwrap();
+
+        for _ in 0..1000 {
+            let flipped = pos.flip_colors();
+            assert_eq!(eval(&flipped), -eval(&pos), "{}", pos.to_fen());
+            assert_eq!(
+                flipped.pawn_structure_eval(),
+                -pos.pawn_structure_eval(),
+                "{}",
+                pos.to_fen()
+            );
+
+            match pos.generate::<BasicMoveList, All, Legal>().choose(&mut rng) {
+                Some(mov) => pos.make_move(mov),
+                None => pos = Position::from_fen(START_POSITION).unwrap(),
+            }
+        }
+    }
+}
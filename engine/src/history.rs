@@ -1,6 +1,6 @@
 //! History tables.
 
-use core::position::{Player, Square};
+use core::position::{Piece, Player, Square};
 
 /// Butterfly boards.
 ///
@@ -64,6 +64,13 @@ where
     }
 }
 
+impl<T> Butterfly<T> {
+    /// An iterator over mutable references to every entry on the board.
+    pub fn values_mut(&mut self) -> impl Iterator<Item = &mut T> {
+        self.data.iter_mut().flatten()
+    }
+}
+
 impl<T> Butterfly<T>
 where
     T: Copy,
@@ -104,14 +111,22 @@ where
     }
 }
 
-/// A structure storing two butterfly tables of `u32`s, used to record the history value of moves
+/// The bound on the magnitude of history values. Values approach this under repeated bonuses, but
+/// never reach it, so they always fit in the `i16` used to score moves.
+pub const HISTORY_MAX: i32 = 16_384;
+
+/// A structure storing two butterfly tables of `i32`s, used to record the history value of moves
 /// during search.
 ///
+/// Entries are indexed by the move's origin square, its destination square and the side which made
+/// it, `(orig, dest, turn)`. The same from-to pair is scored separately for each side, since a
+/// move which is good for White says nothing about the same squares for Black.
+///
 /// This data structure occupies about 32KB of memory.
 #[derive(Debug)]
 pub struct HistoryTable {
-    white: Butterfly<u32>,
-    black: Butterfly<u32>,
+    white: Butterfly<i32>,
+    black: Butterfly<i32>,
 }
 
 impl HistoryTable {
@@ -122,31 +137,61 @@ impl HistoryTable {
         }
     }
 
-    pub fn inc(&mut self, from: Square, to: Square, amt: u32, side: Player) {
-        match side {
-            Player::WHITE => self.white.inc(from, to, amt),
-            Player::BLACK => self.black.inc(from, to, amt),
-        }
+    /// Apply `bonus` to the move from `from` to `to` by `side`. A positive bonus rewards the move,
+    /// and a negative one penalises it.
+    ///
+    /// The update is scaled by how far the current value is from the bound (`history += bonus -
+    /// history * |bonus| / HISTORY_MAX`), so repeated bonuses converge on `HISTORY_MAX` rather than
+    /// overflowing, and a value which has grown large is quick to fall back when the move stops
+    /// working.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if the squares passed are not valid squares, or if `|bonus|` exceeds
+    /// `HISTORY_MAX`.
+    pub fn update(&mut self, from: Square, to: Square, bonus: i32, side: Player) {
+        assert!(bonus.abs() <= HISTORY_MAX);
+
+        let table = match side {
+            Player::WHITE => &mut self.white,
+            Player::BLACK => &mut self.black,
+        };
+
+        let value = table.get(from, to);
+        table.inc(from, to, bonus - value * bonus.abs() / HISTORY_MAX);
     }
 
-    pub fn get(&self, from: Square, to: Square, side: Player) -> u32 {
+    /// Get the history value of the move `from`-`to` for `side`.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if either square is not a valid square.
+    pub fn get(&self, from: Square, to: Square, side: Player) -> i32 {
         match side {
             Player::WHITE => self.white.get(from, to),
             Player::BLACK => self.black.get(from, to),
         }
     }
 
-    pub unsafe fn inc_unchecked(&mut self, from: Square, to: Square, amt: u32, side: Player) {
+    /// Get the history value of the move `from`-`to` for `side`, without checking the squares in
+    /// release mode. See [`Butterfly::get_unchecked`].
+    ///
+    /// # Safety
+    ///
+    /// Both squares must be valid squares (i.e. have value < 64). Otherwise, the read is out of
+    /// bounds in release mode.
+    pub unsafe fn get_unchecked(&self, from: Square, to: Square, side: Player) -> i32 {
         match side {
-            Player::WHITE => self.white.inc_unchecked(from, to, amt),
-            Player::BLACK => self.black.inc_unchecked(from, to, amt),
+            Player::WHITE => self.white.get_unchecked(from, to),
+            Player::BLACK => self.black.get_unchecked(from, to),
         }
     }
 
-    pub unsafe fn get_unchecked(&self, from: Square, to: Square, side: Player) -> u32 {
-        match side {
-            Player::WHITE => self.white.get_unchecked(from, to),
-            Player::BLACK => self.black.get_unchecked(from, to),
+    /// Halve every entry, so that what was learnt earlier counts for less than what is learnt
+    /// from here on.
+    pub fn decay(&mut self) {
+        for value in self.white.values_mut().chain(self.black.values_mut()) {
+            *value /= 2;
         }
     }
 
@@ -155,3 +200,167 @@ impl HistoryTable {
         *self = Self::new()
     }
 }
+
+impl Default for HistoryTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The piece which made a move and the square it moved to. Continuation history is indexed by two
+/// of these: one for an earlier move in the line and one for the move being scored.
+pub type PieceTo = (Piece, Square);
+
+/// The number of entries in each dimension of a `ContinuationHistory`: one per piece (including
+/// `Piece::None`) and destination square.
+const PIECE_TO: usize = 13 * 64;
+
+/// Continuation history: the history of a move, given an earlier move in the line.
+///
+/// Where the butterfly `HistoryTable` asks "is this move good?", this asks "is this move good in
+/// reply to that one?". Entries are indexed by the piece and destination square of the earlier
+/// move and of the move being scored, so the table is shared by both sides; the piece says whose
+/// move it is. A table keyed on the opponent's last move scores natural replies to it, and one
+/// keyed on our own previous move scores natural follow-ups.
+///
+/// This data structure occupies about 2.7MB of memory, so it lives on the heap.
+#[derive(Debug)]
+pub struct ContinuationHistory {
+    data: Box<[i32]>,
+}
+
+impl ContinuationHistory {
+    pub fn new() -> Self {
+        ContinuationHistory {
+            data: vec![0; PIECE_TO * PIECE_TO].into_boxed_slice(),
+        }
+    }
+
+    #[inline(always)]
+    fn index(prev: PieceTo, curr: PieceTo) -> usize {
+        debug_assert!(prev.1.is_okay());
+        debug_assert!(curr.1.is_okay());
+
+        let prev = prev.0 as usize * 64 + prev.1 .0 as usize;
+        let curr = curr.0 as usize * 64 + curr.1 .0 as usize;
+        prev * PIECE_TO + curr
+    }
+
+    /// Apply `bonus` to the move `curr` played after `prev`, with the same scaling towards
+    /// `HISTORY_MAX` as `HistoryTable::update`.
+    ///
+    /// # Panics
+    ///
+    /// This method will panic if `|bonus|` exceeds `HISTORY_MAX`.
+    pub fn update(&mut self, prev: PieceTo, curr: PieceTo, bonus: i32) {
+        assert!(bonus.abs() <= HISTORY_MAX);
+
+        let value = &mut self.data[Self::index(prev, curr)];
+        *value += bonus - *value * bonus.abs() / HISTORY_MAX;
+    }
+
+    pub fn get(&self, prev: PieceTo, curr: PieceTo) -> i32 {
+        self.data[Self::index(prev, curr)]
+    }
+
+    /// Halve every entry, so that what was learnt earlier counts for less than what is learnt
+    /// from here on.
+    pub fn decay(&mut self) {
+        for value in self.data.iter_mut() {
+            *value /= 2;
+        }
+    }
+
+    /// Reset the table to zeros.
+    pub fn reset(&mut self) {
+        self.data.fill(0);
+    }
+}
+
+impl Default for ContinuationHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The history tables of one search thread. The engine keeps these between searches, so that what
+/// a thread learnt while searching one move still guides its move ordering on the next.
+#[derive(Debug, Default)]
+pub struct Histories {
+    pub butterfly: HistoryTable,
+    pub continuation: ContinuationHistory,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn repeated_bonuses_converge() {
+        let mut history = HistoryTable::new();
+
+        let mut last = 0;
+        for _ in 0..10_000 {
+            history.update(Square::E2, Square::E4, HISTORY_MAX / 4, Player::WHITE);
+            let value = history.get(Square::E2, Square::E4, Player::WHITE);
+            assert!(value >= last);
+            assert!(value <= HISTORY_MAX);
+            last = value;
+        }
+        assert!(last > HISTORY_MAX * 9 / 10);
+
+        for _ in 0..10_000 {
+            history.update(Square::E2, Square::E4, -HISTORY_MAX / 4, Player::WHITE);
+        }
+        let value = history.get(Square::E2, Square::E4, Player::WHITE);
+        assert!((-HISTORY_MAX..-HISTORY_MAX * 9 / 10).contains(&value));
+
+        // The other side's entry is untouched.
+        assert_eq!(history.get(Square::E2, Square::E4, Player::BLACK), 0);
+    }
+
+    #[test]
+    fn decay_halves_values() {
+        let mut history = HistoryTable::new();
+        history.update(Square::G1, Square::F3, 1000, Player::WHITE);
+        history.update(Square::G8, Square::F6, -1000, Player::BLACK);
+
+        history.decay();
+        assert_eq!(history.get(Square::G1, Square::F3, Player::WHITE), 500);
+        assert_eq!(history.get(Square::G8, Square::F6, Player::BLACK), -500);
+    }
+
+    #[test]
+    fn continuation_history_is_keyed_on_both_moves() {
+        let mut cont = ContinuationHistory::new();
+        let prev = (Piece::BlackKnight, Square::F6);
+        let reply = (Piece::WhitePawn, Square::E5);
+        cont.update(prev, reply, 1000);
+
+        assert_eq!(cont.get(prev, reply), 1000);
+        // The same reply to a different move, and a different reply to the same move, are
+        // scored separately.
+        assert_eq!(cont.get((Piece::BlackBishop, Square::F6), reply), 0);
+        assert_eq!(cont.get(prev, (Piece::WhiteKnight, Square::E5)), 0);
+
+        cont.decay();
+        assert_eq!(cont.get(prev, reply), 500);
+        cont.reset();
+        assert_eq!(cont.get(prev, reply), 0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn out_of_range_squares_are_caught() {
+        HistoryTable::new().get(Square(64), Square::E4, Player::WHITE);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn out_of_range_squares_are_caught_unchecked_in_debug() {
+        unsafe {
+            HistoryTable::new().get_unchecked(Square::E2, Square(64), Player::BLACK);
+        }
+    }
+}
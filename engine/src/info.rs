@@ -7,6 +7,8 @@ use core::mov::Move;
 pub enum Info {
     Pv(PvInfo),
     CurrMove(CurrMoveInfo),
+    /// Free text for the GUI to display, such as diagnostics in debug mode.
+    String(String),
 }
 
 impl std::fmt::Display for Info {
@@ -15,6 +17,7 @@ impl std::fmt::Display for Info {
         match self {
             Pv(i) => i.fmt(f),
             CurrMove(i) => i.fmt(f),
+            String(s) => write!(f, "info string {}", s),
         }
     }
 }
@@ -25,25 +28,30 @@ impl std::fmt::Display for Info {
 #[derive(Debug)]
 pub struct PvInfo {
     pub(super) depth: u8,
+    /// The furthest distance from the root reached, in plies, including quiescence.
+    pub(super) seldepth: u8,
+    /// The 1-indexed rank of this line, when reporting multiple principal variations.
+    pub(super) multipv: usize,
     pub(super) time: usize,
     pub(super) nodes: usize,
     pub(super) pv: String,
     pub(super) score: Score,
     pub(super) hashfull: u16,
     pub(super) nps: u32,
+    pub(super) tbhits: usize,
 }
 
 impl std::fmt::Display for PvInfo {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "info ")?;
         write!(f, "depth {} ", self.depth)?;
-        // write!(f, "seldepth {}", self.seldepth)?; // TODO
-        write!(f, "multipv 1 ")?; // TODO: we don't have an option to send further PVs, so always
-                                  // send this.
-        write!(f, "score {} ", self.score)?;
+        write!(f, "seldepth {} ", self.seldepth)?;
+        write!(f, "multipv {} ", self.multipv)?;
+        write!(f, "score {} ", self.score.to_uci_string())?;
         write!(f, "nodes {} ", self.nodes)?;
         write!(f, "nps {} ", self.nps)?;
         write!(f, "hashfull {} ", self.hashfull)?;
+        write!(f, "tbhits {} ", self.tbhits)?;
         write!(f, "time {} ", self.time)?;
         write!(f, "pv {}", self.pv)
     }
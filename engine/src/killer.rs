@@ -24,7 +24,8 @@ impl Default for Entry {
 }
 
 impl KillerTable {
-    // Create a new `KillerTable`, initialized to `size` plies.
+    /// Create a new `KillerTable`, with room for killers up to `size` plies from the root. This
+    /// should be the maximum search depth; killers further from the root are not stored.
     pub fn new(size: usize) -> Self {
         let mut data = Vec::with_capacity(size);
         data.resize(size, Default::default());
@@ -60,18 +61,27 @@ impl KillerTable {
         (ret1.0, ret2.0)
     }
 
-    /// Store a killer move for a given draft (number of ply down from the root). This function
-    /// does not accept `draft == 0`, since we do not have killer moves at the root node.
-    pub fn store(&mut self, killer: Move, draft: u8) {
-        debug_assert!(draft > 0);
+    /// Whether `mov` is one of the killer moves stored at `draft` distance from the root.
+    pub fn contains(&self, draft: u8, mov: &Move) -> bool {
+        if draft == 0 || draft as usize > self.data.len() {
+            return false;
+        }
+
+        let entry = &self.data[draft as usize - 1];
+        entry.mov_a.0.same_move(mov) || entry.mov_b.0.same_move(mov)
+    }
 
-        if draft as usize > self.data.len() {
+    /// Store a killer move for a given draft (number of ply down from the root). We do not have
+    /// killer moves at the root node, so nothing is stored for `draft == 0`, nor for drafts
+    /// beyond the size of the table.
+    pub fn store(&mut self, killer: Move, draft: u8) {
+        if draft == 0 || draft as usize > self.data.len() {
             return;
         }
 
         let entry = &mut self.data[draft as usize - 1];
 
-        if entry.mov_a.0 == killer || entry.mov_b.0 == killer {
+        if entry.mov_a.0.same_move(&killer) || entry.mov_b.0.same_move(&killer) {
             // This killer move is already included at this draft.
             return;
         }
@@ -101,3 +111,55 @@ impl std::fmt::Display for KillerTable {
         writeln!(f, "}}")
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::mono_traits::{Legal, Quiets};
+    use core::movelist::BasicMoveList;
+
+    /// The killers found at `draft`, in whichever slots they are stored.
+    fn killers(kt: &mut KillerTable, draft: u8, pos: &Position) -> Vec<Move> {
+        let (a, b) = kt.probe(draft, pos);
+        a.into_iter().chain(b).collect()
+    }
+
+    #[test]
+    fn probe_and_store_are_bounds_safe() {
+        core::init::init_globals();
+
+        let pos = Position::start_pos();
+        let quiets = pos.generate::<BasicMoveList, Quiets, Legal>();
+        let (a, b) = (quiets[0], quiets[1]);
+
+        let mut kt = KillerTable::new(40);
+        kt.store(a, 30);
+        kt.store(b, 40);
+        assert_eq!(killers(&mut kt, 30, &pos), vec![a]);
+        assert!(kt.contains(40, &b));
+
+        // Drafts outside the table are ignored rather than panicking.
+        kt.store(a, 0);
+        kt.store(a, 41);
+        kt.store(a, u8::MAX);
+        assert!(killers(&mut kt, 0, &pos).is_empty());
+        assert!(killers(&mut kt, u8::MAX, &pos).is_empty());
+        assert!(!kt.contains(41, &a));
+    }
+
+    #[test]
+    fn killers_must_be_valid_in_the_position() {
+        core::init::init_globals();
+
+        let pos = Position::start_pos();
+        let mov = pos.generate::<BasicMoveList, Quiets, Legal>()[0];
+
+        // After a clash, a killer stored in one position may be probed in a position where it
+        // isn't legal.
+        let mut kt = KillerTable::new(4);
+        kt.store(mov, 1);
+        let empty = Position::from_fen("4k3/8/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert!(killers(&mut kt, 1, &empty).is_empty());
+        assert_eq!(killers(&mut kt, 1, &pos), vec![mov]);
+    }
+}
@@ -0,0 +1,277 @@
+//! A bitbase for king and pawn versus king endgames.
+//!
+//! Every legal configuration of the kings, the pawn and the side to move is classified as won or
+//! drawn by retrograde analysis, and the wins are stored one bit per position. Positions are
+//! normalised so that the strong side is White and the pawn is on the a-d files.
+//!
+//! The bitbase takes a few milliseconds to generate. `init` should be called at startup so that
+//! this doesn't happen during the first search.
+
+use core::position::{Player, Square};
+
+use std::sync::OnceLock;
+
+/// The number of pawn squares: the a-d files on the second to seventh ranks.
+const PAWN_SQUARES: usize = 24;
+
+/// The number of positions in the bitbase.
+const MAX_INDEX: usize = 2 * PAWN_SQUARES * 64 * 64;
+
+/// One bit per position, set if the strong side wins.
+static KPK: OnceLock<Vec<u64>> = OnceLock::new();
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Outcome {
+    Invalid,
+    Unknown,
+    Draw,
+    Win,
+}
+
+/// Generate the bitbase, if it hasn't been generated already.
+pub fn init() {
+    KPK.get_or_init(generate);
+}
+
+/// Whether the side with the pawn wins, given the squares of its king and pawn, the square of the
+/// defending king, and whether it is to move.
+pub fn probe(
+    strong: Player,
+    strong_king: Square,
+    pawn: Square,
+    weak_king: Square,
+    strong_to_move: bool,
+) -> bool {
+    let mut wk = strong.relative_square(strong_king).0;
+    let mut psq = strong.relative_square(pawn).0;
+    let mut bk = strong.relative_square(weak_king).0;
+
+    // Mirror onto the queenside.
+    if file(psq) >= 4 {
+        wk ^= 7;
+        psq ^= 7;
+        bk ^= 7;
+    }
+
+    let bits = KPK.get_or_init(generate);
+    let idx = index(strong_to_move, wk, bk, psq);
+    bits[idx / 64] & (1 << (idx % 64)) != 0
+}
+
+fn generate() -> Vec<u64> {
+    let mut db: Vec<Outcome> = (0..MAX_INDEX).map(initial_outcome).collect();
+
+    // Repeatedly classify the unknown positions from their successors, until nothing changes.
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for idx in 0..MAX_INDEX {
+            if db[idx] == Outcome::Unknown {
+                let outcome = classify(&db, idx);
+                if outcome != Outcome::Unknown {
+                    db[idx] = outcome;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    // Anything still unknown can't be forced to a win.
+    let mut bits = vec![0; MAX_INDEX / 64];
+    for (idx, outcome) in db.iter().enumerate() {
+        if *outcome == Outcome::Win {
+            bits[idx / 64] |= 1 << (idx % 64);
+        }
+    }
+
+    bits
+}
+
+/// The outcome of a position which is evident without looking at its successors.
+fn initial_outcome(idx: usize) -> Outcome {
+    let (white_to_move, wk, bk, psq) = decode(idx);
+
+    if distance(wk, bk) <= 1 || wk == psq || bk == psq {
+        return Outcome::Invalid;
+    }
+
+    if white_to_move {
+        // The side not to move can't be in check.
+        if pawn_attacks(psq, bk) {
+            return Outcome::Invalid;
+        }
+
+        // The pawn promotes, and the queen can't be taken.
+        let queening = psq + 8;
+        if rank(psq) == 6
+            && wk != queening
+            && bk != queening
+            && (distance(bk, queening) > 1 || distance(wk, queening) == 1)
+        {
+            return Outcome::Win;
+        }
+    } else {
+        if black_king_moves(wk, bk, psq).next().is_none() {
+            return match pawn_attacks(psq, bk) {
+                true => Outcome::Win,
+                false => Outcome::Draw,
+            };
+        }
+
+        // The undefended pawn is taken.
+        if distance(bk, psq) == 1 && distance(wk, psq) > 1 {
+            return Outcome::Draw;
+        }
+    }
+
+    Outcome::Unknown
+}
+
+/// The outcome of a position from the outcomes of its successors, if these decide it.
+fn classify(db: &[Outcome], idx: usize) -> Outcome {
+    let (white_to_move, wk, bk, psq) = decode(idx);
+
+    if white_to_move {
+        let mut successors: Vec<usize> = king_moves(wk)
+            .filter(|&to| to != psq && distance(to, bk) > 1)
+            .map(|to| index(false, to, bk, psq))
+            .collect();
+
+        // Promotions are dealt with by `initial_outcome`.
+        let push = psq + 8;
+        if rank(psq) < 6 && push != wk && push != bk {
+            successors.push(index(false, wk, bk, push));
+
+            let double_push = push + 8;
+            if rank(psq) == 1 && double_push != wk && double_push != bk {
+                successors.push(index(false, wk, bk, double_push));
+            }
+        }
+
+        if successors.iter().any(|&s| db[s] == Outcome::Win) {
+            Outcome::Win
+        } else if successors.iter().all(|&s| db[s] == Outcome::Draw) {
+            Outcome::Draw
+        } else {
+            Outcome::Unknown
+        }
+    } else {
+        let successors: Vec<usize> = black_king_moves(wk, bk, psq)
+            .map(|to| index(true, wk, to, psq))
+            .collect();
+
+        if successors.iter().any(|&s| db[s] == Outcome::Draw) {
+            Outcome::Draw
+        } else if successors.iter().all(|&s| db[s] == Outcome::Win) {
+            Outcome::Win
+        } else {
+            Outcome::Unknown
+        }
+    }
+}
+
+/// The index of a position. `psq` must be on the a-d files and the second to seventh ranks.
+fn index(white_to_move: bool, wk: u8, bk: u8, psq: u8) -> usize {
+    let pawn = file(psq) as usize * 6 + rank(psq) as usize - 1;
+    ((pawn * 2 + white_to_move as usize) * 64 + wk as usize) * 64 + bk as usize
+}
+
+/// The inverse of `index`.
+fn decode(idx: usize) -> (bool, u8, u8, u8) {
+    let bk = (idx % 64) as u8;
+    let wk = (idx / 64 % 64) as u8;
+    let white_to_move = idx / 4096 % 2 == 1;
+    let pawn = (idx / 8192) as u8;
+    let psq = (pawn % 6 + 1) * 8 + pawn / 6;
+
+    (white_to_move, wk, bk, psq)
+}
+
+/// The squares the black king can legally move to. Capturing the pawn is only legal if it is
+/// undefended, in which case the position is a draw anyway.
+fn black_king_moves(wk: u8, bk: u8, psq: u8) -> impl Iterator<Item = u8> {
+    king_moves(bk).filter(move |&to| distance(to, wk) > 1 && !pawn_attacks(psq, to))
+}
+
+fn king_moves(sq: u8) -> impl Iterator<Item = u8> {
+    (0..64).filter(move |&to| distance(sq, to) == 1)
+}
+
+fn pawn_attacks(psq: u8, sq: u8) -> bool {
+    rank(sq) == rank(psq) + 1 && file(sq).abs_diff(file(psq)) == 1
+}
+
+fn distance(a: u8, b: u8) -> u8 {
+    std::cmp::max(file(a).abs_diff(file(b)), rank(a).abs_diff(rank(b)))
+}
+
+fn file(sq: u8) -> u8 {
+    sq % 8
+}
+
+fn rank(sq: u8) -> u8 {
+    sq / 8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::position::{PieceType, Position};
+
+    fn wins(fen: &str) -> bool {
+        core::init::init_globals();
+
+        let pos = Position::from_fen(fen).unwrap();
+        let strong = match pos.piece_bb(Player::WHITE, PieceType::Pawn).is_empty() {
+            true => Player::BLACK,
+            false => Player::WHITE,
+        };
+
+        probe(
+            strong,
+            pos.king_sq(strong),
+            pos.piece_bb(strong, PieceType::Pawn).to_square(),
+            pos.king_sq(strong.other_player()),
+            pos.turn() == strong,
+        )
+    }
+
+    #[test]
+    fn index_round_trips() {
+        for idx in [0, 1, 4095, 4096, 8191, 8192, MAX_INDEX - 1] {
+            let (white_to_move, wk, bk, psq) = decode(idx);
+            assert_eq!(index(white_to_move, wk, bk, psq), idx);
+        }
+    }
+
+    #[test]
+    fn king_on_the_sixth_rank_wins() {
+        // With the king on the sixth rank in front of its pawn, it wins whoever is to move.
+        assert!(wins("4k3/8/4K3/4P3/8/8/8/8 w - - 0 1"));
+        assert!(wins("4k3/8/4K3/4P3/8/8/8/8 b - - 0 1"));
+    }
+
+    #[test]
+    fn opposition() {
+        // With no spare pawn move, the side to move loses the opposition.
+        assert!(!wins("8/4k3/8/4K3/4P3/8/8/8 w - - 0 1"));
+        assert!(wins("8/4k3/8/4K3/4P3/8/8/8 b - - 0 1"));
+    }
+
+    #[test]
+    fn rook_pawn_draws() {
+        assert!(!wins("k7/8/K7/P7/8/8/8/8 w - - 0 1"));
+        assert!(!wins("k7/8/8/8/8/8/P7/K7 w - - 0 1"));
+    }
+
+    #[test]
+    fn rule_of_the_square() {
+        // The defending king can only catch the pawn if it can step into its square.
+        assert!(wins("8/8/8/2k5/7P/8/8/K7 w - - 0 1"));
+        assert!(!wins("8/8/8/2k5/7P/8/8/K7 b - - 0 1"));
+
+        // The same positions, with the colours reversed.
+        assert!(wins("k7/8/8/7p/2K5/8/8/8 b - - 0 1"));
+        assert!(!wins("k7/8/8/7p/2K5/8/8/8 w - - 0 1"));
+    }
+}
@@ -2,19 +2,25 @@
 #![feature(slice_from_ptr_range)]
 #![feature(iter_intersperse)]
 
+pub mod countermove;
 pub mod engine;
 pub mod eval;
 pub mod history;
 pub mod info;
 pub mod killer;
+pub mod kpk;
+pub mod lmr;
 pub mod options;
 pub mod ordering;
+pub mod pawns;
 pub mod perft;
 pub mod pv_table;
 pub mod score;
 pub mod search;
 pub mod see;
 pub mod session;
+#[cfg(feature = "syzygy")]
+pub mod tablebase;
 pub mod time;
 pub mod trace;
 pub mod tt;
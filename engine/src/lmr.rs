@@ -0,0 +1,57 @@
+//! Late move reductions.
+//!
+//! With good move ordering, a cutoff is most likely to come from one of the first few moves at a
+//! node. Later quiet moves are searched to a reduced depth, and only re-searched at full depth if
+//! they unexpectedly beat alpha.
+
+use std::sync::OnceLock;
+
+/// Late move reductions are only applied to moves after this many have been searched at a node.
+pub const LMR_MOVE_THRESHOLD: u8 = 3;
+
+/// The minimum remaining depth at which late move reductions are applied.
+pub const LMR_MIN_DEPTH: u8 = 3;
+
+/// Reductions, indexed by remaining depth and then move count.
+static REDUCTIONS: OnceLock<[[u8; 64]; 64]> = OnceLock::new();
+
+fn init_reductions() -> [[u8; 64]; 64] {
+    let mut table = [[0; 64]; 64];
+
+    for (depth, row) in table.iter_mut().enumerate().skip(1) {
+        for (move_count, r) in row.iter_mut().enumerate().skip(1) {
+            let reduction = 0.75 + (depth as f64).ln() * (move_count as f64).ln() / 2.25;
+            *r = reduction as u8;
+        }
+    }
+
+    table
+}
+
+/// The number of plies by which to reduce the search of the `move_count`th move at a node with
+/// `depth` plies remaining. The reduction always leaves at least one ply to search.
+pub fn reduction(depth: u8, move_count: u8) -> u8 {
+    let table = REDUCTIONS.get_or_init(init_reductions);
+    let r = table[std::cmp::min(depth, 63) as usize][std::cmp::min(move_count, 63) as usize];
+
+    std::cmp::min(r, depth.saturating_sub(2))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reductions_grow_with_depth_and_move_count() {
+        assert_eq!(reduction(3, 4), 1);
+        assert!(reduction(20, 40) > reduction(8, 40));
+        assert!(reduction(20, 40) > reduction(20, 8));
+
+        // A reduced search always has at least one ply remaining.
+        for depth in LMR_MIN_DEPTH..=100 {
+            for move_count in 0..=255 {
+                assert!(reduction(depth, move_count) + 2 <= depth);
+            }
+        }
+    }
+}
@@ -1,3 +1,49 @@
+/// The default size of the transposition table, in megabytes.
+pub const HASH_DEFAULT_MB: usize = 16;
+
+/// The minimum size of the transposition table, in megabytes.
+pub const HASH_MIN_MB: usize = 1;
+
+/// The maximum size of the transposition table, in megabytes.
+pub const HASH_MAX_MB: usize = 4096;
+
+/// The default number of search threads.
+pub const THREADS_DEFAULT: usize = 1;
+
+/// The minimum number of search threads.
+pub const THREADS_MIN: usize = 1;
+
+/// The maximum number of search threads.
+pub const THREADS_MAX: usize = 256;
+
+/// The default number of principal variations to report.
+pub const MULTIPV_DEFAULT: usize = 1;
+
+/// The minimum number of principal variations to report.
+pub const MULTIPV_MIN: usize = 1;
+
+/// The maximum number of principal variations to report.
+pub const MULTIPV_MAX: usize = 256;
+
+/// The default contempt, in centipawns.
+pub const CONTEMPT_DEFAULT: i32 = 0;
+
+/// The minimum contempt, in centipawns.
+pub const CONTEMPT_MIN: i32 = -100;
+
+/// The maximum contempt, in centipawns.
+pub const CONTEMPT_MAX: i32 = 100;
+
+/// The default time held back from each move for communication delays, in milliseconds. This is
+/// on top of the buffer time which the engine always holds back.
+pub const MOVE_OVERHEAD_DEFAULT: usize = 0;
+
+/// The minimum move overhead, in milliseconds.
+pub const MOVE_OVERHEAD_MIN: usize = 0;
+
+/// The maximum move overhead, in milliseconds.
+pub const MOVE_OVERHEAD_MAX: usize = 5000;
+
 /// Engine configuration.
 #[derive(Debug)]
 pub struct Config {
@@ -5,12 +51,63 @@ pub struct Config {
     debug_mode: bool,
     /// Transposition table configuration.
     hash: HashConfig,
+    /// The number of threads to search with.
+    threads: usize,
+    /// The number of principal variations to report.
+    multipv: usize,
+    /// How much the engine dislikes draws, in centipawns. A positive contempt makes the engine
+    /// avoid draws against what it assumes is a weaker opponent.
+    contempt: i32,
+    /// The time held back from each move for communication delays, in milliseconds.
+    move_overhead: usize,
+    /// Whether the GUI may send `go ponder`. This is only informational; the engine ponders
+    /// whenever it's told to.
+    ponder: bool,
+    /// The directories to load Syzygy tablebases from, if any.
+    #[cfg(feature = "syzygy")]
+    syzygy_path: Option<String>,
 }
 
 impl Config {
     pub fn debug_mode(&self) -> bool {
         self.debug_mode
     }
+
+    /// The configured size of the transposition table, in megabytes.
+    pub fn hash_size(&self) -> usize {
+        self.hash.size()
+    }
+
+    /// The configured number of search threads.
+    pub fn threads(&self) -> usize {
+        self.threads
+    }
+
+    /// The configured number of principal variations to report.
+    pub fn multipv(&self) -> usize {
+        self.multipv
+    }
+
+    /// The configured contempt, in centipawns.
+    pub fn contempt(&self) -> i32 {
+        self.contempt
+    }
+
+    /// The configured move overhead, in milliseconds.
+    pub fn move_overhead(&self) -> usize {
+        self.move_overhead
+    }
+
+    /// Whether pondering is enabled.
+    pub fn ponder(&self) -> bool {
+        self.ponder
+    }
+
+    /// The configured Syzygy tablebase directories, if any.
+    #[cfg(feature = "syzygy")]
+    pub fn syzygy_path(&self) -> Option<&str> {
+        self.syzygy_path.as_deref()
+    }
 }
 
 impl Default for Config {
@@ -18,20 +115,41 @@ impl Default for Config {
         Self {
             debug_mode: false,
             hash: Default::default(),
+            threads: THREADS_DEFAULT,
+            multipv: MULTIPV_DEFAULT,
+            contempt: CONTEMPT_DEFAULT,
+            move_overhead: MOVE_OVERHEAD_DEFAULT,
+            ponder: false,
+            #[cfg(feature = "syzygy")]
+            syzygy_path: None,
         }
     }
 }
 
 /// Transposition table configuration.
-#[derive(Debug, Default)]
+#[derive(Debug)]
 pub struct HashConfig {
-    /// Size of the hash table
+    /// Size of the hash table, in megabytes.
     size: usize,
 }
 
+impl Default for HashConfig {
+    fn default() -> Self {
+        Self {
+            size: HASH_DEFAULT_MB,
+        }
+    }
+}
+
 impl HashConfig {
+    /// Set the size of the hash table in megabytes. Values outside the supported range are
+    /// clamped.
     pub fn set_size(&mut self, size: usize) {
-        self.size = size;
+        self.size = size.clamp(HASH_MIN_MB, HASH_MAX_MB);
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
     }
 }
 
@@ -53,6 +171,15 @@ impl Config {
         match o {
             EngineOpt::Hash(v) => self.hash.set_size(v),
             EngineOpt::DebugMode(b) => self.debug_mode = b,
+            EngineOpt::Threads(v) => self.threads = v.clamp(THREADS_MIN, THREADS_MAX),
+            EngineOpt::MultiPv(v) => self.multipv = v.clamp(MULTIPV_MIN, MULTIPV_MAX),
+            EngineOpt::Contempt(v) => self.contempt = v.clamp(CONTEMPT_MIN, CONTEMPT_MAX),
+            EngineOpt::MoveOverhead(v) => {
+                self.move_overhead = v.clamp(MOVE_OVERHEAD_MIN, MOVE_OVERHEAD_MAX)
+            }
+            EngineOpt::Ponder(b) => self.ponder = b,
+            #[cfg(feature = "syzygy")]
+            EngineOpt::SyzygyPath(path) => self.syzygy_path = path,
         }
     }
 }
@@ -64,4 +191,50 @@ pub enum EngineOpt {
     Hash(usize),
     /// Whether debug mode is turned on.
     DebugMode(bool),
+    /// The number of search threads.
+    Threads(usize),
+    /// The number of principal variations to report.
+    MultiPv(usize),
+    /// The contempt, in centipawns.
+    Contempt(i32),
+    /// The time in milliseconds to hold back from each move for communication delays.
+    MoveOverhead(usize),
+    /// Whether pondering is enabled.
+    Ponder(bool),
+    /// The directories to load Syzygy tablebases from, or `None` to unload them.
+    #[cfg(feature = "syzygy")]
+    SyzygyPath(Option<String>),
+}
+
+/// The options the engine supports, as `option` lines for the `uci` handshake.
+pub fn uci_options() -> Vec<String> {
+    let mut options = vec![
+        format!(
+            "option name Hash type spin default {} min {} max {}",
+            HASH_DEFAULT_MB, HASH_MIN_MB, HASH_MAX_MB
+        ),
+        format!(
+            "option name Threads type spin default {} min {} max {}",
+            THREADS_DEFAULT, THREADS_MIN, THREADS_MAX
+        ),
+        format!(
+            "option name MultiPV type spin default {} min {} max {}",
+            MULTIPV_DEFAULT, MULTIPV_MIN, MULTIPV_MAX
+        ),
+        format!(
+            "option name Contempt type spin default {} min {} max {}",
+            CONTEMPT_DEFAULT, CONTEMPT_MIN, CONTEMPT_MAX
+        ),
+        format!(
+            "option name Move Overhead type spin default {} min {} max {}",
+            MOVE_OVERHEAD_DEFAULT, MOVE_OVERHEAD_MIN, MOVE_OVERHEAD_MAX
+        ),
+        "option name Ponder type check default false".to_string(),
+    ];
+
+    if cfg!(feature = "syzygy") {
+        options.push("option name SyzygyPath type string default <empty>".to_string());
+    }
+
+    options
 }
@@ -16,6 +16,19 @@ use std::slice::Iter as SliceIter;
 
 pub type ScoredMove = (Move, i16);
 
+/// Piece values are all multiples of this, so static exchange evaluations are too.
+pub const SEE_GRANULARITY: i16 = 100;
+
+/// The move ordering score of a capture of `victim` by `attacker`: its static exchange evaluation
+/// `see`, plus an MVV-LVA (most valuable victim, least valuable attacker) tiebreak. The tiebreak is
+/// always less than `SEE_GRANULARITY`, so it orders captures with equal SEE without reordering any
+/// others.
+#[inline]
+pub fn capture_score(see: i16, victim: PieceType, attacker: PieceType) -> i16 {
+    debug_assert_eq!(see % SEE_GRANULARITY, 0);
+    see + 10 * victim as i16 + PieceType::King as i16 - attacker as i16
+}
+
 /// An entry in the move ordering `ArrayVec` buffer.
 #[derive(Debug)]
 struct Entry {
@@ -168,7 +181,7 @@ impl<'a> Iterator for KillerIter<'a> {
 
                 // If this move matches one in the hash segment, skip it.
                 for hm in self.hash_segment.iter() {
-                    if entry.sm.0 == hm.sm.0 {
+                    if entry.sm.0.same_move(&hm.sm.0) {
                         return self.next();
                     }
                 }
@@ -202,14 +215,14 @@ impl<'a> Iterator for QuietsIter<'a> {
             Some(mov) => {
                 // If this move matches one in the hash segment, skip it.
                 for hm in self.hash_segment.iter() {
-                    if *mov == hm.sm.0 {
+                    if mov.same_move(&hm.sm.0) {
                         return self.next();
                     }
                 }
 
                 // If this move matches one in the killer segment, skip it.
                 for km in self.killer_segment.iter() {
-                    if *mov == km.sm.0 {
+                    if mov.same_move(&km.sm.0) {
                         return self.next();
                     }
                 }
@@ -272,7 +285,7 @@ pub enum Phase {
     EqualCaptures,
     /// Quiet moves appearing in the killer tables. Such a move caused a cutoff at the same ply in
     /// another variation, and is therefore considered likely to have a similarly positive effect
-    /// in this position too.
+    /// in this position too. The countermove to the previous move, if any, is tried with them.
     Killers,
     /// All other quiet (i.e. non-capturing or promoting) moves. These are further sorted according
     /// to the history heuristic, which scores moves based on how many times have they have caused
@@ -316,7 +329,8 @@ pub trait Loader {
     /// each move.
     fn score_captures(&mut self, _scorer: Scorer) {}
 
-    /// Load killers into the passed `MoveList`.
+    /// Load killers, and any other quiet moves expected to cause a cutoff, such as the
+    /// countermove, into the passed `MoveList`.
     fn load_killers(&mut self, _movelist: &mut ScoredMoveList) {}
 
     /// Load quiet moves into the passed `MoveList`.
@@ -616,7 +630,7 @@ impl OrderedMoves {
     fn dedup_segments(&self, tgt: Segment<'_>, src: Segment<'_>) {
         for tgt_entry in tgt {
             for src_entry in src {
-                if tgt_entry.sm.0 == src_entry.sm.0 {
+                if tgt_entry.sm.0.same_move(&src_entry.sm.0) {
                     // SAFETY: we can mark entries as yielded, since no-one outside the API has a
                     // reference to the `yielded` flag.
                     unsafe {
@@ -674,13 +688,13 @@ impl OrderedMoves {
     #[inline]
     fn good_capt_iter<'a>(&'a self) -> SelectionSort<'a> {
         let segment = self.capt_segment();
-        SelectionSort::from(segment, |sm| sm.1 > 0)
+        SelectionSort::from(segment, |sm| sm.1 >= SEE_GRANULARITY)
     }
 
     #[inline]
     fn equal_capt_iter<'a>(&'a self) -> SelectionSort<'a> {
         let segment = self.capt_segment();
-        SelectionSort::from(segment, |sm| sm.1 == 0)
+        SelectionSort::from(segment, |sm| (0..SEE_GRANULARITY).contains(&sm.1))
     }
 
     #[inline]
@@ -945,4 +959,74 @@ mod tests {
             assert_eq!(perft, r);
         }
     }
+
+    /// A loader which yields a fixed hash move, followed by every legal move.
+    struct HashThenAllLoader<'a> {
+        pos: &'a mut Position,
+        hash_move: Move,
+    }
+
+    impl<'a> Loader for HashThenAllLoader<'a> {
+        fn load_hash(&mut self, movelist: &mut ScoredMoveList) {
+            movelist.push(self.hash_move);
+        }
+
+        fn load_promotions(&mut self, movelist: &mut ScoredMoveList) {
+            self.pos.generate_in::<_, QueenPromotions, Legal>(movelist);
+        }
+
+        fn load_captures(&mut self, movelist: &mut ScoredMoveList) {
+            self.pos.generate_in::<_, Captures, Legal>(movelist);
+        }
+
+        fn load_quiets(&mut self, movelist: &mut ScoredMoveList) {
+            self.pos.generate_in::<_, Quiets, Legal>(movelist);
+        }
+    }
+
+    #[test]
+    fn hash_move_dedups_despite_differing_flags() {
+        use crate::tt::PackedMove;
+        use core::mov::MoveType;
+
+        core::init::init_globals();
+
+        let fen = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+        let mut pos = Position::from_fen(fen).unwrap();
+        let legal = pos.generate::<BasicMoveList, All, Legal>();
+
+        for gen_mov in &legal {
+            // Both a faithful TT reconstruction and one with the flags stripped must be
+            // recognised as the same move as the generated one.
+            let packed = PackedMove::from_move(gen_mov).to_move(&pos);
+            let stripped = Move::build(
+                gen_mov.orig(),
+                gen_mov.dest(),
+                gen_mov.promo_piece_type(),
+                if gen_mov.is_promotion() {
+                    MoveType::PROMOTION
+                } else {
+                    MoveType::QUIET
+                },
+            );
+
+            for hash_move in [packed, stripped] {
+                let mut moves = OrderedMoves::new();
+                let mut yielded = Vec::new();
+
+                while moves.load_next_phase(HashThenAllLoader {
+                    pos: &mut pos,
+                    hash_move,
+                }) {
+                    for mov in &moves {
+                        yielded.push(*mov);
+                    }
+                }
+
+                let times = yielded.iter().filter(|m| m.same_move(gen_mov)).count();
+                assert_eq!(times, 1, "{} yielded {} times", gen_mov, times);
+                assert_eq!(yielded.len(), legal.len());
+            }
+        }
+    }
 }
@@ -0,0 +1,140 @@
+//! Pawn structure evaluation.
+//!
+//! Pawns are penalised for being doubled or isolated, and rewarded for being passed. Each term has
+//! a midgame and an endgame value, which are blended by the game phase like the piece-square
+//! tables.
+
+use super::eval::taper;
+
+use core::position::{file_bb, PieceType, Player, Position};
+
+/// Penalty for each pawn on a file beyond the first.
+const DOUBLED_PAWN: (i32, i32) = (-10, -20);
+
+/// Penalty for a pawn with no friendly pawns on the adjacent files.
+const ISOLATED_PAWN: (i32, i32) = (-10, -15);
+
+/// Bonus for a passed pawn, indexed by its rank relative to its owner.
+const PASSED_PAWN: [(i32, i32); 8] = [
+    (0, 0),
+    (5, 10),
+    (10, 15),
+    (15, 25),
+    (25, 45),
+    (40, 80),
+    (60, 120),
+    (0, 0),
+];
+
+/// The pawn structure evaluation, from White's point of view.
+pub fn pawn_structure(pos: &Position) -> i16 {
+    let (mg, eg) = pawn_structure_terms(pos);
+    taper(mg, eg, pos.game_phase()) as i16
+}
+
+/// The midgame and endgame pawn structure scores, from White's point of view, before tapering.
+/// These depend only on the pawns, so can be cached by pawn key; the game phase can't.
+pub fn pawn_structure_terms(pos: &Position) -> (i32, i32) {
+    let (w_mg, w_eg) = player_pawn_structure(pos, Player::WHITE);
+    let (b_mg, b_eg) = player_pawn_structure(pos, Player::BLACK);
+
+    (w_mg - b_mg, w_eg - b_eg)
+}
+
+/// The midgame and endgame pawn structure scores for `player`'s pawns.
+fn player_pawn_structure(pos: &Position, player: Player) -> (i32, i32) {
+    let pawns = pos.piece_bb(player, PieceType::Pawn).0;
+    let their_pawns = pos.piece_bb(player.other_player(), PieceType::Pawn).0;
+    let (mut mg, mut eg) = (0, 0);
+
+    for file in 0..8 {
+        let on_file = (pawns & file_bb(file)).count_ones() as i32;
+        if on_file > 1 {
+            mg += DOUBLED_PAWN.0 * (on_file - 1);
+            eg += DOUBLED_PAWN.1 * (on_file - 1);
+        }
+    }
+
+    for sq in pos.piece_bb(player, PieceType::Pawn) {
+        let file = sq.file();
+        let adjacent = adjacent_files(file);
+
+        if pawns & adjacent == 0 {
+            mg += ISOLATED_PAWN.0;
+            eg += ISOLATED_PAWN.1;
+        }
+
+        let ahead = ranks_ahead(player, sq.rank());
+        let blocked_by_own = pawns & file_bb(file) & ahead != 0;
+        let stoppers = their_pawns & (file_bb(file) | adjacent) & ahead;
+
+        if stoppers == 0 && !blocked_by_own {
+            let (bonus_mg, bonus_eg) = PASSED_PAWN[player.relative_rank(sq.rank()) as usize];
+            mg += bonus_mg;
+            eg += bonus_eg;
+        }
+    }
+
+    (mg, eg)
+}
+
+/// The files either side of `file`. Files are numbered from 0, so that `file_bb(file)` is the
+/// whole file.
+fn adjacent_files(file: u8) -> u64 {
+    let left = if file > 0 { file_bb(file - 1) } else { 0 };
+    let right = if file < 7 { file_bb(file + 1) } else { 0 };
+    left | right
+}
+
+/// All squares on the ranks in front of `rank`, from `player`'s point of view.
+fn ranks_ahead(player: Player, rank: u8) -> u64 {
+    match player {
+        Player::WHITE => (!0u64).checked_shl(8 * (rank as u32 + 1)).unwrap_or(0),
+        Player::BLACK => (1u64 << (8 * rank as u32)) - 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn doubled_isolated_pawns_are_worse_than_a_chain() {
+        core::init::init_globals();
+
+        let doubled = Position::from_fen("4k3/2ppp3/8/8/2P5/2P5/8/4K3 w - - 0 1").unwrap();
+        let chain = Position::from_fen("4k3/2ppp3/8/8/3P4/2P5/8/4K3 w - - 0 1").unwrap();
+
+        assert!(pawn_structure(&doubled) < pawn_structure(&chain));
+        assert!(pawn_structure(&doubled) < 0);
+    }
+
+    #[test]
+    fn advanced_passed_pawns_get_a_bonus() {
+        core::init::init_globals();
+
+        let passed = Position::from_fen("4k3/8/3P4/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert!(pawn_structure(&passed) >= 50);
+
+        // A pawn is passed however many pieces there are, but it counts for less in the midgame.
+        let midgame = Position::from_fen("rnbqkbnr/8/3P4/8/8/8/8/RNBQKBNR w - - 0 1").unwrap();
+        assert!(pawn_structure(&midgame) > 0);
+        assert!(pawn_structure(&midgame) < pawn_structure(&passed));
+
+        // The bonus is mirrored for Black, and grows with rank.
+        let black = Position::from_fen("4k3/8/8/8/8/3p4/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(pawn_structure(&black), -pawn_structure(&passed));
+
+        let less_advanced = Position::from_fen("4k3/8/8/8/3P4/8/8/4K3 w - - 0 1").unwrap();
+        assert!(pawn_structure(&less_advanced) < pawn_structure(&passed));
+    }
+
+    #[test]
+    fn blocked_pawns_are_not_passed() {
+        core::init::init_globals();
+
+        // The e7 pawn covers d6's path, and the d6 pawn covers e7's.
+        let pos = Position::from_fen("4k3/4p3/3P4/8/8/8/8/4K3 w - - 0 1").unwrap();
+        assert_eq!(pawn_structure(&pos), 0);
+    }
+}
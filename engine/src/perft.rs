@@ -1,11 +1,14 @@
 use core::mono_traits::{All, Captures, Legal};
 use core::mov::Move;
-use core::movelist::BasicMoveList;
+use core::movelist::{BasicMoveList, MoveList, MoveStack};
 use core::position::{Position, START_POSITION};
 
 use separator::Separatable;
 
 use std::fmt;
+use std::ops::{AddAssign, Sub};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
 use std::time::Instant;
 
 #[derive(Copy, Clone, Eq, PartialEq)]
@@ -87,6 +90,50 @@ impl PerftData {
     }
 }
 
+impl fmt::Display for PerftData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.print_nodes(f)?;
+        self.print_captures(f)?;
+        self.print_en_passant(f)?;
+        self.print_castles(f)?;
+        self.print_promotions(f)?;
+        self.print_checkmate(f)?;
+        self.print_check(f)
+    }
+}
+
+/// The result of a "divide" perft run: the number of leaf nodes below each root move, as well as
+/// the totals.
+pub struct PerftResult {
+    /// The total number of leaf nodes.
+    pub nodes: usize,
+    /// Each legal root move, with the number of leaf nodes below it, sorted by move.
+    pub moves: Vec<(Move, usize)>,
+    /// The totals, including any detailed or check data which was collected.
+    pub data: PerftData,
+}
+
+impl PerftResult {
+    /// The number of leaf nodes below the given root move, or `None` if it isn't a legal move.
+    pub fn nodes_for(&self, mov: &str) -> Option<usize> {
+        self.moves
+            .iter()
+            .find(|(m, _)| m.to_string() == mov)
+            .map(|&(_, nodes)| nodes)
+    }
+}
+
+impl fmt::Display for PerftResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (mov, nodes) in &self.moves {
+            writeln!(f, "{}: {}", mov, nodes.separated_string())?;
+        }
+        writeln!(f)?;
+        write!(f, "{}", self.data)
+    }
+}
+
+#[derive(Clone, Copy)]
 pub struct PerftOptions {
     /// Should this perft run collect detailed data on captures, en passant, castles and
     /// promotions.
@@ -96,31 +143,108 @@ pub struct PerftOptions {
     pub detailed: bool,
     /// Should this perft run collect information about checks and checkmates.
     pub checks: bool,
+    /// Should this perft run count the leaf nodes in bulk. If so, the moves generated one ply
+    /// above the leaves are counted without being made. Otherwise every leaf move is made and
+    /// unmade, which is much slower but also exercises `make_move` and `unmake_move` on the
+    /// leaves, so is useful for debugging.
+    pub bulk: bool,
+    /// The size in megabytes of the table caching node counts of subtrees, or 0 for no caching.
+    /// Only the node count is cached, so the table isn't used when collecting detailed or check
+    /// data.
+    pub hash: usize,
+    /// The number of threads to split the root moves between. Each thread runs perft on its own
+    /// clone of the position, with its own hash table.
+    pub threads: usize,
+    /// Should this perft run keep the moves at each ply in a `Frame` of a `MoveStack`, rather than
+    /// in a `BasicMoveList` on the program stack. This is mainly useful for testing the `Frame`
+    /// machinery.
+    pub movestack: bool,
 }
 
 impl PerftOptions {
     pub fn new(detailed: bool, checks: bool) -> Self {
-        Self { detailed, checks }
+        Self {
+            detailed,
+            checks,
+            bulk: true,
+            hash: 0,
+            threads: 1,
+            movestack: false,
+        }
+    }
+
+    /// Set whether to count the leaf nodes in bulk.
+    pub fn with_bulk(mut self, bulk: bool) -> Self {
+        self.bulk = bulk;
+        self
+    }
+
+    /// Set the size in megabytes of the table caching node counts, or 0 for no caching.
+    pub fn with_hash(mut self, hash: usize) -> Self {
+        self.hash = hash;
+        self
+    }
+
+    /// Set the number of threads to split the root moves between.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// Set whether to keep the moves at each ply in a `MoveStack`.
+    pub fn with_movestack(mut self, movestack: bool) -> Self {
+        self.movestack = movestack;
+        self
+    }
+}
+
+/// An entry in the `PerftTable`.
+#[derive(Clone, Copy, Default)]
+struct PerftEntry {
+    key: u64,
+    depth: usize,
+    nodes: usize,
+}
+
+/// A table caching the node count of each subtree by its position and depth, so that subtrees
+/// reached by transposition are only counted once. Like the search's transposition table, it has a
+/// power-of-2 number of entries, indexed by the low bits of the zobrist key. Entries are always
+/// replaced.
+struct PerftTable {
+    data: Vec<PerftEntry>,
+    mask: usize,
+}
+
+impl PerftTable {
+    /// Create a new `PerftTable` of at most `size` megabytes.
+    fn new(size: usize) -> Self {
+        let desired_entries = size * 1_024 * 1_024 / std::mem::size_of::<PerftEntry>();
+        let entries = (desired_entries + 1).next_power_of_two() / 2;
+
+        Self {
+            data: vec![PerftEntry::default(); entries],
+            mask: entries - 1,
+        }
+    }
+
+    fn probe(&self, key: u64, depth: usize) -> Option<usize> {
+        let entry = &self.data[key as usize & self.mask];
+        if entry.key == key && entry.depth == depth {
+            Some(entry.nodes)
+        } else {
+            None
+        }
+    }
+
+    fn store(&mut self, key: u64, depth: usize, nodes: usize) {
+        self.data[key as usize & self.mask] = PerftEntry { key, depth, nodes };
     }
 }
 
 impl fmt::Display for Perft<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        let out = self.output();
         writeln!(f)?;
-        out.print_nodes(f)?;
-
-        if self.options.detailed {
-            out.print_captures(f)?;
-            out.print_en_passant(f)?;
-            out.print_castles(f)?;
-            out.print_promotions(f)?;
-            if self.options.checks {
-                out.print_checkmate(f)?;
-                out.print_check(f)?;
-            }
-        }
-        write!(f, "")
+        write!(f, "{}", self.output())
     }
 }
 
@@ -138,57 +262,117 @@ impl PerftDataInternal {
     }
 }
 
+impl AddAssign for PerftDataInternal {
+    fn add_assign(&mut self, other: Self) {
+        self.nodes += other.nodes;
+        self.captures += other.captures;
+        self.en_passant += other.en_passant;
+        self.castles += other.castles;
+        self.promotions += other.promotions;
+        self.checkmate += other.checkmate;
+        self.check += other.check;
+    }
+}
+
+impl Sub for PerftDataInternal {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self {
+            nodes: self.nodes - other.nodes,
+            captures: self.captures - other.captures,
+            en_passant: self.en_passant - other.en_passant,
+            castles: self.castles - other.castles,
+            promotions: self.promotions - other.promotions,
+            checkmate: self.checkmate - other.checkmate,
+            check: self.check - other.check,
+        }
+    }
+}
+
 pub struct Perft<'a> {
     options: PerftOptions,
     position: &'a mut Position,
     data: PerftDataInternal,
+    table: Option<PerftTable>,
+    movestack: MoveStack,
 }
 
 impl<'a> Perft<'a> {
     fn new(position: &'a mut Position, options: PerftOptions) -> Self {
+        // With several threads, each thread counts its subtrees with a `Perft` of its own, so the
+        // one splitting up the root moves never needs a table.
+        let table =
+            if options.hash > 0 && options.threads <= 1 && !options.detailed && !options.checks {
+                Some(PerftTable::new(options.hash))
+            } else {
+                None
+            };
+
         Self {
             options,
             position,
             data: PerftDataInternal::new(),
+            table,
+            movestack: MoveStack::new(),
         }
     }
 
     fn output(&self) -> PerftData {
-        let check: Option<usize>;
-        let checkmate: Option<usize>;
-
-        if self.options.checks {
-            check = Some(self.data.check);
-            checkmate = Some(self.data.checkmate);
-        } else {
-            check = None;
-            checkmate = None;
-        };
+        let detailed = |n: usize| self.options.detailed.then_some(n);
+        let checks = |n: usize| self.options.checks.then_some(n);
 
         PerftData {
             nodes: Some(self.data.nodes),
-            captures: Some(self.data.captures),
-            en_passant: Some(self.data.en_passant),
-            castles: Some(self.data.castles),
-            promotions: Some(self.data.promotions),
-            checkmate,
-            check,
+            captures: detailed(self.data.captures),
+            en_passant: detailed(self.data.en_passant),
+            castles: detailed(self.data.castles),
+            promotions: detailed(self.data.promotions),
+            checkmate: checks(self.data.checkmate),
+            check: checks(self.data.check),
         }
     }
 
     fn perft_inner(&mut self, depth: usize) {
         if depth == 0 {
             self.data.nodes += 1;
+            return;
         }
 
-        let moves = self.position.generate::<_, All, Legal>();
+        if self.options.movestack {
+            let mut moves = self.movestack.new_frame();
+            self.position.generate_in::<_, All, Legal>(&mut moves);
+            self.perft_moves(&moves, depth);
+        } else {
+            let moves = self.position.generate::<BasicMoveList, All, Legal>();
+            self.perft_moves(&moves, depth);
+        }
+    }
 
+    /// Count the nodes below each of `moves`, the legal moves in the current position.
+    fn perft_moves<'m, ML>(&mut self, moves: &'m ML, depth: usize)
+    where
+        ML: MoveList,
+        &'m ML: IntoIterator<Item = &'m Move>,
+    {
         if depth == 1 {
-            self.handle_leaf(&moves);
-        } else {
-            for mov in &moves {
-                self.recurse(mov, depth - 1);
-            }
+            self.handle_leaf(moves);
+            return;
+        }
+
+        let key = self.position.zobrist().0;
+        if let Some(nodes) = self.table.as_ref().and_then(|t| t.probe(key, depth)) {
+            self.data.nodes += nodes;
+            return;
+        }
+
+        let nodes_before = self.data.nodes;
+        for mov in moves {
+            self.recurse(mov, depth - 1);
+        }
+
+        if let Some(table) = self.table.as_mut() {
+            table.store(key, depth, self.data.nodes - nodes_before);
         }
     }
 
@@ -200,10 +384,25 @@ impl<'a> Perft<'a> {
         print_data: bool,
     ) -> PerftData {
         let perft_options = PerftOptions::new(collect_detailed_data, collect_check_data);
+        Self::perft_with_options(position, depth, perft_options, print_data)
+    }
+
+    /// Runs perft on the given position and to the given depth, with the given `PerftOptions`.
+    pub fn perft_with_options(
+        position: &'a mut Position,
+        depth: usize,
+        perft_options: PerftOptions,
+        print_data: bool,
+    ) -> PerftData {
         let mut perft = Self::new(position, perft_options);
 
         let start = Instant::now();
-        perft.perft_inner(depth);
+        if perft.options.threads > 1 && depth >= 2 {
+            let moves = perft.position.generate::<_, All, Legal>();
+            perft.subtrees(&moves, depth);
+        } else {
+            perft.perft_inner(depth);
+        }
         let elapsed = start.elapsed();
 
         if print_data {
@@ -226,38 +425,111 @@ impl<'a> Perft<'a> {
         depth: usize,
         collect_detailed_data: bool,
         collect_check_data: bool,
-    ) -> PerftData {
-        assert!(depth >= 1);
+    ) -> PerftResult {
         let perft_options = PerftOptions::new(collect_detailed_data, collect_check_data);
-        let mut perft = Self::new(position, perft_options);
+        Self::divide_with_options(position, depth, perft_options)
+    }
 
-        let mut cumulative_nodes: usize = 0;
-        let start = Instant::now();
+    /// Runs the "divide" perft routine on the given position and to the given depth, with the
+    /// given `PerftOptions`. The root moves are sorted, so that the result doesn't depend on the
+    /// number of threads.
+    pub fn divide_with_options(
+        position: &'a mut Position,
+        depth: usize,
+        perft_options: PerftOptions,
+    ) -> PerftResult {
+        assert!(depth >= 1);
+        let mut perft = Self::new(position, perft_options);
 
-        let moves = perft.position.generate::<_, All, Legal>();
+        let mut moves = perft.position.generate::<BasicMoveList, All, Legal>();
+        moves.sort_by_key(|mov| mov.to_string());
 
-        if depth == 1 {
+        let counts: Vec<usize> = if depth == 1 {
             perft.handle_leaf(&moves);
-            for mov in &moves {
-                println!("{}: 1", mov);
-            }
+            vec![1; moves.len()]
         } else {
-            for mov in &moves {
-                perft.recurse(mov, depth - 1);
-                let new_nodes_for_mov = perft.data.nodes - cumulative_nodes;
-                println!("{}: {}", mov, new_nodes_for_mov.separated_string());
-                cumulative_nodes += new_nodes_for_mov;
-            }
+            let subtrees = perft.subtrees(&moves, depth);
+            subtrees.iter().map(|data| data.nodes).collect()
+        };
+
+        PerftResult {
+            nodes: perft.data.nodes,
+            moves: moves.iter().copied().zip(counts).collect(),
+            data: perft.output(),
         }
-        let elapsed = start.elapsed();
-        println!("{}", perft);
-        println!("Time: {}ms", elapsed.as_millis());
-        perft.output()
+    }
+
+    /// Runs perft to `depth - 1` after each of the root `moves`, adding the results to the
+    /// running totals and returning the data for each move's subtree in turn.
+    ///
+    /// With more than one thread, each thread works on its own clone of the position, repeatedly
+    /// taking the next root move which no thread has started on.
+    fn subtrees(&mut self, moves: &BasicMoveList, depth: usize) -> Vec<PerftDataInternal> {
+        if self.options.threads <= 1 {
+            return moves
+                .iter()
+                .map(|mov| {
+                    let before = self.data;
+                    self.recurse(mov, depth - 1);
+                    self.data - before
+                })
+                .collect();
+        }
+
+        let next = AtomicUsize::new(0);
+        let position: &Position = self.position;
+        let options = self.options.with_threads(1);
+
+        let mut subtrees = vec![PerftDataInternal::new(); moves.len()];
+        let results: Vec<(usize, PerftDataInternal)> = thread::scope(|s| {
+            let handles: Vec<_> = (0..self.options.threads)
+                .map(|_| {
+                    s.spawn(|| {
+                        let mut position = position.clone();
+                        let mut perft = Perft::new(&mut position, options);
+                        let mut results = Vec::new();
+
+                        loop {
+                            let i = next.fetch_add(1, Ordering::Relaxed);
+                            if i >= moves.len() {
+                                break results;
+                            }
+
+                            let before = perft.data;
+                            perft.recurse(&moves[i], depth - 1);
+                            results.push((i, perft.data - before));
+                        }
+                    })
+                })
+                .collect();
+
+            handles
+                .into_iter()
+                .flat_map(|h| h.join().expect("perft thread panicked"))
+                .collect()
+        });
+
+        for (i, data) in results {
+            subtrees[i] = data;
+            self.data += data;
+        }
+
+        subtrees
     }
 
     #[inline(always)]
-    fn handle_leaf(&mut self, moves: &BasicMoveList) {
-        self.data.nodes += moves.len();
+    fn handle_leaf<'m, ML>(&mut self, moves: &'m ML)
+    where
+        ML: MoveList,
+        &'m ML: IntoIterator<Item = &'m Move>,
+    {
+        if self.options.bulk {
+            self.data.nodes += moves.len();
+        } else {
+            for mov in moves {
+                self.recurse(mov, 0);
+            }
+        }
 
         if self.options.detailed || self.options.checks {
             for mov in moves {
@@ -274,7 +546,7 @@ impl<'a> Perft<'a> {
                         self.data.castles += 1;
                     }
 
-                    if mov.is_promo() {
+                    if mov.is_promotion() {
                         self.data.promotions += 1;
                     }
                 }
@@ -334,6 +606,8 @@ mod tests {
         res.nodes.unwrap()
     }
 
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
     /// Run a comprehensive perft suite based on the position found at 
     /// the [chess programming wiki](https://www.chessprogramming.org/Perft_Results) 
     /// to test for any movegen, make move or unmake move regressions.
@@ -346,4 +620,138 @@ mod tests {
             assert_eq!(run_perft(p, d), r);
         }
     }
+
+    /// Counting the leaves in bulk must give the same result as making every leaf move.
+    #[test]
+    fn bulk_counting_matches_make_unmake() {
+        setup();
+
+        let nodes = |bulk: bool| {
+            let mut pos = Position::from_fen(KIWIPETE).unwrap();
+            let options = PerftOptions::new(true, false).with_bulk(bulk);
+            let res = Perft::perft_with_options(&mut pos, 4, options, false);
+            (
+                res.nodes,
+                res.captures,
+                res.en_passant,
+                res.castles,
+                res.promotions,
+            )
+        };
+
+        let bulk = nodes(true);
+        assert_eq!(
+            bulk,
+            (
+                Some(4_085_603),
+                Some(757_163),
+                Some(1_929),
+                Some(128_013),
+                Some(15_172)
+            )
+        );
+        assert_eq!(bulk, nodes(false));
+    }
+
+    /// Keeping the moves in a `MoveStack` must give the same result as keeping them in arrays.
+    #[test]
+    fn movestack_perft_matches_array_perft() {
+        setup();
+
+        let nodes = |movestack: bool, bulk: bool, depth: usize| {
+            let mut pos = Position::from_fen(KIWIPETE).unwrap();
+            let options = PerftOptions::new(true, false)
+                .with_movestack(movestack)
+                .with_bulk(bulk);
+            let res = Perft::perft_with_options(&mut pos, depth, options, false);
+            (
+                res.nodes,
+                res.captures,
+                res.en_passant,
+                res.castles,
+                res.promotions,
+            )
+        };
+
+        let movestack = nodes(true, true, 4);
+        assert_eq!(movestack.0, Some(4_085_603));
+        assert_eq!(movestack, nodes(false, true, 4));
+
+        // Making every leaf move pushes and drops a frame below each of them.
+        assert_eq!(nodes(true, false, 4), nodes(false, false, 4));
+    }
+
+    /// Caching subtree counts must give the same result as counting every subtree.
+    #[test]
+    fn hashed_perft_matches_unhashed() {
+        setup();
+
+        let nodes = |hash: usize| {
+            let mut pos = Position::from_fen(KIWIPETE).unwrap();
+            let options = PerftOptions::new(false, false).with_hash(hash);
+            Perft::perft_with_options(&mut pos, 4, options, false).nodes
+        };
+
+        assert_eq!(nodes(16), Some(4_085_603));
+        assert_eq!(nodes(16), nodes(0));
+
+        // A table too small to hold much still gives the right count.
+        assert_eq!(nodes(1), Some(4_085_603));
+    }
+
+    /// Splitting the root moves between threads must give the same result as a single thread.
+    #[test]
+    fn parallel_perft_matches_single_threaded() {
+        setup();
+
+        let nodes = |threads: usize| {
+            let mut pos = Position::from_fen(KIWIPETE).unwrap();
+            let options = PerftOptions::new(true, false).with_threads(threads);
+            let res = Perft::perft_with_options(&mut pos, 4, options, false);
+            (
+                res.nodes,
+                res.captures,
+                res.en_passant,
+                res.castles,
+                res.promotions,
+            )
+        };
+
+        assert_eq!(nodes(4).0, Some(4_085_603));
+        assert_eq!(nodes(4), nodes(1));
+
+        // Each thread caches subtree counts in a table of its own, and the perft splitting up the
+        // root moves doesn't allocate one.
+        let mut pos = Position::from_fen(KIWIPETE).unwrap();
+        let options = PerftOptions::new(false, false)
+            .with_threads(4)
+            .with_hash(16);
+        assert!(Perft::new(&mut pos, options).table.is_none());
+        let res = Perft::perft_with_options(&mut pos, 4, options, false);
+        assert_eq!(res.nodes, Some(4_085_603));
+    }
+
+    #[test]
+    fn divide_counts_each_root_move() {
+        setup();
+
+        let mut pos = Position::start_pos();
+        let res = Perft::divide(&mut pos, 3, false, false);
+
+        assert_eq!(res.nodes, 8_902);
+        assert_eq!(res.moves.len(), 20);
+        assert_eq!(res.moves.iter().map(|&(_, n)| n).sum::<usize>(), res.nodes);
+
+        #[rustfmt::skip]
+        let expected = [
+            ("a2a3", 380), ("b2b3", 420), ("c2c3", 420), ("d2d3", 539), ("e2e3", 599),
+            ("f2f3", 380), ("g2g3", 420), ("h2h3", 380), ("a2a4", 420), ("b2b4", 421),
+            ("c2c4", 441), ("d2d4", 560), ("e2e4", 600), ("f2f4", 401), ("g2g4", 421),
+            ("h2h4", 420), ("b1a3", 400), ("b1c3", 440), ("g1f3", 440), ("g1h3", 400),
+        ];
+        for (mov, nodes) in expected {
+            assert_eq!(res.nodes_for(mov), Some(nodes), "{}", mov);
+        }
+        assert_eq!(res.nodes_for("e2e5"), None);
+    }
 }
@@ -16,71 +16,63 @@
 use core::mov::Move;
 
 /// Table for storing the principal variation during search.
+///
+/// The table is triangular and indexed by ply: the row for ply `k` holds the best line found from
+/// the node currently being searched at that ply, and has room for `max_ply - k + 1` moves. Rows
+/// are indexed by ply rather than remaining depth, so that lines may be extended or reduced.
 pub struct PVTable {
     data: Vec<Move>,
-    depth: usize,
+    size: usize,
 }
 
 impl PVTable {
-    pub fn new(depth: u8) -> Self {
-        let d = depth as usize;
+    /// Create a table for lines which reach at most `max_ply` plies from the root.
+    pub fn new(max_ply: u8) -> Self {
+        let size = max_ply as usize + 1;
         Self {
-            data: vec![Move::null(); d * d],
-            depth: d,
+            data: vec![Move::null(); size * size],
+            size,
         }
     }
 
-    /// Called when a move searched at depth `d` improves the score. Copies the current principal
-    /// variation from depth `d-1` and copies it into the depth `d` column of the table, appending
-    /// the new move to the end.
-    pub fn copy_to(&mut self, d: u8, mov: Move) {
-        match d {
-            1 => self.update_leaf(mov),
-            n => self.update_internal(n, mov),
-        }
-    }
-
-    /// Called when a move searched at depth `d` turns out to be a checkmate or stalemate position,
-    /// meaning that there is no variation following this point, and no move to include.
-    pub fn pv_leaf_at(&mut self, d: u8) {
-        let m = self.depth;
-        let d = d as usize;
-        let k = m - d;
-        let _ = &mut self.data[(k * m)..(k * m + d)].fill(Move::null());
-    }
-
+    /// Called when a node at `ply` is entered, clearing any line left in its row by an earlier
+    /// node at the same ply. If the node turns out to be a leaf, e.g. checkmate or stalemate, it
+    /// leaves no variation following it.
     #[inline(always)]
-    fn update_leaf(&mut self, mov: Move) {
-        // Safety: we never mutate `self.depth` after the `PVTable` struct is created, so this
-        // index is guaranteed to exist.
-        let m = self.depth;
-        unsafe { *self.data.get_unchecked_mut(m * (m - 1)) = mov };
+    pub fn clear(&mut self, ply: u8) {
+        let k = ply as usize;
+        debug_assert!(k < self.size);
+
+        // Safety: `k < self.size`, so this index is within the table.
+        unsafe { *self.data.get_unchecked_mut(k * self.size) = Move::null() };
     }
 
-    #[inline(always)]
-    fn update_internal(&mut self, d: u8, mov: Move) {
-        let m = self.depth;
-        let d = d as usize;
-        let k = m - d;
+    /// Called when a move searched at `ply` improves the score. Writes the move to the row for
+    /// `ply`, followed by the line from the row for `ply + 1`.
+    pub fn copy_to(&mut self, ply: u8, mov: Move) {
+        let m = self.size;
+        let k = ply as usize;
+        debug_assert!(k < m);
 
-        let _ = &mut self
-            .data
-            .copy_within(((k + 1) * m)..((k + 1) * m + d - 1), k * m);
+        self.data[k * m] = mov;
 
-        self.data[k * m + d - 1] = mov;
+        if k + 1 < m {
+            self.data
+                .copy_within(((k + 1) * m)..((k + 1) * m + m - k - 1), k * m + 1);
+        }
     }
 
     /// Get an iterator over the principal variation.
     pub fn pv(&self) -> PVIter<'_> {
         PVIter {
-            iter: self.data[0..self.depth].iter().rev(),
+            iter: self.data[0..self.size].iter(),
         }
     }
 }
 
 /// An iterator over the principal variation.
 pub struct PVIter<'a> {
-    iter: std::iter::Rev<std::slice::Iter<'a, Move>>,
+    iter: std::slice::Iter<'a, Move>,
 }
 
 impl<'a> Iterator for PVIter<'a> {
@@ -95,7 +87,7 @@ impl<'a> Iterator for PVIter<'a> {
 
 impl std::fmt::Debug for PVTable {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let d = self.depth;
+        let d = self.size;
 
         write!(f, "    │ ")?;
         for col in 0..d {
@@ -112,7 +104,7 @@ impl std::fmt::Debug for PVTable {
         for row in 0..d {
             write!(f, " {:>2} │ ", row)?;
             for col in 0..d {
-                let mov = self.data[col * d + (d - row - 1)];
+                let mov = self.data[col * d + row];
                 if mov.is_null() {
                     write!(f, "  *   │ ")?;
                 } else {
@@ -12,8 +12,13 @@ use std::ops::{Add, Neg, Sub};
 /// We had to write a rather complex custom implementation of `std::cmp::Ord` for this data
 /// structure, in order to use it effectively as a score. This involved a match statement with lots
 /// of arms to handle the full cartesian product of variants. This is too expensive for a structure
-/// on the hot path. We can instead implement the whole thing with a single i16 and some judicious
-/// choices of value.
+/// on the hot path. We can instead implement the whole thing with a single integer and some
+/// judicious choices of value.
+///
+/// The integer is an i32, although every score fits in an i16. This leaves headroom for
+/// intermediate arithmetic on scores (e.g. adding a margin to a mate score) to be carried out
+/// without overflowing before it is clamped. Scores are still stored as i16 in the transposition
+/// table.
 ///
 /// * -10_000 - 10_000 -> centipawn evaluations
 /// * 20_000 - 20_100 -> positive mate-in-N (i.e. the player to move is mating the opponent)
@@ -25,7 +30,18 @@ use std::ops::{Add, Neg, Sub};
 /// * -30_000 -> negative infinity
 /// * 30_000 -> positive infinity
 #[derive(Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
-pub struct Score(i16);
+pub struct Score(i32);
+
+/// The score given to a tablebase win at the node where it is found. This is above any evaluation
+/// the search would otherwise reach, but below mate scores.
+pub const TB_WIN: i32 = 9_000;
+
+/// Scores between this and `TB_WIN` are tablebase wins, found some number of plies further on.
+/// This leaves room for a win found at any ply of the search.
+const TB_WIN_FURTHEST: i32 = TB_WIN - 128;
+
+/// The most moves to mate a `Score` can represent: mate-in-100 plies is the furthest mate.
+pub const MAX_MATE_MOVES: u8 = 50;
 
 impl Score {
     /// Represents negative infinity.
@@ -34,10 +50,12 @@ impl Score {
     /// Represents positive infinity.
     pub const INF_P: Score = Score(30_000);
 
-    /// Increment the depth to mate if this is a mate score. Otherwise, leave.
+    /// Increment the depth to mate if this is a mate score, or the distance to a tablebase result
+    /// if this is a tablebase score. Otherwise, leave.
     ///
     /// This is useful in search routines where we recursively call and need to increment the depth
-    /// to mate from the parent position.
+    /// to mate from the parent position. Counting down tablebase scores in the same way means the
+    /// search prefers the nearer of two tablebase wins, so that it makes progress towards them.
     pub fn inc_mate(self) -> Self {
         // We can't increment mate further when we are at mate-in-100 (represented by +/- 20_000).
         // But of course, this is never going to happen.
@@ -50,6 +68,10 @@ impl Score {
             Score(self.0 + 1)
         } else if self.0 > 20_000 {
             Score(self.0 - 1)
+        } else if self.0 > TB_WIN_FURTHEST && self.0 <= TB_WIN {
+            Score(self.0 - 1)
+        } else if self.0 < -TB_WIN_FURTHEST && self.0 >= -TB_WIN {
+            Score(self.0 + 1)
         } else {
             self
         }
@@ -60,17 +82,16 @@ impl Score {
         debug_assert!(n.abs() <= 100);
 
         if n > 0 {
-            Score(20_100 - n as i16)
+            Score(20_100 - n as i32)
         } else {
-            Score(-20_100 - n as i16)
+            Score(-20_100 - n as i32)
         }
     }
 
-    /// Construct a score representing `x` centipawns.
-    pub fn cp(x: i16) -> Self {
-        debug_assert!(x <= 10_000);
-        debug_assert!(x >= -10_000);
-        Score(x)
+    /// Construct a score representing `x` centipawns. Values outside of the centipawn range
+    /// saturate at its bounds.
+    pub fn cp(x: i32) -> Self {
+        Score(x.clamp(-10_000, 10_000))
     }
 
     /// Convenience for `Score::cp(0)`.
@@ -78,8 +99,15 @@ impl Score {
         Self::cp(0)
     }
 
+    /// The compact representation of this score, e.g. for storage in the transposition table.
     pub fn to_i16(&self) -> i16 {
-        self.0
+        debug_assert!(self.0.abs() <= 30_000);
+        self.0 as i16
+    }
+
+    /// The inverse of `to_i16`.
+    pub fn from_i16(x: i16) -> Self {
+        Score(x as i32)
     }
 
     /// True if this `Score` represents a forced mate-in-n.
@@ -91,6 +119,16 @@ impl Score {
         }
     }
 
+    /// The number of plies to mate, if this `Score` represents the side to move mating the
+    /// opponent.
+    pub fn plies_to_mate(&self) -> Option<u8> {
+        if self.0 > 20_000 && self.0 <= 20_100 {
+            Some((20_100 - self.0) as u8)
+        } else {
+            None
+        }
+    }
+
     /// True if this `Score` represents a centipawn evaluation.
     pub fn is_cp(&self) -> bool {
         if -10_000 <= self.0 && self.0 <= 10_000 {
@@ -106,6 +144,29 @@ impl Score {
     pub fn inc_one(&self) -> Self {
         Self(self.0 + 1)
     }
+
+    /// The score as reported to the GUI after `score` in a UCI info line: `cp <x>` for a centipawn
+    /// score, or `mate <y>` for a mate score, where `y` is the number of moves (not plies) to mate,
+    /// negative if the side to move is getting mated.
+    pub fn to_uci_string(&self) -> String {
+        debug_assert!(self.0.abs() < 30_000);
+
+        if self.0 > 20_000 {
+            let plies_to_mate = 20_100 - self.0;
+            format!("mate {}", (plies_to_mate + 1) / 2)
+        } else if self.0 < -20_000 {
+            let plies_to_mate = self.0 + 20_100;
+            format!("mate -{}", (plies_to_mate + 1) / 2)
+        } else {
+            format!("cp {}", self.0)
+        }
+    }
+
+    /// Decrement the value of the score by 1, regardless of whether it is a centipawn score or a
+    /// mate-in-N score. The counterpart to `inc_one`, for building null windows just below a bound.
+    pub fn dec_one(&self) -> Self {
+        Self(self.0 - 1)
+    }
 }
 
 impl Neg for Score {
@@ -168,24 +229,8 @@ impl std::fmt::Display for Score {
             write!(f, "+∞")
         } else if self.0 == -30_000 {
             write!(f, "-∞")
-        } else if self.0 < -20_000 {
-            let plies_to_mate = self.0 + 20_100;
-            let moves_to_mate = plies_to_mate / 2;
-
-            debug_assert!(plies_to_mate % 2 == 0); // When negative, the side to move is getting mated,
-                                                   // so this should always be an even number of plies.
-
-            write!(f, "mate -{}", moves_to_mate)
-        } else if self.0 > 20_000 {
-            let plies_to_mate = 20_100 - self.0;
-            let moves_to_mate = (plies_to_mate + 1) / 2;
-
-            debug_assert!(plies_to_mate % 2 == 1); // When positive, the opponent is getting mated,
-                                                   // so this should always be an odd number of plies.
-
-            write!(f, "mate {}", moves_to_mate)
         } else {
-            write!(f, "cp {}", self.0)
+            write!(f, "{}", self.to_uci_string())
         }
     }
 }
@@ -213,4 +258,67 @@ mod tests {
         assert!(Score::cp(0) > Score::INF_N);
         assert!(Score::cp(0) < Score::INF_P);
     }
+
+    #[test]
+    fn uci_strings() {
+        assert_eq!(Score::cp(35).to_uci_string(), "cp 35");
+        assert_eq!(Score::cp(-120).to_uci_string(), "cp -120");
+        assert_eq!(Score::zero().to_uci_string(), "cp 0");
+
+        // Mating the opponent on our 1st and 3rd moves: 1 and 5 plies.
+        assert_eq!(Score::mate(1).to_uci_string(), "mate 1");
+        assert_eq!(Score::mate(5).to_uci_string(), "mate 3");
+
+        // Getting mated on the opponent's 1st and 2nd moves: 2 and 4 plies.
+        assert_eq!(Score::mate(-2).to_uci_string(), "mate -1");
+        assert_eq!(Score::mate(-4).to_uci_string(), "mate -2");
+
+        assert_eq!(format!("{}", Score::mate(5)), "mate 3");
+        assert_eq!(format!("{}", Score::INF_N), "-∞");
+    }
+
+    #[test]
+    fn arithmetic_saturates() {
+        // Centipawn arithmetic saturates at the centipawn bounds, rather than overflowing.
+        assert_eq!(
+            Score::cp(i16::MAX as i32) - Score::cp(30_000),
+            Score::zero()
+        );
+        assert_eq!(Score::cp(9_000) + Score::cp(5_000), Score::cp(10_000));
+        assert_eq!(Score::cp(-9_000) - Score::cp(5_000), Score::cp(-10_000));
+        assert!(Score::cp(10_000) < Score::mate(99));
+
+        // Mate scores are unaffected by centipawn margins.
+        assert_eq!(Score::mate(3) + Score::cp(500), Score::mate(3));
+        assert_eq!(Score::mate(-4) - Score::cp(500), Score::mate(-4));
+
+        // Incrementing the distance to mate keeps the ordering of mate distances, and leaves
+        // the infinities alone.
+        assert_eq!(Score::mate(1).inc_mate(), Score::mate(2));
+        assert_eq!(Score::mate(-2).inc_mate(), Score::mate(-3));
+        assert!(Score::mate(1).inc_mate() > Score::mate(3));
+        assert!(Score::mate(-2).inc_mate() > Score::mate(-2));
+        assert_eq!(Score::INF_P.inc_mate(), Score::INF_P);
+        assert_eq!(Score::INF_N.inc_mate(), Score::INF_N);
+        assert_eq!(-Score::INF_P, Score::INF_N);
+
+        // Tablebase wins and losses count down in the same way, so a nearer win outranks a farther
+        // one, but ordinary evaluations are left alone.
+        let tb_win = Score::cp(TB_WIN);
+        assert!(tb_win > tb_win.inc_mate());
+        assert!(tb_win.inc_mate() > tb_win.inc_mate().inc_mate());
+        assert!((-tb_win).inc_mate() > -tb_win);
+        assert_eq!(Score::cp(250).inc_mate(), Score::cp(250));
+
+        // Every score survives the round trip through its compact representation.
+        for score in [
+            Score::INF_N,
+            Score::mate(-7),
+            Score::cp(-42),
+            Score::mate(5),
+            Score::INF_P,
+        ] {
+            assert_eq!(Score::from_i16(score.to_i16()), score);
+        }
+    }
 }
@@ -1,23 +1,48 @@
-use crate::history::HistoryTable;
+use crate::history::{ContinuationHistory, Histories, HistoryTable, PieceTo, HISTORY_MAX};
 
-use super::eval::Evaluation;
+use super::countermove::CounterMoveTable;
+use super::eval::{
+    fifty_move_scale, piece_value, scale_eval, Evaluation, PawnHashTable, BISHOP_VALUE, ROOK_VALUE,
+};
 use super::info::{CurrMoveInfo, Info, PvInfo};
 use super::killer::KillerTable;
-use super::ordering::{Loader, OrderedMoves, ScoredMoveList, Scorer};
+use super::lmr::{self, LMR_MIN_DEPTH, LMR_MOVE_THRESHOLD};
+use super::ordering::{capture_score, Loader, OrderedMoves, ScoredMoveList, Scorer};
 use super::pv_table::PVTable;
 use super::score::Score;
-use super::trace::Tracer;
+use super::see::exchange_pieces;
+#[cfg(feature = "syzygy")]
+use super::tablebase::{self, Tablebases};
+use super::time::Deadline;
+use super::trace::{SearchStats, Tracer};
 use super::tt::{Bound, Table};
 
 use core::mono_traits::{All as AllGen, Captures, Legal, QueenPromotions, Quiets};
 use core::mov::Move;
 use core::movelist::{BasicMoveList, MoveList};
-use core::position::{Player, Position};
+use core::position::{Piece, PieceType, Player, Position, Square};
 
 use separator::Separatable;
 
 use std::ops::Neg;
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// The maximum depth of search, in plies. This is bounded by the distance to mate which a `Score`
+/// can represent (99 plies), less one for mate distance pruning at the deepest node.
+pub const MAX_PLY: u8 = 98;
+
+/// The margin for delta pruning in quiescence search. A capture is skipped if winning the captured
+/// piece outright would still leave the static evaluation this far below alpha.
+const DELTA_MARGIN: i32 = 200;
+
+/// Delta pruning is only applied while the side to move has more than this much non-pawn material.
+/// In endgames a single capture can swing the evaluation by far more than the material it wins.
+const DELTA_PRUNING_MIN_MATERIAL: i16 = ROOK_VALUE + BISHOP_VALUE;
+
+/// The clock is only read once in this many nodes, as reading it at every node costs a noticeable
+/// share of the search speed. Must be a power of two.
+const TIME_CHECK_INTERVAL: usize = 2048;
 
 /// Trait to monomorphize search functionality over different thread types: master and worker.
 ///
@@ -56,7 +81,13 @@ impl Thread for Worker {
 /// checks) is an All node.
 /// * A Cut node becomes an All node once all the candidate cutoff moves are searched.
 /// * Children of All nodes are Cut nodes.
+///
+/// `NonPv` is a generic non-PV node for callers which don't care about the Cut/All distinction
+/// (e.g. razoring). Its children are also `NonPv`.
 pub trait NodeType {
+    /// The node type of a child searched with a null window from a node of this type.
+    type NullWindowChild: NodeType;
+
     fn pv() -> bool;
     fn cut() -> bool;
     fn all() -> bool;
@@ -66,6 +97,8 @@ pub trait NodeType {
 /// Dummy type representing a PV node.
 pub struct Pv;
 impl NodeType for Pv {
+    type NullWindowChild = Cut;
+
     fn pv() -> bool {
         true
     }
@@ -83,6 +116,8 @@ impl NodeType for Pv {
 /// Dummy type representing a non-PV node.
 pub struct NonPv;
 impl NodeType for NonPv {
+    type NullWindowChild = NonPv;
+
     fn pv() -> bool {
         false
     }
@@ -100,6 +135,8 @@ impl NodeType for NonPv {
 /// Dummy type representing a CUT node.
 pub struct Cut;
 impl NodeType for Cut {
+    type NullWindowChild = All;
+
     fn pv() -> bool {
         false
     }
@@ -117,6 +154,8 @@ impl NodeType for Cut {
 /// Dummy type representing an ALL node.
 pub struct All;
 impl NodeType for All {
+    type NullWindowChild = Cut;
+
     fn pv() -> bool {
         false
     }
@@ -134,6 +173,8 @@ impl NodeType for All {
 /// Dummy type representing the root node. This is also a PV node.
 pub struct Root;
 impl NodeType for Root {
+    type NullWindowChild = Cut;
+
     fn pv() -> bool {
         true
     }
@@ -148,6 +189,18 @@ impl NodeType for Root {
     }
 }
 
+/// A search feature which tests can switch off, to measure what it is worth. Outside of tests,
+/// every feature is always on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Feature {
+    LateMoveReductions,
+    CounterMoves,
+    ButterflyHistory,
+    ContinuationHistory,
+    CheckExtensions,
+    DeltaPruning,
+}
+
 /// Manages the search.
 pub struct Search<'engine> {
     /// The internal board position.
@@ -160,37 +213,186 @@ pub struct Search<'engine> {
     tt: &'engine Table,
     /// The killer move table.
     kt: KillerTable,
+    /// The countermove table.
+    counters: CounterMoveTable,
     /// The history table.
     history: HistoryTable,
+    /// Continuation history, keyed on the moves one and two plies earlier in the line.
+    cont_history: ContinuationHistory,
+    /// Cache of pawn structure evaluations.
+    pawn_hash: PawnHashTable,
     /// Flag to indicate when the search should start unwinding due to user intervention.
     stopping: &'engine AtomicBool,
-    /// Time to at which to end search.
-    stop_time: Option<std::time::Instant>,
+    /// Time to at which to end search. This can be moved by the engine while the search is
+    /// running (e.g. on `ponderhit`).
+    stop_time: &'engine Deadline,
+    /// Whether the deadline had passed when the clock was last read.
+    time_up: bool,
     search_depth: u8,
+    /// The distance of the current node from the root, in plies. Reductions mean that this can be
+    /// less than the difference between the search depth and the remaining depth.
+    ply: u8,
+    /// The furthest distance from the root reached so far in the search, including quiescence.
+    seldepth: u8,
     depth_reached: u8,
+    /// The index of this search thread. The master thread is thread 0.
+    thread_id: usize,
+    /// The number of principal variations to search for.
+    multipv: usize,
+    /// Root moves which are excluded from the search, because they head principal variations
+    /// already found at the current depth.
+    excluded: Vec<Move>,
+    /// The root moves to search, if restricted by `go searchmoves`.
+    root_moves: Option<Vec<Move>>,
+    /// The principal variations from the last completed depth, best first.
+    lines: Vec<(Score, Vec<Move>)>,
+    /// The number of nodes after which the search should stop, if any.
+    node_limit: Option<u64>,
+    /// When searching for a mate, the maximum number of moves to mate.
+    mate_limit: Option<u8>,
+    /// Whether this is an infinite search, which must not report a best move until stopped.
+    infinite: bool,
+    /// How much the root side dislikes draws, in centipawns.
+    contempt: i32,
+    /// Whether to report telemetry about the search as `info string`s, under UCI `debug on`.
+    debug: bool,
+    /// The search features which a test has switched off.
+    #[cfg(test)]
+    disabled: Vec<Feature>,
+    /// Syzygy tablebases to probe, if any are configured.
+    #[cfg(feature = "syzygy")]
+    tb: Option<&'engine Tablebases>,
 }
 
 impl<'engine> Search<'engine> {
     pub fn new(
         pos: Position,
         flag: &'engine AtomicBool,
-        stop_time: Option<std::time::Instant>,
+        stop_time: &'engine Deadline,
         tt: &'engine Table,
     ) -> Self {
         Self {
             pos,
             tt,
-            kt: KillerTable::new(20),
+            kt: KillerTable::new(MAX_PLY as usize),
+            counters: CounterMoveTable::new(),
             history: HistoryTable::new(),
-            pvt: PVTable::new(8),
+            cont_history: ContinuationHistory::new(),
+            pawn_hash: PawnHashTable::new(),
+            pvt: PVTable::new(MAX_PLY),
             trace: Tracer::new(),
             stopping: flag,
             stop_time,
+            time_up: false,
             search_depth: 0,
+            ply: 0,
+            seldepth: 0,
             depth_reached: 0,
+            thread_id: 0,
+            multipv: 1,
+            excluded: Vec::new(),
+            root_moves: None,
+            lines: Vec::new(),
+            node_limit: None,
+            mate_limit: None,
+            infinite: false,
+            contempt: 0,
+            debug: false,
+            #[cfg(test)]
+            disabled: Vec::new(),
+            #[cfg(feature = "syzygy")]
+            tb: None,
+        }
+    }
+
+    /// Set the index of this search thread, for Lazy SMP. Worker threads start iterative
+    /// deepening at staggered depths, so that they diversify the tree rather than all repeating
+    /// the master's work.
+    pub fn with_thread_id(mut self, thread_id: usize) -> Self {
+        self.thread_id = thread_id;
+        self
+    }
+
+    /// Carry on from the history tables left by an earlier search, if any, rather than starting
+    /// from empty ones.
+    pub fn with_history(mut self, history: Option<Histories>) -> Self {
+        if let Some(history) = history {
+            self.history = history.butterfly;
+            self.cont_history = history.continuation;
+        }
+        self
+    }
+
+    /// Give up the history tables, to hand on to the next search.
+    pub fn into_history(self) -> Histories {
+        Histories {
+            butterfly: self.history,
+            continuation: self.cont_history,
         }
     }
 
+    /// Set the number of principal variations to search for and report. At each depth, the best
+    /// line is found first, and then the root is re-searched with the root moves of the lines
+    /// found so far excluded, until there are `multipv` lines or no root moves remain.
+    pub fn with_multipv(mut self, multipv: usize) -> Self {
+        debug_assert!(multipv > 0);
+        self.multipv = multipv;
+        self
+    }
+
+    /// Set a limit on the number of nodes (main search and quiescence) to visit. Once the limit is
+    /// reached, the search stops as if it had run out of time.
+    pub fn with_node_limit(mut self, node_limit: Option<u64>) -> Self {
+        self.node_limit = node_limit;
+        self
+    }
+
+    /// Search for a forced mate in at most `mate_limit` moves. Iterative deepening ends as soon as
+    /// such a mate is proven. If none is found, the search runs to its depth limit and returns the
+    /// best move anyway.
+    pub fn with_mate_limit(mut self, mate_limit: Option<u8>) -> Self {
+        self.mate_limit = mate_limit;
+        self
+    }
+
+    /// Restrict the search to the given root moves, if any. All other root moves are ignored, and
+    /// multiple principal variations are only drawn from these moves.
+    pub fn with_root_moves(mut self, root_moves: Option<Vec<Move>>) -> Self {
+        debug_assert!(root_moves.as_ref().is_none_or(|moves| !moves.is_empty()));
+        self.root_moves = root_moves;
+        self
+    }
+
+    /// Mark this as an infinite search (`go infinite`, or pondering). Under UCI, the best move of
+    /// an infinite search is only reported once the search is stopped, so if iterative deepening
+    /// runs out of depth first, the master waits for the stop signal.
+    pub fn with_infinite(mut self, infinite: bool) -> Self {
+        self.infinite = infinite;
+        self
+    }
+
+    /// Set the contempt, in centipawns. Draws are scored as this much worse than equal for the
+    /// side to move at the root, and this much better for its opponent.
+    pub fn with_contempt(mut self, contempt: i32) -> Self {
+        self.contempt = contempt;
+        self
+    }
+
+    /// Report telemetry about the search once it ends, as `info string`s. This is for UCI `debug
+    /// on`, and only the master reports it.
+    pub fn with_debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    /// Probe the given Syzygy tablebases, if any. If the root position is in the tables, only the
+    /// move which best preserves its outcome is searched.
+    #[cfg(feature = "syzygy")]
+    pub fn with_tablebases(mut self, tb: Option<&'engine Tablebases>) -> Self {
+        self.tb = tb;
+        self
+    }
+
     pub fn run<T: Thread>(&mut self, d: u8) -> (Score, Move) {
         self.trace = Tracer::new();
 
@@ -199,60 +401,128 @@ impl<'engine> Search<'engine> {
         // Some bookeeping and prep.
         let start_zob = self.pos.zobrist();
 
-        // TODO: shouldn't have to do this. There is a bug somewhere. It seems to have something to
-        // do with the PVS returning immediately from tt stored moves.
-        self.tt.clear();
+        #[cfg(feature = "syzygy")]
+        self.restrict_to_tablebase_moves();
+
+        // History from an earlier search is kept, but halved, so that moves which are good in the
+        // current position soon outweigh it.
+        self.history.decay();
+        self.cont_history.decay();
 
         self.trace.commence_search();
+        self.time_up = false;
+        self.seldepth = 0;
+        self.depth_reached = 0;
         self.search_depth = d;
 
-        let (score, best_move) = self.iterative_deepening::<T>(d);
+        let (score, best_move) = match self.game_over() {
+            Some(score) => {
+                self.lines.clear();
+                (score, Move::null())
+            }
+            None => self.iterative_deepening::<T>(std::cmp::min(d, MAX_PLY)),
+        };
         self.trace.end_search();
 
+        if T::is_master() && self.infinite {
+            while !self.stop_requested() {
+                std::thread::sleep(Duration::from_millis(1));
+                self.time_up = self.stop_time.passed();
+            }
+        }
+
         assert_eq!(start_zob, self.pos.zobrist());
 
         if T::is_master() {
-            self.report_telemetry(d, score);
-            println!("bestmove {}", best_move);
-        }
+            // The master's result is final, so signal any worker threads to stop.
+            self.stopping.store(true, Ordering::Relaxed);
 
-        self.history.reset();
+            self.report_telemetry(score);
+            self.report_best_move(&best_move);
+        }
 
         (score, best_move)
     }
 
+    /// Statistics from the last call to `run`, or from the search so far if it is still running.
+    pub fn stats(&self) -> SearchStats {
+        self.trace.stats(self.depth_reached, self.seldepth)
+    }
+
     fn iterative_deepening<T: Thread>(&mut self, depth: u8) -> (Score, Move) {
         let mut score = Score::INF_N;
         let mut best_move = Move::null();
+        let start_depth = std::cmp::min(1 + (self.thread_id % 4) as u8, depth);
+
+        let root_moves = match &self.root_moves {
+            Some(moves) => moves.len(),
+            None => self.pos.generate::<BasicMoveList, AllGen, Legal>().len(),
+        };
+        let num_lines = std::cmp::max(1, std::cmp::min(self.multipv, root_moves));
+
+        self.lines.clear();
 
-        for d in 1..=depth {
+        for d in start_depth..=depth {
             if self.stopping() {
                 break;
             }
 
-            self.pvt = PVTable::new(d);
             self.search_depth = d;
-            let value = self.search::<T, Root>(Score::INF_N, Score::INF_P, d);
+            self.excluded.clear();
+            let mut depth_lines = Vec::with_capacity(num_lines);
+
+            for line in 0..num_lines {
+                self.pvt = PVTable::new(self.max_line_ply());
+                let value = self.search::<T, Root>(Score::INF_N, Score::INF_P, d);
+
+                if self.stopping() {
+                    break;
+                }
+
+                let mut pv: Vec<Move> = self.pvt.pv().copied().collect();
+                if pv.is_empty() && line == 0 {
+                    // A restricted root search never cuts off on the table, so it always finds a
+                    // principal variation. Its entry may be missing or hold a move outside of the
+                    // search moves, so the table is no help to it here.
+                    debug_assert!(self.root_moves.is_none());
+                    let entry = self.tt.probe(&self.pos).into_inner();
+                    let tt_entry = entry.read();
+                    assert!(!tt_entry.is_empty());
+                    pv.push(tt_entry.mov.to_move(&self.pos));
+                }
+
+                if let Some(mov) = pv.first() {
+                    self.excluded.push(*mov);
+                }
+                depth_lines.push((value, pv));
+            }
 
             if !self.stopping() {
-                score = value;
-                best_move = match self.pvt.pv().into_iter().next() {
-                    Some(mov) => *mov,
-                    None => {
-                        let entry = self.tt.probe(&self.pos).into_inner();
-                        let tt_entry = entry.read();
-                        assert!(!tt_entry.is_empty());
-                        tt_entry.mov.to_move(&self.pos)
-                    }
-                };
+                score = depth_lines[0].0;
+                best_move = depth_lines[0].1[0];
+                self.lines = depth_lines;
                 self.depth_reached = d;
             }
 
             if T::is_master() {
-                self.report_pv(self.depth_reached, score);
+                for (i, (score, pv)) in self.lines.iter().enumerate() {
+                    self.report_pv(self.depth_reached, i + 1, *score, pv);
+                }
+            }
+
+            if let Some(moves) = self.mate_limit {
+                let max_plies = (2 * moves as u32).saturating_sub(1);
+                let proven = score
+                    .plies_to_mate()
+                    .is_some_and(|plies| plies as u32 <= max_plies);
+                if self.depth_reached == d && proven {
+                    break;
+                }
             }
         }
 
+        self.excluded.clear();
+
         (score, best_move)
     }
 
@@ -263,23 +533,43 @@ impl<'engine> Search<'engine> {
         depth: u8,
     ) -> Score {
         self.trace.visit_node();
+        self.check_node_limit();
+        self.check_time();
 
-        let draft = self.search_depth - depth;
+        let draft = self.ply;
+        self.seldepth = std::cmp::max(self.seldepth, self.ply);
         let mut tt_move = false;
 
+        self.pvt.clear(self.ply);
+
         debug_assert!(Score::INF_N <= alpha);
         debug_assert!(alpha < beta);
         debug_assert!(beta <= Score::INF_P);
         debug_assert!(Node::pv() || alpha.inc_one() == beta);
+        debug_assert!(!Node::pv() || !(Node::cut() || Node::all()));
+        debug_assert!(Node::root() == (draft == 0));
 
         // Step 1. Check for aborted search and immediate draw.
         if self.stopping() {
             return Score::zero();
         }
 
-        // Step 2. check for immediate draw.
-        if self.pos.in_threefold() || self.pos.half_move_clock() >= 50 {
-            return Score::zero();
+        // Extensions are capped so that no line should get this far, but if one does, stop here
+        // rather than overrun the tables indexed by ply.
+        if self.ply >= MAX_PLY {
+            return self.evaluate();
+        }
+
+        // Step 2. check for immediate draw. We don't claim draws at the root, because we still
+        // need to return a move.
+        if !Node::root() && (self.pos.in_threefold() || self.pos.is_insufficient_material()) {
+            return self.draw_score();
+        }
+
+        // Under the fifty-move rule, checkmate takes priority: if the move which reached 100
+        // half-moves delivered mate, fall through so that Step 23 scores the mate.
+        if !Node::root() && self.pos.half_move_clock() >= 100 && !self.pos.in_checkmate() {
+            return self.draw_score();
         }
 
         // Step 2. Mate distance pruning.
@@ -353,24 +643,49 @@ impl<'engine> Search<'engine> {
             }
         }
 
-        // Step 5. Straight to quiescence search if depth <= 0.
-        if depth == 0 {
-            let score = self.quiesce::<T, Node>(alpha, beta);
-            if score == Score::mate(0) {
-                self.pvt.pv_leaf_at(0);
+        // Step 4a. Probe the tablebases. WDL values are only exact straight after a capture or pawn
+        // move, because of the fifty-move rule, so we only probe then. A win or a loss is only a
+        // bound, because the search may still find a mate.
+        #[cfg(feature = "syzygy")]
+        if let Some(tb) = self.tb {
+            if !Node::root() && self.pos.half_move_clock() == 0 {
+                if let Some(wdl) = tb.probe_wdl(&self.pos) {
+                    self.trace.tb_hit();
+
+                    let value = tablebase::wdl_score(wdl);
+                    let bound = if value > Score::zero() {
+                        Bound::Lower
+                    } else if value < Score::zero() {
+                        Bound::Upper
+                    } else {
+                        Bound::Exact
+                    };
+
+                    if bound == Bound::Exact
+                        || (bound == Bound::Lower && value >= beta)
+                        || (bound == Bound::Upper && value <= alpha)
+                    {
+                        tt_entry.write(&self.pos, value, depth, bound, &Move::null());
+                        return value;
+                    }
+                }
             }
+        }
 
-            return score;
+        // Step 5. Straight to quiescence search if depth <= 0.
+        if depth == 0 {
+            return self.quiesce::<T, Node>(alpha, beta);
         }
 
         // Step 6. Static evaluation.
         let eval = self.evaluate();
+        let in_check = self.pos.in_check();
 
         // Step 7. Razoring.
         // When eval is very low, check with quiescence whether it has any hope of raising alpha. If
         // not, return a fail low.
-        if depth <= 6 && eval + Score::cp(426 + 252 * depth as i16 * depth as i16) < alpha {
-            let value = self.quiesce::<Master, NonPv>(alpha - Score::cp(1), alpha);
+        if depth <= 6 && eval + Score::cp(426 + 252 * depth as i32 * depth as i32) < alpha {
+            let value = self.quiesce::<Master, NonPv>(alpha.dec_one(), alpha);
             if value < alpha {
                 return value;
             }
@@ -390,11 +705,7 @@ impl<'engine> Search<'engine> {
 
         // Step 12. If depth <= 0, run quiescence search.
         // if depth == 0 {
-        //     let score = self.quiesce::<T>(alpha, beta);
-        //     if score == Score::mate(0) {
-        //         self.pvt.pv_leaf_at(0);
-        //     }
-        //     return score;
+        //     return self.quiesce::<T>(alpha, beta);
         // }
 
         // Step 13. In non-PV nodes with depth >= 7 and not in TT, decrease depth by 2.
@@ -409,13 +720,22 @@ impl<'engine> Search<'engine> {
         let mut moves = OrderedMoves::new();
         let mut move_count = 0;
         let mut did_raise_alpha = false;
+        let prev_move = self.prev_move();
+        let cont_moves = self.cont_moves();
+        let mut quiets_tried = BasicMoveList::empty();
 
-        'move_loop: while moves.load_next_phase(MoveLoader::from(self, tt_mov, draft)) {
+        'move_loop: while moves
+            .load_next_phase(MoveLoader::from(self, tt_mov, draft, prev_move, cont_moves))
+        {
             for mov in &moves {
                 if self.stopping() {
                     break 'move_loop;
                 }
 
+                if Node::root() && !self.is_root_move_searchable(mov) {
+                    continue;
+                }
+
                 move_count += 1;
                 let mut value = Score::INF_N;
 
@@ -424,21 +744,68 @@ impl<'engine> Search<'engine> {
                     self.report_curr_move(depth, &mov, move_count);
                 }
 
-                // Step 16. Reductions & extensions.
-                //          TODO
+                // Step 16. Extensions. Checks are extended by one ply, so that forcing lines are
+                // searched deeper. To stop lines from exploding, no line may be extended beyond
+                // the maximum line length.
+                let gives_check = self.pos.gives_check(mov);
+                let extension = if self.enabled(Feature::CheckExtensions)
+                    && gives_check
+                    && self.ply + depth < self.max_line_ply()
+                {
+                    1
+                } else {
+                    0
+                };
+
+                // Step 17. Late move reduction. At non-PV nodes, late quiet moves which aren't
+                // killers are searched to a reduced depth, unless we are in check or the move gives
+                // check.
+                let reduction = if self.enabled(Feature::LateMoveReductions)
+                    && !Node::pv()
+                    && depth >= LMR_MIN_DEPTH
+                    && move_count > LMR_MOVE_THRESHOLD
+                    && !in_check
+                    && !gives_check
+                    && mov.is_quiet()
+                    && !self.kt.contains(draft, mov)
+                {
+                    lmr::reduction(depth, move_count)
+                } else {
+                    0
+                };
 
-                // Step 17. Late move reduction.
-                //          TODO
+                let new_depth = depth - 1 + extension;
 
-                // Step 18. Make the move.
+                // Step 18. Make the move, prefetching the child's transposition table entry first
+                // so that it is in cache by the time the child probes it.
+                self.tt.prefetch(self.pos.key_after(mov));
                 self.pos.make_move(mov);
+                self.ply += 1;
 
                 // Step 19. Search non-PV move with null window.
+                //
+                // From a PV node, we expect the null window search to prove that this move is no
+                // better than our current best, so the child is an expected Cut node. Children of
+                // Cut nodes are All nodes, and vice versa.
                 if !Node::pv() || move_count > 1 {
+                    debug_assert!(Node::pv() || alpha.inc_one() == beta);
+
                     value = self
-                        .search::<T, NonPv>(-alpha.inc_one(), -alpha, depth - 1)
+                        .search::<T, Node::NullWindowChild>(
+                            -alpha.inc_one(),
+                            -alpha,
+                            new_depth - reduction,
+                        )
                         .neg()
                         .inc_mate();
+
+                    // A reduced move which beats alpha is re-searched at full depth.
+                    if reduction > 0 && value > alpha {
+                        value = self
+                            .search::<T, Node::NullWindowChild>(-alpha.inc_one(), -alpha, new_depth)
+                            .neg()
+                            .inc_mate();
+                    }
                 }
 
                 // Step 20. Search PV move, or perform re-search if null window search failed high.
@@ -449,7 +816,7 @@ impl<'engine> Search<'engine> {
                     && (move_count == 1 || (value > alpha && (Node::root() || value < beta)))
                 {
                     value = self
-                        .search::<T, Pv>(-beta, -alpha, depth - 1)
+                        .search::<T, Pv>(-beta, -alpha, new_depth)
                         .neg()
                         .inc_mate();
                 }
@@ -457,6 +824,7 @@ impl<'engine> Search<'engine> {
                 debug_assert!(Node::pv() || !(value > alpha && (Node::root() || value < beta)));
 
                 // Step 21. Undo move.
+                self.ply -= 1;
                 self.pos.unmake_move();
 
                 debug_assert!(value > Score::INF_N);
@@ -469,7 +837,7 @@ impl<'engine> Search<'engine> {
                     if value > alpha {
                         best_move = *mov;
 
-                        self.pvt.copy_to(depth, *mov);
+                        self.pvt.copy_to(draft, *mov);
 
                         if Node::pv() && value < beta {
                             alpha = value;
@@ -477,47 +845,71 @@ impl<'engine> Search<'engine> {
                             // TODO: reduce depth on remaining moves.
                         } else {
                             debug_assert!(value >= beta);
-                            // beta-cutoff; record killer and history
+                            // beta-cutoff; record killer, countermove and history
                             if mov.is_quiet() {
                                 self.kt.store(*mov, draft);
-                            }
 
-                            // self.history.inc(
-                            //     mov.orig(),
-                            //     mov.dest(),
-                            //     depth as u32 * depth as u32,
-                            //     self.pos.turn(),
-                            // );
+                                if let Some((piece, dest)) = prev_move {
+                                    self.counters.store(piece, dest, *mov);
+                                }
+
+                                // Reward the cutoff move. Continuation history also penalises
+                                // the quiets tried before it which failed to cut off; doing the
+                                // same to the butterfly table costs more nodes than it saves.
+                                let bonus = (depth as i32 * depth as i32).min(HISTORY_MAX);
+                                if self.enabled(Feature::ButterflyHistory) {
+                                    let turn = self.pos.turn();
+                                    self.history.update(mov.orig(), mov.dest(), bonus, turn);
+                                }
+
+                                for prev in cont_moves.into_iter().flatten() {
+                                    let curr = (self.pos.piece_at_sq(mov.orig()), mov.dest());
+                                    self.cont_history.update(prev, curr, bonus);
+
+                                    for tried in &quiets_tried {
+                                        let curr =
+                                            (self.pos.piece_at_sq(tried.orig()), tried.dest());
+                                        self.cont_history.update(prev, curr, -bonus);
+                                    }
+                                }
+                            }
 
                             break 'move_loop;
                         }
                     }
                 }
+
+                if mov.is_quiet() {
+                    quiets_tried.push(*mov);
+                }
             }
         }
 
-        debug_assert!(
-            move_count > 0 || self.pos.generate::<BasicMoveList, AllGen, Legal>().len() == 0
-        );
-
         if self.stopping() {
             return Score::zero();
         }
 
+        debug_assert!(
+            move_count > 0 || self.pos.generate::<BasicMoveList, AllGen, Legal>().len() == 0
+        );
+
         // Step 23. Check for mate and stalemate.
         if move_count == 0 {
-            self.pvt.pv_leaf_at(depth);
-
             best_value = if self.pos.in_check() {
                 Score::mate(0)
             } else {
-                Score::cp(0)
+                self.draw_score()
             };
         }
 
         debug_assert!(best_value > Score::INF_N);
 
-        // Step 24. Write node information to the transposition table.
+        // Step 24. Write node information to the transposition table. A root search with excluded
+        // or restricted moves is incomplete, so its result isn't stored.
+        if Node::root() && (!self.excluded.is_empty() || self.root_moves.is_some()) {
+            return best_value;
+        }
+
         tt_entry.write(
             &self.pos,
             best_value,
@@ -526,7 +918,7 @@ impl<'engine> Search<'engine> {
                 debug_assert!(
                     !best_move.is_null()
                         || best_value == Score::mate(0)
-                        || best_value == Score::zero()
+                        || best_value == self.draw_score()
                 );
                 Bound::Lower
             } else if Node::pv() && !best_move.is_null() {
@@ -543,22 +935,191 @@ impl<'engine> Search<'engine> {
         best_value
     }
 
+    /// If the root position is in the tablebases, restrict the root moves to those which the
+    /// tables say preserve its outcome, and in a won position make the most progress towards it.
+    /// This respects the fifty-move rule, which the search can't see far enough to do. See
+    /// `Tablebases::probe_root`.
+    #[cfg(feature = "syzygy")]
+    fn restrict_to_tablebase_moves(&mut self) {
+        let mut moves = match self.tb.and_then(|tb| tb.probe_root(&self.pos)) {
+            Some(moves) => moves,
+            None => return,
+        };
+        self.trace.tb_hit();
+
+        if let Some(allowed) = &self.root_moves {
+            moves.retain(|mov| allowed.contains(mov));
+        }
+        if !moves.is_empty() {
+            self.root_moves = Some(moves);
+        }
+    }
+
+    /// Signal the search to stop if the node limit has been reached. To keep this cheap, the count
+    /// is only checked every 1024 nodes. We always complete the first iteration, so that there is
+    /// a best move to return.
+    #[inline(always)]
+    fn check_node_limit(&self) {
+        if let Some(limit) = self.node_limit {
+            let nodes = self.trace.all_nodes_visited() as u64;
+            if nodes.is_multiple_of(1024) && nodes >= limit && self.depth_reached > 0 {
+                self.stopping.store(true, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// Read the clock to see whether the deadline has passed. To keep this cheap, the clock is
+    /// only read every `TIME_CHECK_INTERVAL` nodes, and the answer is cached in between.
+    #[inline(always)]
+    fn check_time(&mut self) {
+        if self
+            .trace
+            .all_nodes_visited()
+            .is_multiple_of(TIME_CHECK_INTERVAL)
+        {
+            self.time_up = self.stop_time.passed();
+        }
+    }
+
+    /// Whether the search has been told to stop or has run out of time.
+    #[inline(always)]
+    fn stop_requested(&self) -> bool {
+        self.stopping.load(Ordering::Relaxed) || self.time_up
+    }
+
+    /// Whether the search should unwind, because it was told to stop or has run out of time.
+    ///
+    /// The master thread ignores this until it has completed an iteration, so that it always has
+    /// a best move to report. After that, the result of an unfinished iteration is thrown away, so
+    /// the move reported is always the one from the last completed depth.
     #[inline(always)]
     fn stopping(&self) -> bool {
-        self.stopping.load(Ordering::Relaxed)
-            || self
-                .stop_time
-                .map(|s| s <= std::time::Instant::now())
-                .unwrap_or(false)
+        self.stop_requested() && (self.depth_reached > 0 || self.thread_id != 0)
+    }
+
+    /// If the side to move at the root has no legal moves, the score of the finished game: mated
+    /// or stalemated. There is nothing to search, and the best move is reported as the null move.
+    fn game_over(&self) -> Option<Score> {
+        if !self
+            .pos
+            .generate::<BasicMoveList, AllGen, Legal>()
+            .is_empty()
+        {
+            return None;
+        }
+
+        if self.pos.in_check() {
+            Some(Score::mate(0))
+        } else {
+            Some(self.draw_score())
+        }
+    }
+
+    /// The score of a draw for the side to move at the current node, after contempt. The root side
+    /// is to move at even plies.
+    #[inline(always)]
+    fn draw_score(&self) -> Score {
+        if self.ply.is_multiple_of(2) {
+            Score::cp(-self.contempt)
+        } else {
+            Score::cp(self.contempt)
+        }
+    }
+
+    /// The maximum distance from the root which any line may reach, once extended. This is twice
+    /// the nominal search depth, so the total extensions along a line are capped at the search
+    /// depth.
+    #[inline(always)]
+    fn max_line_ply(&self) -> u8 {
+        std::cmp::min(2 * self.search_depth, MAX_PLY)
+    }
+
+    /// Whether `feature` is in use. This is only ever false in tests.
+    #[cfg(test)]
+    fn enabled(&self, feature: Feature) -> bool {
+        !self.disabled.contains(&feature)
+    }
+
+    /// Whether `feature` is in use. Every feature is always on outside of tests.
+    #[cfg(not(test))]
+    #[inline(always)]
+    fn enabled(&self, _feature: Feature) -> bool {
+        true
+    }
+
+    /// The piece moved by the move which led to the current node, and the square it moved to. This
+    /// indexes the countermove table, so it is `None` after a null move, at the start of the game,
+    /// or when countermoves are disabled.
+    #[inline(always)]
+    fn prev_move(&self) -> Option<(Piece, Square)> {
+        if !self.enabled(Feature::CounterMoves) {
+            return None;
+        }
+
+        match self.pos.history().last() {
+            Some(prev) if !prev.is_null() => Some((prev.piece, prev.dest)),
+            _ => None,
+        }
+    }
+
+    /// The piece and destination square of the moves one and two plies before the current node,
+    /// which key the continuation history. Either is `None` if it was a null move or precedes the
+    /// start of the game, and both are `None` when continuation history is disabled.
+    #[inline(always)]
+    fn cont_moves(&self) -> [Option<PieceTo>; 2] {
+        if !self.enabled(Feature::ContinuationHistory) {
+            return [None, None];
+        }
+
+        let history = self.pos.history();
+        let piece_to = |back: usize| {
+            history
+                .len()
+                .checked_sub(back)
+                .map(|i| &history[i])
+                .filter(|prev| !prev.is_null())
+                .map(|prev| (prev.piece, prev.dest))
+        };
+
+        [piece_to(1), piece_to(2)]
+    }
+
+    /// Whether a root move should be searched: it must be one of the search moves, if these are
+    /// restricted, and mustn't head a principal variation already found at this depth.
+    fn is_root_move_searchable(&self, mov: &Move) -> bool {
+        let allowed = match &self.root_moves {
+            Some(moves) => moves.contains(mov),
+            None => true,
+        };
+
+        allowed && !self.excluded.iter().any(|excluded| excluded.same_move(mov))
     }
 
     /// Returns the static evaluation, from the perspective of the side to move.
     #[inline(always)]
     fn evaluate(&mut self) -> Score {
-        let material = (self.pos.material_eval() * self.pov()) as f32;
-        let hmc = (50 - std::cmp::min(self.pos.half_move_clock(), 50)) as f32 / 50.;
-        let scaled_material = (material * hmc).round() as i16;
-        Score::cp(scaled_material)
+        let eval = match self.pos.endgame_eval() {
+            Some(eval) => eval as i32,
+            None => scale_eval(
+                self.pos.material_eval() as i32
+                    + self.pos.psqt_eval() as i32
+                    + self.pawn_hash.pawn_structure(&self.pos, &mut self.trace) as i32
+                    + self.pos.imbalance_eval() as i32,
+                self.pos.endgame_scale(),
+                self.pos.game_phase(),
+            ),
+        } * self.pov() as i32;
+        Score::cp(fifty_move_scale(eval, self.pos.half_move_clock()))
+    }
+
+    /// The move ordering score of the capture `mov`: its static exchange evaluation, with captures
+    /// of equal SEE told apart by MVV-LVA.
+    #[inline(always)]
+    fn capture_score(&mut self, mov: &Move) -> i16 {
+        let (victim, attacker) = exchange_pieces(&self.pos, mov);
+        let see = self.see(mov.orig(), mov.dest(), victim, attacker).to_i16();
+
+        capture_score(see, victim, attacker)
     }
 
     /// Returns 1 if the player to move is White, -1 if Black. Useful wherever we are using
@@ -572,9 +1133,18 @@ impl<'engine> Search<'engine> {
         }
     }
 
-    /// The quiescence search.
-    fn quiesce<T: Thread, Node: NodeType>(&mut self, mut alpha: Score, mut beta: Score) -> Score {
+    /// The quiescence search. This searches the noisy moves (captures and queen promotions) until
+    /// the position is quiet, or every evasion when in check.
+    ///
+    /// The search is fail-soft: the value returned may lie outside the window, as a bound on the
+    /// true value. Results are stored in the transposition table at depth 0, below any result of
+    /// the main search. As there, scores are relative to the node, so a mate found here is stored
+    /// as a distance from this position and needs no adjustment for the ply.
+    fn quiesce<T: Thread, Node: NodeType>(&mut self, mut alpha: Score, beta: Score) -> Score {
         self.trace.visit_q_node();
+        self.check_node_limit();
+        self.check_time();
+        self.seldepth = std::cmp::max(self.seldepth, self.ply);
 
         debug_assert!(!Node::root());
         debug_assert!(Score::INF_N <= alpha);
@@ -587,95 +1157,106 @@ impl<'engine> Search<'engine> {
             return Score::zero();
         }
 
-        // Step 1. Check for an immediate draw or max ply reached.
-        //         TODO
+        // Step 1. Check for an immediate draw or max ply reached. Each capture or promotion made
+        // here bounds the line, but in check every evasion is searched, so a long enough run of
+        // checks and evasions could otherwise reach any ply.
+        // TODO: immediate draws.
+        if self.ply >= MAX_PLY {
+            return self.evaluate();
+        }
 
-        // Step 2. Load transposition table entry.
-        let (tt_entry, tt_mov, tt_value) = {
+        // Step 2. Load transposition table entry. Any entry for this position is at least as deep
+        // as a quiescence search, so its score can be used whether or not it has a move.
+        let (tt_entry, tt_hit) = {
             use super::tt::Probe::*;
             match self.tt.probe(&self.pos) {
                 Hit(entry) => {
                     let e = entry.read();
                     if e.mov.is_null() {
-                        (entry, None, None)
+                        (entry, true)
+                    } else if self.pos.valid_move(&e.mov.to_move(&self.pos)) {
+                        self.trace.hash_hit();
+                        (entry, true)
                     } else {
-                        let mov = e.mov.to_move(&self.pos);
-                        let val = e.score;
-                        if self.pos.valid_move(&mov) {
-                            self.trace.hash_hit();
-                            (entry, Some(mov), Some(val))
-                        } else {
-                            self.trace.hash_collision();
-                            (entry, None, None)
-                        }
+                        self.trace.hash_collision();
+                        (entry, false)
                     }
                 }
                 Clash(entry) => {
                     self.trace.hash_clash();
-                    (entry, None, None)
+                    (entry, false)
                 }
-                Empty(entry) => (entry, None, None),
+                Empty(entry) => (entry, false),
             }
         };
 
         // Step 3. Check for early TT cutoff.
-        if !Node::pv() {
+        if !Node::pv() && tt_hit {
             let entry = tt_entry.read();
 
-            if !entry.is_empty() && entry.depth >= 0 {
-                match entry.bound() {
-                    Bound::Exact => {
+            match entry.bound() {
+                Bound::Exact => {
+                    return entry.score;
+                }
+                Bound::Lower => {
+                    if entry.score >= beta {
                         return entry.score;
                     }
-                    Bound::Lower => {
-                        if entry.score > beta {
-                            return entry.score;
-                        } else if entry.score > alpha {
-                            alpha = entry.score
-                        }
-                    }
-                    Bound::Upper => {
-                        if entry.score < alpha {
-                            return entry.score;
-                        } else if entry.score < beta {
-                            beta = entry.score
-                        }
+                }
+                Bound::Upper => {
+                    if entry.score <= alpha {
+                        return entry.score;
                     }
                 }
             }
-
-            if alpha == beta {
-                return alpha;
-            }
         }
 
-        // Step 4. Static evaluation.
-        let stand_pat = match tt_value {
-            Some(s) => s,
-            None => self.evaluate(),
-        };
-
-        if stand_pat >= beta {
-            return beta;
-        }
+        // Step 4. Static evaluation. When in check, standing pat isn't an option: the side to move
+        // must find an evasion, so all of them are searched, quiet moves included.
+        let in_check = self.pos.in_check();
+        let orig_alpha = alpha;
+        let mut best_value = Score::INF_N;
+        let mut best_move = Move::null();
+        let mut delta_base = None;
+
+        if !in_check {
+            // A bound from the table which is tighter than the static evaluation in the right
+            // direction is a better estimate of the value of standing pat.
+            let mut stand_pat = self.evaluate();
+            if tt_hit {
+                let entry = tt_entry.read();
+                let tighter = match entry.bound() {
+                    Bound::Exact => true,
+                    Bound::Lower => entry.score > stand_pat,
+                    Bound::Upper => entry.score < stand_pat,
+                };
+                if tighter {
+                    stand_pat = entry.score;
+                }
+            }
 
-        if alpha < stand_pat {
-            alpha = stand_pat;
-        }
+            if stand_pat >= beta {
+                if tt_entry.read().is_empty() || tt_entry.read().depth == 0 {
+                    tt_entry.write(&self.pos, stand_pat, 0, Bound::Lower, &Move::null());
+                }
+                return stand_pat;
+            }
 
-        // TODO: deal with this by looking at quiet check evasions, rather than going back to main
-        // search.
-        if self.pos.in_check() {
-            // A one move search extension. The main alphabeta function will tell us if we are in
-            // checkmate or stalemate, and if not, it will try the possible evasions.
+            best_value = stand_pat;
+            if alpha < stand_pat {
+                alpha = stand_pat;
+            }
 
-            // Commenting out as this is currently causing stack overflows. We need to generate
-            // evasions when in check, instead of dropping back to main search.
-            // return self.search::<T, Pv>(alpha, beta, 1);
+            if self.enabled(Feature::DeltaPruning)
+                && self.pos.non_pawn_material(self.pos.turn()) > DELTA_PRUNING_MIN_MATERIAL
+            {
+                delta_base = Some(stand_pat + Score::cp(DELTA_MARGIN));
+            }
         }
 
         let mut score: Score;
         let mut moves = OrderedMoves::new();
+        let mut move_count: u8 = 0;
 
         // Step 5. Loop through all the moves until no moves remain or a beta cutoff occurs.
         'move_loop: while moves.load_next_phase(QMoveLoader::from(self)) {
@@ -684,39 +1265,91 @@ impl<'engine> Search<'engine> {
                     break 'move_loop;
                 }
 
+                move_count += 1;
+
+                // Delta pruning. Skip captures which can't raise alpha, even if they win the
+                // captured piece for free.
+                if let Some(delta_base) = delta_base {
+                    if mov.is_capture() && !mov.is_promotion() {
+                        let captured = if mov.is_en_passant() {
+                            PieceType::Pawn
+                        } else {
+                            self.pos.piece_at_sq(mov.dest()).type_of()
+                        };
+
+                        if delta_base + Score::cp(piece_value(captured).into()) < alpha {
+                            self.trace.delta_prune_node();
+                            continue;
+                        }
+                    }
+                }
+
                 self.pos.make_move(mov);
+                self.ply += 1;
                 score = self.quiesce::<T, Node>(-beta, -alpha).neg().inc_mate();
+                self.ply -= 1;
                 self.pos.unmake_move();
 
-                if score >= beta {
-                    return beta;
-                }
+                if score > best_value {
+                    best_value = score;
+
+                    if score > alpha {
+                        best_move = *mov;
+
+                        if score >= beta {
+                            break 'move_loop;
+                        }
 
-                if score > alpha {
-                    alpha = score;
+                        alpha = score;
+                    }
                 }
             }
         }
 
-        alpha
+        if self.stopping() {
+            return Score::zero();
+        }
+
+        // Step 6. Check for mate. Every evasion was generated, so if there were none, this is
+        // checkmate.
+        let bound = if in_check && move_count == 0 {
+            best_value = Score::mate(0);
+            Bound::Exact
+        } else if best_value >= beta {
+            Bound::Lower
+        } else if Node::pv() && best_value > orig_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+
+        // Step 7. Write the result to the transposition table, unless the slot holds a result from
+        // the main search, which is worth more.
+        if tt_entry.read().is_empty() || tt_entry.read().depth == 0 {
+            tt_entry.write(&self.pos, best_value, 0, bound, &best_move);
+        }
+
+        best_value
     }
 
-    fn report_pv(&self, depth: u8, score: Score) {
+    fn report_pv(&self, depth: u8, multipv: usize, score: Score, pv: &[Move]) {
         println!(
             "{}",
             Info::Pv(PvInfo {
                 depth,
+                seldepth: self.seldepth,
+                multipv,
                 score,
                 time: self.trace.live_elapsed().as_millis() as usize,
                 nodes: self.trace.nodes_visited(),
-                pv: self
-                    .pvt
-                    .pv()
+                pv: pv
+                    .iter()
                     .map(|m| format!("{}", m))
                     .intersperse(" ".to_string())
                     .collect::<String>(),
                 hashfull: self.tt.hashfull(),
                 nps: self.trace.live_nps() as u32,
+                tbhits: self.trace.tb_hits(),
             })
         );
     }
@@ -732,106 +1365,157 @@ impl<'engine> Search<'engine> {
         );
     }
 
-    /// Detailed debug info about the search, printed after the end of search in debug mode.
-    fn report_telemetry(&self, depth: u8, score: Score) {
-        if false {
-            println!(
-                "nodes:     {}",
-                self.trace.all_nodes_visited().separated_string()
-            );
-            println!(
-                "% q_nodes: {:.2}%",
-                self.trace.q_nodes_visited() as f32 / self.trace.all_nodes_visited() as f32 * 100.0
-            );
-            println!(
-                "nps:       {}",
-                self.trace
-                    .nps()
-                    .expect("`end_search` was called, so this should always work")
-                    .separated_string()
-            );
-            println!(
-                "see skips: {}",
-                self.trace.see_skipped_nodes().separated_string()
-            );
-            println!(
-                "time:      {}ms",
-                self.trace
-                    .elapsed()
-                    .expect("we called `end_search`")
-                    .as_millis()
-                    .separated_string()
-            );
-            println!(
-                "eff. bf:   {}",
-                self.trace.eff_branching(depth).separated_string()
-            );
-            println!("tt stats ----------------");
-            println!(
-                " size: {}MB, slots: {}",
-                self.tt.capacity_mb(),
-                self.tt.capacity_entries().separated_string()
-            );
-            println!(
-                " hits:       {:>8} ({:.1}%)",
-                self.trace.hash_hits().separated_string(),
-                self.trace.hash_hits() as f64 / self.trace.hash_probes() as f64 * 100.
-            );
-            println!(
-                " collisions: {:>8} ({:.1}%)",
-                self.trace.hash_collisions().separated_string(),
-                self.trace.hash_collisions() as f64 / self.trace.hash_probes() as f64 * 100.
-            );
-            println!(
-                " clashes:    {:>8} ({:.1}%)",
-                self.trace.hash_clashes().separated_string(),
-                self.trace.hash_clashes() as f64 / self.trace.hash_probes() as f64 * 100.
-            );
-            println!(" hashfull: {:.2}%", self.tt.hashfull() as f64 / 10.);
-            println!("-------------------------");
-            println!(
-                "pv:        {}",
-                self.pvt
-                    .pv()
-                    .map(|m| m.to_uci_string())
-                    .collect::<Vec<String>>()
-                    .join(" ")
-            );
-            println!("score:     {:?}", score);
-            println!(
-                "tt move found at {:.2}% of nodes",
-                self.trace.hash_found.avg() * 100_f64
-            );
-            println!(
-                "killers found per node: {:.2}",
-                self.trace.killers_per_node.avg() * 2_f64
-            );
+    /// Detailed debug info about the search, reported after the end of search in debug mode.
+    fn report_telemetry(&self, score: Score) {
+        for info in self.telemetry(score) {
+            println!("{}", info);
         }
     }
 
-    fn report_best_move(&self) {
-        // Get TT entry.
-        let entry = self.tt.probe(&self.pos).into_inner();
-        let tt_entry = entry.read();
-        assert!(!tt_entry.is_empty());
-        println!("bestmove {}", tt_entry.mov.to_move(&self.pos));
+    /// The lines of the telemetry report, as `info string`s for the GUI. This is empty unless
+    /// debug mode is on.
+    fn telemetry(&self, score: Score) -> Vec<Info> {
+        if !self.debug {
+            return Vec::new();
+        }
+
+        let mut lines = Vec::new();
+        lines.push(format!(
+            "nodes:     {}",
+            self.trace.all_nodes_visited().separated_string()
+        ));
+        lines.push(format!(
+            "% q_nodes: {:.2}%",
+            self.trace.q_nodes_visited() as f32 / self.trace.all_nodes_visited() as f32 * 100.0
+        ));
+        lines.push(format!(
+            "nps:       {}",
+            self.trace
+                .nps()
+                .expect("`end_search` was called, so this should always work")
+                .separated_string()
+        ));
+        lines.push(format!(
+            "see skips: {}",
+            self.trace.see_skipped_nodes().separated_string()
+        ));
+        lines.push(format!(
+            "delta prunes: {}",
+            self.trace.delta_pruned_nodes().separated_string()
+        ));
+        lines.push(format!(
+            "time:      {}ms",
+            self.trace
+                .elapsed()
+                .expect("we called `end_search`")
+                .as_millis()
+                .separated_string()
+        ));
+        lines.push(format!(
+            "eff. bf:   {}",
+            self.trace
+                .eff_branching(self.depth_reached)
+                .separated_string()
+        ));
+        lines.push("tt stats ----------------".to_string());
+        lines.push(format!(
+            " size: {}MB, slots: {}",
+            self.tt.capacity_mb(),
+            self.tt.capacity_entries().separated_string()
+        ));
+        lines.push(format!(
+            " hits:       {:>8} ({:.1}%)",
+            self.trace.hash_hits().separated_string(),
+            self.trace.hash_hits() as f64 / self.trace.hash_probes() as f64 * 100.
+        ));
+        lines.push(format!(
+            " collisions: {:>8} ({:.1}%)",
+            self.trace.hash_collisions().separated_string(),
+            self.trace.hash_collisions() as f64 / self.trace.hash_probes() as f64 * 100.
+        ));
+        lines.push(format!(
+            " clashes:    {:>8} ({:.1}%)",
+            self.trace.hash_clashes().separated_string(),
+            self.trace.hash_clashes() as f64 / self.trace.hash_probes() as f64 * 100.
+        ));
+        lines.push(format!(
+            " hashfull: {:.2}%",
+            self.tt.hashfull() as f64 / 10.
+        ));
+        lines.push(format!(
+            " pawn hits:  {:>8} ({:.1}%)",
+            self.trace.pawn_hash_hits().separated_string(),
+            self.trace.pawn_hash_hits() as f64 / self.trace.pawn_hash_probes() as f64 * 100.
+        ));
+        lines.push("-------------------------".to_string());
+        lines.push(format!(
+            "pv:        {}",
+            self.pvt
+                .pv()
+                .map(|m| m.to_uci_string())
+                .collect::<Vec<String>>()
+                .join(" ")
+        ));
+        lines.push(format!("score:     {}", score));
+        lines.push(format!(
+            "tt move found at {:.2}% of nodes",
+            self.trace.hash_found.avg() * 100_f64
+        ));
+        lines.push(format!(
+            "killers found per node: {:.2}",
+            self.trace.killers_per_node.avg() * 2_f64
+        ));
+
+        lines.into_iter().map(Info::String).collect()
     }
-}
 
-pub struct MoveLoader<'a, 'search> {
-    search: &'a mut Search<'search>,
-    hash_move: Option<Move>,
-    draft: u8,
+    /// Report the best move, along with the move we expect in reply so that the GUI can let us
+    /// ponder on it.
+    fn report_best_move(&self, best_move: &Move) {
+        println!("{}", self.best_move_line(best_move));
+    }
+
+    /// The `bestmove` line for the GUI, with the move to ponder on if the principal variation has
+    /// one.
+    fn best_move_line(&self, best_move: &Move) -> String {
+        match self.ponder_move() {
+            Some(ponder) => format!("bestmove {} ponder {}", best_move, ponder),
+            None => format!("bestmove {}", best_move),
+        }
+    }
+
+    /// The move to ponder on: the reply to the best move in the principal variation.
+    fn ponder_move(&self) -> Option<Move> {
+        self.lines.first().and_then(|(_, pv)| pv.get(1)).copied()
+    }
+}
+
+pub struct MoveLoader<'a, 'search> {
+    search: &'a mut Search<'search>,
+    hash_move: Option<Move>,
+    draft: u8,
+    prev_move: Option<(Piece, Square)>,
+    cont_moves: [Option<PieceTo>; 2],
 }
 
 impl<'a, 'engine> MoveLoader<'a, 'engine> {
-    /// Create a `MoveLoader` from the passed `Search`.
+    /// Create a `MoveLoader` from the passed `Search`. `prev_move` is the piece and destination
+    /// square of the move which led to this node, if any, for looking up its countermove.
+    /// `cont_moves` are the moves one and two plies back, for looking up continuation history.
     #[inline(always)]
-    pub fn from(search: &'a mut Search<'engine>, hash_move: Option<Move>, draft: u8) -> Self {
+    pub fn from(
+        search: &'a mut Search<'engine>,
+        hash_move: Option<Move>,
+        draft: u8,
+        prev_move: Option<(Piece, Square)>,
+        cont_moves: [Option<PieceTo>; 2],
+    ) -> Self {
         MoveLoader {
             search,
             hash_move,
             draft,
+            prev_move,
+            cont_moves,
         }
     }
 }
@@ -873,6 +1557,18 @@ impl<'a, 'search> Loader for MoveLoader<'a, 'search> {
             movelist.push(km2.unwrap());
         }
         self.search.trace.killers_per_node.push_many(cnt, 2);
+
+        // The countermove follows the killers, unless it is one of them.
+        if let Some((piece, dest)) = self.prev_move {
+            if let Some(cm) = self.search.counters.probe(piece, dest, &self.search.pos) {
+                let is_killer = [km1, km2]
+                    .iter()
+                    .any(|km| km.is_some_and(|km| km.same_move(&cm)));
+                if !is_killer {
+                    movelist.push(cm);
+                }
+            }
+        }
     }
 
     fn load_quiets(&mut self, movelist: &mut ScoredMoveList) {
@@ -882,15 +1578,7 @@ impl<'a, 'search> Loader for MoveLoader<'a, 'search> {
     fn score_captures(&mut self, captures: Scorer) {
         for (mov, score) in captures {
             if mov.is_capture() {
-                *score = self
-                    .search
-                    .see(
-                        mov.orig(),
-                        mov.dest(),
-                        self.search.pos.piece_at_sq(mov.dest()).type_of(),
-                        self.search.pos.piece_at_sq(mov.orig()).type_of(),
-                    )
-                    .to_i16();
+                *score = self.search.capture_score(mov);
             }
         }
     }
@@ -898,13 +1586,15 @@ impl<'a, 'search> Loader for MoveLoader<'a, 'search> {
     fn score_quiets(&mut self, quiets: Scorer) {
         let turn = self.search.pos.turn();
         for (mov, score) in quiets {
-            // SAFETY: these are legal moves, so the squares must be valid.
-            unsafe {
-                *score = self
-                    .search
-                    .history
-                    .get_unchecked(mov.orig(), mov.dest(), turn) as i16;
+            let mut value = self.search.history.get(mov.orig(), mov.dest(), turn);
+
+            let curr = (self.search.pos.piece_at_sq(mov.orig()), mov.dest());
+            for prev in self.cont_moves.into_iter().flatten() {
+                value += self.search.cont_history.get(prev, curr);
             }
+
+            // Each table is bounded by `HISTORY_MAX`, but their sum can overflow an `i16`.
+            *score = value.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
         }
     }
 }
@@ -942,15 +1632,7 @@ impl<'a, 'search> Loader for QMoveLoader<'a, 'search> {
     fn score_captures(&mut self, captures: Scorer) {
         for (mov, score) in captures {
             if mov.is_capture() {
-                *score = self
-                    .search
-                    .see(
-                        mov.orig(),
-                        mov.dest(),
-                        self.search.pos.piece_at_sq(mov.dest()).type_of(),
-                        self.search.pos.piece_at_sq(mov.orig()).type_of(),
-                    )
-                    .to_i16();
+                *score = self.search.capture_score(mov);
             }
         }
     }
@@ -958,13 +1640,7 @@ impl<'a, 'search> Loader for QMoveLoader<'a, 'search> {
     fn score_quiets(&mut self, quiets: Scorer) {
         let turn = self.search.pos.turn();
         for (mov, score) in quiets {
-            // SAFETY: these are legal moves, so the squares must be valid.
-            unsafe {
-                *score = self
-                    .search
-                    .history
-                    .get_unchecked(mov.orig(), mov.dest(), turn) as i16;
-            }
+            *score = self.search.history.get(mov.orig(), mov.dest(), turn) as i16;
         }
     }
 }
@@ -972,6 +1648,33 @@ impl<'a, 'search> Loader for QMoveLoader<'a, 'search> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::ordering::Phase;
+    use crate::time::TimingMode;
+    use core::position::START_POSITION;
+    use std::time::Instant;
+
+    /// What a test search borrows: a stop flag and a deadline, which are left unset unless the
+    /// test sets them, and a transposition table.
+    struct Fixture {
+        flag: AtomicBool,
+        deadline: Deadline,
+        tt: Table,
+    }
+
+    impl Fixture {
+        /// A fixture with a fresh table of `mb` MB.
+        fn new(mb: usize) -> Self {
+            Self {
+                flag: AtomicBool::new(false),
+                deadline: Deadline::none(),
+                tt: Table::new(mb),
+            }
+        }
+
+        fn search(&self, pos: Position) -> Search<'_> {
+            Search::new(pos, &self.flag, &self.deadline, &self.tt)
+        }
+    }
 
     fn suite() -> Vec<(&'static str, u8, Score, Score, &'static str)> {
         // Test position tuples have the form:
@@ -994,16 +1697,24 @@ mod tests {
                 ("r5k1/2qn2pp/2nN1p2/3pP2Q/3P1p2/5N2/4B1PP/1b4K1 w - - 0 25", 8, Score::mate(7), Score::mate(7), "h5f7"),
 
                 // // Winning material
-                ("rn1q1rk1/5pp1/pppb4/5Q1p/3P4/3BPP1P/PP3PK1/R1B2R2 b - - 1 15", 7, Score::cp(290), Score::cp(310), "g7g6"),
-                ("4k3/8/8/4q3/8/8/7P/3K2R1 w - - 0 1", 3, Score::cp(100), Score::cp(100), "g1e1"), 
+                ("rn1q1rk1/5pp1/pppb4/5Q1p/3P4/3BPP1P/PP3PK1/R1B2R2 b - - 1 15", 7, Score::cp(200), Score::cp(275), "g7g6"),
+                // Qxe1+ Kxe1 leaves a rook pawn ending, which the KPK bitbase knows is drawn.
+                // Everything else loses to the queen.
+                ("4k3/8/8/4q3/8/8/7P/3K2R1 w - - 0 1", 3, Score::cp(0), Score::cp(0), "g1e1"),
                 ("6k1/8/3q4/8/8/3B4/2P5/1K1R4 w - - 0 1", 3, Score::cp(850), Score::cp(950), "d3c4"),
-                ("r5k1/p1P5/8/8/8/8/3RK3/8 w - - 0 1", 6, Score::cp(900), Score::cp(900), "d2d8"),
-                ("6k1/8/8/3q4/8/8/P7/1KNB4 w - - 0 1", 4, Score::cp(380), Score::cp(420), "d1b3"),
-                ("2kr3r/ppp1qpb1/5n2/5b1p/6p1/1PNP4/PBPQBPPP/2KRR3 b - - 6 14", 5, Score::cp(380), Score::cp(420), "g7h6"),
+                ("r5k1/p1P5/8/8/8/8/3RK3/8 w - - 0 1", 6, Score::cp(850), Score::cp(900), "d2d8"),
+                ("6k1/8/8/3q4/8/8/P7/1KNB4 w - - 0 1", 4, Score::cp(360), Score::cp(420), "d1b3"),
+                ("2kr3r/ppp1qpb1/5n2/5b1p/6p1/1PNP4/PBPQBPPP/2KRR3 b - - 6 14", 5, Score::cp(340), Score::cp(420), "g7h6"),
                 ("7k/2R5/8/8/6q1/7p/7P/7K w - - 0 1", 6, Score::cp(0), Score::cp(0), "c7h7"),
 
-                // Pawn race
-                ("8/6pk/8/8/8/8/P7/K7 w - - 0 1", 22, Score::cp(700), Score::cp(920), "a1b1"),
+                // Draws
+                ("k7/8/8/8/8/8/1r6/K1N5 w - - 0 1", 3, Score::cp(0), Score::cp(0), "a1b2"),
+                // Mate on the 100th half-move beats the fifty-move draw.
+                ("k7/8/1K6/8/8/8/8/7R w - - 99 80", 2, Score::mate(1), Score::mate(1), "h1h8"),
+
+                // Pawn race. The king catches the g-pawn while the a-pawn runs to queen, leaving a
+                // queen against a pawn.
+                ("8/6pk/8/8/8/8/P7/K7 w - - 0 1", 22, Score::cp(620), Score::cp(720), "a1b1"),
             ]
         }
     }
@@ -1018,14 +1729,876 @@ mod tests {
 
         for (fen, depth, lo, hi, bm) in suite {
             let pos = Position::from_fen(fen).unwrap();
-            let flag = AtomicBool::new(false);
-            let tt = Table::new(16);
-            let mut search = Search::new(pos, &flag, None, &tt);
+            let fixture = Fixture::new(16);
+            let mut search = fixture.search(pos);
             let (s, m) = search.run::<Master>(depth);
 
-            assert!(lo <= s);
-            assert!(s <= hi);
-            assert_eq!(m.to_uci_string(), bm);
+            assert!(lo <= s && s <= hi, "{}: {}", fen, s);
+            assert_eq!(m.to_uci_string(), bm, "{}", fen);
+        }
+    }
+
+    /// Lazy SMP with several threads sharing the transposition table must still find the right
+    /// mates. Each thread orders and reduces moves differently, so at a fixed depth the master can
+    /// pick up a worker's entry for a longer mate than the shortest one. The mate itself and the
+    /// move must be found, and the mate can never be shorter than the real one.
+    #[test]
+    fn lazy_smp_finds_mates() {
+        core::init::init_globals();
+
+        let mates = suite().into_iter().filter(|(_, _, lo, _, _)| lo.is_mate());
+
+        for (fen, depth, _, hi, bm) in mates {
+            let pos = Position::from_fen(fen).unwrap();
+            let fixture = Fixture::new(16);
+
+            let (s, m) = std::thread::scope(|scope| {
+                for i in 1..4 {
+                    let pos = pos.clone();
+                    let fixture = &fixture;
+                    scope.spawn(move || {
+                        fixture.search(pos).with_thread_id(i).run::<Worker>(depth);
+                    });
+                }
+
+                fixture.search(pos.clone()).run::<Master>(depth)
+            });
+
+            assert!(s.is_mate() && s <= hi, "{}: {}", fen, s);
+            assert_eq!(m.to_uci_string(), bm, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn search_stats() {
+        core::init::init_globals();
+
+        let pos = Position::start_pos();
+        let fixture = Fixture::new(16);
+        let mut search = fixture.search(pos);
+        search.run::<Worker>(6);
+
+        let stats = search.stats();
+        assert_eq!(stats.depth, 6);
+        assert!(stats.seldepth >= 6);
+        assert!(stats.nodes > 0 && stats.q_nodes > 0);
+        assert_eq!(stats.all_nodes(), search.trace.all_nodes_visited());
+        assert!(stats.nps > 0);
+        assert!(stats.eff_branching > 1.);
+
+        // The table is large for a search this small, so most occupied slots probed hold the
+        // position searched for, but not all.
+        assert!(stats.hash_hits > 0 && stats.hash_clashes > 0);
+        let hit_rate = stats.hash_hit_rate();
+        assert!(hit_rate > 0.5 && hit_rate < 1., "{}", hit_rate);
+    }
+
+    /// A search feature, the features also switched off to measure it alone, the quiet positions
+    /// and depth it's measured over, and whether to count every node or only quiescence nodes.
+    type FeatureCase = (
+        Feature,
+        &'static [Feature],
+        &'static [&'static str],
+        u8,
+        bool,
+    );
+
+    /// The search features which tests switch off.
+    fn features() -> [FeatureCase; 5] {
+        use Feature::*;
+
+        const OPENING: &str = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+        const QGD: &str = "r2q1rk1/pp2bppp/2n1pn2/3p4/3P4/2NBPN2/PP3PPP/R2Q1RK1 w - - 0 10";
+        const SICILIAN: &str = "2r2rk1/pp1bqppp/2nppn2/8/2PNP3/2N1B3/PP2BPPP/R2Q1RK1 w - - 0 11";
+
+        // The history tables overlap with countermoves and with each other, so they're left out
+        // where they would hide the feature being measured.
+        [
+            (
+                LateMoveReductions,
+                &[],
+                &[START_POSITION, OPENING, QGD],
+                6,
+                false,
+            ),
+            (
+                CounterMoves,
+                &[ButterflyHistory, ContinuationHistory],
+                &[START_POSITION, OPENING, QGD],
+                6,
+                false,
+            ),
+            (
+                ButterflyHistory,
+                &[ContinuationHistory],
+                &[OPENING, QGD, SICILIAN],
+                7,
+                false,
+            ),
+            (
+                ContinuationHistory,
+                &[],
+                &[
+                    OPENING,
+                    QGD,
+                    "rnbqkb1r/pp2pppp/3p1n2/8/3NP3/8/PPP2PPP/RNBQKB1R w KQkq - 1 5",
+                    "r1bq1rk1/ppp2ppp/2np1n2/2b1p3/2B1P3/2NP1N2/PPP2PPP/R1BQ1RK1 w - - 0 7",
+                    SICILIAN,
+                ],
+                7,
+                false,
+            ),
+            (
+                DeltaPruning,
+                &[],
+                &[
+                    OPENING,
+                    QGD,
+                    "r1bq1rk1/pp3ppp/2n1pn2/2bp4/2P5/2N1PN2/PPQ2PPP/R1B1KB1R w KQ - 0 8",
+                ],
+                5,
+                true,
+            ),
+        ]
+    }
+
+    /// Each search feature should shrink the tree searched in quiet positions.
+    #[test]
+    fn features_reduce_nodes() {
+        core::init::init_globals();
+
+        for (feature, others, fens, depth, q_nodes) in features() {
+            let nodes = |fen: &str, on: bool| {
+                let pos = Position::from_fen(fen).unwrap();
+                let fixture = Fixture::new(16);
+                let mut search = fixture.search(pos);
+                search.disabled = others.to_vec();
+                if !on {
+                    search.disabled.push(feature);
+                }
+                search.run::<Worker>(depth);
+                if q_nodes {
+                    search.trace.q_nodes_visited()
+                } else {
+                    search.trace.all_nodes_visited()
+                }
+            };
+
+            // The effect on any one position is noisy, so compare the total over several.
+            let with: usize = fens.iter().map(|fen| nodes(fen, true)).sum();
+            let without: usize = fens.iter().map(|fen| nodes(fen, false)).sum();
+            assert!(with < without, "{:?}: {} >= {}", feature, with, without);
+        }
+    }
+
+    /// No search feature should change the outcome of the mate suite.
+    #[test]
+    fn features_keep_mates() {
+        core::init::init_globals();
+
+        for (feature, others, _, _, _) in features() {
+            for (fen, depth, lo, hi, bm) in suite() {
+                if !lo.is_mate() {
+                    continue;
+                }
+
+                let run = |on: bool| {
+                    let pos = Position::from_fen(fen).unwrap();
+                    let fixture = Fixture::new(16);
+                    let mut search = fixture.search(pos);
+                    search.disabled = others.to_vec();
+                    if !on {
+                        search.disabled.push(feature);
+                    }
+                    let (s, m) = search.run::<Worker>(depth);
+                    (s, m.to_uci_string())
+                };
+
+                // A feature can find a shorter mate first, so the mate length needn't match the
+                // run without it, as long as it's within the suite's range.
+                let (with, without) = (run(true), run(false));
+                let in_range = lo <= with.0 && with.0 <= hi;
+                assert!(
+                    with.0 == without.0 || in_range,
+                    "{:?}, {}: {}",
+                    feature,
+                    fen,
+                    with.0
+                );
+                assert_eq!(with.1, without.1, "{:?}, {}", feature, fen);
+                assert_eq!(with.1, bm, "{:?}, {}", feature, fen);
+            }
+        }
+    }
+
+    /// The killer table used to hold 20 plies. Searches deeper than that must still store and
+    /// probe killers safely.
+    #[test]
+    fn searches_deeper_than_twenty_plies() {
+        core::init::init_globals();
+
+        let pos = Position::from_fen("4k3/8/8/p1p1p1p1/P1P1P1P1/8/8/4K3 w - - 0 1").unwrap();
+        let fixture = Fixture::new(16);
+        let mut search = fixture.search(pos);
+        let (_, m) = search.run::<Worker>(22);
+
+        assert!(!m.is_null());
+        assert_eq!(search.depth_reached, 22);
+    }
+
+    /// Extending checks should let a shallow search see through a forcing sequence of checks.
+    #[test]
+    fn check_extensions_find_deeper_mates() {
+        core::init::init_globals();
+
+        let fen = "6rk/p7/1pq1p2p/4P3/5BrP/P3Qp2/1P1R1K1P/5R2 b - - 0 34";
+        let score = |check_extensions: bool| {
+            let pos = Position::from_fen(fen).unwrap();
+            let fixture = Fixture::new(16);
+            let mut search = fixture.search(pos);
+            if !check_extensions {
+                search.disabled.push(Feature::CheckExtensions);
+            }
+            search.run::<Worker>(4).0
+        };
+
+        assert_eq!(score(true), Score::mate(7));
+        assert!(!score(false).is_mate());
+    }
+
+    /// Mate distances mustn't drift when positions are found in the transposition table at a
+    /// different distance from the root than where they were stored.
+    #[test]
+    fn mate_scores_are_stable_across_tt_hits() {
+        core::init::init_globals();
+
+        let pos =
+            Position::from_fen("5rk1/rb3ppp/p7/1pn1q3/8/1BP2Q2/PP3PPP/3R1RK1 w - - 7 21").unwrap();
+        let search = |pos: &Position, fixture: &Fixture, depth: u8| {
+            let mut search = fixture.search(pos.clone());
+            let score = search.run::<Worker>(depth).0;
+            (score, search.lines[0].1.clone())
+        };
+
+        let (score, pv) = search(&pos, &Fixture::new(16), 6);
+        assert_eq!(score, Score::mate(5));
+
+        // Search the position two plies into the mate first, so that the table holds entries
+        // stored nearer the root than they are found in the searches which follow.
+        let mut later = pos.clone();
+        later.make_move(&pv[0]);
+        later.make_move(&pv[1]);
+
+        let fixture = Fixture::new(16);
+        assert_eq!(search(&later, &fixture, 6).0, Score::mate(3));
+        for depth in 5..=8 {
+            assert_eq!(
+                search(&pos, &fixture, depth).0,
+                Score::mate(5),
+                "depth {}",
+                depth
+            );
+        }
+        assert_eq!(search(&later, &fixture, 4).0, Score::mate(3));
+    }
+
+    /// In check, quiescence must search the quiet evasions rather than standing pat.
+    #[test]
+    fn quiescence_searches_check_evasions() {
+        core::init::init_globals();
+
+        let quiesce = |fen: &str| {
+            let pos = Position::from_fen(fen).unwrap();
+            let fixture = Fixture::new(1);
+            let mut search = fixture.search(pos);
+            search.quiesce::<Worker, Pv>(Score::INF_N, Score::INF_P)
+        };
+
+        // White is a queen up, but the knight forks king and queen. Every evasion is a king move,
+        // after which the queen is lost.
+        let score = quiesce("k7/8/8/8/8/5n2/3Q4/6K1 w - - 0 1");
+        assert!(
+            Score::cp(-350) < score && score < Score::cp(-200),
+            "{}",
+            score
+        );
+
+        // A back rank mate, with no evasions.
+        assert_eq!(quiesce("k7/8/8/8/8/8/5PPP/3r2K1 w - - 0 1"), Score::mate(0));
+    }
+
+    /// A promotion is searched in quiescence even though it isn't a capture.
+    #[test]
+    fn quiescence_searches_promotions() {
+        core::init::init_globals();
+
+        let pos = Position::from_fen("8/4P3/7k/8/8/2K5/8/r7 w - - 0 1").unwrap();
+        let fixture = Fixture::new(1);
+        let mut search = fixture.search(pos);
+
+        // Standing pat leaves white a rook for a pawn down, but e8=Q wins it back with interest.
+        let score = search.quiesce::<Worker, Pv>(Score::INF_N, Score::INF_P);
+        assert!(score > Score::cp(300), "{}", score);
+    }
+
+    /// An en passant capture is ordered like any other pawn capture, though its destination square
+    /// is empty.
+    #[test]
+    fn en_passant_captures_are_scored_as_pawn_captures() {
+        core::init::init_globals();
+
+        let pos = Position::from_fen("4k3/8/5p2/3pP3/8/8/8/4K3 w - d6 0 1").unwrap();
+        let captures = pos.generate::<BasicMoveList, Captures, Legal>();
+        let capture_to = |sq: Square| *captures.iter().find(|mov| mov.dest() == sq).unwrap();
+        let en_passant = capture_to(Square::D6);
+        let pawn_capture = capture_to(Square::F6);
+        assert!(en_passant.is_en_passant());
+
+        let fixture = Fixture::new(1);
+        let mut search = fixture.search(pos);
+        assert_eq!(
+            search.capture_score(&en_passant),
+            search.capture_score(&pawn_capture)
+        );
+    }
+
+    /// No node is searched beyond `MAX_PLY`, however the search gets there.
+    #[test]
+    fn search_stops_at_max_ply() {
+        core::init::init_globals();
+
+        // A king and pawn race, where both sides queen and checks follow.
+        let pos = Position::from_fen("8/6pk/8/8/8/8/P7/K7 w - - 0 1").unwrap();
+        let fixture = Fixture::new(16);
+
+        // At the maximum ply, both searches return the static evaluation without making a move.
+        let mut search = fixture.search(pos.clone());
+        let eval = search.evaluate();
+        search.ply = MAX_PLY;
+        let score = search.quiesce::<Worker, Pv>(Score::INF_N, Score::INF_P);
+        assert_eq!(score, eval);
+        let score = search.search::<Worker, Pv>(Score::INF_N, Score::INF_P, 3);
+        assert_eq!(score, eval);
+        assert_eq!(search.trace.all_nodes_visited(), 2);
+
+        // A search to the maximum depth, stopped by its node limit, extends lines deep into the
+        // race without overflowing the stack.
+        let mut search = fixture.search(pos).with_node_limit(Some(2_000_000));
+        search.run::<Worker>(MAX_PLY);
+        assert!(search.seldepth <= MAX_PLY, "{}", search.seldepth);
+    }
+
+    /// A mate found in quiescence is reported with its distance from the root, and stored in the
+    /// table with its distance from the node which found it.
+    #[test]
+    fn quiescence_mates_are_reported_with_their_distance() {
+        core::init::init_globals();
+
+        // Qxf7+ Kh8 Qxg7#, where both of White's moves are captures.
+        let fen = "r5k1/5ppp/8/8/2B5/8/5Q2/1K4R1 w - - 0 1";
+        let pos = Position::from_fen(fen).unwrap();
+        let fixture = Fixture::new(1);
+
+        let mut search = fixture.search(pos.clone());
+        let score = search.quiesce::<Worker, Pv>(Score::INF_N, Score::INF_P);
+        assert_eq!(score, Score::mate(3));
+
+        let mut search = fixture.search(pos.clone());
+        let (score, mov) = search.run::<Worker>(1);
+        assert_eq!(score, Score::mate(3));
+        assert_eq!(score.to_uci_string(), "mate 2");
+        assert_eq!(mov.to_uci_string(), "f2f7");
+
+        // After Qxf7+ Kh8, White mates in one, whatever the distance from the root.
+        let mut pos = pos;
+        pos.make_uci_move("f2f7").unwrap();
+        pos.make_uci_move("g8h8").unwrap();
+        let entry = fixture.tt.probe(&pos).into_inner().read().clone();
+        assert_eq!(entry.depth, 0);
+        assert_eq!(entry.score, Score::mate(1));
+        // The window there may already be narrowed to the mate, in which case it fails high.
+        assert_ne!(entry.bound(), Bound::Upper);
+    }
+
+    /// Deep exact entries from an earlier search should survive several later shallow searches.
+    #[test]
+    fn deep_entries_survive_shallow_searches() {
+        core::init::init_globals();
+
+        let pos = Position::from_fen("8/8/4k3/8/8/3QK3/8/8 w - - 0 1").unwrap();
+        let fixture = Fixture::new(1);
+        fixture.search(pos.clone()).run::<Worker>(10);
+
+        let deep = fixture.tt.probe(&pos).into_inner().read().clone();
+        assert_eq!(deep.depth, 10);
+        assert_eq!(deep.bound(), Bound::Exact);
+
+        for _ in 0..3 {
+            fixture.tt.new_generation();
+            fixture.search(pos.clone()).run::<Worker>(3);
         }
+
+        let entry = fixture.tt.probe(&pos);
+        assert!(entry.is_hit());
+        let entry = entry.into_inner();
+        assert_eq!(entry.read().depth, 10);
+        assert_eq!(entry.read().bound(), Bound::Exact);
+        assert_eq!(entry.read().score, deep.score);
+    }
+
+    /// Entries left in the table by earlier searches, including a search of the same position,
+    /// must not change the result. The table is no longer cleared between moves.
+    #[test]
+    fn warm_table_gives_correct_answers() {
+        core::init::init_globals();
+
+        let fixture = Fixture::new(16);
+
+        for (fen, depth, lo, hi, bm) in suite().into_iter().chain(suite()) {
+            for _ in 0..2 {
+                let pos = Position::from_fen(fen).unwrap();
+                // The master raises the stop flag when it finishes, so lower it again.
+                fixture.flag.store(false, Ordering::Relaxed);
+                fixture.tt.new_generation();
+                let (s, m) = fixture.search(pos).run::<Master>(depth);
+
+                assert!(lo <= s && s <= hi, "{}: {}", fen, s);
+                assert_eq!(m.to_uci_string(), bm, "{}", fen);
+            }
+        }
+    }
+
+    #[test]
+    fn multipv_reports_both_winning_captures() {
+        core::init::init_globals();
+
+        // Either knight can take the queen.
+        let pos = Position::from_fen("k7/8/8/3q4/8/2N1N3/8/K7 w - - 0 1").unwrap();
+        let fixture = Fixture::new(1);
+        let mut search = fixture.search(pos).with_multipv(2);
+        search.run::<Worker>(6);
+
+        assert_eq!(search.lines.len(), 2);
+
+        let mut best: Vec<String> = search
+            .lines
+            .iter()
+            .map(|(_, pv)| pv[0].to_uci_string())
+            .collect();
+        best.sort();
+        assert_eq!(best, vec!["c3d5", "e3d5"]);
+
+        for (score, _) in &search.lines {
+            assert!(*score > Score::cp(500));
+        }
+    }
+
+    #[test]
+    fn root_moves_restrict_search() {
+        core::init::init_globals();
+
+        let pos = Position::from_fen("k7/8/8/3q4/8/2N1N3/8/K7 w - - 0 1").unwrap();
+        let fixture = Fixture::new(1);
+
+        // Neither capture of the queen is allowed, so the best move must be a king move.
+        let king_moves: Vec<Move> = ["a1b1", "a1b2"]
+            .iter()
+            .map(|uci| pos.parse_uci_move(uci).unwrap())
+            .collect();
+        let mut search = fixture
+            .search(pos.clone())
+            .with_root_moves(Some(king_moves.clone()));
+        let (_, best_move) = search.run::<Worker>(4);
+        assert!(king_moves.contains(&best_move));
+
+        // With MultiPV, the lines are only drawn from the search moves.
+        let root_moves: Vec<Move> = ["a1b1", "a1b2", "c3d5"]
+            .iter()
+            .map(|uci| pos.parse_uci_move(uci).unwrap())
+            .collect();
+        let mut search = fixture
+            .search(pos)
+            .with_multipv(5)
+            .with_root_moves(Some(root_moves.clone()));
+        search.run::<Worker>(4);
+
+        assert_eq!(search.lines.len(), 3);
+        assert_eq!(search.lines[0].1[0].to_uci_string(), "c3d5");
+        for (_, pv) in &search.lines {
+            assert!(root_moves.contains(&pv[0]));
+        }
+    }
+
+    #[test]
+    fn seldepth_includes_quiescence() {
+        core::init::init_globals();
+
+        // Kiwipete is full of captures, so quiescence runs well beyond the nominal depth.
+        let pos = Position::from_fen(
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+        )
+        .unwrap();
+        let fixture = Fixture::new(1);
+        let mut search = fixture.search(pos);
+        search.run::<Worker>(3);
+
+        assert_eq!(search.depth_reached, 3);
+        assert!(search.seldepth > 3, "{}", search.seldepth);
+        assert_eq!(search.ply, 0);
+    }
+
+    #[test]
+    fn equal_captures_are_ordered_by_mvv_lva() {
+        core::init::init_globals();
+
+        // Qxd5 and axb5 each lose the capturing piece back, so both have SEE 0.
+        let pos = Position::from_fen("6k1/8/2p1p3/1p1q4/P7/8/8/3Q2K1 w - - 0 1").unwrap();
+        let fixture = Fixture::new(1);
+        let mut search = fixture.search(pos);
+
+        let mut moves = OrderedMoves::new();
+        let mut captures = Vec::new();
+        while moves.load_next_phase(MoveLoader::from(&mut search, None, 1, None, [None, None])) {
+            let phase = moves.phase();
+            for mov in &moves {
+                if mov.is_capture() {
+                    captures.push((mov.to_string(), phase));
+                }
+            }
+        }
+
+        assert_eq!(
+            captures,
+            [
+                (String::from("d1d5"), Phase::EqualCaptures),
+                (String::from("a4b5"), Phase::EqualCaptures),
+            ]
+        );
+    }
+
+    #[test]
+    fn evaluation_shrinks_as_the_fifty_move_clock_rises() {
+        core::init::init_globals();
+
+        let fixture = Fixture::new(1);
+        let evaluate = |fen| {
+            let pos = Position::from_fen(fen).unwrap();
+            fixture.search(pos).evaluate()
+        };
+
+        // White is a rook up.
+        let fresh = evaluate("4k3/pppp4/8/8/8/8/PPPP4/R3K3 w - - 0 40");
+        let early = evaluate("4k3/pppp4/8/8/8/8/PPPP4/R3K3 w - - 20 40");
+        let late = evaluate("4k3/pppp4/8/8/8/8/PPPP4/R3K3 w - - 80 40");
+        let limit = evaluate("4k3/pppp4/8/8/8/8/PPPP4/R3K3 w - - 100 40");
+
+        assert!(fresh > Score::cp(400));
+        assert_eq!(early, fresh);
+        assert!(late < fresh);
+        assert!(late > Score::cp(0));
+        assert_eq!(limit, Score::cp(0));
+
+        // The scaling is the same for the side which is behind.
+        let black = evaluate("4k3/pppp4/8/8/8/8/PPPP4/R3K3 b - - 80 40");
+        assert_eq!(black, -late);
+    }
+
+    #[test]
+    fn ponder_move_is_second_move_of_pv() {
+        core::init::init_globals();
+
+        let pos = Position::start_pos();
+        let fixture = Fixture::new(1);
+        let mut search = fixture.search(pos);
+        let (_, best_move) = search.run::<Master>(4);
+
+        let (_, pv) = &search.lines[0];
+        assert_eq!(pv[0], best_move);
+        assert_eq!(search.ponder_move(), Some(pv[1]));
+
+        // The ponder move must be legal after the best move.
+        let mut pos = Position::start_pos();
+        pos.make_move(&best_move);
+        assert!(pos.valid_move(&search.ponder_move().unwrap()));
+
+        assert_eq!(
+            search.best_move_line(&best_move),
+            format!("bestmove {} ponder {}", best_move, pv[1])
+        );
+    }
+
+    #[test]
+    fn mate_in_one_has_no_ponder_move() {
+        core::init::init_globals();
+
+        // Back rank mate: after Re8# there is no reply to ponder on.
+        let pos = Position::from_fen("6k1/5ppp/8/8/8/8/5PPP/4R1K1 w - - 0 1").unwrap();
+        let fixture = Fixture::new(1);
+        let mut search = fixture.search(pos);
+        let (score, best_move) = search.run::<Worker>(4);
+
+        assert_eq!(format!("{}", score), "mate 1");
+        assert_eq!(search.ponder_move(), None);
+        assert_eq!(search.best_move_line(&best_move), "bestmove e1e8");
+    }
+
+    #[test]
+    fn telemetry_is_only_reported_in_debug_mode() {
+        core::init::init_globals();
+
+        let fixture = Fixture::new(1);
+
+        let mut search = fixture.search(Position::start_pos());
+        let (score, _) = search.run::<Worker>(4);
+        assert!(search.telemetry(score).is_empty());
+
+        let mut search = fixture.search(Position::start_pos()).with_debug(true);
+        let (score, _) = search.run::<Worker>(4);
+        let lines: Vec<String> = search
+            .telemetry(score)
+            .iter()
+            .map(|info| info.to_string())
+            .collect();
+        assert!(!lines.is_empty());
+        assert!(lines.iter().all(|line| line.starts_with("info string ")));
+        assert!(lines.iter().any(|line| line.contains("nodes:")));
+    }
+
+    #[test]
+    fn mated_and_stalemated_roots() {
+        core::init::init_globals();
+
+        for (fen, expected) in [
+            // Fool's mate.
+            (
+                "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3",
+                Score::mate(0),
+            ),
+            ("7k/5Q2/6K1/8/8/8/8/8 b - - 0 1", Score::zero()),
+        ] {
+            let pos = Position::from_fen(fen).unwrap();
+            let fixture = Fixture::new(1);
+            let mut search = fixture.search(pos);
+            let (score, best_move) = search.run::<Worker>(5);
+
+            assert_eq!(score, expected, "{}", fen);
+            assert!(best_move.is_null(), "{}", fen);
+            assert_eq!(search.best_move_line(&best_move), "bestmove 0000");
+        }
+    }
+
+    #[test]
+    fn node_limit() {
+        core::init::init_globals();
+
+        let pos = Position::start_pos();
+        let fixture = Fixture::new(1);
+        let mut search = fixture.search(pos.clone()).with_node_limit(Some(100_000));
+        let (_, best_move) = search.run::<Worker>(u8::MAX);
+
+        // The search stopped at the first check after the limit, plus the nodes visited while
+        // unwinding.
+        let nodes = search.trace.all_nodes_visited();
+        assert!((100_000..100_000 + 2048).contains(&nodes));
+        assert!(fixture.flag.load(Ordering::Relaxed));
+
+        assert!(pos.valid_move(&best_move));
+    }
+
+    /// Wherever the search is stopped, the result is the one from the last completed depth, which
+    /// is what an uninterrupted search to that depth returns.
+    #[test]
+    fn aborted_searches_report_the_last_completed_depth() {
+        core::init::init_globals();
+
+        let fen = "r2q1rk1/pp2bppp/2n1pn2/3p4/3P4/2NBPN2/PP3PPP/R2Q1RK1 w - - 0 10";
+        let pos = Position::from_fen(fen).unwrap();
+        let complete = |depth: u8| Fixture::new(16).search(pos.clone()).run::<Worker>(depth);
+
+        // A stop which arrives before the search starts still lets the first iteration finish.
+        let fixture = Fixture::new(16);
+        fixture.flag.store(true, Ordering::Relaxed);
+        let mut search = fixture.search(pos.clone());
+        let (score, best_move) = search.run::<Worker>(u8::MAX);
+        assert_eq!(search.depth_reached, 1);
+        assert_eq!((score, best_move), complete(1));
+
+        // Node limits stop the search at many different points within an iteration.
+        for limit in [1, 3_000, 7_000, 15_000, 40_000, 90_000, 200_000] {
+            let fixture = Fixture::new(16);
+            let mut search = fixture.search(pos.clone()).with_node_limit(Some(limit));
+            let (score, best_move) = search.run::<Worker>(u8::MAX);
+
+            assert!(!best_move.is_null(), "limit {}", limit);
+            assert!(pos.valid_move(&best_move), "limit {}", limit);
+            assert_eq!(
+                (score, best_move),
+                complete(search.depth_reached),
+                "limit {}",
+                limit
+            );
+        }
+    }
+
+    #[test]
+    fn movetime() {
+        core::init::init_globals();
+
+        let pos = Position::start_pos();
+        let fixture = Fixture::new(1);
+
+        let start = Instant::now();
+        fixture.deadline.set(TimingMode::MoveTime(200).stop_time(
+            start,
+            pos.move_number(),
+            pos.turn(),
+        ));
+        assert_eq!(
+            fixture.deadline.get(),
+            Some(start + Duration::from_millis(200))
+        );
+        let (_, best_move) = fixture.search(pos.clone()).run::<Worker>(u8::MAX);
+        let elapsed = start.elapsed();
+
+        // The search runs until the deadline. How far past it the search gets depends on the
+        // machine, so the bound is loose; a search which ignored the deadline would run for far
+        // longer.
+        assert!(elapsed >= Duration::from_millis(200));
+        assert!(elapsed < Duration::from_secs(2), "{:?}", elapsed);
+        assert!(pos.valid_move(&best_move));
+    }
+
+    #[test]
+    fn movetime_is_respected_between_clock_reads() {
+        core::init::init_globals();
+
+        // The clock is only read every few thousand nodes, so the search overruns its deadline by
+        // at most the time taken to visit that many nodes, and to unwind.
+        let pos =
+            Position::from_fen("r1bq1rk1/pp2bppp/2n1pn2/3p4/2PP4/2N2N2/PP3PPP/R1BQKB1R w KQ - 4 8")
+                .unwrap();
+        for movetime in [10, 50, 100] {
+            let fixture = Fixture::new(1);
+
+            let start = Instant::now();
+            fixture
+                .deadline
+                .set(TimingMode::MoveTime(movetime).stop_time(
+                    start,
+                    pos.move_number(),
+                    pos.turn(),
+                ));
+            let (_, best_move) = fixture.search(pos.clone()).run::<Worker>(u8::MAX);
+            let elapsed = start.elapsed();
+
+            let movetime = Duration::from_millis(movetime as u64);
+            assert!(elapsed >= movetime);
+            // The upper bound is loose, so that a busy machine doesn't fail the test. A search which
+            // ignored the deadline would run for far longer.
+            assert!(elapsed < movetime + Duration::from_secs(1), "{:?}", elapsed);
+            assert!(pos.valid_move(&best_move));
+        }
+    }
+
+    #[test]
+    fn mate_search_stops_when_mate_is_proven() {
+        core::init::init_globals();
+
+        // A mate in 3, so `go mate 5` searches to at most 10 plies, but should stop early.
+        let pos = Position::from_fen("8/2R2pp1/k3p3/8/5Bn1/6P1/5r1r/1R4K1 w - - 4 3").unwrap();
+        let fixture = Fixture::new(1);
+        let mut search = fixture.search(pos.clone()).with_mate_limit(Some(5));
+        let (score, best_move) = search.run::<Worker>(10);
+
+        assert_eq!(score, Score::mate(5));
+        assert_eq!(format!("{}", score), "mate 3");
+        assert_eq!(best_move.to_uci_string(), "c7c6");
+        assert!(search.depth_reached < 10);
+
+        // A limit beyond the longest mate a score can represent still stops at the mate.
+        let mut search = fixture.search(pos).with_mate_limit(Some(60));
+        let (score, _) = search.run::<Worker>(10);
+        assert_eq!(score, Score::mate(5));
+        assert!(search.depth_reached < 10);
+    }
+
+    #[test]
+    fn infinite_search_runs_until_stopped() {
+        core::init::init_globals();
+
+        // In the first position, iterative deepening is still running when we stop. In the second,
+        // it runs out of depth almost immediately, and must wait for the stop.
+        for fen in [START_POSITION, "k7/8/1K6/8/8/8/8/7R w - - 0 1"] {
+            let pos = Position::from_fen(fen).unwrap();
+            let fixture = Fixture::new(1);
+
+            let ((_, best_move), stopped_at, returned_at) = std::thread::scope(|s| {
+                let search = s.spawn(|| {
+                    let result = fixture
+                        .search(pos.clone())
+                        .with_infinite(true)
+                        .run::<Master>(MAX_PLY);
+                    (result, Instant::now())
+                });
+
+                std::thread::sleep(Duration::from_millis(200));
+                assert!(!search.is_finished());
+
+                let stopped_at = Instant::now();
+                fixture.flag.store(true, Ordering::Relaxed);
+                let (result, returned_at) = search.join().unwrap();
+                (result, stopped_at, returned_at)
+            });
+
+            // The search only returns once it is stopped, however long it takes to notice.
+            assert!(returned_at >= stopped_at);
+            assert!(pos.valid_move(&best_move));
+        }
+    }
+
+    /// Run a handful of searches in debug mode so that the node type assertions (null windows at
+    /// non-PV nodes, root only at ply 0) are exercised through the Cut/All alternation.
+    #[test]
+    fn node_type_invariants_hold() {
+        core::init::init_globals();
+
+        let fens = [
+            core::position::START_POSITION,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ];
+
+        for fen in fens {
+            let pos = Position::from_fen(fen).unwrap();
+            let fixture = Fixture::new(1);
+            let mut search = fixture.search(pos);
+            search.run::<Worker>(4);
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn root_node_only_at_ply_zero() {
+        core::init::init_globals();
+
+        let pos = Position::start_pos();
+        let fixture = Fixture::new(1);
+        let mut search = fixture.search(pos);
+        search.ply = 1;
+        search.search::<Worker, Root>(Score::INF_N, Score::INF_P, 2);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic]
+    fn non_pv_node_requires_null_window() {
+        core::init::init_globals();
+
+        let pos = Position::start_pos();
+        let fixture = Fixture::new(1);
+        let mut search = fixture.search(pos);
+        search.search_depth = 3;
+        search.search::<Worker, Cut>(Score::cp(-50), Score::cp(50), 2);
     }
 }
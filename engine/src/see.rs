@@ -5,100 +5,221 @@ use super::score::Score;
 use super::search::Search;
 
 use core::bb::Bitboard;
-use core::position::{PieceType, Player, Square};
+use core::mov::Move;
+use core::position::{PieceType, Player, Position, Square};
 
 use std::cmp::max;
 
 impl<'engine> Search<'engine> {
-    /// The SEE swap algorithm.
-    ///
-    /// Returns the statically minimaxed outcome of exchanges on square `to` after current player
-    /// captures a piece of type `target` with a piece of type `attacker` on square `from`. This
-    /// analysis includes the effect of x-rays by sliding pieces through friendly pieces which
-    /// move earlier (e.g. rook batteries along a file).
+    /// The SEE swap algorithm, on the search's position. See `swap`.
     pub fn see(
         &mut self,
         from: Square,
         to: Square,
         target: PieceType,
-        mut attacker: PieceType,
+        attacker: PieceType,
     ) -> Score {
-        let mut gain: [Score; 32] = [Score::cp(0); 32];
-        let mut d: usize = 0;
+        swap(&self.pos, from, to, target, attacker)
+    }
+}
 
-        let may_xray = self.pos.piece_bb_both_players(PieceType::Pawn)
-            | self.pos.piece_bb_both_players(PieceType::Bishop)
-            | self.pos.piece_bb_both_players(PieceType::Rook)
-            | self.pos.piece_bb_both_players(PieceType::Queen);
-        let mut from_set = from.to_bb();
-        let mut occ = self.pos.occupied();
-        let mut atta_def = self.pos.attack_defend(occ, to);
-        let mut side = self.pos.turn().other_player();
+/// Adds static exchange evaluation to a type representing a chess position.
+pub trait StaticExchange {
+    /// The material gained or lost, in centipawns, by the side to move when it plays the capture
+    /// `mov` and both sides then recapture on its destination square while it pays to do so.
+    /// Promotions are valued as captures by a pawn.
+    fn see(&self, mov: &Move) -> i32;
 
-        // Need to track the processed sliding pieces to ensure that we don't repeatedly process
-        // them in x-ray attacks.
-        let mut processed = Bitboard::empty();
+    /// Whether `see(mov)` is at least `threshold`. This is what pruning decisions need, and is
+    /// cheaper than `see`, as it stops as soon as the outcome is known rather than working out the
+    /// whole sequence of exchanges.
+    fn see_ge(&self, mov: &Move, threshold: i32) -> bool;
+}
 
-        gain[0] = Score::cp(piece_value(target));
+impl StaticExchange for Position {
+    fn see(&self, mov: &Move) -> i32 {
+        let (target, attacker) = exchange_pieces(self, mov);
+        swap(self, mov.orig(), mov.dest(), target, attacker).to_i16() as i32
+    }
 
-        while !from_set.is_empty() {
-            d += 1;
+    fn see_ge(&self, mov: &Move, threshold: i32) -> bool {
+        let (target, attacker) = exchange_pieces(self, mov);
+        let (from, to) = (mov.orig(), mov.dest());
 
-            gain[d] = Score::cp(piece_value(attacker)) - gain[d - 1];
+        // If the capture loses even when it isn't recaptured, or wins even when it is, we're done.
+        let mut swap = value(target) - threshold;
+        if swap < 0 {
+            return false;
+        }
+
+        swap = value(attacker) - swap;
+        if swap <= 0 {
+            return true;
+        }
 
-            if max(-gain[d - 1], gain[d]) < Score::cp(0) {
+        let may_xray = self.piece_bb_both_players(PieceType::Pawn)
+            | self.piece_bb_both_players(PieceType::Bishop)
+            | self.piece_bb_both_players(PieceType::Rook)
+            | self.piece_bb_both_players(PieceType::Queen);
+        let unavailable = self.pinned_off_line(to);
+        let mut occ = self.occupied() ^ from.to_bb();
+        let mut processed = from.to_bb();
+        let mut atta_def = self.attack_defend(occ, to) & !unavailable & !processed;
+        let mut side = self.turn();
+
+        // `res` is whether the side to move comes out ahead if the exchange stops here. Each
+        // recapture flips it, and `swap` tracks how far the recapturing side would then have to
+        // fall behind for the capture before to still stand.
+        let mut res = true;
+        loop {
+            side = side.other_player();
+
+            let (attacker, from_set) = least_valuable_piece(self, atta_def, side);
+            if from_set.is_empty() {
+                break;
+            }
+
+            res = !res;
+
+            // The king can only recapture if the square isn't defended.
+            if attacker == PieceType::King {
+                let defended = (atta_def & self.get_occupied_player_runtime(side.other_player()))
+                    .is_not_empty();
+                return if defended { !res } else { res };
+            }
+
+            swap = value(attacker) - swap;
+            if swap < res as i32 {
                 break;
             }
 
             atta_def ^= from_set;
             occ ^= from_set;
             processed ^= from_set;
-
             if !(from_set & may_xray).is_empty() {
-                atta_def |= self.pos.attack_defend_sliding(occ, from_set.to_square()) & !processed;
+                atta_def |= self.attack_defend_sliding(occ, to) & !processed & !unavailable;
             }
+        }
+
+        res
+    }
+}
 
-            (attacker, from_set) = self.least_valuable_piece(atta_def, side);
+/// The pieces which are captured and which capture in `mov`, for static exchange evaluation.
+pub(crate) fn exchange_pieces(pos: &Position, mov: &Move) -> (PieceType, PieceType) {
+    let target = if mov.is_en_passant() {
+        PieceType::Pawn
+    } else {
+        pos.piece_at_sq(mov.dest()).type_of()
+    };
 
-            side = side.other_player();
+    (target, pos.piece_at_sq(mov.orig()).type_of())
+}
+
+#[inline(always)]
+fn value(piece_type: PieceType) -> i32 {
+    piece_value(piece_type) as i32
+}
+
+/// The SEE swap algorithm.
+///
+/// Returns the statically minimaxed outcome of exchanges on square `to` after current player
+/// captures a piece of type `target` with a piece of type `attacker` on square `from`. This
+/// analysis includes the effect of x-rays by sliding pieces through friendly pieces which move
+/// earlier (e.g. rook batteries along a file).
+///
+/// Pieces which are pinned to their king are not allowed to recapture, unless the capture keeps
+/// them on the line of the pin. A king is only allowed to recapture if the opponent has no
+/// attackers left on the square.
+fn swap(
+    pos: &Position,
+    from: Square,
+    to: Square,
+    target: PieceType,
+    mut attacker: PieceType,
+) -> Score {
+    let mut gain: [Score; 32] = [Score::cp(0); 32];
+    let mut d: usize = 0;
+
+    let may_xray = pos.piece_bb_both_players(PieceType::Pawn)
+        | pos.piece_bb_both_players(PieceType::Bishop)
+        | pos.piece_bb_both_players(PieceType::Rook)
+        | pos.piece_bb_both_players(PieceType::Queen);
+    let mut from_set = from.to_bb();
+    let mut occ = pos.occupied();
+    let unavailable = pos.pinned_off_line(to) & !from.to_bb();
+    let mut atta_def = pos.attack_defend(occ, to) & !unavailable;
+    let mut side = pos.turn().other_player();
+
+    // Need to track the processed sliding pieces to ensure that we don't repeatedly process them
+    // in x-ray attacks.
+    let mut processed = Bitboard::empty();
+
+    gain[0] = Score::cp(piece_value(target).into());
+
+    while !from_set.is_empty() {
+        d += 1;
+
+        gain[d] = Score::cp(piece_value(attacker).into()) - gain[d - 1];
+
+        atta_def ^= from_set;
+        occ ^= from_set;
+        processed ^= from_set;
+
+        // Removing the capturing piece can reveal a slider behind it on the same line. Look for
+        // sliding attackers of `to` through the vacated square.
+        if !(from_set & may_xray).is_empty() {
+            atta_def |= pos.attack_defend_sliding(occ, to) & !processed & !unavailable;
         }
 
-        d -= 1;
+        (attacker, from_set) = least_valuable_piece(pos, atta_def, side);
 
-        while d > 0 {
-            gain[d - 1] = -max(-gain[d - 1], gain[d]);
-            d -= 1;
+        // The king can't capture onto a defended square.
+        if attacker == PieceType::King
+            && (atta_def & pos.get_occupied_player_runtime(side.other_player())).is_not_empty()
+        {
+            break;
         }
 
-        gain[0]
+        side = side.other_player();
     }
 
-    fn least_valuable_piece(&self, atta_def: Bitboard, side: Player) -> (PieceType, Bitboard) {
-        const PIECES: [PieceType; 6] = [
-            PieceType::Pawn,
-            PieceType::Knight,
-            PieceType::Bishop,
-            PieceType::Rook,
-            PieceType::Queen,
-            PieceType::King,
-        ];
+    d -= 1;
+
+    while d > 0 {
+        gain[d - 1] = -max(-gain[d - 1], gain[d]);
+        d -= 1;
+    }
 
-        for piece_type in PIECES {
-            let subset = atta_def & self.pos.piece_bb(side, piece_type);
+    gain[0]
+}
 
-            if !subset.is_empty() {
-                return (piece_type, subset.lsb());
-            }
-        }
+fn least_valuable_piece(pos: &Position, atta_def: Bitboard, side: Player) -> (PieceType, Bitboard) {
+    const PIECES: [PieceType; 6] = [
+        PieceType::Pawn,
+        PieceType::Knight,
+        PieceType::Bishop,
+        PieceType::Rook,
+        PieceType::Queen,
+        PieceType::King,
+    ];
+
+    for piece_type in PIECES {
+        let subset = atta_def & pos.piece_bb(side, piece_type);
 
-        return (PieceType::None, Bitboard::empty());
+        if !subset.is_empty() {
+            return (piece_type, subset.lsb());
+        }
     }
+
+    return (PieceType::None, Bitboard::empty());
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use core::position::Position;
+    use core::mono_traits::{Captures, Legal};
+    use core::movelist::BasicMoveList;
     use std::sync::atomic::AtomicBool;
 
     #[test]
@@ -113,7 +234,7 @@ mod tests {
                 ("k3q3/4r3/4p3/8/8/4R3/4R3/K3Q3 w - - 0 1", Square::E3, Square::E6, PieceType::Pawn, PieceType::Rook, Score::cp(100)),
                 ("k3q3/4r3/4p3/8/8/8/4R3/K3Q3 w - - 0 1", Square::E2, Square::E6, PieceType::Pawn, PieceType::Rook, Score::cp(-400)),
                 ("k3nrn1/4b3/3q1p1R/8/4N1NB/2Q5/5R2/K7 w - - 0 1", Square::E4, Square::F6, PieceType::Pawn, PieceType::Knight, Score::cp(100)),
-                ("k3nrn1/4b3/3q1p1R/8/4N1NB/2Q5/5R2/K7 w - - 0 1", Square::C3, Square::F6, PieceType::Pawn, PieceType::Queen, Score::cp(-800)),
+                ("k3nrn1/4b3/3q1p1R/8/4N1NB/2Q5/5R2/K7 w - - 0 1", Square::C3, Square::F6, PieceType::Pawn, PieceType::Queen, Score::cp(-500)),
                 ("k4r2/8/5q2/6P1/4N3/8/8/K7 w - - 0 1", Square::G5, Square::F6, PieceType::Queen, PieceType::Pawn, Score::cp(900)),
                 ("k4r2/8/5q2/6P1/4N3/8/8/K7 w - - 0 1", Square::E4, Square::F6, PieceType::Queen, PieceType::Knight, Score::cp(900)),
                 ("k7/3n4/8/4n3/2N5/5N2/8/K7 w - - 0 1", Square::C4, Square::E5, PieceType::Knight, PieceType::Knight, Score::cp(300)),
@@ -121,34 +242,150 @@ mod tests {
                 ("k7/8/3n4/5N2/8/8/8/K7 b - - 0 1", Square::D6, Square::F5, PieceType::Knight, PieceType::Knight, Score::cp(300)),
                 ("k4r2/8/8/5N2/8/8/8/K7 b - - 0 1", Square::F8, Square::F5, PieceType::Knight, PieceType::Rook, Score::cp(300)),
                 ("k4r2/8/8/5N2/8/6N1/8/K7 b - - 0 1", Square::F8, Square::F5, PieceType::Knight, PieceType::Rook, Score::cp(-200)),
-                ("k6q/6b1/5b2/4B3/8/2B5/1B6/K7 b - - 0 1", Square::F6, Square::E5, PieceType::Bishop, PieceType::Bishop, Score::cp(0)),
-                ("k7/8/2B2n2/8/4Q3/8/3n1N2/K7 b - - 0 1", Square::F6, Square::E4, PieceType::Queen, PieceType::Knight, Score::cp(900)),
+                ("k6q/6b1/5b2/4B3/8/2B5/1B6/K7 b - - 0 1", Square::F6, Square::E5, PieceType::Bishop, PieceType::Bishop, Score::cp(300)),
+                ("k7/8/2B2n2/8/4Q3/8/3n1N2/K7 b - - 0 1", Square::F6, Square::E4, PieceType::Queen, PieceType::Knight, Score::cp(600)),
                 ("k7/8/8/3p1p2/4P3/3P1P2/8/K7 b - - 0 1", Square::D5, Square::E4, PieceType::Pawn, PieceType::Pawn, Score::cp(0)),
                 ("k7/7b/8/3p1p2/4P3/3P1P2/8/K7 b - - 0 1", Square::D5, Square::E4, PieceType::Pawn, PieceType::Pawn, Score::cp(100)),
                 ("k7/7b/8/5p2/4PK2/8/5N2/8 b - - 0 1", Square::F5, Square::E4, PieceType::Pawn, PieceType::Pawn, Score::cp(0)),
                 ("8/1b6/3k4/3p4/3KP3/8/6B1/8 w - - 0 1", Square::E4, Square::D5, PieceType::Pawn, PieceType::Pawn, Score::cp(100)),
 
+                ("k7/8/2B2n2/8/4Q3/5P2/3n4/K7 b - - 0 1", Square::F6, Square::E4, PieceType::Queen, PieceType::Knight, Score::cp(600)),
+                ("k7/8/3np3/5R2/8/3Q2N1/8/K4R2 b - - 0 1", Square::E6, Square::F5, PieceType::Rook, PieceType::Pawn, Score::cp(400)),
+
                 // TODO: need to reflect the positions and discussion here. Pawns promoting with
                 // capture, or pawns promoting without capture as the first move of SEE need
                 // attention. It might be easiest to use a search extension whenever we have a pawn
                 // on the 7th?
                 // http://www.talkchess.com/forum3/viewtopic.php?f=7&t=77787
-
-                // In these examples, the answer returned is not the true result because of
-                // pruning. The result is nevertheless the same (in terms of whether the initial
-                // capture is deemed favourable).
-                ("k7/8/2B2n2/8/4Q3/5P2/3n4/K7 b - - 0 1", Square::F6, Square::E4, PieceType::Queen, PieceType::Knight, Score::cp(900)),
-                ("k7/8/3np3/5R2/8/3Q2N1/8/K4R2 b - - 0 1", Square::E6, Square::F5, PieceType::Rook, PieceType::Pawn, Score::cp(500)),
             ]
         };
 
         for (fen, from, to, target, attacker, score) in suite {
             let pos = Position::from_fen(fen).unwrap();
             let flag = AtomicBool::new(false);
+            let deadline = crate::time::Deadline::none();
             let tt = crate::tt::Table::new(1);
-            let mut search = Search::new(pos, &flag, None, &tt);
+            let mut search = Search::new(pos, &flag, &deadline, &tt);
             let see = search.see(from, to, target, attacker);
             assert_eq!(see, score);
         }
     }
+
+    #[test]
+    fn pinned_pieces_and_kings() {
+        core::init::init_globals();
+
+        let suite = #[rustfmt::skip] {
+            vec![
+                // The knight on d7 is pinned to its king, so it can't recapture on e5.
+                ("4k3/3n4/8/4p3/B1N5/8/8/K7 w - - 0 1", Square::C4, Square::E5, PieceType::Pawn, PieceType::Knight, Score::cp(100)),
+                // The rook on e4 is pinned, but can recapture on e8 because it stays on the line
+                // of the pin.
+                ("k2qr3/8/3N4/8/4R3/8/8/4K3 w - - 0 1", Square::D6, Square::E8, PieceType::Rook, PieceType::Knight, Score::cp(500)),
+                // The king is the only recapturer, but e7 is defended by the bishop.
+                ("3k4/4p3/8/8/7B/8/8/K3R3 w - - 0 1", Square::E1, Square::E7, PieceType::Pawn, PieceType::Rook, Score::cp(100)),
+                // Without the bishop, the king can recapture.
+                ("3k4/4p3/8/8/8/8/8/K3R3 w - - 0 1", Square::E1, Square::E7, PieceType::Pawn, PieceType::Rook, Score::cp(-400)),
+            ]
+        };
+
+        for (fen, from, to, target, attacker, score) in suite {
+            let pos = Position::from_fen(fen).unwrap();
+            let flag = AtomicBool::new(false);
+            let deadline = crate::time::Deadline::none();
+            let tt = crate::tt::Table::new(1);
+            let mut search = Search::new(pos, &flag, &deadline, &tt);
+            let see = search.see(from, to, target, attacker);
+            assert_eq!(see, score, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn position_see() {
+        core::init::init_globals();
+
+        let suite = [
+            // A pawn defended by a pawn.
+            ("k7/8/3p4/4p3/8/8/4Q3/K7 w - - 0 1", "e2e5", -800),
+            // An undefended pawn.
+            ("k7/8/8/4p3/8/8/4Q3/K7 w - - 0 1", "e2e5", 100),
+            // Rook takes knight, defended by a knight.
+            ("k7/8/3n4/5n2/8/8/5R2/K7 w - - 0 1", "f2f5", -200),
+            // En passant.
+            ("k7/8/8/3pP3/8/8/8/K7 w - d6 0 2", "e5d6", 100),
+            // Equal trade.
+            ("k7/8/3n4/5n2/8/4N3/8/K7 w - - 0 1", "e3f5", 0),
+        ];
+
+        for (fen, uci, value) in suite {
+            let pos = Position::from_fen(fen).unwrap();
+            let mov = pos.parse_uci_move(uci).expect(uci);
+            assert_eq!(pos.see(&mov), value, "{}", fen);
+            assert!(pos.see_ge(&mov, value), "{}", fen);
+            assert!(!pos.see_ge(&mov, value + 1), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn see_ge_agrees_with_see() {
+        core::init::init_globals();
+
+        let fens = [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "k3nrn1/4b3/3q1p1R/8/4N1NB/2Q5/5R2/K7 w - - 0 1",
+            "k2r3q/8/5n2/8/3Q4/8/1B6/6K1 w - - 0 1",
+            "k7/4r3/4n3/8/8/8/4R2r/K3R3 w - - 0 1",
+            "4k3/3n4/8/4p3/B1N5/8/8/K7 w - - 0 1",
+            "3k4/4p3/8/8/8/8/8/K3R3 w - - 0 1",
+            "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+        ];
+
+        for fen in fens {
+            let pos = Position::from_fen(fen).unwrap();
+            let captures = pos.generate::<BasicMoveList, Captures, Legal>();
+            for mov in captures.iter() {
+                let see = pos.see(mov);
+                for threshold in [-1000, -300, -100, 0, 100, 300, 1000, see, see + 1] {
+                    assert_eq!(
+                        pos.see_ge(mov, threshold),
+                        see >= threshold,
+                        "{} {} {}",
+                        fen,
+                        mov,
+                        threshold
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn xray_attackers() {
+        core::init::init_globals();
+
+        let suite = #[rustfmt::skip] {
+            vec![
+                // The rook on e1 recaptures through e2. The rook on h2 attacks the vacated square
+                // along the rank, but not e6.
+                ("k7/4r3/4n3/8/8/8/4R2r/K3R3 w - - 0 1", Square::E2, Square::E6, PieceType::Knight, PieceType::Rook, Score::cp(300)),
+                // Without the battery, the knight is defended once too often.
+                ("k7/4r3/4n3/8/8/8/4R2r/K7 w - - 0 1", Square::E2, Square::E6, PieceType::Knight, PieceType::Rook, Score::cp(-200)),
+                // The bishop on b2 recaptures through d4 after the queens are traded. The rook on
+                // d8 attacks the vacated square along the file, but not f6.
+                ("k2r3q/8/5n2/8/3Q4/8/1B6/6K1 w - - 0 1", Square::D4, Square::F6, PieceType::Knight, PieceType::Queen, Score::cp(300)),
+                // Without the bishop, white loses the queen for a knight.
+                ("k2r3q/8/5n2/8/3Q4/8/8/6K1 w - - 0 1", Square::D4, Square::F6, PieceType::Knight, PieceType::Queen, Score::cp(-600)),
+            ]
+        };
+
+        for (fen, from, to, target, attacker, score) in suite {
+            let pos = Position::from_fen(fen).unwrap();
+            let flag = AtomicBool::new(false);
+            let deadline = crate::time::Deadline::none();
+            let tt = crate::tt::Table::new(1);
+            let mut search = Search::new(pos, &flag, &deadline, &tt);
+            let see = search.see(from, to, target, attacker);
+            assert_eq!(see, score, "{}", fen);
+        }
+    }
 }
@@ -0,0 +1,193 @@
+//! Syzygy endgame tablebase probing. Only compiled with the `syzygy` feature.
+//!
+//! Positions are handed to the tablebases through their fen, and moves back through their UCI
+//! string, so probing is only done where it is likely to pay for itself: at interior nodes
+//! straight after a capture or pawn move, and once at the root.
+
+use super::score::{Score, TB_WIN};
+
+use core::mov::Move;
+use core::position::Position;
+
+use shakmaty::fen::Fen;
+use shakmaty::{CastlingMode, Chess, Position as _};
+use shakmaty_syzygy::{AmbiguousWdl, Tablebase, Wdl};
+
+use std::cmp::Reverse;
+use std::io;
+
+/// A set of Syzygy tables, opened from one or more directories.
+pub struct Tablebases {
+    tables: Tablebase<Chess>,
+}
+
+impl std::fmt::Debug for Tablebases {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Tablebases {{ max_pieces: {} }}", self.max_pieces())
+    }
+}
+
+impl Tablebases {
+    /// Open the tables in each directory of `path`. Directories are separated by `:`, or `;` on
+    /// Windows, as for the UCI `SyzygyPath` option. The table files are memory-mapped.
+    pub fn open(path: &str) -> io::Result<Self> {
+        let separator = if cfg!(windows) { ';' } else { ':' };
+
+        // Safety: the table files must not be modified while the engine is running.
+        let mut tables = unsafe { Tablebase::with_mmap_filesystem() };
+        for dir in path.split(separator).filter(|dir| !dir.is_empty()) {
+            tables.add_directory(dir)?;
+        }
+
+        Ok(Self { tables })
+    }
+
+    /// The largest number of pieces, kings included, of any table found.
+    pub fn max_pieces(&self) -> usize {
+        self.tables.max_pieces()
+    }
+
+    /// Whether `pos` could be in the tables. The tables don't cover positions with castling
+    /// rights.
+    pub fn covers(&self, pos: &Position) -> bool {
+        pos.occupied().popcnt() as usize <= self.max_pieces() && pos.castling_rights().is_empty()
+    }
+
+    /// The WDL value of `pos`, from the point of view of the side to move, assuming that it was
+    /// reached by a capture or pawn move. `None` if the position isn't in the tables.
+    pub fn probe_wdl(&self, pos: &Position) -> Option<Wdl> {
+        if !self.covers(pos) {
+            return None;
+        }
+
+        self.tables.probe_wdl_after_zeroing(&to_chess(pos)?).ok()
+    }
+
+    /// The moves which preserve the best outcome at the root, taking the fifty-move rule into
+    /// account, so that a won position isn't thrown away to it. `None` if the position isn't in
+    /// the tables.
+    ///
+    /// In a won position, only the winning moves which make the most progress by distance to
+    /// zeroing are kept, as the search can't tell one winning move from another. Otherwise, every
+    /// move which keeps the outcome is kept, so that MultiPV still has lines to report.
+    pub fn probe_root(&self, pos: &Position) -> Option<Vec<Move>> {
+        if !self.covers(pos) {
+            return None;
+        }
+
+        // The outcome after each move, from the point of view of the opponent. This considers
+        // the halfmove clock, so a win which the fifty-move rule would frustrate ranks below one
+        // which it wouldn't.
+        let root = to_chess(pos)?;
+        let mut outcomes = Vec::new();
+        for mov in root.legal_moves() {
+            let mut after = root.clone();
+            after.play_unchecked(mov.clone());
+            let wdl = self.tables.probe_wdl(&after).ok()?;
+            let dtz = self.tables.probe_dtz(&after).ok()?.ignore_rounding();
+
+            // Mating is best, then zeroing the halfmove clock, then leaving the opponent as few
+            // plies as possible before it is zeroed.
+            let progress = (!after.is_checkmate(), !mov.is_zeroing(), Reverse(dtz));
+            outcomes.push((mov, wdl, progress));
+        }
+
+        let best = outcomes.iter().map(|&(_, wdl, _)| wdl).min()?;
+        outcomes.retain(|&(_, wdl, _)| wdl == best);
+
+        if matches!(best, AmbiguousWdl::Loss | AmbiguousWdl::MaybeLoss) {
+            let most = outcomes.iter().map(|&(_, _, progress)| progress).min()?;
+            outcomes.retain(|&(_, _, progress)| progress == most);
+        }
+
+        outcomes
+            .into_iter()
+            .map(|(mov, _, _)| pos.parse_uci_move(&mov.to_uci(CastlingMode::Standard).to_string()))
+            .collect()
+    }
+}
+
+/// The score of a tablebase result, from the point of view of the side to move. Wins and losses
+/// which the fifty-move rule turns into draws are scored as draws.
+///
+/// Like a mate score, this is relative to the node, so it can be stored in the transposition table
+/// as it is. `Score::inc_mate` counts it down on the way back to the root, so that a nearer win
+/// outranks a farther one.
+pub fn wdl_score(wdl: Wdl) -> Score {
+    match wdl {
+        Wdl::Win => Score::cp(TB_WIN),
+        Wdl::Loss => Score::cp(-TB_WIN),
+        Wdl::CursedWin | Wdl::BlessedLoss | Wdl::Draw => Score::zero(),
+    }
+}
+
+fn to_chess(pos: &Position) -> Option<Chess> {
+    let fen: Fen = pos.to_fen().parse().ok()?;
+    fen.into_position(CastlingMode::Standard).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The tables are opened from the directories in the `SYZYGY_PATH` environment variable. If
+    /// it isn't set, tests which need the tables are skipped.
+    fn tablebases() -> Option<Tablebases> {
+        let path = std::env::var("SYZYGY_PATH").ok()?;
+        let tb = Tablebases::open(&path).expect("couldn't open SYZYGY_PATH");
+        if tb.max_pieces() < 4 {
+            return None;
+        }
+
+        Some(tb)
+    }
+
+    #[test]
+    fn scores_are_below_mate() {
+        assert!(wdl_score(Wdl::Win) < Score::mate(99));
+        assert_eq!(wdl_score(Wdl::Loss), -wdl_score(Wdl::Win));
+        assert_eq!(wdl_score(Wdl::CursedWin), Score::zero());
+
+        // A win found nearer the root outranks one found further away.
+        let near = wdl_score(Wdl::Win).inc_mate();
+        let far = wdl_score(Wdl::Win).inc_mate().inc_mate().inc_mate();
+        assert!(near > far);
+        assert!(far > Score::cp(TB_WIN - 128));
+    }
+
+    #[test]
+    fn kqkr_is_won() {
+        core::init::init_globals();
+
+        let tb = match tablebases() {
+            Some(tb) => tb,
+            None => return,
+        };
+
+        let pos = Position::from_fen("8/8/8/3k4/8/8/2r5/KQ6 w - - 0 1").unwrap();
+        assert_eq!(tb.probe_wdl(&pos), Some(Wdl::Win));
+
+        let moves = tb.probe_root(&pos).unwrap();
+        assert!(!moves.is_empty());
+        for mov in &moves {
+            let mut after = pos.clone();
+            after.make_move(mov);
+            assert_eq!(tb.probe_wdl(&after), Some(Wdl::Loss));
+        }
+
+        // The move the tables recommend by distance to zeroing is among them.
+        let (best, _) = tb
+            .tables
+            .best_move(&to_chess(&pos).unwrap())
+            .unwrap()
+            .unwrap();
+        let best = pos
+            .parse_uci_move(&best.to_uci(CastlingMode::Standard).to_string())
+            .unwrap();
+        assert!(moves.contains(&best));
+
+        // Positions with castling rights aren't covered.
+        let castling = Position::from_fen("4k3/8/8/8/8/8/8/R3K3 w Q - 0 1").unwrap();
+        assert_eq!(tb.probe_wdl(&castling), None);
+    }
+}
@@ -1,19 +1,88 @@
+use super::options::MOVE_OVERHEAD_DEFAULT;
 use core::position::Player;
 
-use std::cmp::max;
+use std::cmp::{max, min};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
 
 static AVERAGE_GAME_LENGTH: u32 = 40;
 static MINIMUM_REMAINING_MOVES: u32 = 20;
 static PER_MOVE_BUFFER_TIME: u32 = 150;
+static MINIMUM_TIME_PER_MOVE: u32 = 50;
+/// In sudden death, the number of moves the time left on the clock must last for.
+static SUDDEN_DEATH_MOVES: u32 = 30;
+/// In sudden death with less than this many milliseconds left, the time left must last for
+/// `SUDDEN_DEATH_LOW_TIME_MOVES` moves instead.
+static SUDDEN_DEATH_LOW_TIME: u32 = 10_000;
+static SUDDEN_DEATH_LOW_TIME_MOVES: u32 = 40;
 
 #[derive(Clone, Debug)]
 pub enum TimingMode {
+    /// Search for a share of the time left on the clock.
     Timed(TimeControl),
+    /// Search for exactly this many milliseconds. Unlike `Timed`, no buffer time is held back, as
+    /// the GUI has asked for this precise amount of time.
     MoveTime(usize),
     Depth(u8),
+    /// Search until the given number of nodes have been visited.
+    Nodes(u64),
+    /// Search for a forced mate in at most this many moves.
+    Mate(u8),
     Infinite,
 }
 
+impl TimingMode {
+    /// Hold back `move_overhead` milliseconds from each move for communication delays, on top of
+    /// the buffer time. Only a share of the clock is affected; a fixed move time is used exactly as
+    /// given.
+    pub fn with_move_overhead(self, move_overhead: usize) -> Self {
+        match self {
+            TimingMode::Timed(tc) => TimingMode::Timed(tc.with_move_overhead(move_overhead)),
+            timing_mode => timing_mode,
+        }
+    }
+
+    /// The time at which a search using this timing mode, starting at `start`, should stop. Returns
+    /// `None` if the search is not time-limited.
+    pub fn stop_time(
+        &self,
+        start: Instant,
+        curr_move_number: u32,
+        turn: Player,
+    ) -> Option<Instant> {
+        match self {
+            TimingMode::Timed(tc) => {
+                let move_time = tc.to_move_time(curr_move_number, turn);
+                Some(start + Duration::from_millis(move_time.into()))
+            }
+            TimingMode::MoveTime(t) => Some(start + Duration::from_millis(*t as u64)),
+            TimingMode::Depth(_)
+            | TimingMode::Nodes(_)
+            | TimingMode::Mate(_)
+            | TimingMode::Infinite => None,
+        }
+    }
+
+    /// The time at which a ponder search should stop, after `ponderhit` arrives at `ponderhit`.
+    /// The time already spent pondering, `pondered`, counts towards the move, since the search has
+    /// already done that much work; see `TimeControl::to_move_time_after`.
+    pub fn ponderhit_stop_time(
+        &self,
+        ponderhit: Instant,
+        pondered: Duration,
+        curr_move_number: u32,
+        turn: Player,
+    ) -> Option<Instant> {
+        match self {
+            TimingMode::Timed(tc) => {
+                let move_time = tc.to_move_time_after(curr_move_number, turn, pondered);
+                Some(ponderhit + Duration::from_millis(move_time.into()))
+            }
+            _ => self.stop_time(ponderhit, curr_move_number, turn),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct TimeControl {
     /// Time remaning on white's clock, in milliseconds.
@@ -27,6 +96,8 @@ pub struct TimeControl {
     /// Number of moves until the time control changes / is reset. If `None`, there no further time
     /// controls.
     moves_to_go: Option<usize>,
+    /// Time held back from each move for communication delays, in milliseconds.
+    move_overhead: usize,
 }
 
 impl TimeControl {
@@ -43,39 +114,256 @@ impl TimeControl {
             winc,
             binc,
             moves_to_go,
+            move_overhead: MOVE_OVERHEAD_DEFAULT,
         }
     }
 
+    /// Set the time to hold back from each move for communication delays, in milliseconds. This is
+    /// taken from each budget after the buffer time, but never takes it below the minimum move
+    /// time; see `minimum_move_time`.
+    pub fn with_move_overhead(mut self, move_overhead: usize) -> Self {
+        self.move_overhead = move_overhead;
+        self
+    }
+
     /// Convert this time control into a fixed number of milliseconds we should allow searching
-    /// for.
+    /// for. This is never less than the minimum move time; see `minimum_move_time`.
     pub fn to_move_time(&self, curr_move_number: u32, turn: Player) -> u32 {
+        let base_time = self.time_left(turn);
+
+        let inc = if turn.is_white() {
+            self.winc as u32
+        } else {
+            self.binc as u32
+        };
+
+        let budget = if inc == 0 && self.moves_to_go.is_none() {
+            self.to_sudden_death_budget(base_time)
+        } else {
+            self.to_incremental_budget(base_time, inc, curr_move_number)
+        };
+
+        max(
+            budget.saturating_sub(self.move_overhead as u32),
+            Self::minimum_move_time(base_time),
+        )
+    }
+
+    /// The time left on `turn`'s clock, in milliseconds.
+    fn time_left(&self, turn: Player) -> u32 {
+        if turn.is_white() {
+            self.wtime as u32
+        } else {
+            self.btime as u32
+        }
+    }
+
+    /// The least time a move is given, however the budget works out, so that the search has time
+    /// to finish a useful iteration. This is `MINIMUM_TIME_PER_MOVE`, but never more than the share
+    /// of the clock a move gets in sudden death with little time left, so that an almost empty
+    /// clock isn't run down by the minimum itself.
+    fn minimum_move_time(base_time: u32) -> u32 {
+        min(
+            MINIMUM_TIME_PER_MOVE,
+            base_time / SUDDEN_DEATH_LOW_TIME_MOVES,
+        )
+    }
+
+    /// The share of the clock for this move, less the buffer time, when there is an increment or a
+    /// further time control.
+    fn to_incremental_budget(&self, base_time: u32, inc: u32, curr_move_number: u32) -> u32 {
         // An estimate for how many moves we expect to have to play with the time remaining on our
         // clock.
         let est_remaining_moves = match self.moves_to_go {
-            Some(n) => n as u32,
+            Some(n) => max(n as u32, 1),
             None => max(
                 MINIMUM_REMAINING_MOVES,
-                AVERAGE_GAME_LENGTH - curr_move_number,
+                AVERAGE_GAME_LENGTH.saturating_sub(curr_move_number),
             ),
         };
 
-        let base_time = if turn.is_white() {
-            self.wtime
+        // Estimate for how much base time we can afford to use.
+        // This is an integer in milliseconds, and so can be 0, if we have very little time
+        // remaining.
+        let base_time_per_move = base_time / est_remaining_moves;
+
+        (base_time_per_move + inc).saturating_sub(PER_MOVE_BUFFER_TIME)
+    }
+
+    /// The share of the clock for this move, less the buffer time, in sudden death, where there is
+    /// no increment and no further time control, so every remaining move of the game must be
+    /// played with the time left. However long the game has gone on, we assume it will last for
+    /// `SUDDEN_DEATH_MOVES` more moves, or more once the clock runs low, so that we never run out
+    /// of time.
+    fn to_sudden_death_budget(&self, base_time: u32) -> u32 {
+        let moves = if base_time < SUDDEN_DEATH_LOW_TIME {
+            SUDDEN_DEATH_LOW_TIME_MOVES
         } else {
-            self.btime
+            SUDDEN_DEATH_MOVES
         };
 
-        let inc = if turn.is_white() {
-            self.winc as u32
-        } else {
-            self.binc as u32
+        (base_time / moves).saturating_sub(PER_MOVE_BUFFER_TIME)
+    }
+
+    /// The move time left when `elapsed` has already been spent on the move, e.g. pondering. This
+    /// is never less than the minimum move time, so that the search still has time to finish a
+    /// useful iteration after a long ponder.
+    pub fn to_move_time_after(
+        &self,
+        curr_move_number: u32,
+        turn: Player,
+        elapsed: Duration,
+    ) -> u32 {
+        let elapsed = elapsed.as_millis().min(u32::MAX as u128) as u32;
+        max(
+            self.to_move_time(curr_move_number, turn)
+                .saturating_sub(elapsed),
+            Self::minimum_move_time(self.time_left(turn)),
+        )
+    }
+}
+
+/// The time at which a search should stop, shared between the engine and any running searches.
+///
+/// The deadline can be moved while a search is in progress. This is needed for pondering: a ponder
+/// search runs without a deadline, and on `ponderhit` the engine sets one, measured from the moment
+/// the `ponderhit` arrived.
+///
+/// Internally, the deadline is stored as a number of nanoseconds since `epoch` in an atomic, so
+/// that the search can cheaply check it at every node.
+#[derive(Debug)]
+pub struct Deadline {
+    epoch: Instant,
+    at: AtomicU64,
+}
+
+impl Deadline {
+    /// Sentinel value representing no deadline.
+    const NONE: u64 = u64::MAX;
+
+    /// Create a `Deadline` which is not set.
+    pub fn none() -> Self {
+        Self {
+            epoch: Instant::now(),
+            at: AtomicU64::new(Self::NONE),
+        }
+    }
+
+    /// Set the deadline to `stop_time`, or clear it if `None`.
+    pub fn set(&self, stop_time: Option<Instant>) {
+        let at = match stop_time {
+            Some(t) => t.saturating_duration_since(self.epoch).as_nanos() as u64,
+            None => Self::NONE,
         };
 
-        // Estimate for how much base time we can afford to use.
-        // This is an integer in milliseconds, and so can be 0, if we have very little time
-        // remaining.
-        let base_time_per_move = base_time as u32 / est_remaining_moves;
+        self.at.store(at, Ordering::Relaxed);
+    }
+
+    /// Returns the deadline, if one is set.
+    pub fn get(&self) -> Option<Instant> {
+        match self.at.load(Ordering::Relaxed) {
+            Self::NONE => None,
+            at => Some(self.epoch + Duration::from_nanos(at)),
+        }
+    }
+
+    /// Returns true if a deadline is set and it has passed.
+    #[inline(always)]
+    pub fn passed(&self) -> bool {
+        let at = self.at.load(Ordering::Relaxed);
+        at != Self::NONE && self.epoch.elapsed().as_nanos() as u64 >= at
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ponder_time_counts_towards_the_move() {
+        let tc = TimeControl::new(60_000, 60_000, 1_000, 1_000, None);
+        let move_time = tc.to_move_time(10, Player::WHITE);
+
+        // A ponder miss, or no pondering at all, leaves the whole budget.
+        assert_eq!(
+            tc.to_move_time_after(10, Player::WHITE, Duration::ZERO),
+            move_time
+        );
+
+        let pondered = Duration::from_millis(500);
+        assert_eq!(
+            tc.to_move_time_after(10, Player::WHITE, pondered),
+            move_time - 500
+        );
+
+        // Pondering for longer than the budget still leaves the minimum.
+        let pondered = Duration::from_millis(move_time as u64 * 2);
+        assert_eq!(
+            tc.to_move_time_after(10, Player::WHITE, pondered),
+            MINIMUM_TIME_PER_MOVE
+        );
+    }
+
+    #[test]
+    fn sudden_death_allocates_a_safe_fraction() {
+        for time in [0, 1, 100, 150, 1_000, 9_999, 10_000, 60_000, 600_000] {
+            let tc = TimeControl::new(time, time, 0, 0, None);
+            for move_number in [1, 30, 40, 41, 200] {
+                for turn in [Player::WHITE, Player::BLACK] {
+                    let move_time = tc.to_move_time(move_number, turn) as usize;
+                    assert!(move_time <= time / SUDDEN_DEATH_MOVES as usize);
+                    if time < SUDDEN_DEATH_LOW_TIME as usize {
+                        assert!(move_time <= time / SUDDEN_DEATH_LOW_TIME_MOVES as usize);
+                    }
+                }
+            }
+        }
+
+        // With a second left, the whole allocation goes on the buffer time. The minimum is capped
+        // by the clock, so the search gets less than `MINIMUM_TIME_PER_MOVE`.
+        let tc = TimeControl::new(1_000, 1_000, 0, 0, None);
+        assert_eq!(
+            tc.to_move_time(60, Player::WHITE),
+            1_000 / SUDDEN_DEATH_LOW_TIME_MOVES
+        );
+    }
+
+    #[test]
+    fn move_overhead_reduces_the_budget() {
+        let tc = TimeControl::new(60_000, 60_000, 1_000, 1_000, None);
+        let move_time = tc.to_move_time(10, Player::WHITE);
+        let with_overhead = tc.clone().with_move_overhead(100);
+        assert_eq!(
+            with_overhead.to_move_time(10, Player::WHITE),
+            move_time - 100
+        );
+
+        // The overhead never takes the budget below the minimum.
+        let with_overhead = tc.with_move_overhead(move_time as usize);
+        assert_eq!(
+            with_overhead.to_move_time(10, Player::WHITE),
+            MINIMUM_TIME_PER_MOVE
+        );
+
+        // Nor does it apply to a fixed move time.
+        let movetime = TimingMode::MoveTime(500).with_move_overhead(100);
+        let start = Instant::now();
+        assert_eq!(
+            movetime.stop_time(start, 10, Player::WHITE),
+            Some(start + Duration::from_millis(500))
+        );
+    }
+
+    #[test]
+    fn late_and_low_time_allocations_dont_underflow() {
+        // Past the average game length, with an increment.
+        let tc = TimeControl::new(5_000, 5_000, 100, 100, None);
+        assert!(tc.to_move_time(80, Player::BLACK) <= 5_000 / MINIMUM_REMAINING_MOVES + 100);
 
-        base_time_per_move + inc - PER_MOVE_BUFFER_TIME
+        // Almost no time left, and less than the buffer time.
+        let tc = TimeControl::new(10, 10, 0, 0, Some(5));
+        assert_eq!(tc.to_move_time(10, Player::WHITE), 0);
+        let tc = TimeControl::new(0, 0, 0, 0, Some(0));
+        assert_eq!(tc.to_move_time(10, Player::WHITE), 0);
     }
 }
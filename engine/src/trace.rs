@@ -4,6 +4,63 @@
 use std::ops::AddAssign;
 use std::time::{Duration, Instant};
 
+/// A summary of the statistics traced during a search, for library users and tests to inspect once
+/// the search is over.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SearchStats {
+    /// The depth of the last completed iteration.
+    pub depth: u8,
+    /// The furthest distance from the root reached, including quiescence.
+    pub seldepth: u8,
+    /// The number of nodes visited during main search.
+    pub nodes: usize,
+    /// The number of nodes visited during quiescence search.
+    pub q_nodes: usize,
+    /// The number of moves skipped due to a failed SEE check.
+    pub see_skipped: usize,
+    /// The number of captures skipped by delta pruning in quiescence search.
+    pub delta_pruned: usize,
+    /// The number of transposition table probes which found the position with a valid move.
+    pub hash_hits: usize,
+    /// The number of transposition table probes which found an invalid move for the position.
+    pub hash_collisions: usize,
+    /// The number of transposition table probes which found a different position.
+    pub hash_clashes: usize,
+    /// The number of pawn hash table probes which found the pawn structure.
+    pub pawn_hash_hits: usize,
+    /// The number of pawn hash table probes.
+    pub pawn_hash_probes: usize,
+    /// The number of successful endgame tablebase probes.
+    pub tb_hits: usize,
+    /// The duration of the search.
+    pub elapsed: Duration,
+    /// The nodes per second, counting both main search and quiescence nodes.
+    pub nps: usize,
+    /// The effective branching factor, for `depth`.
+    pub eff_branching: f32,
+}
+
+impl SearchStats {
+    /// The total number of nodes (main search _and_ quiescence) visited.
+    pub fn all_nodes(&self) -> usize {
+        self.nodes + self.q_nodes
+    }
+
+    /// The total number of transposition table probes which found a non-empty slot.
+    pub fn hash_probes(&self) -> usize {
+        self.hash_hits + self.hash_collisions + self.hash_clashes
+    }
+
+    /// The fraction of transposition table probes of non-empty slots which were hits, or 0 if
+    /// there were none.
+    pub fn hash_hit_rate(&self) -> f64 {
+        match self.hash_probes() {
+            0 => 0.,
+            probes => self.hash_hits as f64 / probes as f64,
+        }
+    }
+}
+
 /// Object responsible for tracing data about the search.
 pub struct Tracer {
     /// The time the search commenced.
@@ -14,12 +71,20 @@ pub struct Tracer {
     q_nodes_visited: usize,
     /// The number of nodes we skip due to a failed SEE check.
     see_skipped_nodes: usize,
+    /// The number of captures skipped by delta pruning in quiescence search.
+    delta_pruned_nodes: usize,
     /// The number of times we had a hash hit which was useable to return immediately.
     hash_hits: usize,
     /// The number of times we had a hash collision.
     hash_collisions: usize,
     /// The number of times we had a hash clash (same table slot, different position).
     hash_clashes: usize,
+    /// The number of times the pawn structure evaluation was found in the pawn hash table.
+    pawn_hash_hits: usize,
+    /// The number of times the pawn structure evaluation had to be computed.
+    pawn_hash_misses: usize,
+    /// The number of successful endgame tablebase probes.
+    tb_hits: usize,
     /// Records the duration between start and end of search. Only populated with `Some(duration)`
     /// when `end_search` is called.
     elapsed: Option<Duration>,
@@ -36,9 +101,13 @@ impl Tracer {
             nodes_visited: 0,
             q_nodes_visited: 0,
             see_skipped_nodes: 0,
+            delta_pruned_nodes: 0,
             hash_hits: 0,
             hash_collisions: 0,
             hash_clashes: 0,
+            pawn_hash_hits: 0,
+            pawn_hash_misses: 0,
+            tb_hits: 0,
             elapsed: None,
             killers_per_node: Averager::new(0),
             hash_found: Averager::new(0),
@@ -73,6 +142,12 @@ impl Tracer {
         self.see_skipped_nodes += 1;
     }
 
+    /// To be called whenever quiescence search skips a capture because of delta pruning.
+    #[inline(always)]
+    pub fn delta_prune_node(&mut self) {
+        self.delta_pruned_nodes += 1;
+    }
+
     /// Record a hash hit.
     #[inline(always)]
     pub fn hash_hit(&mut self) {
@@ -91,11 +166,34 @@ impl Tracer {
         self.hash_clashes += 1;
     }
 
+    /// Record a pawn hash hit.
+    #[inline(always)]
+    pub fn pawn_hash_hit(&mut self) {
+        self.pawn_hash_hits += 1;
+    }
+
+    /// Record a pawn hash miss.
+    #[inline(always)]
+    pub fn pawn_hash_miss(&mut self) {
+        self.pawn_hash_misses += 1;
+    }
+
+    /// Record a successful tablebase probe.
+    #[inline(always)]
+    pub fn tb_hit(&mut self) {
+        self.tb_hits += 1;
+    }
+
     /// The number of nodes skipped due to SEE check failures during search.
     pub fn see_skipped_nodes(&self) -> usize {
         self.see_skipped_nodes
     }
 
+    /// The number of captures skipped by delta pruning during search.
+    pub fn delta_pruned_nodes(&self) -> usize {
+        self.delta_pruned_nodes
+    }
+
     /// The number of hash hits recorded during search.
     pub fn hash_hits(&self) -> usize {
         self.hash_hits
@@ -117,6 +215,21 @@ impl Tracer {
         self.hash_hits + self.hash_collisions + self.hash_clashes
     }
 
+    /// The number of pawn hash hits recorded during search.
+    pub fn pawn_hash_hits(&self) -> usize {
+        self.pawn_hash_hits
+    }
+
+    /// The total number of pawn hash probes recorded during search.
+    pub fn pawn_hash_probes(&self) -> usize {
+        self.pawn_hash_hits + self.pawn_hash_misses
+    }
+
+    /// The number of successful tablebase probes recorded during search.
+    pub fn tb_hits(&self) -> usize {
+        self.tb_hits
+    }
+
     /// The number of nodes visited during main search.
     pub fn nodes_visited(&self) -> usize {
         self.nodes_visited
@@ -163,6 +276,31 @@ impl Tracer {
         self.start_time.elapsed()
     }
 
+    /// A summary of the statistics traced so far. `depth` and `seldepth` are tracked by the search,
+    /// so are passed in. If `end_search` hasn't been called, the elapsed time is the time so far.
+    pub fn stats(&self, depth: u8, seldepth: u8) -> SearchStats {
+        let elapsed = self.elapsed.unwrap_or_else(|| self.live_elapsed());
+        let micros = std::cmp::max(elapsed.as_micros(), 1) as usize;
+
+        SearchStats {
+            depth,
+            seldepth,
+            nodes: self.nodes_visited,
+            q_nodes: self.q_nodes_visited,
+            see_skipped: self.see_skipped_nodes,
+            delta_pruned: self.delta_pruned_nodes,
+            hash_hits: self.hash_hits,
+            hash_collisions: self.hash_collisions,
+            hash_clashes: self.hash_clashes,
+            pawn_hash_hits: self.pawn_hash_hits,
+            pawn_hash_probes: self.pawn_hash_probes(),
+            tb_hits: self.tb_hits,
+            elapsed,
+            nps: self.all_nodes_visited() * 1_000_000 / micros,
+            eff_branching: self.eff_branching(depth),
+        }
+    }
+
     /// The effective branching factor of this search. Note, this method uses a Newton-Raphson
     /// iteration. Although this often converges in a small number of iterations, it is probably
     /// best for performance to only call this at the end of a search, rather than during.
@@ -1,14 +1,25 @@
 //! Transposition table.
 
+use super::options::{HASH_MAX_MB, HASH_MIN_MB};
 use super::score::Score;
 use core::mov::{Move, MoveType};
-use core::position::{PieceType, Position, Square};
+use core::position::{PieceType, Position, Square, Zobrist};
 
 use num_derive::FromPrimitive;
 use num_traits::FromPrimitive;
 
-use std::cell::UnsafeCell;
-use std::marker::PhantomData;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, AtomicU8, Ordering};
+
+/// The number of distinct generations. Generation 0 is reserved to mark empty entries, so live
+/// entries cycle through generations 1-63.
+const GENERATIONS: u8 = 63;
+
+/// How many plies of depth an entry is worth discounting for each generation of age, when deciding
+/// whether to replace it.
+const AGE_DEPTH_PENALTY: u8 = 2;
 
 /// The validity of a stored `Score`.
 ///
@@ -177,6 +188,9 @@ pub struct Entry {
     pub sig: u16,
     pub depth: u8,
     pub gen_bound: GenBound,
+    /// The score of the position. Search scores are relative to the node they belong to, not the
+    /// root: a mate score counts plies from this position. So an entry can be used as it is, at any
+    /// distance from the root, without adjusting mate distances on the way in or out.
     pub score: Score,
     pub mov: PackedMove,
 }
@@ -200,70 +214,125 @@ impl Entry {
         self.gen() == 0
     }
 
-    /// Write data to an entry
-    pub fn write(&mut self) {}
+    /// Pack the entry into a single `u64`, so that it can be stored atomically.
+    ///
+    /// The scheme is, reading from LSB to MSB: signature (16 bits), depth (8 bits), generation and
+    /// bound (8 bits), score (16 bits), move (16 bits).
+    #[inline(always)]
+    fn pack(&self) -> u64 {
+        (self.sig as u64)
+            ^ (self.depth as u64) << 16
+            ^ (self.gen_bound.0 as u64) << 24
+            ^ (self.score.to_i16() as u16 as u64) << 32
+            ^ (self.mov.0 as u64) << 48
+    }
+
+    /// The inverse of `pack`.
+    #[inline(always)]
+    fn unpack(data: u64) -> Self {
+        Self {
+            sig: data as u16,
+            depth: (data >> 16) as u8,
+            gen_bound: GenBound((data >> 24) as u8),
+            score: Score::from_i16((data >> 32) as u16 as i16),
+            mov: PackedMove((data >> 48) as u16),
+        }
+    }
+}
+
+/// A slot in the transposition table's storage.
+///
+/// The slot holds a packed `Entry` in `data`, and the Zobrist key of the position it belongs to
+/// XORed with `data` in `key`. The two halves are written with separate atomic stores, so when
+/// several threads write to the same slot concurrently, a reader may observe the `key` from one
+/// write and the `data` from another. Such a torn entry fails the `key ^ data` check against the
+/// position being probed, so it is treated as a clash rather than returned as a hit.
+#[derive(Debug, Default)]
+struct Slot {
+    key: AtomicU64,
+    data: AtomicU64,
 }
 
 /// Represents a transposition table entry that can be written to.
 #[derive(Debug)]
 pub struct WritableEntry<'a> {
-    ptr: *mut Entry,
-    _marker: PhantomData<&'a Entry>,
+    slot: &'a Slot,
+    /// The entry data as it was when the table was probed.
+    entry: Entry,
+    /// The table's generation when it was probed.
+    gen: u8,
 }
 
 impl<'a> WritableEntry<'a> {
-    /// Create a `WritableEntry` from a raw pointer to the underlying `Entry`.
-    #[inline]
-    fn from_raw_ptr(ptr: *mut Entry) -> Self {
-        Self {
-            ptr,
-            _marker: PhantomData,
-        }
-    }
-
-    /// Write data to the entry.
+    /// Write data to the entry, subject to the replacement policy.
+    ///
+    /// Empty entries, entries which aren't exact and entries no deeper than the new data are
+    /// always replaced. Otherwise, the existing entry is a deep exact result, which we keep unless
+    /// it is old enough that its depth, discounted by `AGE_DEPTH_PENALTY` plies per generation,
+    /// no longer exceeds the new depth. In particular, deep exact entries from the current search
+    /// are never replaced by shallower results.
     #[inline]
     pub fn write(&self, pos: &Position, score: Score, depth: u8, bound: Bound, mov: &Move) {
-        let sig = (pos.zobrist().0 >> 48) as u16;
-
-        // SAFETY: we know that the `'a` reference will be outlived by the table, so we can never
-        // end up writing to a completely unrelated address. However, this may well be racy or
-        // break mutability guarantees. All we know is that we are writing into the table, which is
-        // enough for our use case.
-        unsafe {
-            *self.ptr = Entry {
-                sig,
-                depth,
-                gen_bound: GenBound::from_raw_parts(1, bound),
-                score,
-                mov: PackedMove::from_move(mov),
+        let old = &self.entry;
+        if !old.is_empty() && old.bound() == Bound::Exact && old.depth > depth {
+            let age = (GENERATIONS + self.gen - old.gen()) % GENERATIONS;
+            let discounted = old
+                .depth
+                .saturating_sub(age.saturating_mul(AGE_DEPTH_PENALTY));
+            if discounted > depth {
+                return;
             }
         }
+
+        let key = pos.zobrist().0;
+        let data = Entry {
+            sig: (key >> 48) as u16,
+            depth,
+            gen_bound: GenBound::from_raw_parts(self.gen, bound),
+            score,
+            mov: PackedMove::from_move(mov),
+        }
+        .pack();
+
+        self.slot.data.store(data, Ordering::Relaxed);
+        self.slot.key.store(key ^ data, Ordering::Relaxed);
     }
 
-    /// Get a shared reference to the `Entry` in order to read its current data.
+    /// Get a shared reference to the `Entry` in order to read its data. This is a snapshot taken
+    /// when the table was probed, and doesn't reflect later writes to the slot.
     pub fn read(&self) -> &Entry {
-        unsafe { &*self.ptr }
+        &self.entry
     }
 }
 
+/// The magic number which starts a saved table file.
+const FILE_MAGIC: [u8; 8] = *b"SEABOTT\0";
+
+/// The version of the saved table format. This must be bumped whenever the file layout or the
+/// packing of entries changes, so that files from an older build are rejected.
+const FILE_VERSION: u32 = 1;
+
+/// The length in bytes of a saved table's header: the magic number, the version, the number of
+/// entries and the generation.
+const FILE_HEADER_LEN: u64 = 8 + 4 + 8 + 1;
+
+/// The length in bytes of each saved slot.
+const FILE_SLOT_LEN: u64 = 16;
+
 /// The transposition table.
 ///
-/// The underlying storage for a `Table` uses `UnsafeCell`. Given our use patterns, we are actually
-/// comfortable with this data structure being racy and breaking mutability / pointer aliasing
-/// guarantees. In particular, it is theoretically possible for multiple `&mut` pointers to coexist
-/// for the same entry in the transposition table. When this happens, it is of course possible for
-/// data to get corrupted in an entry, but this should be extremely rare, and will cause less of a
-/// performance hit than using more reliable data structures. Data should never be corrupted in
-/// such a way as to actually cause a crash - just possibly-incorrect results for the probing code.
+/// The table is shared between search threads without any locking. Each slot is stored as a pair
+/// of atomics, and entries which were torn by concurrent writes are detected on probing (see
+/// `Slot`). Concurrent writes to the same slot may still lose one of the results, which only costs
+/// the search some work.
 pub struct Table {
     /// The storage buffer.
-    data: Box<[UnsafeCell<Entry>]>,
+    data: Box<[Slot]>,
     mask: usize,
+    /// The current generation, which is stamped on entries as they are written.
+    generation: AtomicU8,
 }
 
-unsafe impl Sync for Table {}
-
 impl std::fmt::Debug for Table {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         writeln!(f, "Table {{ data: Box<[Entry]>; mask: {} }}", self.mask)
@@ -274,31 +343,53 @@ impl Table {
     /// Create a new transposition table of `size` megabytes.
     ///
     /// The size will be treated as a guide only. The transposition table prioritises efficiency
-    /// and as such must use a power-of-2 number of entries. The table will be sized as close as
-    /// possible to the desired value, while using a power-of-2 entries.
+    /// and as such must use a power-of-2 number of entries. The table will be the largest size
+    /// with a power-of-2 entries which doesn't exceed the desired value.
     ///
     /// Note that we define 1MB = 1_024 * 1_024 bytes.
     pub fn new(size: usize) -> Self {
-        let entries = Table::size_from_mb(size);
+        Table::with_entries(Table::size_from_mb(size))
+    }
+
+    /// Create a new transposition table with a power-of-2 number of entries.
+    fn with_entries(entries: usize) -> Self {
+        debug_assert!(entries.is_power_of_two());
+
         let mut v = Vec::with_capacity(entries);
-        v.resize_with(entries, || UnsafeCell::new(Default::default()));
+        v.resize_with(entries, Default::default);
 
         Table {
             data: v.into_boxed_slice(),
             mask: entries - 1,
+            generation: AtomicU8::new(1),
         }
     }
 
+    /// Start a new generation. This should be called once at the start of each search, so that
+    /// entries from previous searches age and become candidates for replacement.
+    pub fn new_generation(&self) {
+        let gen = self.generation();
+        self.generation
+            .store(gen % GENERATIONS + 1, Ordering::Relaxed);
+    }
+
+    /// Returns the current generation.
+    #[inline(always)]
+    pub fn generation(&self) -> u8 {
+        self.generation.load(Ordering::Relaxed)
+    }
+
     /// Clear the transposition table.
     pub fn clear(&self) {
-        for e in &*self.data {
-            unsafe { e.get().write(Default::default()) }
+        for slot in &*self.data {
+            slot.data.store(0, Ordering::Relaxed);
+            slot.key.store(0, Ordering::Relaxed);
         }
     }
 
     fn size_from_mb(size: usize) -> usize {
-        let desired_entries = size * 1_024 * 1_024 / std::mem::size_of::<Entry>();
-        let log_desired_entries = (desired_entries as f64).log(2.).round();
+        let desired_entries = size * 1_024 * 1_024 / std::mem::size_of::<Slot>();
+        let log_desired_entries = (desired_entries as f64).log(2.).floor();
         let actual_entries = 2_usize.pow(log_desired_entries as u32);
         actual_entries
     }
@@ -312,7 +403,7 @@ impl Table {
     /// Returns the capacity of the transposition table in megabytes.
     #[inline(always)]
     pub fn capacity_mb(&self) -> usize {
-        self.capacity_entries() * std::mem::size_of::<Entry>() / 1_024 / 1_024
+        self.capacity_entries() * std::mem::size_of::<Slot>() / 1_024 / 1_024
     }
 
     /// Returns the idx for a given key. Uses bitwise operation to take the modulus of the key
@@ -322,28 +413,155 @@ impl Table {
         self.mask & (key as usize)
     }
 
-    /// Probe the table for a given `Position`. If an entry is in the table already, a shared
-    /// reference to it is returned. If not, a unique reference to the entry slot is returned,
-    /// which can be overwritten once search has produced a result.
+    /// Probe the table for a given `Position`. The returned entry holds a snapshot of the slot's
+    /// data, and can be overwritten once search has produced a result.
     #[inline(always)]
-    pub fn probe<'tt>(&'_ self, pos: &'_ Position) -> Probe<'tt> {
-        let idx = self.idx(pos.zobrist().0);
-        let sig = (pos.zobrist().0 >> 48) as u16;
+    pub fn probe<'tt>(&'tt self, pos: &'_ Position) -> Probe<'tt> {
+        let key = pos.zobrist().0;
 
         // We don't need to bounds check `idx` because it is guaranteed to be in bounds.
-        let entry = unsafe { self.data.get_unchecked(idx).get() };
-        let writable_entry = WritableEntry::from_raw_ptr(entry);
+        let slot = unsafe { self.data.get_unchecked(self.idx(key)) };
+        let data = slot.data.load(Ordering::Relaxed);
+        let check = slot.key.load(Ordering::Relaxed);
+
+        let entry = WritableEntry {
+            slot,
+            entry: Entry::unpack(data),
+            gen: self.generation(),
+        };
 
         use Probe::*;
-        unsafe {
-            if (*entry).is_empty() {
-                Empty(writable_entry)
-            } else if sig == (*entry).sig {
-                Hit(writable_entry)
-            } else {
-                Clash(writable_entry)
+        if entry.entry.is_empty() {
+            Empty(entry)
+        } else if check ^ data == key {
+            Hit(entry)
+        } else {
+            Clash(entry)
+        }
+    }
+
+    /// Prefetch the slot for the given key into the CPU cache, so that a later probe for that key
+    /// doesn't stall on a cache miss. This is a no-op on targets without SSE.
+    #[inline(always)]
+    pub fn prefetch(&self, key: Zobrist) {
+        #[cfg(all(target_arch = "x86_64", target_feature = "sse"))]
+        {
+            use std::arch::x86_64::{_mm_prefetch, _MM_HINT_T0};
+
+            // SAFETY: `idx` is guaranteed to be in bounds, and prefetching has no observable
+            // effects beyond performance.
+            unsafe {
+                let slot = self.data.get_unchecked(self.idx(key.0));
+                _mm_prefetch::<_MM_HINT_T0>(slot as *const Slot as *const i8);
             }
         }
+
+        #[cfg(not(all(target_arch = "x86_64", target_feature = "sse")))]
+        let _ = key;
+    }
+
+    /// Save the table to the file at `path`, so that it can be reloaded with `Table::load`.
+    ///
+    /// The file starts with a header of a magic number, the format version, the number of entries
+    /// and the current generation, followed by the raw slots. All values are little-endian. The
+    /// table shouldn't be written to while it is being saved, or the file may hold torn entries;
+    /// these are harmless, since they fail the key check on probing.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+
+        w.write_all(&FILE_MAGIC)?;
+        w.write_all(&FILE_VERSION.to_le_bytes())?;
+        w.write_all(&(self.capacity_entries() as u64).to_le_bytes())?;
+        w.write_all(&[self.generation()])?;
+
+        for slot in &*self.data {
+            w.write_all(&slot.key.load(Ordering::Relaxed).to_le_bytes())?;
+            w.write_all(&slot.data.load(Ordering::Relaxed).to_le_bytes())?;
+        }
+
+        w.flush()
+    }
+
+    /// Load a table saved with `Table::save` from the file at `path`.
+    ///
+    /// Returns an error of kind `InvalidData` if the file isn't a saved table, was saved by an
+    /// incompatible version, or holds fewer entries than a table of `HASH_MIN_MB` or more than a
+    /// table of `HASH_MAX_MB`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Table> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let mut r = BufReader::new(file);
+
+        let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidData, msg.to_string());
+
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)?;
+        if magic != FILE_MAGIC {
+            return Err(invalid("not a saved transposition table"));
+        }
+
+        let mut version = [0; 4];
+        r.read_exact(&mut version)?;
+        let version = u32::from_le_bytes(version);
+        if version != FILE_VERSION {
+            return Err(invalid(&format!(
+                "unsupported transposition table version {}; expected {}",
+                version, FILE_VERSION
+            )));
+        }
+
+        let mut entries = [0; 8];
+        r.read_exact(&mut entries)?;
+        let entries = usize::try_from(u64::from_le_bytes(entries))
+            .ok()
+            .filter(|n| n.is_power_of_two())
+            .ok_or_else(|| invalid("the number of entries must be a power of two"))?;
+
+        // Check the number of entries before allocating the table, so that a corrupt header can't
+        // ask for an arbitrary amount of memory.
+        if entries > Table::size_from_mb(HASH_MAX_MB) {
+            return Err(invalid(&format!(
+                "{} entries is more than the largest table holds",
+                entries
+            )));
+        }
+        if entries < Table::size_from_mb(HASH_MIN_MB) {
+            return Err(invalid(&format!(
+                "{} entries is fewer than the smallest table holds",
+                entries
+            )));
+        }
+        if len != FILE_HEADER_LEN + entries as u64 * FILE_SLOT_LEN {
+            return Err(invalid(
+                "the file length doesn't match the number of entries",
+            ));
+        }
+
+        let mut generation = [0; 1];
+        r.read_exact(&mut generation)?;
+        let generation = generation[0];
+        if generation == 0 || generation > GENERATIONS {
+            return Err(invalid("generation out of range"));
+        }
+
+        let table = Table::with_entries(entries);
+        table.generation.store(generation, Ordering::Relaxed);
+
+        let mut buf = [0; FILE_SLOT_LEN as usize];
+        for slot in &*table.data {
+            r.read_exact(&mut buf)?;
+            let (key, data) = buf.split_at(8);
+            slot.key.store(
+                u64::from_le_bytes(key.try_into().unwrap()),
+                Ordering::Relaxed,
+            );
+            slot.data.store(
+                u64::from_le_bytes(data.try_into().unwrap()),
+                Ordering::Relaxed,
+            );
+        }
+
+        Ok(table)
     }
 
     /// Calculate an approximation of the transposition table usage.
@@ -353,11 +571,8 @@ impl Table {
     /// This is used in info reports to the GUI via UCI, among others.
     pub fn hashfull(&self) -> u16 {
         let mut c = 0;
-        for e in &self.data[0..1000] {
-            // SAFETY: we are just reading the value. Not emitting a reference to safe code, nor
-            // mutating the value.
-            let entry = unsafe { &*e.get() };
-            if entry.is_empty() {
+        for slot in &self.data[0..1000] {
+            if Entry::unpack(slot.data.load(Ordering::Relaxed)).is_empty() {
                 c += 1;
             }
         }
@@ -411,29 +626,123 @@ impl<'a> Probe<'a> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use core::mono_traits::{All, Legal};
+    use core::movelist::BasicMoveList;
     use core::position::Position;
     use std::time::Instant;
     use Probe::*;
 
     #[test]
     fn sizes() {
-        assert_eq!(Table::size_from_mb(1), 131072);
-        assert_eq!(Table::size_from_mb(2), 262144);
-        assert_eq!(Table::size_from_mb(4), 524288);
-        assert_eq!(Table::size_from_mb(8), 1048576);
-        assert_eq!(Table::size_from_mb(16), 2097152);
-        assert_eq!(Table::size_from_mb(32), 4194304);
-        assert_eq!(Table::size_from_mb(64), 8388608);
-        assert_eq!(Table::size_from_mb(100), 16777216);
-        assert_eq!(Table::size_from_mb(128), 16777216);
-        assert_eq!(Table::size_from_mb(200), 33554432);
-        assert_eq!(Table::size_from_mb(256), 33554432);
-        assert_eq!(Table::size_from_mb(300), 33554432);
-        assert_eq!(Table::size_from_mb(400), 67108864);
-        assert_eq!(Table::size_from_mb(500), 67108864);
-        assert_eq!(Table::size_from_mb(512), 67108864);
-        assert_eq!(Table::size_from_mb(1000), 134217728);
-        assert_eq!(Table::size_from_mb(1024), 134217728);
+        assert_eq!(Table::size_from_mb(1), 65536);
+        assert_eq!(Table::size_from_mb(2), 131072);
+        assert_eq!(Table::size_from_mb(4), 262144);
+        assert_eq!(Table::size_from_mb(8), 524288);
+        assert_eq!(Table::size_from_mb(16), 1048576);
+        assert_eq!(Table::size_from_mb(32), 2097152);
+        assert_eq!(Table::size_from_mb(64), 4194304);
+        assert_eq!(Table::size_from_mb(100), 4194304);
+        assert_eq!(Table::size_from_mb(128), 8388608);
+        assert_eq!(Table::size_from_mb(200), 8388608);
+        assert_eq!(Table::size_from_mb(256), 16777216);
+        assert_eq!(Table::size_from_mb(300), 16777216);
+        assert_eq!(Table::size_from_mb(400), 16777216);
+        assert_eq!(Table::size_from_mb(500), 16777216);
+        assert_eq!(Table::size_from_mb(512), 33554432);
+        assert_eq!(Table::size_from_mb(1000), 33554432);
+        assert_eq!(Table::size_from_mb(1024), 67108864);
+    }
+
+    #[test]
+    fn concurrent_access() {
+        core::init::init_globals();
+
+        // Collect the positions two plies from the start.
+        let mut positions = Vec::new();
+        let mut pos = Position::start_pos();
+        for mov in &pos.generate::<BasicMoveList, All, Legal>() {
+            pos.make_move(mov);
+            for reply in &pos.generate::<BasicMoveList, All, Legal>() {
+                pos.make_move(reply);
+                positions.push(pos.clone());
+                pos.unmake_move();
+            }
+            pos.unmake_move();
+        }
+
+        // The data written for each position is derived from its key, so any hit can be checked.
+        fn expected(pos: &Position) -> (u8, Score) {
+            let key = pos.zobrist().0;
+            ((key % 64) as u8, Score::cp((key % 1000) as i32))
+        }
+
+        // Use a tiny table, so that threads are constantly overwriting each other's entries.
+        let tt = Table::with_entries(64);
+
+        std::thread::scope(|s| {
+            for t in 0..4 {
+                let (tt, positions) = (&tt, &positions);
+                s.spawn(move || {
+                    for i in 0..50_000 {
+                        let pos = &positions[(i * 7 + t * 101) % positions.len()];
+                        let (depth, score) = expected(pos);
+
+                        match tt.probe(pos) {
+                            Hit(entry) => {
+                                let e = entry.read();
+                                assert_eq!((e.depth, e.score), (depth, score));
+                                assert_eq!(e.bound(), Bound::Exact);
+                            }
+                            Clash(entry) | Empty(entry) => {
+                                entry.write(pos, score, depth, Bound::Exact, &Move::null());
+                            }
+                        }
+                    }
+                });
+            }
+        });
+    }
+
+    #[test]
+    fn replacement() {
+        core::init::init_globals();
+
+        let tt = Table::new(1);
+        let pos = Position::start_pos();
+
+        tt.probe(&pos)
+            .into_inner()
+            .write(&pos, Score::cp(10), 12, Bound::Exact, &Move::null());
+
+        // A shallower result doesn't replace a deep exact entry from the current search...
+        tt.probe(&pos)
+            .into_inner()
+            .write(&pos, Score::cp(20), 3, Bound::Lower, &Move::null());
+        assert_eq!(tt.probe(&pos).into_inner().read().depth, 12);
+
+        // ...nor from a recent one...
+        tt.new_generation();
+        tt.probe(&pos)
+            .into_inner()
+            .write(&pos, Score::cp(20), 3, Bound::Lower, &Move::null());
+        assert_eq!(tt.probe(&pos).into_inner().read().depth, 12);
+
+        // ...but it does once the entry is old enough.
+        for _ in 0..4 {
+            tt.new_generation();
+        }
+        tt.probe(&pos)
+            .into_inner()
+            .write(&pos, Score::cp(20), 3, Bound::Lower, &Move::null());
+        let entry = tt.probe(&pos).into_inner();
+        assert_eq!(entry.read().depth, 3);
+        assert_eq!(entry.read().gen(), tt.generation());
+
+        // Generations wrap around, skipping the generation reserved for empty entries.
+        for _ in 0..GENERATIONS {
+            tt.new_generation();
+            assert!(tt.generation() >= 1 && tt.generation() <= GENERATIONS);
+        }
     }
 
     #[rustfmt::skip]
@@ -461,7 +770,39 @@ mod tests {
             size
         );
         println!("{:?}", tt);
-        assert_eq!(tt.capacity_mb(), 1_024);
+        assert_eq!(tt.capacity_mb(), 512);
+    }
+
+    /// Every legal move survives packing and unpacking, flags included, so that making the unpacked
+    /// move has the same effect as making the original.
+    #[test]
+    fn packed_moves_round_trip() {
+        core::init::init_globals();
+
+        for fen in [
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            // En passant, for each side.
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d6 0 1",
+            "rnbqkbnr/pp1ppppp/8/8/2pPP3/8/PPP2PPP/RNBQKBNR b KQkq d3 0 3",
+            // Promotions and capturing promotions, for each side.
+            "r3k2r/1P4P1/8/8/8/8/1p4p1/R3K2R w KQkq - 0 1",
+            "r3k2r/1P4P1/8/8/8/8/1p4p1/R3K2R b KQkq - 0 1",
+        ] {
+            let pos = Position::from_fen(fen).unwrap();
+            for mov in &pos.generate::<BasicMoveList, All, Legal>() {
+                let unpacked = PackedMove::from_move(mov).to_move(&pos);
+                assert_eq!(unpacked, *mov, "{}", fen);
+
+                let mut original = pos.clone();
+                original.make_move(mov);
+                let mut reconstructed = pos.clone();
+                reconstructed.make_move(&unpacked);
+                assert_eq!(reconstructed.to_fen(), original.to_fen());
+                assert_eq!(reconstructed.zobrist(), original.zobrist());
+            }
+        }
     }
 
     #[test]
@@ -507,4 +848,88 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn save_and_load() {
+        use crate::search::{Search, Worker};
+        use crate::time::Deadline;
+        use std::sync::atomic::AtomicBool;
+
+        core::init::init_globals();
+
+        let fen = "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4";
+        let pos = Position::from_fen(fen).unwrap();
+        let tt = Table::new(1);
+        let flag = AtomicBool::new(false);
+        let deadline = Deadline::none();
+        let (_, best) = Search::new(pos.clone(), &flag, &deadline, &tt).run::<Worker>(5);
+
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("seaborg-tt-{}.bin", std::process::id()));
+        tt.save(&path).unwrap();
+        let loaded = Table::load(&path);
+
+        // A file which isn't a saved table is rejected.
+        let bad = dir.join(format!("seaborg-tt-bad-{}.bin", std::process::id()));
+        std::fs::write(&bad, b"definitely not a table").unwrap();
+        let err = Table::load(&bad).unwrap_err();
+        std::fs::remove_file(&bad).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+        assert_eq!(loaded.capacity_entries(), tt.capacity_entries());
+        assert_eq!(loaded.generation(), tt.generation());
+
+        match loaded.probe(&pos) {
+            Hit(entry) => assert_eq!(entry.read().mov.to_move(&pos), best),
+            _ => panic!("the root position should be in the loaded table"),
+        }
+    }
+
+    #[test]
+    fn load_checks_the_size_before_allocating() {
+        let header = |entries: u64| {
+            let mut file = FILE_MAGIC.to_vec();
+            file.extend(FILE_VERSION.to_le_bytes());
+            file.extend(entries.to_le_bytes());
+            file.push(1);
+            file
+        };
+
+        let path = std::env::temp_dir().join(format!("seaborg-tt-size-{}.bin", std::process::id()));
+        let load = |file: &[u8]| {
+            std::fs::write(&path, file).unwrap();
+            let result = Table::load(&path);
+            std::fs::remove_file(&path).unwrap();
+            result
+        };
+
+        // Far more entries than any table holds.
+        let err = load(&header(1 << 40)).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        // Fewer entries than the smallest table holds, even with all of them in the file.
+        let mut file = header(1);
+        file.extend([0; FILE_SLOT_LEN as usize]);
+        let err = load(&file).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        // A plausible number of entries, but none of them in the file.
+        let entries = Table::size_from_mb(HASH_MIN_MB);
+        let err = load(&header(entries as u64)).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        // Trailing data after the last entry.
+        let mut file = header(entries as u64);
+        file.resize(
+            (FILE_HEADER_LEN + (entries as u64 + 1) * FILE_SLOT_LEN) as usize,
+            0,
+        );
+        let err = load(&file).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+
+        file.truncate((FILE_HEADER_LEN + entries as u64 * FILE_SLOT_LEN) as usize);
+        assert_eq!(load(&file).unwrap().capacity_entries(), entries);
+    }
 }
@@ -1,5 +1,7 @@
 use super::options::EngineOpt;
+use super::score::MAX_MATE_MOVES;
 use super::time::{TimeControl, TimingMode};
+use core::position::Position;
 
 /// A UCI message sent by the GUI to the engine.
 #[derive(Clone, Debug)]
@@ -18,6 +20,14 @@ pub enum Command {
     SetOption(EngineOpt),
     /// Commence the search process.
     Go(TimingMode),
+    /// Commence the search process, only considering the given root moves.
+    GoSearchMoves(Vec<String>, TimingMode),
+    /// Commence a ponder search. The search runs without a time limit until `ponderhit` is
+    /// received, at which point the given timing mode takes effect. Any search moves restrict the
+    /// root moves, as for `GoSearchMoves`.
+    GoPonder(Vec<String>, TimingMode),
+    /// The opponent played the expected move, so switch the ponder search over to a normal search.
+    PonderHit,
     /// Halt the search process, but don't quit the engine.
     Stop,
     /// Stop the search process and quit the engine.
@@ -116,15 +126,50 @@ pub enum Error {
     /// The position provided was invalid.
     InvalidPosition(core::position::FenError),
     /// A move provided as part of setting the position is invalid.
-    InvalidMove,
+    InvalidMove(String),
     /// A go command was issued with an unsupported time control.
     UnsupportedTimeControl,
     /// A go comannd was issued with a time control that was incomplete.
     IncompleteTimeControl,
 }
 
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::NoInput => write!(f, "no input"),
+            Error::ExpectedEnd => write!(f, "unexpected input after the command"),
+            Error::ExpectedNumber => write!(f, "expected a number"),
+            Error::ExpectedString => write!(f, "expected a string"),
+            Error::ExpectedBool => write!(f, "expected true or false"),
+            Error::UnexpectedEnd => write!(f, "unexpected end of input"),
+            Error::UnexpectedToken => write!(f, "unexpected token"),
+            Error::InvalidOption => write!(f, "invalid option"),
+            Error::NoPosition => write!(f, "no position given"),
+            Error::InvalidPosition(err) => write!(f, "{}", err),
+            Error::InvalidMove(mov) => write!(f, "illegal move {}", mov),
+            Error::UnsupportedTimeControl => write!(f, "unsupported time control"),
+            Error::IncompleteTimeControl => write!(f, "incomplete time control"),
+        }
+    }
+}
+
 pub type PResult = Result<Command, Error>;
 
+/// Set up the position given by a `position` command: the fen, followed by each of the moves in
+/// turn. The moves are made on the board, so the position's history holds the whole game and
+/// repetitions are detected. Fails if the fen is invalid or any move is illegal.
+pub fn set_position(fen: &str, moves: &[String]) -> Result<Position, Error> {
+    let mut pos = Position::from_fen(fen).map_err(Error::InvalidPosition)?;
+
+    for mov in moves {
+        if pos.make_uci_move(mov).is_none() {
+            return Err(Error::InvalidMove(mov.clone()));
+        }
+    }
+
+    Ok(pos)
+}
+
 pub struct Parser<'a> {
     raw: &'a str,
     toks: Vec<Token<'a>>,
@@ -202,6 +247,11 @@ impl<'a> Parser<'a> {
         s.parse::<usize>().map_err(|_| Error::ExpectedNumber)
     }
 
+    fn parse_signed_integer(&mut self) -> Result<i32, Error> {
+        let s = self.parse_string()?;
+        s.parse::<i32>().map_err(|_| Error::ExpectedNumber)
+    }
+
     fn parse_bool(&mut self) -> Result<bool, Error> {
         let b = self.parse_string()?;
         match b {
@@ -222,6 +272,7 @@ impl<'a> Parser<'a> {
                 Token::Kw(Keyword::Position) => self.parse_position_and_moves(),
                 Token::Kw(Keyword::Go) => self.parse_go(),
                 Token::Kw(Keyword::Stop) => self.parse_stop(),
+                Token::Kw(Keyword::PonderHit) => self.parse_ponderhit(),
                 Token::Kw(Keyword::Quit) => self.parse_quit(),
                 Token::Kw(Keyword::Display) => self.parse_display(),
                 Token::Kw(Keyword::DisplayLichess) => self.parse_display_lichess(),
@@ -326,22 +377,22 @@ impl<'a> Parser<'a> {
         // - movetime
         // - infinite
         //
-        // We only support time control, depth and infinite for now. We'll match on the next
+        // We only support searchmoves, time control, depth, nodes, mate and infinite for now. We'll match on the next
         // token, and handle the legitimate UCI commands with a panic saying that we don't support
         // that time control (or we could just return `go infinite` and at least not crash).
 
         match self.peek() {
             Some(tok) => match *tok {
-                Token::Kw(Keyword::SearchMoves) => self.unsupported_time_control(),
-                Token::Kw(Keyword::Ponder) => self.unsupported_time_control(),
+                Token::Kw(Keyword::SearchMoves) => self.parse_searchmoves(),
+                Token::Kw(Keyword::Ponder) => self.parse_ponder(),
                 Token::Kw(Keyword::Wtime) => self.parse_time_control(),
                 Token::Kw(Keyword::Btime) => self.parse_time_control(),
                 Token::Kw(Keyword::Winc) => self.parse_time_control(),
                 Token::Kw(Keyword::Binc) => self.parse_time_control(),
                 Token::Kw(Keyword::MovesToGo) => self.parse_time_control(),
                 Token::Kw(Keyword::Depth) => self.parse_depth(),
-                Token::Kw(Keyword::Nodes) => self.unsupported_time_control(),
-                Token::Kw(Keyword::Mate) => self.unsupported_time_control(),
+                Token::Kw(Keyword::Nodes) => self.parse_nodes(),
+                Token::Kw(Keyword::Mate) => self.parse_mate(),
                 Token::Kw(Keyword::MoveTime) => self.parse_movetime(),
                 Token::Kw(Keyword::Infinite) => self.parse_infinite(),
                 Token::Kw(Keyword::PonderHit) => self.unsupported_time_control(),
@@ -351,14 +402,55 @@ impl<'a> Parser<'a> {
         }
     }
 
+    fn parse_searchmoves(&mut self) -> PResult {
+        self.advance().ok_or(Error::UnexpectedEnd)?;
+
+        let mut moves = Vec::new();
+        while let Some(Token::String(mov)) = self.peek() {
+            moves.push(mov.to_string());
+            self.advance();
+        }
+
+        // The moves may be followed by any other `go` parameters. Without any, search until told
+        // to stop.
+        match self.peek() {
+            Some(_) => match self.parse_go()? {
+                Command::Go(timing_mode) => Ok(Command::GoSearchMoves(moves, timing_mode)),
+                Command::GoPonder(more, timing_mode) => {
+                    moves.extend(more);
+                    Ok(Command::GoPonder(moves, timing_mode))
+                }
+                _ => Err(Error::UnexpectedToken),
+            },
+            None => Ok(Command::GoSearchMoves(moves, TimingMode::Infinite)),
+        }
+    }
+
     fn parse_stop(&mut self) -> PResult {
         Ok(Command::Stop)
     }
 
+    fn parse_ponder(&mut self) -> PResult {
+        self.advance().ok_or(Error::UnexpectedEnd)?;
+
+        match self.parse_go()? {
+            Command::Go(timing_mode) => Ok(Command::GoPonder(Vec::new(), timing_mode)),
+            Command::GoSearchMoves(moves, timing_mode) => Ok(Command::GoPonder(moves, timing_mode)),
+            _ => Err(Error::UnexpectedToken),
+        }
+    }
+
+    fn parse_ponderhit(&mut self) -> PResult {
+        self.expect_end(Ok(Command::PonderHit))
+    }
+
     fn parse_quit(&mut self) -> PResult {
         Ok(Command::Quit)
     }
 
+    /// Parse the clock times, increments and moves to go, in any order. A missing increment is
+    /// taken to be zero, and a missing `movestogo` to mean there is no further time control. If
+    /// only one player's time is given, it is taken for both; at least one must be.
     fn parse_time_control(&mut self) -> PResult {
         let mut wtime: Option<usize> = None;
         let mut btime: Option<usize> = None;
@@ -387,13 +479,15 @@ impl<'a> Parser<'a> {
             }
         }
 
-        if wtime.is_none() || btime.is_none() {
-            return Err(Error::IncompleteTimeControl);
-        }
+        let (wtime, btime) = match (wtime, btime) {
+            (Some(wtime), Some(btime)) => (wtime, btime),
+            (Some(time), None) | (None, Some(time)) => (time, time),
+            (None, None) => return Err(Error::IncompleteTimeControl),
+        };
 
         Ok(Command::Go(TimingMode::Timed(TimeControl::new(
-            wtime.expect("should not be None"),
-            btime.expect("should not be None"),
+            wtime,
+            btime,
             winc,
             binc,
             moves_to_go,
@@ -407,6 +501,23 @@ impl<'a> Parser<'a> {
         Ok(Command::Go(TimingMode::Depth(depth)))
     }
 
+    fn parse_nodes(&mut self) -> PResult {
+        self.advance().ok_or(Error::UnexpectedEnd)?;
+
+        let nodes = self.parse_integer()? as u64;
+        self.expect_end(Ok(Command::Go(TimingMode::Nodes(nodes))))
+    }
+
+    fn parse_mate(&mut self) -> PResult {
+        self.advance().ok_or(Error::UnexpectedEnd)?;
+
+        // No score can represent a mate in more moves than this, so longer mates are searched for as
+        // if the limit were the longest one.
+        let moves = u8::try_from(self.parse_integer()?).map_err(|_| Error::ExpectedNumber)?;
+        let moves = std::cmp::min(moves, MAX_MATE_MOVES);
+        self.expect_end(Ok(Command::Go(TimingMode::Mate(moves))))
+    }
+
     fn parse_infinite(&mut self) -> PResult {
         self.advance().ok_or(Error::UnexpectedEnd)?;
         self.expect_end(Ok(Command::Go(TimingMode::Infinite)))
@@ -435,8 +546,22 @@ impl<'a> Parser<'a> {
     fn parse_setoption(&mut self) -> PResult {
         self.expect_kw(Keyword::Name)?;
 
-        match self.parse_string()? {
+        // Option names can contain spaces, so the name runs up to the `value` keyword.
+        let mut name = vec![self.parse_string()?];
+        while let Some(Token::String(s)) = self.peek() {
+            name.push(*s);
+            self.advance();
+        }
+
+        match name.join(" ").as_str() {
             "Hash" => self.parse_hash(),
+            "Threads" => self.parse_threads(),
+            "MultiPV" => self.parse_multipv(),
+            "Contempt" => self.parse_contempt(),
+            "Move Overhead" => self.parse_move_overhead(),
+            "Ponder" => self.parse_ponder_option(),
+            #[cfg(feature = "syzygy")]
+            "SyzygyPath" => self.parse_syzygy_path(),
             _ => Err(Error::InvalidOption),
         }
     }
@@ -449,6 +574,58 @@ impl<'a> Parser<'a> {
         Ok(Command::SetOption(EngineOpt::Hash(v)))
     }
 
+    fn parse_threads(&mut self) -> PResult {
+        self.expect_kw(Keyword::Value)?;
+
+        let v = self.parse_integer()?;
+
+        Ok(Command::SetOption(EngineOpt::Threads(v)))
+    }
+
+    fn parse_multipv(&mut self) -> PResult {
+        self.expect_kw(Keyword::Value)?;
+
+        let v = self.parse_integer()?;
+
+        Ok(Command::SetOption(EngineOpt::MultiPv(v)))
+    }
+
+    fn parse_contempt(&mut self) -> PResult {
+        self.expect_kw(Keyword::Value)?;
+
+        let v = self.parse_signed_integer()?;
+
+        Ok(Command::SetOption(EngineOpt::Contempt(v)))
+    }
+
+    fn parse_move_overhead(&mut self) -> PResult {
+        self.expect_kw(Keyword::Value)?;
+
+        let v = self.parse_integer()?;
+
+        Ok(Command::SetOption(EngineOpt::MoveOverhead(v)))
+    }
+
+    fn parse_ponder_option(&mut self) -> PResult {
+        self.expect_kw(Keyword::Value)?;
+
+        let b = self.parse_bool()?;
+
+        Ok(Command::SetOption(EngineOpt::Ponder(b)))
+    }
+
+    #[cfg(feature = "syzygy")]
+    fn parse_syzygy_path(&mut self) -> PResult {
+        self.expect_kw(Keyword::Value)?;
+
+        let path = match self.parse_string()? {
+            "<empty>" => None,
+            path => Some(path.to_string()),
+        };
+
+        Ok(Command::SetOption(EngineOpt::SyzygyPath(path)))
+    }
+
     fn parse_display(&mut self) -> PResult {
         if self.peek().is_some() {
             match self.advance().unwrap() {
@@ -529,3 +706,145 @@ impl<'a> Token<'a> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::position::Player;
+
+    fn parse_position(line: &str) -> Result<Position, Error> {
+        match Parser::parse(line)? {
+            Command::SetPosition((fen, moves)) => set_position(&fen, &moves),
+            cmd => panic!("{} parsed as {:?}", line, cmd),
+        }
+    }
+
+    #[test]
+    fn position_startpos_moves() {
+        core::init::init_globals();
+
+        let pos = parse_position("position startpos moves e2e4 c7c5 g1f3 d7d6").unwrap();
+        let expected =
+            Position::from_fen("rnbqkbnr/pp2pppp/3p4/2p5/4P3/5N2/PPPP1PPP/RNBQKB1R w KQkq - 0 3")
+                .unwrap();
+        assert_eq!(pos.to_fen(), expected.to_fen());
+        assert_eq!(pos.zobrist(), expected.zobrist());
+        assert_eq!(pos.history().len(), 4);
+
+        let pos = parse_position("position startpos").unwrap();
+        assert_eq!(pos.zobrist(), Position::start_pos().zobrist());
+    }
+
+    #[test]
+    fn position_fen_moves() {
+        core::init::init_globals();
+
+        // Castling and an en passant capture, from kiwipete.
+        let pos = parse_position(
+            "position fen r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1 \
+             moves e1g1 c7c5 d5c6",
+        )
+        .unwrap();
+        let expected =
+            Position::from_fen("r3k2r/p2pqpb1/bnP1pnp1/4N3/1p2P3/2N2Q1p/PPPBBPPP/R4RK1 b kq - 0 2")
+                .unwrap();
+        assert_eq!(pos.to_fen(), expected.to_fen());
+        assert_eq!(pos.zobrist(), expected.zobrist());
+    }
+
+    #[test]
+    fn position_repetitions_are_detected() {
+        core::init::init_globals();
+
+        let pos = parse_position("position startpos moves g1f3 g8f6 f3g1 f6g8 g1f3 g8f6 f3g1 f6g8")
+            .unwrap();
+        assert!(pos.in_threefold());
+    }
+
+    #[test]
+    fn go_time_control() {
+        fn move_time(line: &str, move_number: u32, turn: Player) -> u32 {
+            match Parser::parse(line).unwrap() {
+                Command::Go(TimingMode::Timed(tc)) => tc.to_move_time(move_number, turn),
+                cmd => panic!("{} parsed as {:?}", line, cmd),
+            }
+        }
+
+        let line = "go wtime 300000 btime 240000 winc 2000 binc 1000 movestogo 20";
+        let tc = TimeControl::new(300_000, 240_000, 2_000, 1_000, Some(20));
+        for turn in [Player::WHITE, Player::BLACK] {
+            assert_eq!(move_time(line, 30, turn), tc.to_move_time(30, turn));
+        }
+
+        // The fields may come in any order, and missing ones take their defaults.
+        let tc = TimeControl::new(60_000, 45_000, 0, 500, None);
+        for turn in [Player::WHITE, Player::BLACK] {
+            assert_eq!(
+                move_time("go binc 500 btime 45000 wtime 60000", 30, turn),
+                tc.to_move_time(30, turn)
+            );
+        }
+
+        // With only one clock, it is used for both.
+        let tc = TimeControl::new(60_000, 60_000, 0, 0, None);
+        assert_eq!(
+            move_time("go wtime 60000", 30, Player::BLACK),
+            tc.to_move_time(30, Player::BLACK)
+        );
+
+        assert!(matches!(
+            Parser::parse("go winc 1000 binc 1000"),
+            Err(Error::IncompleteTimeControl)
+        ));
+    }
+
+    #[test]
+    fn go_mate_is_capped_at_the_longest_mate() {
+        assert!(matches!(
+            Parser::parse("go mate 5"),
+            Ok(Command::Go(TimingMode::Mate(5)))
+        ));
+        assert!(matches!(
+            Parser::parse("go mate 60"),
+            Ok(Command::Go(TimingMode::Mate(MAX_MATE_MOVES)))
+        ));
+        assert!(matches!(
+            Parser::parse("go mate 256"),
+            Err(Error::ExpectedNumber)
+        ));
+    }
+
+    #[test]
+    fn go_ponder_with_searchmoves() {
+        for line in [
+            "go searchmoves e2e4 d2d4 ponder wtime 1000 btime 1000",
+            "go ponder searchmoves e2e4 d2d4 wtime 1000 btime 1000",
+        ] {
+            match Parser::parse(line).unwrap() {
+                Command::GoPonder(moves, TimingMode::Timed(_)) => {
+                    assert_eq!(moves, vec!["e2e4", "d2d4"], "{}", line)
+                }
+                cmd => panic!("{} parsed as {:?}", line, cmd),
+            }
+        }
+
+        assert!(matches!(
+            Parser::parse("go ponder wtime 1000 btime 1000"),
+            Ok(Command::GoPonder(moves, _)) if moves.is_empty()
+        ));
+    }
+
+    #[test]
+    fn position_rejects_illegal_moves() {
+        core::init::init_globals();
+
+        assert!(matches!(
+            parse_position("position startpos moves e2e4 e2e4"),
+            Err(Error::InvalidMove(mov)) if mov == "e2e4"
+        ));
+        assert!(matches!(
+            parse_position("position fen 4k3/8/8/8/8/8/8/4K2X w - - 0 1"),
+            Err(Error::InvalidPosition(_))
+        ));
+    }
+}
@@ -1,6 +1,6 @@
 use core::init::init_globals;
 use core::position::Position;
-use engine::perft::Perft;
+use engine::perft::{Perft, PerftOptions};
 
 use separator::Separatable;
 
@@ -16,6 +16,19 @@ pub struct PerftArgs {
     /// promotions) & timing data
     #[clap(short, long, action, default_value_t = false)]
     verbose: bool,
+    /// Make and unmake every leaf move rather than counting the leaves in bulk. This is much
+    /// slower, but useful for debugging make and unmake move
+    #[clap(long, action, default_value_t = false)]
+    no_bulk: bool,
+    /// Size in MB of the table caching the node counts of subtrees; 0 disables caching
+    #[clap(long, action, default_value_t = 0)]
+    hash: usize,
+    /// Number of threads to split the root moves between
+    #[clap(short, long, action, default_value_t = 1)]
+    threads: usize,
+    /// Keep the moves at each ply in a `MoveStack` rather than a fixed size array
+    #[clap(long, action, default_value_t = false)]
+    movestack: bool,
     /// Depth to search
     #[clap(short = 'n', long, action, default_value_t = 1)]
     depth: u8,
@@ -34,10 +47,19 @@ pub fn perft(args: &PerftArgs) {
         Ok(ref mut pos) => {
             let start_zob = pos.zobrist().clone();
             let now = Instant::now();
-            let perft_result = if args.divide {
-                Perft::divide(pos, depth as usize, false, false)
+            let options = PerftOptions::new(false, false)
+                .with_bulk(!args.no_bulk)
+                .with_hash(args.hash)
+                .with_threads(args.threads)
+                .with_movestack(args.movestack);
+            let nodes = if args.divide {
+                let result = Perft::divide_with_options(pos, depth as usize, options);
+                println!("{}", result);
+                result.nodes
             } else {
-                Perft::perft(pos, depth as usize, false, false, true)
+                Perft::perft_with_options(pos, depth as usize, options, true)
+                    .nodes
+                    .unwrap()
             };
 
             let elapsed = now.elapsed();
@@ -49,8 +71,7 @@ pub fn perft(args: &PerftArgs) {
             );
             println!(
                 "{} nodes/sec",
-                ((perft_result.nodes.unwrap() * 1_000_000_000) / (elapsed.as_nanos() as usize))
-                    .separated_string()
+                ((nodes * 1_000_000_000) / (elapsed.as_nanos() as usize)).separated_string()
             );
             let end_zob = pos.zobrist().clone();
             println!();